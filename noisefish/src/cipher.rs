@@ -0,0 +1,311 @@
+use std::io;
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use protofish::StreamId;
+use sha2::Sha256;
+
+use crate::handshake::SplitKeys;
+
+/// Length of the authentication tag added to every sealed message.
+pub(crate) const TAG_LEN: usize = 16;
+/// Length of the explicit nonce prefixed to unreliable messages.
+pub(crate) const NONCE_LEN: usize = 8;
+
+const KEY_INFO: &[u8] = b"protofish noise stream";
+
+/// Derives the keys of one stream from the secret split keys of the
+/// handshake.
+///
+/// Every stream and direction gets its own key, so nonces can start at zero
+/// on each of them. The handshake hash is not used, as it only covers the
+/// public transcript.
+///
+/// # Returns
+///
+/// Returns the sending and receiving key of the side that was the handshake
+/// initiator if `initiator` is set, of the responder otherwise.
+pub(crate) fn stream_keys(split: &SplitKeys, initiator: bool, id: StreamId) -> (Key, Key) {
+    let derive = |secret: &[u8], direction: u8| {
+        let mut info = KEY_INFO.to_vec();
+        info.extend_from_slice(&id.to_le_bytes());
+        info.push(direction);
+
+        let mut key = Key::default();
+        Hkdf::<Sha256>::new(None, secret)
+            .expand(&info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        key
+    };
+
+    // direction 0 is initiator to responder
+    let (outgoing, incoming) = (derive(&split.0, 0), derive(&split.1, 1));
+    if initiator {
+        (outgoing, incoming)
+    } else {
+        (incoming, outgoing)
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+fn auth_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "message authentication failed")
+}
+
+/// Encrypts the messages of one direction of a stream.
+pub(crate) struct Sealer {
+    cipher: ChaCha20Poly1305,
+    next: u64,
+}
+
+impl Sealer {
+    pub fn new(key: &Key) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(key),
+            next: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> io::Result<u64> {
+        let counter = self.next;
+        self.next = counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("stream nonces exhausted"))?;
+        Ok(counter)
+    }
+
+    /// Encrypts the next message of an ordered stream. The nonce is
+    /// implicit.
+    pub fn seal(&mut self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let counter = self.next_nonce()?;
+        self.cipher
+            .encrypt(&nonce(counter), plaintext)
+            .map_err(|_| io::Error::other("encryption failed"))
+    }
+
+    /// Encrypts a message that may be lost or reordered, prefixing it with
+    /// its nonce.
+    pub fn seal_datagram(&mut self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let counter = self.next_nonce()?;
+        let sealed = self
+            .cipher
+            .encrypt(&nonce(counter), plaintext)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+
+        let mut message = Vec::with_capacity(NONCE_LEN + sealed.len());
+        message.extend_from_slice(&counter.to_le_bytes());
+        message.extend_from_slice(&sealed);
+        Ok(message)
+    }
+}
+
+/// Decrypts the messages of one direction of a stream.
+pub(crate) struct Opener {
+    cipher: ChaCha20Poly1305,
+    next: u64,
+    replay: ReplayWindow,
+}
+
+impl Opener {
+    pub fn new(key: &Key) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(key),
+            next: 0,
+            replay: ReplayWindow::default(),
+        }
+    }
+
+    /// Decrypts the next message of an ordered stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the message was tampered with,
+    /// dropped or reordered.
+    pub fn open(&mut self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let plaintext = self
+            .cipher
+            .decrypt(&nonce(self.next), sealed)
+            .map_err(|_| auth_error())?;
+
+        self.next += 1;
+        Ok(plaintext)
+    }
+
+    /// Decrypts a message sealed by `Sealer::seal_datagram`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the message was tampered with or
+    /// was already received.
+    pub fn open_datagram(&mut self, message: &[u8]) -> io::Result<Vec<u8>> {
+        if message.len() < NONCE_LEN + TAG_LEN {
+            return Err(auth_error());
+        }
+
+        let (counter, sealed) = message.split_at(NONCE_LEN);
+        let counter = u64::from_le_bytes(counter.try_into().unwrap());
+
+        if !self.replay.is_fresh(counter) {
+            return Err(auth_error());
+        }
+
+        let plaintext = self
+            .cipher
+            .decrypt(&nonce(counter), sealed)
+            .map_err(|_| auth_error())?;
+
+        self.replay.mark(counter);
+        Ok(plaintext)
+    }
+}
+
+/// Remembers which of the last 64 nonces were received, rejecting repeats
+/// and anything older.
+#[derive(Default)]
+struct ReplayWindow {
+    /// Highest nonce received so far.
+    top: Option<u64>,
+    /// Bit `i` is set if nonce `top - i` was received.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    fn is_fresh(&self, counter: u64) -> bool {
+        match self.top {
+            None => true,
+            Some(top) if counter > top => true,
+            Some(top) => {
+                let age = top - counter;
+                age < 64 && self.seen & (1 << age) == 0
+            }
+        }
+    }
+
+    fn mark(&mut self, counter: u64) {
+        match self.top {
+            Some(top) if counter <= top => self.seen |= 1 << (top - counter),
+            top => {
+                let shift = top.map_or(64, |top| counter - top);
+                self.seen = if shift >= 64 { 0 } else { self.seen << shift };
+                self.seen |= 1;
+                self.top = Some(counter);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::handshake::XX_PARAMS;
+
+    const SPLIT: SplitKeys = ([7u8; 32], [9u8; 32]);
+
+    fn pair(id: StreamId) -> (Sealer, Opener) {
+        let (send, _) = stream_keys(&SPLIT, true, id);
+        let (_, recv) = stream_keys(&SPLIT, false, id);
+        (Sealer::new(&send), Opener::new(&recv))
+    }
+
+    /// Runs an XX handshake in memory, returning the initiator's state.
+    fn handshake() -> snow::HandshakeState {
+        let params: snow::params::NoiseParams = XX_PARAMS.parse().unwrap();
+        let keypair = |params| snow::Builder::new(params).generate_keypair().unwrap();
+        let (a, b) = (keypair(params.clone()), keypair(params.clone()));
+
+        let mut initiator = snow::Builder::new(params.clone())
+            .local_private_key(&a.private)
+            .build_initiator()
+            .unwrap();
+        let mut responder = snow::Builder::new(params)
+            .local_private_key(&b.private)
+            .build_responder()
+            .unwrap();
+
+        let (mut message, mut payload) = ([0u8; 1024], [0u8; 1024]);
+        let mut turn = (&mut initiator, &mut responder);
+        while !turn.0.is_handshake_finished() || !turn.1.is_handshake_finished() {
+            let len = turn.0.write_message(&[], &mut message).unwrap();
+            turn.1.read_message(&message[..len], &mut payload).unwrap();
+            turn = (turn.1, turn.0);
+        }
+
+        initiator
+    }
+
+    #[test]
+    fn test_ordered_roundtrip() {
+        let (mut sealer, mut opener) = pair(0);
+
+        let first = sealer.seal(b"hello").unwrap();
+        let second = sealer.seal(b"world").unwrap();
+        assert_ne!(&first[..5], b"hello");
+
+        assert_eq!(opener.open(&first).unwrap(), b"hello");
+        assert_eq!(opener.open(&second).unwrap(), b"world");
+    }
+
+    #[test]
+    fn test_ordered_rejects_tampering_and_reordering() {
+        let (mut sealer, mut opener) = pair(0);
+
+        let mut first = sealer.seal(b"hello").unwrap();
+        let second = sealer.seal(b"world").unwrap();
+
+        assert!(opener.open(&second).is_err());
+        first[0] ^= 1;
+        assert!(opener.open(&first).is_err());
+    }
+
+    #[test]
+    fn test_keys_differ_per_stream_and_direction() {
+        let (send, recv) = stream_keys(&SPLIT, true, 0);
+        let (other_send, _) = stream_keys(&SPLIT, true, 2);
+
+        assert_ne!(send, recv);
+        assert_ne!(send, other_send);
+    }
+
+    #[test]
+    fn test_handshake_hash_does_not_open_streams() {
+        let mut handshake = handshake();
+        let hash = handshake.get_handshake_hash().to_vec();
+        let split = handshake.dangerously_get_raw_split();
+
+        let (send, _) = stream_keys(&split, true, 0);
+        let sealed = Sealer::new(&send).seal(b"secret").unwrap();
+
+        // an observer knows the hash, so keys derived from it must not work
+        let hash: [u8; 32] = hash.try_into().unwrap();
+        let (_, observed) = stream_keys(&(hash, hash), false, 0);
+        assert!(Opener::new(&observed).open(&sealed).is_err());
+
+        let (_, recv) = stream_keys(&split, false, 0);
+        assert_eq!(Opener::new(&recv).open(&sealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_datagrams_out_of_order_without_replays() {
+        let (mut sealer, mut opener) = pair(1);
+
+        let messages: Vec<_> = (0..100u8)
+            .map(|i| sealer.seal_datagram(&[i]).unwrap())
+            .collect();
+
+        assert_eq!(opener.open_datagram(&messages[5]).unwrap(), [5]);
+        assert_eq!(opener.open_datagram(&messages[3]).unwrap(), [3]);
+        assert!(opener.open_datagram(&messages[5]).is_err());
+
+        assert_eq!(opener.open_datagram(&messages[99]).unwrap(), [99]);
+        // too old to be told apart from a replay
+        assert!(opener.open_datagram(&messages[10]).is_err());
+        assert_eq!(opener.open_datagram(&messages[40]).unwrap(), [40]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::handshake::XX_PARAMS;
+
+/// Decides whether to accept a peer, given its static public key.
+pub type PeerVerifier = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// A static X25519 key pair, identifying one side of a connection.
+#[derive(Clone)]
+pub struct Keypair {
+    public: Vec<u8>,
+    private: Vec<u8>,
+}
+
+impl Keypair {
+    /// Generates a new random key pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no randomness is available.
+    pub fn generate() -> Result<Self> {
+        let keypair = snow::Builder::new(XX_PARAMS.parse()?).generate_keypair()?;
+
+        Ok(Self {
+            public: keypair.public,
+            private: keypair.private,
+        })
+    }
+
+    /// Restores a key pair generated earlier.
+    pub fn from_parts(public: Vec<u8>, private: Vec<u8>) -> Self {
+        Self { public, private }
+    }
+
+    pub fn public(&self) -> &[u8] {
+        &self.public
+    }
+
+    pub fn private(&self) -> &[u8] {
+        &self.private
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Settings of a `NoiseUTP`.
+#[derive(Clone)]
+pub struct NoiseConfig {
+    /// Static key pair of this side
+    pub keypair: Keypair,
+    /// Static public key of the server, if known in advance. Clients that
+    /// set it use the one round trip IK handshake instead of XX.
+    pub remote_public: Option<Vec<u8>>,
+    /// Called with the peer's static public key once the handshake is done
+    pub verifier: Option<PeerVerifier>,
+}
+
+impl NoiseConfig {
+    pub fn new(keypair: Keypair) -> Self {
+        Self {
+            keypair,
+            remote_public: None,
+            verifier: None,
+        }
+    }
+
+    pub fn with_remote_public(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.remote_public = Some(key.into());
+        self
+    }
+
+    pub fn with_verifier<F>(mut self, verifier: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.verifier = Some(Arc::new(verifier));
+        self
+    }
+}
@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("UTP error: {0}")]
+    UTP(#[from] protofish::utp::error::UTPError),
+
+    #[error("Noise error: {0}")]
+    Noise(#[from] snow::Error),
+
+    #[error("Handshake error: {0}")]
+    Handshake(String),
+
+    #[error("Peer rejected")]
+    Rejected,
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+}
+
+impl From<Error> for protofish::utp::error::UTPError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => protofish::utp::error::UTPError::Io(e),
+            Error::UTP(e) => e,
+            other => protofish::utp::error::UTPError::Fatal(other.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
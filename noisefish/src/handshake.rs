@@ -0,0 +1,156 @@
+use protofish::utp::UTPStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::config::NoiseConfig;
+use crate::error::{Error, Result};
+
+pub(crate) const XX_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+pub(crate) const IK_PARAMS: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+
+/// Pattern byte leading the first handshake message.
+const XX: u8 = 0;
+const IK: u8 = 1;
+
+const PROLOGUE: &[u8] = b"protofish noise v1";
+
+/// Largest Noise message.
+const MAX_MESSAGE_LEN: usize = 65535;
+
+/// Secret transport keys of a handshake, initiator to responder first.
+pub(crate) type SplitKeys = ([u8; 32], [u8; 32]);
+
+/// Result of a completed handshake.
+pub(crate) struct Session {
+    /// All stream keys are derived from these.
+    pub split: SplitKeys,
+    pub peer_static: Vec<u8>,
+    pub initiator: bool,
+}
+
+fn prologue(pattern: u8) -> Vec<u8> {
+    // covers the pattern byte, which is sent before any keys are agreed
+    let mut prologue = PROLOGUE.to_vec();
+    prologue.push(pattern);
+    prologue
+}
+
+/// Runs the initiator side of the handshake on `stream`.
+///
+/// Uses IK if the server's key is configured, XX otherwise.
+pub(crate) async fn initiate<S: UTPStream>(stream: S, config: &NoiseConfig) -> Result<Session> {
+    let (pattern, params) = match config.remote_public {
+        Some(_) => (IK, IK_PARAMS),
+        None => (XX, XX_PARAMS),
+    };
+
+    let prologue = prologue(pattern);
+    let mut builder = snow::Builder::new(params.parse()?)
+        .local_private_key(config.keypair.private())
+        .prologue(&prologue);
+    if let Some(key) = &config.remote_public {
+        builder = builder.remote_public_key(key);
+    }
+
+    let (mut writer, mut reader) = stream.split();
+    run(
+        builder.build_initiator()?,
+        &mut writer,
+        &mut reader,
+        Some(pattern),
+        config,
+    )
+    .await
+}
+
+/// Runs the responder side of the handshake on `stream`, in whichever
+/// pattern the initiator chose.
+pub(crate) async fn respond<S: UTPStream>(stream: S, config: &NoiseConfig) -> Result<Session> {
+    let (mut writer, mut reader) = stream.split();
+
+    let first = read_message(&mut reader).await?;
+    let Some((&pattern, message)) = first.split_first() else {
+        return Err(Error::Handshake("empty handshake message".to_string()));
+    };
+
+    let params = match pattern {
+        XX => XX_PARAMS,
+        IK => IK_PARAMS,
+        other => return Err(Error::Handshake(format!("unknown pattern {}", other))),
+    };
+
+    let prologue = prologue(pattern);
+    let mut handshake = snow::Builder::new(params.parse()?)
+        .local_private_key(config.keypair.private())
+        .prologue(&prologue)
+        .build_responder()?;
+
+    let mut buf = vec![0; MAX_MESSAGE_LEN];
+    handshake.read_message(message, &mut buf)?;
+
+    run(handshake, &mut writer, &mut reader, None, config).await
+}
+
+async fn run<W, R>(
+    mut handshake: snow::HandshakeState,
+    writer: &mut W,
+    reader: &mut R,
+    mut pattern: Option<u8>,
+    config: &NoiseConfig,
+) -> Result<Session>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let mut buf = vec![0; MAX_MESSAGE_LEN];
+
+    while !handshake.is_handshake_finished() {
+        if handshake.is_my_turn() {
+            let len = handshake.write_message(&[], &mut buf)?;
+
+            let mut message = Vec::with_capacity(len + 1);
+            message.extend(pattern.take());
+            message.extend_from_slice(&buf[..len]);
+            write_message(writer, &message).await?;
+        } else {
+            let message = read_message(reader).await?;
+            handshake.read_message(&message, &mut buf)?;
+        }
+    }
+
+    let peer_static = handshake
+        .get_remote_static()
+        .ok_or_else(|| Error::Handshake("peer sent no static key".to_string()))?
+        .to_vec();
+
+    if let Some(verifier) = &config.verifier
+        && !verifier(&peer_static)
+    {
+        return Err(Error::Rejected);
+    }
+
+    Ok(Session {
+        split: handshake.dangerously_get_raw_split(),
+        peer_static,
+        initiator: handshake.is_initiator(),
+    })
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &[u8]) -> Result<()> {
+    let len = u16::try_from(message.len())
+        .map_err(|_| Error::Handshake("handshake message too large".to_string()))?;
+
+    writer.write_u16_le(len).await?;
+    writer.write_all(message).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = reader.read_u16_le().await? as usize;
+
+    let mut message = vec![0; len];
+    reader.read_exact(&mut message).await?;
+
+    Ok(message)
+}
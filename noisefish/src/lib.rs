@@ -0,0 +1,14 @@
+//! Noise protocol encryption for transports that have none of their own,
+//! such as `tcpfish` without TLS, `unixfish` or `stdiofish`.
+
+mod cipher;
+pub mod config;
+pub mod error;
+mod handshake;
+pub mod stream;
+pub mod utp;
+
+pub use config::{Keypair, NoiseConfig, PeerVerifier};
+pub use error::{Error, Result};
+pub use stream::{NoiseStream, NoiseStreamRead, NoiseStreamWrite};
+pub use utp::NoiseUTP;
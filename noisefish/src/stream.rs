@@ -0,0 +1,324 @@
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use chacha20poly1305::Key;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTPStream, read_framed_datagram, write_framed_datagram};
+use protofish::{IntegrityType, StreamId};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::cipher::{NONCE_LEN, Opener, Sealer, TAG_LEN, stream_keys};
+use crate::handshake::Session;
+
+/// Largest plaintext sealed into one record of a reliable stream.
+const MAX_RECORD_LEN: usize = 16 * 1024;
+/// Length of the `[len u32 le]` header of a record.
+const RECORD_HEADER_LEN: usize = 4;
+/// Largest message read from an unreliable stream.
+const MAX_DATAGRAM_LEN: usize = 64 * 1024 + NONCE_LEN + TAG_LEN;
+
+/// An encrypted stream over a stream of the wrapped transport.
+///
+/// Reliable streams are cut into sealed records with implicit nonces.
+/// Unreliable messages carry their nonce, so they can be lost or reordered;
+/// forged and replayed messages are dropped.
+pub struct NoiseStream<S: UTPStream> {
+    inner: S,
+    send_key: Key,
+    recv_key: Key,
+}
+
+/// Write half of a `NoiseStream`.
+///
+/// A write that returned `Poll::Pending` must be retried with the same
+/// data, as `write_all` does, since it was already sealed.
+pub struct NoiseStreamWrite<S: UTPStream> {
+    inner: S::StreamWrite,
+    sealer: Sealer,
+    reliable: bool,
+    pending: Option<PendingWrite>,
+}
+
+/// Sealed data not fully written to the wrapped stream yet.
+struct PendingWrite {
+    data: Vec<u8>,
+    written: usize,
+    /// Plaintext length to report once written.
+    len: usize,
+}
+
+/// Read half of a `NoiseStream`.
+pub struct NoiseStreamRead<S: UTPStream> {
+    inner: S::StreamRead,
+    opener: Opener,
+    reliable: bool,
+    /// Received bytes not forming a whole record yet.
+    sealed: BytesMut,
+    /// Decrypted data not read yet.
+    plain: Bytes,
+}
+
+impl<S: UTPStream> NoiseStream<S> {
+    pub(crate) fn new(inner: S, session: &Session) -> Self {
+        let (send_key, recv_key) = stream_keys(&session.split, session.initiator, inner.id());
+
+        Self {
+            inner,
+            send_key,
+            recv_key,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: UTPStream> UTPStream for NoiseStream<S> {
+    type StreamRead = NoiseStreamRead<S>;
+    type StreamWrite = NoiseStreamWrite<S>;
+
+    fn id(&self) -> StreamId {
+        self.inner.id()
+    }
+
+    fn integrity_type(&self) -> IntegrityType {
+        self.inner.integrity_type()
+    }
+
+    fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
+        let reliable = matches!(self.inner.integrity_type(), IntegrityType::Reliable);
+        let (writer, reader) = self.inner.split();
+
+        let writer = NoiseStreamWrite {
+            inner: writer,
+            sealer: Sealer::new(&self.send_key),
+            reliable,
+            pending: None,
+        };
+        let reader = NoiseStreamRead {
+            inner: reader,
+            opener: Opener::new(&self.recv_key),
+            reliable,
+            sealed: BytesMut::new(),
+            plain: Bytes::new(),
+        };
+
+        (writer, reader)
+    }
+
+    /// Seals each message of an unreliable stream on its own and sends it
+    /// as one message of the wrapped stream. Reliable streams use the
+    /// length-prefixed fallback.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        if writer.reliable {
+            return write_framed_datagram(writer, data).await;
+        }
+
+        poll_fn(|cx| writer.poll_drain(cx)).await?;
+
+        let message = writer.sealer.seal_datagram(&data)?;
+        S::send_datagram(&mut writer.inner, message.into()).await
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        if reader.reliable {
+            return read_framed_datagram(reader).await;
+        }
+
+        // left over from a partial `AsyncRead`
+        if !reader.plain.is_empty() {
+            return Ok(std::mem::take(&mut reader.plain));
+        }
+
+        loop {
+            let message = S::recv_datagram(&mut reader.inner).await?;
+
+            match reader.opener.open_datagram(&message) {
+                Ok(data) => return Ok(data.into()),
+                Err(e) => tracing::debug!("dropped datagram on stream: {}", e),
+            }
+        }
+    }
+}
+
+impl<S: UTPStream> NoiseStreamWrite<S> {
+    /// Writes out the pending sealed data.
+    ///
+    /// # Returns
+    ///
+    /// Returns the plaintext length of the pending data, or 0 if there was
+    /// none.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let Some(pending) = self.pending.as_mut() else {
+            return Poll::Ready(Ok(0));
+        };
+
+        while pending.written < pending.data.len() {
+            let written =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &pending.data[pending.written..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            // a message is written whole or not at all
+            pending.written = if self.reliable {
+                pending.written + written
+            } else {
+                pending.data.len()
+            };
+        }
+
+        let len = pending.len;
+        self.pending = None;
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<S: UTPStream> AsyncWrite for NoiseStreamWrite<S> {
+    /// Seals up to one record of `buf` on reliable streams, or all of `buf`
+    /// as one message on unreliable streams, and writes it out before
+    /// returning.
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        if this.pending.is_none() {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let (len, data) = if this.reliable {
+                let len = buf.len().min(MAX_RECORD_LEN);
+                let sealed = this.sealer.seal(&buf[..len])?;
+
+                let mut record = Vec::with_capacity(RECORD_HEADER_LEN + sealed.len());
+                record.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+                record.extend_from_slice(&sealed);
+                (len, record)
+            } else {
+                (buf.len(), this.sealer.seal_datagram(buf)?)
+            };
+
+            this.pending = Some(PendingWrite {
+                data,
+                written: 0,
+                len,
+            });
+        }
+
+        this.poll_drain(cx)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_drain(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_drain(cx))?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: UTPStream> NoiseStreamRead<S> {
+    /// Splits off and decrypts the next whole record, if there is one.
+    fn next_record(&mut self) -> io::Result<Option<Bytes>> {
+        if self.sealed.len() < RECORD_HEADER_LEN {
+            return Ok(None);
+        }
+
+        let len = u32::from_le_bytes(self.sealed[..RECORD_HEADER_LEN].try_into().unwrap()) as usize;
+        if len > MAX_RECORD_LEN + TAG_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record too large",
+            ));
+        }
+        if self.sealed.len() < RECORD_HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        self.sealed.advance(RECORD_HEADER_LEN);
+        let record = self.sealed.split_to(len);
+
+        Ok(Some(self.opener.open(&record)?.into()))
+    }
+
+    fn poll_read_record(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(plain) = self.next_record()? {
+                self.plain = plain;
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0; 8192];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut chunk))?;
+
+            if chunk.filled().is_empty() {
+                if self.sealed.is_empty() {
+                    // end of stream
+                    return Poll::Ready(Ok(()));
+                }
+
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "stream ended mid-record",
+                )));
+            }
+
+            self.sealed.extend_from_slice(chunk.filled());
+        }
+    }
+
+    fn poll_read_message(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            // each read of the wrapped stream returns one message
+            self.sealed.resize(MAX_DATAGRAM_LEN, 0);
+            let mut message = ReadBuf::new(&mut self.sealed);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut message))?;
+
+            let len = message.filled().len();
+            if len == 0 {
+                // end of stream
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.opener.open_datagram(&self.sealed[..len]) {
+                Ok(plain) => {
+                    self.plain = plain.into();
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) => tracing::debug!("dropped datagram on stream: {}", e),
+            }
+        }
+    }
+}
+
+impl<S: UTPStream> AsyncRead for NoiseStreamRead<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        if this.plain.is_empty() {
+            if this.reliable {
+                ready!(this.poll_read_record(cx))?;
+            } else {
+                ready!(this.poll_read_message(cx))?;
+            }
+        }
+
+        let len = this.plain.len().min(buf.remaining());
+        buf.put_slice(&this.plain.split_to(len));
+
+        Poll::Ready(Ok(()))
+    }
+}
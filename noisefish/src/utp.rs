@@ -0,0 +1,147 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTP, UTPEvent, UTPStats, UTPStream};
+use protofish::{IntegrityType, StreamId};
+
+use crate::config::NoiseConfig;
+use crate::error::{Error, Result};
+use crate::handshake::{self, Session};
+use crate::stream::NoiseStream;
+
+/// Encrypts and authenticates another UTP with the Noise protocol.
+///
+/// The client opens one stream of the wrapped transport for a Noise
+/// handshake, which authenticates both sides by their static keys. Every
+/// other stream, including the Primary Messaging Channel, is then encrypted
+/// with keys derived from the handshake, so opening a stream costs no extra
+/// round trips.
+pub struct NoiseUTP<U: UTP> {
+    inner: U,
+    session: Session,
+    /// Stream of the wrapped transport used for the handshake, whose events
+    /// are not passed on.
+    handshake_id: StreamId,
+}
+
+impl<U: UTP> NoiseUTP<U> {
+    /// Connects `inner` and runs the handshake as the client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection or the handshake fails, or the
+    /// verifier rejects the server.
+    pub async fn client(inner: U, config: &NoiseConfig) -> Result<Self> {
+        inner.connect().await?;
+
+        let stream = inner.new_stream(IntegrityType::Reliable).await?;
+        let id = stream.id();
+        let session = handshake::initiate(stream, config).await;
+
+        Self::finish(inner, session, id).await
+    }
+
+    /// Waits for the client's handshake on `inner` and answers it as the
+    /// server.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection closes before the handshake, the
+    /// handshake fails, or the verifier rejects the client.
+    pub async fn server(inner: U, config: &NoiseConfig) -> Result<Self> {
+        if config.remote_public.is_some() {
+            return Err(Error::Config(
+                "Remote public key provided for server".to_string(),
+            ));
+        }
+
+        let UTPEvent::NewStream(id) = inner.next_event().await else {
+            return Err(Error::Handshake(
+                "connection closed before the handshake".to_string(),
+            ));
+        };
+
+        let stream = inner.wait_stream(id, IntegrityType::Reliable).await?;
+        let session = handshake::respond(stream, config).await;
+
+        Self::finish(inner, session, id).await
+    }
+
+    async fn finish(inner: U, session: Result<Session>, handshake_id: StreamId) -> Result<Self> {
+        match session {
+            Ok(session) => Ok(Self {
+                inner,
+                session,
+                handshake_id,
+            }),
+            Err(e) => {
+                let _ = inner.close(0, "noise handshake failed").await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the peer's static public key, as proven in the handshake.
+    pub fn peer_static(&self) -> &[u8] {
+        &self.session.peer_static
+    }
+
+    /// Returns the wrapped transport.
+    pub fn inner(&self) -> &U {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<U: UTP> UTP for NoiseUTP<U> {
+    type Stream = NoiseStream<U::Stream>;
+
+    /// The wrapped transport is already connected by `client`.
+    async fn connect(&self) -> std::result::Result<(), UTPError> {
+        Ok(())
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        loop {
+            match self.inner.next_event().await {
+                UTPEvent::StreamClosed(id) | UTPEvent::StreamReset { id, .. }
+                    if id == self.handshake_id => {}
+                event => return event,
+            }
+        }
+    }
+
+    async fn new_stream(
+        &self,
+        integrity: IntegrityType,
+    ) -> std::result::Result<Self::Stream, UTPError> {
+        let stream = self.inner.new_stream(integrity).await?;
+        Ok(NoiseStream::new(stream, &self.session))
+    }
+
+    async fn wait_stream(
+        &self,
+        id: StreamId,
+        integrity: IntegrityType,
+    ) -> std::result::Result<Self::Stream, UTPError> {
+        let stream = self.inner.wait_stream(id, integrity).await?;
+        Ok(NoiseStream::new(stream, &self.session))
+    }
+
+    async fn close(&self, code: u64, reason: &str) -> std::result::Result<(), UTPError> {
+        self.inner.close(code, reason).await
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn stats(&self) -> UTPStats {
+        self.inner.stats()
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+use bytes::Bytes;
+use noisefish::{Error, Keypair, NoiseConfig, NoiseStream, NoiseUTP};
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPStream};
+use tcpfish::{MuxConfig, MuxStream, MuxUTP};
+
+fn mux_pair() -> (MuxUTP, MuxUTP) {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+
+    (
+        MuxUTP::new(client, false, MuxConfig::default()),
+        MuxUTP::new(server, true, MuxConfig::default()),
+    )
+}
+
+async fn noise_pair(
+    client_config: NoiseConfig,
+    server_config: NoiseConfig,
+) -> (
+    noisefish::Result<NoiseUTP<MuxUTP>>,
+    noisefish::Result<NoiseUTP<MuxUTP>>,
+) {
+    let (client, server) = mux_pair();
+
+    let server = tokio::spawn(async move { NoiseUTP::server(server, &server_config).await });
+    let client = NoiseUTP::client(client, &client_config).await;
+    let server = timeout(Duration::from_secs(2), server)
+        .await
+        .expect("Server timeout")
+        .unwrap();
+
+    (client, server)
+}
+
+#[tokio::test]
+async fn test_protofish_over_noise() {
+    let client_keys = Keypair::generate().unwrap();
+    let server_keys = Keypair::generate().unwrap();
+
+    let (client, server) = noise_pair(
+        NoiseConfig::new(client_keys.clone()),
+        NoiseConfig::new(server_keys.clone()),
+    )
+    .await;
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    assert_eq!(client.peer_static(), server_keys.public());
+    assert_eq!(server.peer_static(), client_keys.public());
+
+    let server_handle = tokio::spawn(protofish::accept(Arc::new(server)));
+    let client = protofish::connect(Arc::new(client)).await.unwrap();
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    // spans several records
+    let data: Vec<u8> = (0..100 * 1024).map(|i| (i % 251) as u8).collect();
+    stream.write_all(&data).await.unwrap();
+
+    let mut received = vec![0; data.len()];
+    timeout(Duration::from_secs(5), peer.read_exact(&mut received))
+        .await
+        .expect("Transfer timeout")
+        .unwrap();
+    assert_eq!(received, data);
+}
+
+#[tokio::test]
+async fn test_known_server_key() {
+    let server_keys = Keypair::generate().unwrap();
+
+    let (client, server) = noise_pair(
+        NoiseConfig::new(Keypair::generate().unwrap()).with_remote_public(server_keys.public()),
+        NoiseConfig::new(server_keys.clone()),
+    )
+    .await;
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+    let id = stream.id();
+    let (mut writer, _reader) = stream.split();
+
+    let peer = server
+        .wait_stream(id, IntegrityType::Unreliable)
+        .await
+        .unwrap();
+    let (_writer, mut reader) = peer.split();
+
+    NoiseStream::<MuxStream>::send_datagram(&mut writer, Bytes::from_static(b"ping"))
+        .await
+        .unwrap();
+    let data = timeout(
+        Duration::from_secs(2),
+        NoiseStream::<MuxStream>::recv_datagram(&mut reader),
+    )
+    .await
+    .expect("Datagram timeout")
+    .unwrap();
+    assert_eq!(&data[..], b"ping");
+}
+
+#[tokio::test]
+async fn test_wrong_server_key_fails() {
+    let wrong = Keypair::generate().unwrap();
+
+    let (client, server) = noise_pair(
+        NoiseConfig::new(Keypair::generate().unwrap()).with_remote_public(wrong.public()),
+        NoiseConfig::new(Keypair::generate().unwrap()),
+    )
+    .await;
+
+    assert!(server.is_err());
+    assert!(client.is_err());
+}
+
+#[tokio::test]
+async fn test_verifier_rejects_client() {
+    let trusted = Keypair::generate().unwrap();
+
+    let (_client, server) = noise_pair(
+        NoiseConfig::new(Keypair::generate().unwrap()),
+        NoiseConfig::new(Keypair::generate().unwrap())
+            .with_verifier(move |key| key == trusted.public()),
+    )
+    .await;
+
+    assert!(matches!(server, Err(Error::Rejected)));
+}
+
+#[tokio::test]
+async fn test_server_rejects_remote_public() {
+    let (_client, server) = mux_pair();
+    let config = NoiseConfig::new(Keypair::generate().unwrap())
+        .with_remote_public(Keypair::generate().unwrap().public());
+
+    let result = NoiseUTP::server(server, &config).await;
+    assert!(matches!(result, Err(Error::Config(_))));
+}
@@ -0,0 +1,205 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// Where to connect to or listen on, given as a URL-like string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// `quic://host:port`
+    Quic { host: String, port: u16 },
+    /// `tcp://host:port`, or `tls://host:port` with `tls` set
+    Tcp { host: String, port: u16, tls: bool },
+    /// `ws://host:port/path` or `wss://host:port/path`
+    Ws {
+        host: String,
+        port: u16,
+        path: String,
+        secure: bool,
+    },
+    /// `unix:/path/to/socket`
+    Unix(PathBuf),
+    /// `shm:/path/to/socket`
+    Shm(PathBuf),
+    /// `exec:command`, a child process run with `sh -c` and spoken to over
+    /// its stdin and stdout
+    Exec(String),
+    /// `stdio:`, this process's stdin and stdout
+    Stdio,
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("missing scheme in {:?}", s))?;
+
+        match scheme {
+            "quic" | "tcp" | "tls" | "ws" | "wss" => {
+                let rest = rest
+                    .strip_prefix("//")
+                    .ok_or_else(|| format!("expected {}://host:port", scheme))?;
+                let (authority, path) = match rest.find('/') {
+                    Some(i) => rest.split_at(i),
+                    None => (rest, ""),
+                };
+                let (host, port) = parse_authority(authority)?;
+
+                match scheme {
+                    "quic" => Ok(Address::Quic { host, port }),
+                    "tcp" | "tls" => Ok(Address::Tcp {
+                        host,
+                        port,
+                        tls: scheme == "tls",
+                    }),
+                    _ => Ok(Address::Ws {
+                        host,
+                        port,
+                        path: if path.is_empty() { "/" } else { path }.to_string(),
+                        secure: scheme == "wss",
+                    }),
+                }
+            }
+            "unix" | "shm" => {
+                if rest.is_empty() {
+                    return Err(format!("expected {}:/path", scheme));
+                }
+                let path = PathBuf::from(rest.strip_prefix("//").unwrap_or(rest));
+
+                Ok(if scheme == "unix" {
+                    Address::Unix(path)
+                } else {
+                    Address::Shm(path)
+                })
+            }
+            "exec" if !rest.is_empty() => Ok(Address::Exec(rest.to_string())),
+            "exec" => Err("expected exec:command".to_string()),
+            "stdio" if rest.is_empty() => Ok(Address::Stdio),
+            "stdio" => Err("stdio: takes no argument".to_string()),
+            _ => Err(format!("unknown scheme {:?}", scheme)),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Quic { host, port } => write!(f, "quic://{}", join(host, *port)),
+            Address::Tcp { host, port, tls } => {
+                let scheme = if *tls { "tls" } else { "tcp" };
+                write!(f, "{}://{}", scheme, join(host, *port))
+            }
+            Address::Ws {
+                host,
+                port,
+                path,
+                secure,
+            } => {
+                let scheme = if *secure { "wss" } else { "ws" };
+                write!(f, "{}://{}{}", scheme, join(host, *port), path)
+            }
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+            Address::Shm(path) => write!(f, "shm:{}", path.display()),
+            Address::Exec(command) => write!(f, "exec:{}", command),
+            Address::Stdio => write!(f, "stdio:"),
+        }
+    }
+}
+
+/// Splits `host:port`, accepting bracketed IPv6 hosts.
+fn parse_authority(authority: &str) -> Result<(String, u16), String> {
+    let (host, port) = authority
+        .rsplit_once(':')
+        .ok_or_else(|| format!("missing port in {:?}", authority))?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+
+    if host.is_empty() {
+        return Err(format!("missing host in {:?}", authority));
+    }
+
+    let port = port
+        .parse()
+        .map_err(|_| format!("invalid port {:?}", port))?;
+
+    Ok((host.to_string(), port))
+}
+
+/// Joins a host and port, bracketing IPv6 hosts.
+pub fn join(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Address;
+
+    #[test]
+    fn test_parse_network_addresses() {
+        assert_eq!(
+            "quic://localhost:4433".parse::<Address>().unwrap(),
+            Address::Quic {
+                host: "localhost".into(),
+                port: 4433
+            }
+        );
+        assert_eq!(
+            "tls://[::1]:443".parse::<Address>().unwrap(),
+            Address::Tcp {
+                host: "::1".into(),
+                port: 443,
+                tls: true
+            }
+        );
+        assert_eq!(
+            "ws://127.0.0.1:8080".parse::<Address>().unwrap(),
+            Address::Ws {
+                host: "127.0.0.1".into(),
+                port: 8080,
+                path: "/".into(),
+                secure: false
+            }
+        );
+        assert_eq!(
+            "wss://example.com:443/fish"
+                .parse::<Address>()
+                .unwrap()
+                .to_string(),
+            "wss://example.com:443/fish"
+        );
+    }
+
+    #[test]
+    fn test_parse_local_addresses() {
+        assert_eq!(
+            "unix:/tmp/fish.sock".parse::<Address>().unwrap(),
+            Address::Unix(PathBuf::from("/tmp/fish.sock"))
+        );
+        assert_eq!(
+            "shm:///tmp/fish.sock".parse::<Address>().unwrap(),
+            Address::Shm(PathBuf::from("/tmp/fish.sock"))
+        );
+        assert_eq!(
+            "exec:protofish serve stdio:".parse::<Address>().unwrap(),
+            Address::Exec("protofish serve stdio:".into())
+        );
+        assert_eq!("stdio:".parse::<Address>().unwrap(), Address::Stdio);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("localhost:4433".parse::<Address>().is_err());
+        assert!("quic://localhost".parse::<Address>().is_err());
+        assert!("quic://:4433".parse::<Address>().is_err());
+        assert!("tcp://localhost:http".parse::<Address>().is_err());
+        assert!("unix:".parse::<Address>().is_err());
+        assert!("gopher://localhost:70".parse::<Address>().is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use clap::Args;
+use protofish::{IntegrityType, UTP};
+
+use crate::{address::Address, tls::TlsArgs};
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Server to benchmark, which must run `protofish serve`
+    pub address: Address,
+
+    /// Bytes to send, with an optional K, M or G suffix
+    #[arg(long, default_value = "64M", value_parser = parse_size)]
+    pub bytes: u64,
+
+    /// Send over an unreliable stream instead
+    #[arg(long)]
+    pub unreliable: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Runs one benchmark over a new context and prints the throughput.
+pub async fn run<U: UTP>(utp: Arc<U>, args: &BenchArgs) -> anyhow::Result<()> {
+    let conn = protofish::connect(utp).await?;
+    let integrity = if args.unreliable {
+        IntegrityType::Unreliable
+    } else {
+        IntegrityType::Reliable
+    };
+
+    let arb = conn.new_arb();
+    let report = arb.run_benchmark(integrity.clone(), args.bytes).await?;
+    arb.finish();
+
+    println!(
+        "{:?}: sent {} bytes in {:?}, {:.2} MiB/s",
+        integrity,
+        report.bytes,
+        report.elapsed,
+        report.bytes_per_second() / (1024.0 * 1024.0)
+    );
+
+    if let Err(e) = conn.close(0, "done").await {
+        tracing::debug!("failed to close connection: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Parses a byte count such as `1500`, `64K` or `1G`, in powers of 1024.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(b'K' | b'k') => (&s[..s.len() - 1], 10),
+        Some(b'M' | b'm') => (&s[..s.len() - 1], 20),
+        Some(b'G' | b'g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size {:?}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1500"), Ok(1500));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("99999999999G").is_err());
+    }
+}
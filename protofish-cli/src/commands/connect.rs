@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use clap::Args;
+use protofish::UTP;
+use tokio::time::Instant;
+
+use crate::{address::Address, tls::TlsArgs};
+
+#[derive(Args, Debug)]
+pub struct ConnectArgs {
+    /// Server to connect to, e.g. `quic://localhost:4433`
+    pub address: Address,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Performs the handshake and prints what is known about the server.
+pub async fn run<U: UTP>(utp: Arc<U>, args: &ConnectArgs) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let conn = protofish::connect(utp).await?;
+    let elapsed = started.elapsed();
+
+    println!("connected to {} in {:?}", args.address, elapsed);
+
+    if let Some(hello) = conn.server_hello() {
+        let version = &hello.version;
+        println!(
+            "protocol version: {}.{}.{}",
+            version.major, version.minor, version.patch
+        );
+        if let Some(token) = &hello.connection_token {
+            println!("connection token: {} bytes", token.len());
+        }
+        if let Some(message) = &hello.message {
+            println!("message: {}", message);
+        }
+    }
+
+    if let Some(addr) = conn.peer_addr() {
+        println!("peer address: {}", addr);
+    }
+    if let Some(addr) = conn.local_addr() {
+        println!("local address: {}", addr);
+    }
+    if let Some(rtt) = conn.stats().rtt {
+        println!("rtt: {:?}", rtt);
+    }
+
+    if let Err(e) = conn.close(0, "done").await {
+        tracing::debug!("failed to close connection: {}", e);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+use protofish::dissect::{FieldValue, Frame, FrameContent, dissect, payload_fields};
+use serde_json::{Map, Value, json};
+use tokio::io::AsyncReadExt;
+
+#[derive(Args, Debug)]
+pub struct DecodeArgs {
+    /// Byte dump of a PMC stream; stdin if omitted or `-`
+    pub file: Option<PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One line per frame
+    Text,
+    /// A JSON array with one object per frame
+    Json,
+}
+
+/// Prints the frames of a PMC byte dump. Corrupt and truncated frames are
+/// flagged in the output rather than failing the command.
+pub async fn run(args: &DecodeArgs) -> anyhow::Result<()> {
+    let dump = match &args.file {
+        Some(path) if path.as_os_str() != "-" => tokio::fs::read(path).await?,
+        _ => {
+            let mut dump = Vec::new();
+            tokio::io::stdin().read_to_end(&mut dump).await?;
+            dump
+        }
+    };
+
+    let frames = dissect(&dump);
+
+    match args.format {
+        Format::Text => {
+            for frame in &frames {
+                println!("{}", frame);
+            }
+        }
+        Format::Json => {
+            let frames: Vec<Value> = frames.iter().map(frame_json).collect();
+            println!("{}", serde_json::to_string_pretty(&frames)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn frame_json(frame: &Frame) -> Value {
+    let mut value = json!({
+        "index": frame.index,
+        "offset": frame.offset,
+        "length": frame.length,
+        "context_id": frame.context_id(),
+    });
+    let object = value.as_object_mut().expect("built as an object");
+
+    match &frame.content {
+        FrameContent::Message(message) => {
+            object.insert("status".into(), "ok".into());
+            object.insert("variant".into(), message.payload.name().into());
+            if let Some(budget) = message.budget {
+                object.insert("budget_millis".into(), (budget.as_millis() as u64).into());
+            }
+
+            let fields: Map<String, Value> = payload_fields(&message.payload)
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), field_json(value)))
+                .collect();
+            object.insert("fields".into(), fields.into());
+        }
+        FrameContent::Corrupt { reason, .. } => {
+            object.insert("status".into(), "corrupt".into());
+            object.insert("reason".into(), reason.clone().into());
+        }
+        FrameContent::Truncated { available } => {
+            object.insert("status".into(), "truncated".into());
+            object.insert("available".into(), (*available).into());
+        }
+    }
+
+    value
+}
+
+/// Converts a field to JSON, with bytes as a hex string.
+fn field_json(value: FieldValue) -> Value {
+    match value {
+        FieldValue::Uint(value) => value.into(),
+        FieldValue::Bool(value) => value.into(),
+        FieldValue::Text(value) => value.into(),
+        FieldValue::Bytes(value) => value
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+            .into(),
+        FieldValue::None => Value::Null,
+    }
+}
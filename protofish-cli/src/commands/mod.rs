@@ -0,0 +1,6 @@
+pub mod bench;
+pub mod connect;
+pub mod decode;
+pub mod pipe;
+pub mod send;
+pub mod serve;
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use clap::Args;
+use protofish::{IntegrityType, ProtofishStream, UTP, utp::UTPStream};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    address::Address,
+    tls::TlsArgs,
+    transport::{self, Listener, with_link},
+};
+
+#[derive(Args, Debug)]
+pub struct PipeArgs {
+    /// Server to open the stream to, or the address to listen on with
+    /// `--listen`
+    pub address: Address,
+
+    /// Wait for a peer to open a stream instead
+    #[arg(long)]
+    pub listen: bool,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Bridges a reliable stream to stdin and stdout, like `nc`.
+pub async fn run(args: &PipeArgs) -> anyhow::Result<()> {
+    if args.listen {
+        let mut listener = Listener::bind(&args.address, &args.tls).await?;
+        if let Some(addr) = listener.local_addr() {
+            tracing::info!("listening on {}", addr);
+        }
+
+        let link = listener.accept().await.context("listener closed")?;
+        with_link!(link, utp => listen(utp).await)
+    } else {
+        let link = transport::dial(&args.address, &args.tls).await?;
+        with_link!(link, utp => open(utp).await)
+    }
+}
+
+async fn open<U: UTP>(utp: Arc<U>) -> anyhow::Result<()> {
+    let conn = protofish::connect(utp).await?;
+    let arb = conn.new_arb();
+    let stream = arb.new_stream(IntegrityType::Reliable).await?;
+
+    bridge(stream).await?;
+    arb.finish();
+
+    Ok(())
+}
+
+async fn listen<U: UTP>(utp: Arc<U>) -> anyhow::Result<()> {
+    let conn = protofish::accept(utp).await?;
+    let arb = conn.next_arb().await.context("connection closed")?;
+    let stream = arb.wait_stream().await?;
+
+    bridge(stream).await
+}
+
+/// Copies stdin to the stream and the stream to stdout, until the peer
+/// finishes sending.
+async fn bridge<S: UTPStream>(stream: ProtofishStream<S>) -> anyhow::Result<()> {
+    let (mut write, mut read) = stream.split();
+
+    let upload = tokio::spawn(async move {
+        tokio::io::copy(&mut tokio::io::stdin(), &mut write).await?;
+        write.shutdown().await
+    });
+
+    let mut stdout = tokio::io::stdout();
+    let download = tokio::io::copy(&mut read, &mut stdout).await;
+    stdout.flush().await?;
+
+    // stdin may still be open, e.g. on a terminal
+    upload.abort();
+
+    download?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use bytes::BytesMut;
+use clap::Args;
+use protofish::UTP;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{address::Address, tls::TlsArgs};
+
+/// Largest message read from stdin at once.
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Args, Debug)]
+pub struct SendArgs {
+    /// Server to send to
+    pub address: Address,
+
+    /// Exit after receiving this many messages
+    #[arg(long)]
+    pub replies: Option<u64>,
+
+    /// Once stdin is exhausted, how long to wait for more messages when
+    /// `--replies` is not given, e.g. `500ms` or `2s`
+    #[arg(long, default_value = "500ms", value_parser = parse_duration)]
+    pub linger: Duration,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+/// Opens a context, sends stdin over it as `ArbitaryData` messages and
+/// writes the messages received on it to stdout.
+pub async fn run<U: UTP>(utp: Arc<U>, args: &SendArgs) -> anyhow::Result<()> {
+    let conn = protofish::connect(utp).await?;
+    let arb = conn.new_arb();
+
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    let mut input_done = false;
+    let mut received = 0u64;
+
+    loop {
+        if args.replies.is_some_and(|replies| received >= replies) {
+            break;
+        }
+
+        let mut buf = BytesMut::with_capacity(CHUNK);
+
+        tokio::select! {
+            read = stdin.read_buf(&mut buf), if !input_done => {
+                if read? == 0 {
+                    input_done = true;
+                } else {
+                    arb.write(buf.freeze()).await?;
+                }
+            }
+            message = arb.read() => {
+                stdout.write_all(&message?).await?;
+                stdout.flush().await?;
+                received += 1;
+            }
+            _ = tokio::time::sleep(args.linger), if input_done && args.replies.is_none() => break,
+        }
+    }
+
+    arb.finish();
+
+    if let Err(e) = conn.close(0, "done").await {
+        tracing::debug!("failed to close connection: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Parses a duration such as `250ms`, `2s` or a plain number of seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {:?}", s);
+
+    if let Some(millis) = s.strip_suffix("ms") {
+        millis
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| invalid())
+    } else {
+        let secs = s.strip_suffix('s').unwrap_or(s);
+        secs.parse::<f64>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .ok_or_else(invalid)
+    }
+}
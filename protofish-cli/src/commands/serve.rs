@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use clap::{Args, ValueEnum};
+use protofish::{ArbContext, ArbError, IntegrityType, ProtofishStream, UTP};
+use tokio::{
+    io::AsyncWriteExt,
+    signal::unix::{SignalKind, signal},
+};
+
+use crate::{
+    address::Address,
+    tls::TlsArgs,
+    transport::{Listener, with_link},
+};
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on, e.g. `quic://0.0.0.0:4433` or `unix:/tmp/fish.sock`
+    pub address: Address,
+
+    /// What to do with received data and streams
+    #[arg(long, value_enum, default_value_t = Mode::Echo)]
+    pub mode: Mode,
+
+    #[command(flatten)]
+    pub tls: TlsArgs,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Send everything back on the same context or stream
+    Echo,
+    /// Discard everything
+    Sink,
+}
+
+/// Accepts connections until interrupted, serving data, streams and
+/// benchmarks on every context.
+pub async fn run(args: &ServeArgs) -> anyhow::Result<()> {
+    let mut listener = Listener::bind(&args.address, &args.tls).await?;
+
+    match listener.local_addr() {
+        Some(addr) => tracing::info!("listening on {} ({})", args.address, addr),
+        None => tracing::info!("listening on {}", args.address),
+    }
+
+    let mode = args.mode;
+    let mut connections = tokio::task::JoinSet::new();
+
+    loop {
+        tokio::select! {
+            link = listener.accept() => {
+                let Some(link) = link else { break };
+                connections.spawn(async move {
+                    with_link!(link, utp => serve_connection(utp, mode).await)
+                });
+            }
+            _ = shutdown_signal() => break,
+        }
+    }
+
+    // `stdio:` stops accepting after its only connection, which is served
+    // until it ends
+    if matches!(listener, Listener::Stdio(_)) {
+        while connections.join_next().await.is_some() {}
+    }
+
+    Ok(())
+}
+
+/// Completes on Ctrl-C or SIGTERM, so that socket files of the listener are
+/// removed on the way out.
+async fn shutdown_signal() {
+    let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+async fn serve_connection<U: UTP>(utp: Arc<U>, mode: Mode) {
+    let conn = match protofish::accept(utp).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            tracing::warn!("handshake failed: {}", e);
+            return;
+        }
+    };
+
+    let peer = conn
+        .peer_addr()
+        .map_or_else(|| "peer".to_string(), |addr| addr.to_string());
+    tracing::info!("{} connected", peer);
+
+    while let Some(arb) = conn.next_arb().await {
+        tokio::spawn(serve_context(arb, mode));
+    }
+
+    tracing::info!("{} disconnected: {:?}", peer, conn.state());
+}
+
+/// Serves a context until the peer cancels it or the connection ends.
+async fn serve_context<U: UTP>(arb: ArbContext<U>, mode: Mode) {
+    loop {
+        let result = tokio::select! {
+            biased;
+            start = arb.recv_benchmark() => match start {
+                Ok(start) => match arb.serve_benchmark(start).await {
+                    Ok(report) => {
+                        tracing::info!(
+                            "benchmark: received {} bytes in {:?}",
+                            report.bytes,
+                            report.elapsed
+                        );
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                },
+                Err(ArbError::UnexpectedData(e)) => {
+                    tracing::debug!("ignoring control payload: {}", e);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+            stream = arb.wait_stream() => stream.map(|stream| {
+                tokio::spawn(serve_stream(stream, mode));
+            }),
+            data = arb.read() => match data {
+                Ok(data) if mode == Mode::Echo => arb.write(data).await,
+                Ok(_) => Ok(()),
+                Err(e) => Err(e),
+            },
+        };
+
+        if let Err(e) = result {
+            tracing::debug!("context ended: {}", e);
+            break;
+        }
+    }
+}
+
+async fn serve_stream<S: protofish::utp::UTPStream>(stream: ProtofishStream<S>, mode: Mode) {
+    let result = match (stream.integrity_type(), mode) {
+        (IntegrityType::Reliable, Mode::Echo) => {
+            let (mut write, mut read) = stream.split();
+            match tokio::io::copy(&mut read, &mut write).await {
+                Ok(_) => write.shutdown().await,
+                Err(e) => Err(e),
+            }
+        }
+        (IntegrityType::Reliable, Mode::Sink) => {
+            let (_write, mut read) = stream.split();
+            tokio::io::copy(&mut read, &mut tokio::io::sink())
+                .await
+                .map(|_| ())
+        }
+        (IntegrityType::Unreliable, mode) => {
+            let mut stream = stream;
+            loop {
+                let message = match stream.recv_datagram().await {
+                    Ok(message) => message,
+                    Err(e) => break Err(std::io::Error::other(e)),
+                };
+                if mode == Mode::Sink {
+                    continue;
+                }
+                if let Err(e) = stream.send_datagram(message).await {
+                    break Err(std::io::Error::other(e));
+                }
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::debug!("stream ended: {}", e);
+    }
+}
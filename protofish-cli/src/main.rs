@@ -0,0 +1,82 @@
+//! `protofish`, a command-line tool for poking at protofish endpoints over
+//! any of the bundled transports.
+//!
+//! Addresses select the transport: `quic://`, `tcp://`, `tls://`, `ws://`,
+//! `wss://`, `unix:`, `shm:`, `exec:` and `stdio:`.
+
+mod address;
+mod commands;
+mod tls;
+mod transport;
+
+use clap::{ArgAction, Parser, Subcommand};
+use tracing::Level;
+
+use crate::{
+    commands::{
+        bench::BenchArgs, connect::ConnectArgs, decode::DecodeArgs, pipe::PipeArgs, send::SendArgs,
+        serve::ServeArgs,
+    },
+    transport::with_link,
+};
+
+#[derive(Parser, Debug)]
+#[command(name = "protofish", version, about)]
+struct Cli {
+    /// Log more to stderr, repeat for more detail
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Perform the handshake and print server info
+    Connect(ConnectArgs),
+    /// Open a context and exchange `ArbitaryData` messages over stdin and stdout
+    Send(SendArgs),
+    /// Run an echo or sink server
+    Serve(ServeArgs),
+    /// Measure throughput against `protofish serve`
+    Bench(BenchArgs),
+    /// Bridge a reliable stream to stdin and stdout
+    Pipe(PipeArgs),
+    /// Decode a byte dump of a Primary Messaging Channel
+    Decode(DecodeArgs),
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let level = match cli.verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    match &cli.command {
+        Command::Connect(args) => {
+            let link = transport::dial(&args.address, &args.tls).await?;
+            with_link!(link, utp => commands::connect::run(utp, args).await)
+        }
+        Command::Send(args) => {
+            let link = transport::dial(&args.address, &args.tls).await?;
+            with_link!(link, utp => commands::send::run(utp, args).await)
+        }
+        Command::Bench(args) => {
+            let link = transport::dial(&args.address, &args.tls).await?;
+            with_link!(link, utp => commands::bench::run(utp, args).await)
+        }
+        Command::Serve(args) => commands::serve::run(args).await,
+        Command::Pipe(args) => commands::pipe::run(args).await,
+        Command::Decode(args) => commands::decode::run(args).await,
+    }
+}
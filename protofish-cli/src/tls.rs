@@ -0,0 +1,160 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context, bail};
+use clap::Args;
+use rustls::{
+    DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+};
+
+/// Certificate options of the QUIC, TLS and secure WebSocket transports.
+#[derive(Args, Debug, Clone, Default)]
+pub struct TlsArgs {
+    /// PEM certificate chain served by `serve` (self-signed if omitted)
+    #[arg(long, requires = "key")]
+    pub cert: Option<PathBuf>,
+
+    /// PEM private key of `--cert`
+    #[arg(long, requires = "cert")]
+    pub key: Option<PathBuf>,
+
+    /// PEM certificates trusted by clients
+    #[arg(long, conflicts_with = "insecure")]
+    pub ca: Option<PathBuf>,
+
+    /// Accept any server certificate
+    #[arg(long)]
+    pub insecure: bool,
+
+    /// Name the server certificate is checked against (defaults to the host)
+    #[arg(long)]
+    pub server_name: Option<String>,
+}
+
+impl TlsArgs {
+    /// Builds the rustls settings of a client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if neither `--ca` nor `--insecure` is given, or the
+    /// certificates cannot be loaded.
+    pub fn client_config(&self) -> anyhow::Result<rustls::ClientConfig> {
+        let builder = rustls::ClientConfig::builder();
+
+        let config = match (&self.ca, self.insecure) {
+            (Some(ca), _) => {
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(ca)
+                    .with_context(|| format!("failed to read {}", ca.display()))?
+                {
+                    roots.add(cert?)?;
+                }
+                builder.with_root_certificates(roots)
+            }
+            (None, true) => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider()))),
+            (None, false) => {
+                bail!("no trust anchor for the server certificate, pass --ca or --insecure")
+            }
+        };
+
+        Ok(config.with_no_client_auth())
+    }
+
+    /// Builds the rustls settings of a server, generating a self-signed
+    /// certificate for `host` unless `--cert` and `--key` are given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the certificate or key cannot be loaded or
+    /// generated.
+    pub fn server_config(&self, host: &str) -> anyhow::Result<rustls::ServerConfig> {
+        let (chain, key) = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                let chain = CertificateDer::pem_file_iter(cert)
+                    .with_context(|| format!("failed to read {}", cert.display()))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                let key = PrivateKeyDer::from_pem_file(key)
+                    .with_context(|| format!("failed to read {}", key.display()))?;
+                (chain, key)
+            }
+            _ => {
+                tracing::warn!("no --cert given, serving a self-signed certificate");
+                let cert = rcgen::generate_simple_self_signed(vec![
+                    "localhost".to_string(),
+                    host.to_string(),
+                ])?;
+                let key = PrivateKeyDer::try_from(cert.key_pair.serialize_der())
+                    .map_err(anyhow::Error::msg)?;
+                (vec![cert.cert.der().clone()], key)
+            }
+        };
+
+        Ok(rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)?)
+    }
+
+    /// Returns the name to check the server certificate against.
+    pub fn server_name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(host)
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()))
+}
+
+/// Verifies signatures but not the certificate itself, for `--insecure`.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
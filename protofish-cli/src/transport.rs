@@ -0,0 +1,270 @@
+use std::{net::SocketAddr, process::Stdio, sync::Arc};
+
+use anyhow::{Context, bail};
+use quicfish::{QuicConfig, QuicEndpoint, QuicUTP};
+use shmfish::{ShmConfig, ShmEndpoint, ShmUTP};
+use stdiofish::StdioUTP;
+use tcpfish::{MuxConfig, MuxUTP, TcpConfig, TcpEndpoint, TlsAcceptor};
+use tokio::{
+    net::TcpListener,
+    process::{Child, Command},
+};
+use unixfish::{UnixConfig, UnixEndpoint, UnixUTP};
+use wsfish::{WsConfig, WsEndpoint};
+
+use crate::{address::Address, tls::TlsArgs};
+
+/// A transport connection of any of the bundled transports.
+///
+/// Use `with_link!` to run code generic over `protofish::UTP` on it.
+pub enum Link {
+    /// Clients keep their endpoint alongside the connection
+    Quic(QuicUTP, Option<QuicEndpoint>),
+    /// TCP, TLS and WebSocket connections, which all run the `tcpfish`
+    /// multiplexer
+    Mux(MuxUTP),
+    Unix(UnixUTP),
+    Shm(ShmUTP),
+    /// The child process is kept so it is killed with the connection
+    Stdio(StdioUTP, Option<Child>),
+}
+
+/// Binds `$utp` to an `Arc` of the transport of a `Link` and evaluates
+/// `$body` with it.
+macro_rules! with_link {
+    ($link:expr, $utp:ident => $body:expr) => {
+        match $link {
+            $crate::transport::Link::Quic(utp, _endpoint) => {
+                let $utp = ::std::sync::Arc::new(utp);
+                $body
+            }
+            $crate::transport::Link::Mux(utp) => {
+                let $utp = ::std::sync::Arc::new(utp);
+                $body
+            }
+            $crate::transport::Link::Unix(utp) => {
+                let $utp = ::std::sync::Arc::new(utp);
+                $body
+            }
+            $crate::transport::Link::Shm(utp) => {
+                let $utp = ::std::sync::Arc::new(utp);
+                $body
+            }
+            $crate::transport::Link::Stdio(utp, _child) => {
+                let $utp = ::std::sync::Arc::new(utp);
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use with_link;
+
+/// Opens a transport connection to `address`.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be connected to, or is only valid
+/// for listening.
+pub async fn dial(address: &Address, tls: &TlsArgs) -> anyhow::Result<Link> {
+    let link = match address {
+        Address::Quic { host, port } => {
+            let peer = resolve(host, *port).await?;
+            let bind: SocketAddr = if peer.is_ipv4() {
+                "0.0.0.0:0".parse()?
+            } else {
+                "[::]:0".parse()?
+            };
+            let config = QuicConfig::client_default().with_client_crypto(tls.client_config()?);
+            let endpoint = QuicEndpoint::client(bind, config)?;
+            let connection = endpoint.connect(peer, tls.server_name(host)).await?;
+
+            Link::Quic(QuicUTP::new(connection, false), Some(endpoint))
+        }
+        Address::Tcp {
+            host,
+            port,
+            tls: secure,
+        } => {
+            let peer = resolve(host, *port).await?;
+            let mut config = TcpConfig::default();
+            if *secure {
+                config = config.with_client_tls(tls.client_config()?);
+            }
+
+            Link::Mux(
+                TcpEndpoint::client(config)?
+                    .connect(peer, tls.server_name(host))
+                    .await?,
+            )
+        }
+        Address::Ws { secure, .. } => {
+            let mut config = WsConfig::default();
+            if *secure {
+                config = config.with_client_tls(tls.client_config()?);
+            }
+
+            Link::Mux(
+                WsEndpoint::client(config)
+                    .connect(&address.to_string())
+                    .await?,
+            )
+        }
+        Address::Unix(path) => Link::Unix(
+            UnixEndpoint::client(UnixConfig::default())?
+                .connect(path)
+                .await?,
+        ),
+        Address::Shm(path) => Link::Shm(
+            ShmEndpoint::client(ShmConfig::default())
+                .connect(path)
+                .await?,
+        ),
+        Address::Exec(command) => {
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .with_context(|| format!("failed to run {:?}", command))?;
+            let utp = StdioUTP::from_child(&mut child, MuxConfig::default())?;
+
+            Link::Stdio(utp, Some(child))
+        }
+        Address::Stdio => Link::Stdio(StdioUTP::stdio(MuxConfig::default()), None),
+    };
+
+    Ok(link)
+}
+
+/// A bound server endpoint of any of the bundled transports.
+pub enum Listener {
+    Quic(QuicEndpoint),
+    Tcp(TcpEndpoint),
+    Ws(WsEndpoint),
+    /// `wss://`, terminating TLS before the WebSocket upgrade
+    Wss {
+        listener: TcpListener,
+        tls: Arc<rustls::ServerConfig>,
+        ws: WsEndpoint,
+    },
+    Unix(UnixEndpoint),
+    Shm(ShmEndpoint),
+    /// `stdio:` serves a single connection
+    Stdio(Option<StdioUTP>),
+}
+
+impl Listener {
+    /// Binds a server endpoint on `address`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound, or is only valid for
+    /// connecting.
+    pub async fn bind(address: &Address, tls: &TlsArgs) -> anyhow::Result<Self> {
+        let listener = match address {
+            Address::Quic { host, port } => {
+                let config =
+                    QuicConfig::server_default().with_server_crypto(tls.server_config(host)?);
+                Listener::Quic(QuicEndpoint::server(resolve(host, *port).await?, config)?)
+            }
+            Address::Tcp {
+                host,
+                port,
+                tls: secure,
+            } => {
+                let mut config = TcpConfig::default();
+                if *secure {
+                    config = config.with_server_tls(tls.server_config(host)?);
+                }
+                Listener::Tcp(TcpEndpoint::server(resolve(host, *port).await?, config)?)
+            }
+            Address::Ws {
+                host,
+                port,
+                path,
+                secure: false,
+            } => {
+                let config = WsConfig::default().with_path(path.clone());
+                Listener::Ws(WsEndpoint::server(resolve(host, *port).await?, config)?)
+            }
+            Address::Ws {
+                host,
+                port,
+                path,
+                secure: true,
+            } => Listener::Wss {
+                listener: TcpListener::bind(resolve(host, *port).await?).await?,
+                tls: Arc::new(tls.server_config(host)?),
+                ws: WsEndpoint::client(WsConfig::default().with_path(path.clone())),
+            },
+            Address::Unix(path) => {
+                Listener::Unix(UnixEndpoint::server(path, UnixConfig::default())?)
+            }
+            Address::Shm(path) => Listener::Shm(ShmEndpoint::server(path, ShmConfig::default())?),
+            Address::Stdio => Listener::Stdio(Some(StdioUTP::stdio(MuxConfig::default()))),
+            Address::Exec(_) => bail!("cannot listen on {}", address),
+        };
+
+        Ok(listener)
+    }
+
+    /// Returns the bound address of network listeners, which tells the
+    /// actual port when binding port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Listener::Quic(endpoint) => endpoint.local_addr().ok(),
+            Listener::Tcp(endpoint) => endpoint.local_addr().ok(),
+            Listener::Ws(endpoint) => endpoint.local_addr().ok(),
+            Listener::Wss { listener, .. } => listener.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    /// Waits for the next transport connection.
+    ///
+    /// Returns `None` once the listener fails or, for `stdio:`, after the
+    /// first connection.
+    pub async fn accept(&mut self) -> Option<Link> {
+        match self {
+            Listener::Quic(endpoint) => {
+                let connection = endpoint.accept().await?;
+                Some(Link::Quic(QuicUTP::new(connection, true), None))
+            }
+            Listener::Tcp(endpoint) => endpoint.accept().await.map(Link::Mux),
+            Listener::Ws(endpoint) => endpoint.accept().await.map(Link::Mux),
+            Listener::Wss { listener, tls, ws } => loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("TCP accept failure: {}", e);
+                        return None;
+                    }
+                };
+
+                let upgraded = match TlsAcceptor::from(tls.clone()).accept(stream).await {
+                    Ok(stream) => ws.accept_stream(stream).await.map_err(anyhow::Error::from),
+                    Err(e) => Err(e.into()),
+                };
+
+                match upgraded {
+                    Ok(utp) => return Some(Link::Mux(utp)),
+                    Err(e) => tracing::warn!("wss handshake with {} failed: {}", peer, e),
+                }
+            },
+            Listener::Unix(endpoint) => endpoint.accept().await.map(Link::Unix),
+            Listener::Shm(endpoint) => endpoint.accept().await.map(Link::Shm),
+            Listener::Stdio(utp) => utp.take().map(|utp| Link::Stdio(utp, None)),
+        }
+    }
+}
+
+async fn resolve(host: &str, port: u16) -> anyhow::Result<SocketAddr> {
+    tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("failed to resolve {}", host))?
+        .next()
+        .with_context(|| format!("no address for {}", host))
+}
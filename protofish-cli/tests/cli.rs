@@ -0,0 +1,169 @@
+use std::{
+    net::UdpSocket,
+    path::PathBuf,
+    process::{Output, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use protofish::{ArbitaryData, Message, Payload, dissect::encode_frame};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+    time::timeout,
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_protofish");
+
+fn socket_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "protofish-cli-{}-{}.sock",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Starts `protofish serve` and waits until it is listening.
+async fn serve(address: &str) -> Child {
+    let mut child = Command::new(BIN)
+        .args(["serve", "-v", address])
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stderr.take().unwrap()).lines();
+    timeout(Duration::from_secs(10), async {
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if line.contains("listening on") {
+                return;
+            }
+        }
+        panic!("server exited before listening");
+    })
+    .await
+    .expect("server did not start");
+
+    // keep draining the log so the server never blocks on it
+    tokio::spawn(async move { while let Ok(Some(_)) = lines.next_line().await {} });
+
+    child
+}
+
+/// Runs the tool with `stdin` as input and returns its output.
+async fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(BIN)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let mut input = child.stdin.take().unwrap();
+    input.write_all(stdin).await.unwrap();
+    drop(input);
+
+    let output = timeout(Duration::from_secs(20), child.wait_with_output())
+        .await
+        .expect("command timed out")
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[tokio::test]
+async fn test_send_to_echo_server_over_unix() {
+    let path = socket_path();
+    let address = format!("unix:{}", path.display());
+    let _server = serve(&address).await;
+
+    let output = run(&["send", &address, "--replies", "1"], b"muffin").await;
+
+    assert_eq!(output.stdout, b"muffin");
+}
+
+#[tokio::test]
+async fn test_connect_and_bench_over_quic() {
+    let address = format!("quic://127.0.0.1:{}", free_udp_port());
+    let _server = serve(&address).await;
+
+    let output = run(&["connect", &address, "--insecure"], b"").await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("protocol version: 1.0.0"), "{}", stdout);
+    assert!(stdout.contains("peer address: 127.0.0.1:"), "{}", stdout);
+
+    let output = run(&["bench", &address, "--insecure", "--bytes", "1M"], b"").await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("sent 1048576 bytes"), "{}", stdout);
+}
+
+#[tokio::test]
+async fn test_client_requires_trust_anchor() {
+    let output = Command::new(BIN)
+        .args(["connect", "tls://127.0.0.1:1"])
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--insecure"));
+}
+
+#[tokio::test]
+async fn test_pipe_through_child_process() {
+    let server = format!("exec:{} serve stdio:", BIN);
+    let input = vec![7u8; 100_000];
+
+    let output = run(&["pipe", &server], &input).await;
+
+    assert_eq!(output.stdout, input);
+}
+
+#[tokio::test]
+async fn test_decode_flags_bad_frames() {
+    let mut dump = encode_frame(Message {
+        context_id: 2,
+        payload: Payload::ArbitaryData(ArbitaryData {
+            content: Bytes::from_static(b"hi"),
+        }),
+        budget: None,
+    })
+    .to_vec();
+    dump.extend_from_slice(&2u64.to_le_bytes());
+    dump.extend_from_slice(&[0xff, 0xff]);
+    dump.extend_from_slice(&100u64.to_le_bytes());
+
+    let output = run(&["decode", "--format", "json"], &dump).await;
+    let frames: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(frames[0]["status"], "ok");
+    assert_eq!(frames[0]["context_id"], 2);
+    assert_eq!(frames[0]["variant"], "ArbitaryData");
+    assert_eq!(frames[0]["fields"]["content"], "6869");
+    assert_eq!(frames[1]["status"], "corrupt");
+    assert_eq!(frames[2]["status"], "truncated");
+
+    let output = run(&["decode"], &dump).await;
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(text.lines().count(), 3);
+    assert!(text.contains("ctx=2 ArbitaryData content=6869"), "{}", text);
+}
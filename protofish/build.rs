@@ -1,45 +1,95 @@
-use std::{error, fs, path::PathBuf, process::Command};
+//! The code generated from the schema in `proto/` is checked in under
+//! `src/prost_generated`, so normal builds need neither `protoc` nor `buf`.
+//!
+//! With the `regenerate` feature, the generated code is rebuilt from `proto/`
+//! and `proto-ext/` with `prost-build`, which needs `protoc` (or `PROTOC`
+//! pointing at it). Setting `PROTOFISH_BUF_EXPORT=1` as well first refreshes
+//! `proto/` from the Buf Schema Registry with `buf export`.
+//!
+//! `proto/` holds the published schema only. Additions that are not
+//! published yet live in `proto-ext/`, which `buf export` leaves alone.
 
-use walkdir::WalkDir;
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
 
-fn main() -> Result<(), Box<dyn error::Error>> {
-    fetch_proto()
+    #[cfg(feature = "regenerate")]
+    if let Err(e) = regenerate::run() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn fetch_proto() -> Result<(), Box<dyn error::Error>> {
-    let out_dir = ".proto";
-    let buf_module = "buf.build/zako-ac/protofish";
-    let prost_out_dir = "src/prost_generated";
+#[cfg(feature = "regenerate")]
+mod regenerate {
+    use std::{env, path::PathBuf, process::Command};
 
-    if !fs::exists(out_dir)? {
-        Command::new("buf")
-            .args(["export", buf_module, "--output", out_dir])
-            .status()?;
-    }
+    use walkdir::WalkDir;
 
-    let _ = fs::create_dir_all(prost_out_dir);
+    const PROTO_DIR: &str = "proto";
+    const PROTO_EXT_DIR: &str = "proto-ext";
+    const PROST_OUT_DIR: &str = "src/prost_generated";
+    const BUF_MODULE: &str = "buf.build/zako-ac/protofish";
+    const BUF_EXPORT_ENV: &str = "PROTOFISH_BUF_EXPORT";
 
-    let protos = list_protos(out_dir);
-    println!("{:#?}", protos);
+    pub fn run() -> Result<(), String> {
+        println!("cargo:rerun-if-changed={}", PROTO_DIR);
+        println!("cargo:rerun-if-changed={}", PROTO_EXT_DIR);
+        println!("cargo:rerun-if-env-changed={}", BUF_EXPORT_ENV);
 
-    prost_build::Config::new()
-        .out_dir(prost_out_dir)
-        .compile_protos(&protos, &[out_dir])?;
+        if env::var_os(BUF_EXPORT_ENV).is_some_and(|value| value == "1") {
+            buf_export()?;
+        }
 
-    Ok(())
-}
+        let mut protos = list_protos(PROTO_DIR);
+        if protos.is_empty() {
+            return Err(format!("no .proto files found in {}", PROTO_DIR));
+        }
+        protos.extend(list_protos(PROTO_EXT_DIR));
+
+        prost_build::Config::new()
+            .out_dir(PROST_OUT_DIR)
+            .compile_protos(&protos, &[PROTO_DIR, PROTO_EXT_DIR])
+            .map_err(|e| {
+                format!(
+                    "failed to generate code from {} and {}: {}",
+                    PROTO_DIR, PROTO_EXT_DIR, e
+                )
+            })
+    }
 
-fn list_protos(dir: &str) -> Vec<PathBuf> {
-    WalkDir::new(dir)
-        .into_iter()
-        .filter_map(|e| {
-            let entry = e.ok()?;
-            let path = entry.path();
-            if path.extension()? == "proto" {
-                Some(path.to_path_buf())
-            } else {
-                None
-            }
-        })
-        .collect()
+    fn buf_export() -> Result<(), String> {
+        let status = Command::new("buf")
+            .args(["export", BUF_MODULE, "--output", PROTO_DIR])
+            .status()
+            .map_err(|e| {
+                format!(
+                    "failed to run `buf export {}`: {} (is the Buf CLI installed?)",
+                    BUF_MODULE, e
+                )
+            })?;
+
+        if !status.success() {
+            return Err(format!(
+                "`buf export {}` failed with {}",
+                BUF_MODULE, status
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn list_protos(dir: &str) -> Vec<PathBuf> {
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_map(|e| {
+                let entry = e.ok()?;
+                let path = entry.path();
+                if path.extension()? == "proto" {
+                    Some(path.to_path_buf())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,61 @@
+//! Writes the seed corpora of the fuzz targets to `seeds/`, from the PMC
+//! stream of a real connection.
+//!
+//! ```sh
+//! cargo run --example seeds
+//! ```
+
+use std::{fs, path::Path};
+
+use protofish::dissect::dissect;
+use protofish_fuzz::{block_on, record_session};
+
+fn main() -> std::io::Result<()> {
+    let session = block_on(record_session());
+    let seeds = Path::new(env!("CARGO_MANIFEST_DIR")).join("seeds");
+
+    let dir = seeds.join("frame_stream");
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("session"), &session.client_to_server)?;
+
+    let dir = seeds.join("handshake");
+    fs::create_dir_all(&dir)?;
+    let client_hello = frames(&session.client_to_server)[0].0;
+    let server_hello = frames(&session.server_to_client)[0].0;
+    fs::write(
+        dir.join("server"),
+        [&[0], &session.client_to_server[..client_hello]].concat(),
+    )?;
+    fs::write(
+        dir.join("client"),
+        [&[1], &session.server_to_client[..server_hello]].concat(),
+    )?;
+
+    let dir = seeds.join("message");
+    fs::create_dir_all(&dir)?;
+    for (name, stream) in [
+        ("client", &session.client_to_server),
+        ("server", &session.server_to_client),
+    ] {
+        for (index, (_, body)) in frames(stream).into_iter().enumerate() {
+            fs::write(dir.join(format!("{}-{}", name, index)), body)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a PMC stream into frames, returning the end offset and body of
+/// each.
+fn frames(stream: &[u8]) -> Vec<(usize, &[u8])> {
+    dissect(stream)
+        .into_iter()
+        .filter_map(|frame| {
+            let length = frame.length? as usize;
+            let start = frame.offset + 8;
+            stream
+                .get(start..start + length)
+                .map(|body| (start + length, body))
+        })
+        .collect()
+}
@@ -0,0 +1,11 @@
+//! Raw bytes on the PMC stream of a server, through the handshake and the
+//! contexts opened after it.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protofish_fuzz::{block_on, feed_server};
+
+fuzz_target!(|data: &[u8]| {
+    block_on(feed_server(data, true));
+});
@@ -0,0 +1,19 @@
+//! Raw bytes answering the handshake of either side. The first byte picks
+//! the side: even bytes feed a server, odd bytes a client.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protofish_fuzz::{block_on, feed_client, feed_server};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((side, bytes)) = data.split_first() else {
+        return;
+    };
+
+    if side % 2 == 0 {
+        block_on(feed_server(bytes, false));
+    } else {
+        block_on(feed_client(bytes));
+    }
+});
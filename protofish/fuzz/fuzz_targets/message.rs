@@ -0,0 +1,18 @@
+//! A single frame body decoded as a `Message`, which must survive a round
+//! trip if it decodes at all.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use protofish::dissect::{decode_message, dissect, encode_frame};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = decode_message(data) else {
+        return;
+    };
+
+    let frames = dissect(&encode_frame(message.clone()));
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].context_id(), Some(message.context_id));
+    assert_eq!(frames[0].variant(), Some(message.payload.name()));
+});
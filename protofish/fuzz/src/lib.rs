@@ -0,0 +1,187 @@
+//! Harnesses shared by the fuzz targets.
+//!
+//! Each harness plays one side of a connection with raw bytes, against a
+//! real `protofish` peer over the in-memory transport of
+//! `protofish::testkit`. They run on a paused tokio clock, so a peer waiting
+//! for bytes that never come times out instantly instead of stalling the
+//! fuzzer.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use protofish::{
+    ArbContext, Connection, IntegrityType, accept, connect,
+    testkit::{LinkConfig, TestUTP, pair},
+    utp::{UTP, UTPEvent, UTPStream},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::timeout,
+};
+
+/// How long a harness lets the peer run, on the paused clock.
+const RUN_TIME: Duration = Duration::from_secs(30);
+
+/// Runs `future` to completion on a single-threaded runtime with a paused
+/// clock.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .start_paused(true)
+        .build()
+        .expect("failed to build the runtime")
+        .block_on(future)
+}
+
+/// Writes `bytes` on the PMC stream of a server, as the client would.
+///
+/// With `serve` set, contexts opened after the handshake are served: their
+/// data is read, and benchmarks and streams are accepted.
+pub async fn feed_server(bytes: &[u8], serve: bool) {
+    let (server, peer) = pair(LinkConfig::default());
+
+    let server = tokio::spawn(async move {
+        let Ok(connection) = accept(Arc::new(server)).await else {
+            return;
+        };
+        if serve {
+            serve_contexts(connection).await;
+        }
+    });
+
+    let Ok(stream) = peer.new_stream(IntegrityType::Reliable).await else {
+        return;
+    };
+    write_raw(stream, bytes).await;
+
+    let _ = timeout(RUN_TIME, server).await;
+}
+
+/// Writes `bytes` on the PMC stream of a client, as the server would.
+pub async fn feed_client(bytes: &[u8]) {
+    let (client, peer) = pair(LinkConfig::default());
+
+    let client = tokio::spawn(async move {
+        let _ = connect(Arc::new(client)).await;
+    });
+
+    let UTPEvent::NewStream(id) = peer.next_event().await else {
+        return;
+    };
+    let Ok(stream) = peer.wait_stream(id, IntegrityType::Reliable).await else {
+        return;
+    };
+    write_raw(stream, bytes).await;
+
+    let _ = timeout(RUN_TIME, client).await;
+}
+
+/// Raw bytes sent each way on the PMC stream of a connection.
+pub struct Session {
+    pub client_to_server: Vec<u8>,
+    pub server_to_client: Vec<u8>,
+}
+
+/// Records the PMC stream of a real connection: a handshake and an exchange
+/// of arbitrary data.
+///
+/// The client and server talk through a relay that copies the stream
+/// between two transport pairs.
+pub async fn record_session() -> Session {
+    let (client, relay_client) = pair(LinkConfig::default());
+    let (relay_server, server) = pair(LinkConfig::default());
+
+    tokio::spawn(async move {
+        let connection = accept(Arc::new(server)).await.unwrap();
+        let arb = connection.next_arb().await.unwrap();
+        let content = arb.read().await.unwrap();
+        arb.write(content).await.unwrap();
+        connection.closed().await;
+    });
+
+    tokio::spawn(async move {
+        let connection = connect(Arc::new(client)).await.unwrap();
+        let arb = connection.new_arb();
+        arb.write(bytes::Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        arb.read().await.unwrap();
+        connection.close(0, "done").await.unwrap();
+    });
+
+    let UTPEvent::NewStream(id) = relay_client.next_event().await else {
+        panic!("client opened no stream");
+    };
+    let (client_write, client_read) = relay_client
+        .wait_stream(id, IntegrityType::Reliable)
+        .await
+        .unwrap()
+        .split();
+    let (server_write, server_read) = relay_server
+        .new_stream(IntegrityType::Reliable)
+        .await
+        .unwrap()
+        .split();
+
+    let (client_to_server, server_to_client) = tokio::join!(
+        relay(client_read, server_write),
+        relay(server_read, client_write),
+    );
+
+    Session {
+        client_to_server,
+        server_to_client,
+    }
+}
+
+/// Copies `read` to `write` until the stream ends or goes quiet, returning
+/// the bytes copied.
+async fn relay(mut read: impl AsyncRead + Unpin, mut write: impl AsyncWrite + Unpin) -> Vec<u8> {
+    let mut copied = Vec::new();
+    let mut buf = [0; 4096];
+
+    while let Ok(Ok(n)) = timeout(Duration::from_secs(1), read.read(&mut buf)).await {
+        if n == 0 || write.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+        copied.extend_from_slice(&buf[..n]);
+    }
+
+    copied
+}
+
+/// Writes `bytes` on `stream` and closes it, discarding what the peer sends
+/// back.
+async fn write_raw(stream: <TestUTP as UTP>::Stream, bytes: &[u8]) {
+    let (mut write, mut read) = stream.split();
+
+    tokio::spawn(async move {
+        let mut sink = Vec::new();
+        let _ = read.read_to_end(&mut sink).await;
+    });
+
+    let _ = write.write_all(bytes).await;
+    let _ = write.shutdown().await;
+}
+
+async fn serve_contexts(connection: Connection<TestUTP>) {
+    while let Some(arb) = connection.next_arb().await {
+        tokio::spawn(serve_context(arb));
+    }
+}
+
+async fn serve_context(arb: ArbContext<TestUTP>) {
+    let data = async { while arb.read().await.is_ok() {} };
+    let control = async {
+        if let Ok(start) = arb.recv_benchmark().await {
+            let _ = arb.serve_benchmark(start).await;
+        }
+    };
+    let streams = async {
+        while let Ok(mut stream) = arb.wait_stream().await {
+            let mut sink = Vec::new();
+            let _ = stream.read_to_end(&mut sink).await;
+        }
+    };
+
+    tokio::join!(data, control, streams);
+}
@@ -0,0 +1,113 @@
+//! Recording and replaying the traffic of a connection, for debugging.
+//!
+//! A `Capture` attached with `Connection::with_capture` writes every
+//! `Message` sent or received on the Primary Messaging Channel to a file,
+//! with a timestamp and direction, along with the stream events reported by
+//! the transport. Stream data itself is not recorded.
+//!
+//! `read_capture` loads the file again, and `Replay` sends the messages the
+//! recorded side received from a fake peer, to reproduce a bug in a test:
+//!
+//! ```no_run
+//! use protofish::capture::Replay;
+//! use protofish::local::connection_pair;
+//!
+//! # async fn example() {
+//! // the recorded side was a server, so the fake peer is the client
+//! let (peer, server) = connection_pair().await.unwrap();
+//!
+//! let replay = Replay::load("server.pfcap").unwrap();
+//! tokio::spawn(async move { replay.run(&peer).await });
+//!
+//! let arb = server.next_arb().await.unwrap();
+//! # }
+//! ```
+
+mod record;
+mod replay;
+
+use std::sync::{Arc, OnceLock};
+
+pub use record::{Capture, CaptureEvent, CaptureReader, CaptureRecord, Direction, read_capture};
+pub use replay::Replay;
+
+/// Capture of a connection, shared by its PMC and its event watcher. Empty
+/// until `Connection::with_capture` is called.
+pub(crate) type CaptureSlot = Arc<OnceLock<Capture>>;
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::local::connection_pair;
+    use crate::schema::Payload;
+
+    fn capture_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("protofish-{}-{}.pfcap", std::process::id(), name))
+    }
+
+    fn arbitrary_data(record: &CaptureRecord) -> Option<&Bytes> {
+        match &record.event {
+            CaptureEvent::Message(message) => match &message.payload {
+                Payload::ArbitaryData(data) => Some(&data.content),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    async fn record_server(path: &PathBuf) {
+        let (client, server) = connection_pair().await.unwrap();
+        let server = server.with_capture(Capture::create(path).unwrap());
+
+        let arb = client.new_arb();
+        arb.write(Bytes::from_static(b"muffin")).await.unwrap();
+
+        let peer = server.next_arb().await.unwrap();
+        assert_eq!(peer.read().await.unwrap(), Bytes::from_static(b"muffin"));
+        peer.write(Bytes::from_static(b"waffle")).await.unwrap();
+        assert_eq!(arb.read().await.unwrap(), Bytes::from_static(b"waffle"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_capture() {
+        let path = capture_path("capture");
+        record_server(&path).await;
+
+        let records = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let received = records
+            .iter()
+            .find(|record| arbitrary_data(record).is_some_and(|data| data == "muffin"))
+            .expect("received message not recorded");
+        let sent = records
+            .iter()
+            .find(|record| arbitrary_data(record).is_some_and(|data| data == "waffle"))
+            .expect("sent message not recorded");
+
+        assert_eq!(received.direction, Direction::Received);
+        assert_eq!(sent.direction, Direction::Sent);
+        assert_eq!(received.context_id(), sent.context_id());
+        assert!(received.timestamp <= sent.timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_replay_into_connection() {
+        let path = capture_path("replay");
+        record_server(&path).await;
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (peer, server) = connection_pair().await.unwrap();
+        let sent = replay.run(&peer).await.unwrap();
+        assert_eq!(sent, 1);
+
+        let arb = server.next_arb().await.unwrap();
+        assert_eq!(arb.read().await.unwrap(), Bytes::from_static(b"muffin"));
+    }
+}
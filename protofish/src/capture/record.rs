@@ -0,0 +1,268 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    internal::serialize::{deserialize_message, serialize_message},
+    schema::{ContextId, Message, StreamId},
+};
+
+/// Leads every capture file; the last byte is the format version.
+const MAGIC: &[u8; 8] = b"PFCAP\0\0\x01";
+/// Largest record accepted when reading, to fail early on corrupt files.
+const MAX_RECORD_LEN: usize = 256 * 1024 * 1024;
+/// Length of the `[timestamp u64][direction u8][kind u8][id u64]` record
+/// header, all little endian.
+const RECORD_HEADER_LEN: usize = 18;
+
+const KIND_MESSAGE: u8 = 0;
+const KIND_STREAM_OPENED: u8 = 1;
+const KIND_STREAM_CLOSED: u8 = 2;
+const KIND_STREAM_RESET: u8 = 3;
+
+/// Whether the recorded side sent or received an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Something that happened on a recorded connection.
+#[derive(Clone, Debug)]
+pub enum CaptureEvent {
+    /// A message on the Primary Messaging Channel
+    Message(Message),
+    /// The peer opened a stream
+    StreamOpened(StreamId),
+    /// The peer finished sending on a stream
+    StreamClosed(StreamId),
+    /// A stream was abruptly terminated
+    StreamReset { id: StreamId, code: u64 },
+}
+
+/// One entry of a capture file.
+#[derive(Clone, Debug)]
+pub struct CaptureRecord {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub event: CaptureEvent,
+}
+
+impl CaptureRecord {
+    /// Returns the context of a recorded message, or `None` for stream
+    /// events.
+    pub fn context_id(&self) -> Option<ContextId> {
+        match &self.event {
+            CaptureEvent::Message(message) => Some(message.context_id),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (kind, id, body) = match &self.event {
+            CaptureEvent::Message(message) => (
+                KIND_MESSAGE,
+                message.context_id,
+                serialize_message(message.clone()).to_vec(),
+            ),
+            CaptureEvent::StreamOpened(id) => (KIND_STREAM_OPENED, *id, Vec::new()),
+            CaptureEvent::StreamClosed(id) => (KIND_STREAM_CLOSED, *id, Vec::new()),
+            CaptureEvent::StreamReset { id, code } => {
+                (KIND_STREAM_RESET, *id, code.to_le_bytes().to_vec())
+            }
+        };
+
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let direction = match self.direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        };
+
+        let len = RECORD_HEADER_LEN + body.len();
+        let mut record = Vec::with_capacity(4 + len);
+        record.extend_from_slice(&(len as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.push(direction);
+        record.push(kind);
+        record.extend_from_slice(&id.to_le_bytes());
+        record.extend_from_slice(&body);
+        record
+    }
+
+    fn decode(record: &[u8]) -> io::Result<Self> {
+        if record.len() < RECORD_HEADER_LEN {
+            return Err(invalid("record too short"));
+        }
+
+        let (header, body) = record.split_at(RECORD_HEADER_LEN);
+        let timestamp = u64::from_le_bytes(header[0..8].try_into().unwrap());
+        let direction = match header[8] {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            _ => return Err(invalid("unknown direction")),
+        };
+        let id = u64::from_le_bytes(header[10..18].try_into().unwrap());
+
+        let event = match header[9] {
+            KIND_MESSAGE => CaptureEvent::Message(
+                deserialize_message(body).map_err(|_| invalid("malformed message"))?,
+            ),
+            KIND_STREAM_OPENED => CaptureEvent::StreamOpened(id),
+            KIND_STREAM_CLOSED => CaptureEvent::StreamClosed(id),
+            KIND_STREAM_RESET => {
+                let code = body
+                    .try_into()
+                    .map(u64::from_le_bytes)
+                    .map_err(|_| invalid("malformed stream reset"))?;
+                CaptureEvent::StreamReset { id, code }
+            }
+            _ => return Err(invalid("unknown record kind")),
+        };
+
+        Ok(Self {
+            timestamp: UNIX_EPOCH + Duration::from_micros(timestamp),
+            direction,
+            event,
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Writes the traffic of a connection to a capture file.
+///
+/// Attach it with `Connection::with_capture`. Records are flushed one by
+/// one, so a capture is readable up to the last record even if the process
+/// crashes. Write failures are logged once and the capture stops.
+#[derive(Clone)]
+pub struct Capture {
+    inner: Arc<Mutex<CaptureWriter>>,
+}
+
+struct CaptureWriter {
+    out: Box<dyn Write + Send>,
+    failed: bool,
+}
+
+impl Capture {
+    /// Creates a capture file at `path`, replacing any existing file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Writes the capture to `out`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file header cannot be written.
+    pub fn new(mut out: impl Write + Send + 'static) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.flush()?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(CaptureWriter {
+                out: Box::new(out),
+                failed: false,
+            })),
+        })
+    }
+
+    pub(crate) fn record(&self, direction: Direction, event: CaptureEvent) {
+        let record = CaptureRecord {
+            timestamp: SystemTime::now(),
+            direction,
+            event,
+        }
+        .encode();
+
+        let mut writer = self.inner.lock();
+        if writer.failed {
+            return;
+        }
+
+        let result = writer
+            .out
+            .write_all(&record)
+            .and_then(|_| writer.out.flush());
+        if let Err(e) = result {
+            tracing::warn!("capture write failure, capture stopped: {}", e);
+            writer.failed = true;
+        }
+    }
+}
+
+/// Reads the records of a capture file in order.
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// # Errors
+    ///
+    /// Returns an error if `reader` does not start with a capture header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a protofish capture"));
+        }
+
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<CaptureRecord>> {
+        let mut len = [0; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(invalid("record too large"));
+        }
+
+        let mut record = vec![0; len];
+        self.reader.read_exact(&mut record)?;
+
+        CaptureRecord::decode(&record).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Reads all records of the capture file at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or is corrupt, including when
+/// its last record was cut short.
+pub fn read_capture(path: impl AsRef<Path>) -> io::Result<Vec<CaptureRecord>> {
+    CaptureReader::new(BufReader::new(File::open(path)?))?.collect()
+}
@@ -0,0 +1,87 @@
+use std::{io, path::Path, time::SystemTime};
+
+use crate::{
+    capture::record::{CaptureEvent, CaptureRecord, Direction, read_capture},
+    core::common::connection::Connection,
+    schema::Payload,
+    utp::{UTP, error::UTPError},
+};
+
+/// Plays back the messages the recorded side of a capture received.
+///
+/// The messages are sent from a fake peer, with their original context IDs,
+/// so the peer should take the role the recorded side's peer had: a client
+/// when a server was recorded and the other way around. Handshake messages
+/// are skipped, as the new connection made its own.
+pub struct Replay {
+    records: Vec<CaptureRecord>,
+    timing: bool,
+}
+
+impl Replay {
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        Self {
+            records,
+            timing: false,
+        }
+    }
+
+    /// Loads the capture file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is corrupt.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(read_capture(path)?))
+    }
+
+    /// Waits between messages as long as they were apart when recorded,
+    /// instead of sending them back to back.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Sends the recorded messages from `peer`, in order.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of messages sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sending fails.
+    pub async fn run<U: UTP>(&self, peer: &Connection<U>) -> Result<usize, UTPError> {
+        let mut previous: Option<SystemTime> = None;
+        let mut sent = 0;
+
+        for record in &self.records {
+            let (Direction::Received, CaptureEvent::Message(message)) =
+                (record.direction, &record.event)
+            else {
+                continue;
+            };
+
+            if matches!(
+                message.payload,
+                Payload::ClientHello(_) | Payload::ServerHello(_)
+            ) {
+                continue;
+            }
+
+            if self.timing {
+                if let Some(gap) =
+                    previous.and_then(|previous| record.timestamp.duration_since(previous).ok())
+                {
+                    tokio::time::sleep(gap).await;
+                }
+                previous = Some(record.timestamp);
+            }
+
+            peer.pmc.send_raw(message.clone()).await?;
+            sent += 1;
+        }
+
+        Ok(sent)
+    }
+}
@@ -6,3 +6,9 @@ pub const VERSION: Version = Version {
     minor: 0,
     patch: 0,
 };
+
+/// Largest PMC frame body accepted from a peer, in bytes.
+///
+/// A peer announcing a longer frame is treated as a protocol violation and
+/// the connection's PMC is closed, since its stream can no longer be framed.
+pub const MAX_FRAME_LEN: u64 = 16 * 1024 * 1024;
@@ -11,7 +11,7 @@ use crate::{
         pmc::PMC,
     },
     error::ProtofishError,
-    schema::{ClientHello, IntegrityType, Payload},
+    schema::{ClientHello, IntegrityType, Payload, ServerHello},
     utp::{UTP, UTPStream},
 };
 
@@ -47,15 +47,15 @@ where
     let stream = utp.new_stream(IntegrityType::Reliable).await?;
     let pmc = PMC::new(false, stream);
 
-    let _ = client_handshake(pmc.create_context(), None).await?;
+    let server_hello = client_handshake(pmc.create_context(), None).await?;
 
-    Ok(Connection::new(utp.clone(), pmc))
+    Ok(Connection::new(utp.clone(), pmc).with_server_hello(server_hello))
 }
 
 async fn client_handshake<S: UTPStream>(
     ctx: (ContextWriter<S>, ContextReader),
     resume_token: Option<Bytes>,
-) -> Result<Bytes, ProtofishError> {
+) -> Result<ServerHello, ProtofishError> {
     let (tx, rx) = ctx;
 
     let client_hello = ClientHello {
@@ -65,15 +65,17 @@ async fn client_handshake<S: UTPStream>(
 
     tx.write(Payload::ClientHello(client_hello)).await?;
 
-    let server_hello = rx.read().await?;
+    let server_hello = rx.read_control().await?;
 
     if let Payload::ServerHello(server_hello) = server_hello {
         if server_hello.ok {
-            Ok(server_hello
-                .connection_token
-                .ok_or(ProtofishError::Connection(ConnectionError::MalformedData(
+            if server_hello.connection_token.is_none() {
+                return Err(ProtofishError::Connection(ConnectionError::MalformedData(
                     "connection token is not provided".into(),
-                )))?)
+                )));
+            }
+
+            Ok(server_hello)
         } else {
             let msg = server_hello.message.unwrap_or("unknown error".to_string());
 
@@ -109,7 +111,7 @@ mod tests {
 
         tokio::spawn(async move {
             let (tx, rx) = server_pmc.next_context().await.unwrap();
-            let payload = rx.read().await.unwrap();
+            let payload = rx.read_control().await.unwrap();
 
             if let Payload::ClientHello(_) = payload {
                 tx.write(Payload::ServerHello(ServerHello {
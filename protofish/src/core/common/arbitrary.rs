@@ -1,16 +1,27 @@
-use std::sync::Arc;
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
 use bytes::Bytes;
 use thiserror::Error;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     IntegrityType, StreamCreateMeta, StreamOpen,
     core::common::{
-        context::{Context, ContextReader, ContextWriter},
+        context::{Context, ContextQueue, ContextReader, ContextWriter},
+        deadline::{earliest, with_deadline},
         error::ConnectionError,
-        stream::ProtofishStream,
+        rate::{RateLimiter, RateLimits},
+        stream::{ProtofishStream, StreamOptions},
     },
-    schema::{ArbitaryData, Payload},
+    schema::{ArbitaryData, Cancel, Error, ErrorType, Payload, StreamReport},
     utp::{UTP, UTPStream, error::UTPError},
 };
 
@@ -18,10 +29,28 @@ use crate::{
 ///
 /// This type provides a simplified interface for sending and receiving
 /// arbitrary binary data through a Protofish context.
+///
+/// Incoming data, stream opens and control payloads are queued separately,
+/// so `read`, `wait_stream` and `recv_report` may be called in any order or
+/// concurrently, and a context can carry any number of streams.
+///
+/// A context opened with `Connection::new_arb` is cancelled on the peer when
+/// it is dropped after having sent something, unless `finish` was called.
+/// Either way, payloads the peer sends on it afterwards are discarded.
+///
+/// A context may carry a deadline, which is sent to the peer as a remaining
+/// budget with the first message. Once it passes, reads on either side fail
+/// with `ArbError::Timeout` and an `Error` payload of type `Timeout` is sent
+/// to the peer.
 pub struct ArbContext<U: UTP> {
     writer: ContextWriter<U::Stream>,
     reader: ContextReader,
     utp: Arc<U>,
+    limits: RateLimits,
+    initiator: bool,
+    written: AtomicBool,
+    finished: bool,
+    timed_out: AtomicBool,
 }
 
 /// Errors that can occur during arbitrary data operations.
@@ -31,85 +60,314 @@ pub enum ArbError {
     #[error("connection error: {0}")]
     Connection(#[from] ConnectionError),
 
-    /// Received a payload that was not `ArbitaryData`
+    /// Received a payload of an unexpected type
     #[error("unexpected data: {0}")]
     UnexpectedData(String),
 
     /// UTP Error
     #[error("UTP error: {0}")]
     UTP(#[from] UTPError),
+
+    /// The deadline of the context passed
+    #[error("deadline exceeded")]
+    Timeout,
 }
 
 impl<U: UTP> ArbContext<U> {
+    /// Adds a rate limit to this context.
+    ///
+    /// The limit applies to data written with `write` and to every stream
+    /// opened on this context afterwards. The limiter may be shared with
+    /// other contexts to cap them together.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limits.push(limiter);
+        self
+    }
+
+    /// Sets the deadline of this context.
+    ///
+    /// The deadline only ever moves earlier, and is sent to the peer with the
+    /// first message, so it must be set before anything is written.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.reader.deadline = earliest(self.reader.deadline, Some(deadline));
+        self
+    }
+
+    /// Sets the deadline of this context to `timeout` from now.
+    ///
+    /// See `with_deadline`.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns the deadline of this context, either set locally or received
+    /// from the peer.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.reader.deadline
+    }
+
+    /// Returns the time left until the deadline of this context, if any.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.reader
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Runs `future` with this context's deadline in scope, so that contexts
+    /// opened with `Connection::new_arb` while handling this request inherit
+    /// it.
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        with_deadline(self.reader.deadline, future).await
+    }
+
     /// Writes arbitrary binary data to this context.
     ///
     /// The bytes will be wrapped in an `ArbitaryData` payload and sent
-    /// to the peer.
+    /// to the peer, once the context and connection rate limits allow it.
     ///
     /// # Errors
     ///
     /// Returns an error if the underlying write operation fails.
     pub async fn write(&self, content: Bytes) -> Result<(), ArbError> {
-        let payload = Payload::ArbitaryData(ArbitaryData {
-            content: content.into(),
-        });
+        self.limits.acquire(content.len()).await;
 
-        self.writer.write(payload).await?;
+        let payload = Payload::ArbitaryData(ArbitaryData { content });
 
-        Ok(())
+        self.write_payload(payload).await
     }
 
     /// Reads arbitrary binary data from this context.
     ///
-    /// This method expects the next payload to be `ArbitaryData` and
-    /// extracts the bytes from it.
+    /// This method waits for the next `ArbitaryData` payload and extracts the
+    /// bytes from it. Stream opens and control payloads received meanwhile
+    /// stay queued for `wait_stream` and the other readers.
     ///
     /// # Returns
     ///
-    /// Returns the binary content, or an error if the payload is not
-    /// `ArbitaryData` or the read fails.
+    /// Returns the binary content, or an error if the read fails.
     ///
     /// # Errors
     ///
-    /// Returns `ArbError::UnexpectedData` if a non-`ArbitaryData` payload
-    /// is received, or `ArbError::Connection` if the read fails.
+    /// Returns `ArbError::Connection` if the read fails, or
+    /// `ArbError::Timeout` if the deadline of the context passes.
     pub async fn read(&self) -> Result<Bytes, ArbError> {
-        let data_got = self.reader.read().await?;
-
-        if let Payload::ArbitaryData(data) = data_got {
-            Ok(Bytes::from(data.content))
-        } else {
-            Err(ArbError::UnexpectedData("expected ArbitaryData".into()))
+        match self.recv_payload(ContextQueue::Data).await? {
+            Payload::ArbitaryData(data) => Ok(data.content),
+            other => Err(ArbError::UnexpectedData(format!(
+                "expected ArbitaryData, got {:?}",
+                other
+            ))),
         }
     }
 
+    /// Waits for the peer to open a stream on this context.
+    ///
+    /// Streams are returned in the order the peer opened them, regardless of
+    /// data sent on the context in between.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the read fails, the deadline of the context
+    /// passes, or the announced stream cannot be accepted.
     pub async fn wait_stream(&self) -> Result<ProtofishStream<U::Stream>, ArbError> {
-        let data_got = self.reader.read().await?;
-
-        if let Payload::StreamOpen(meta) = data_got {
-            let utp_stream = self
-                .utp
-                .wait_stream(meta.stream_id, meta.meta.integrity_type)
-                .await?;
-            Ok(ProtofishStream::new(utp_stream))
-        } else {
-            Err(ArbError::UnexpectedData("expected ArbitaryData".into()))
-        }
+        let meta = match self.recv_payload(ContextQueue::Streams).await? {
+            Payload::StreamOpen(meta) => meta,
+            other => {
+                return Err(ArbError::UnexpectedData(format!(
+                    "expected StreamOpen, got {:?}",
+                    other
+                )));
+            }
+        };
+
+        let utp_stream = self
+            .utp
+            .wait_stream(meta.stream_id, meta.meta.integrity_type)
+            .await?;
+
+        Ok(ProtofishStream::new(
+            utp_stream,
+            self.writer.clone(),
+            self.limits.clone(),
+            StreamOptions::default(),
+        ))
     }
+
     pub async fn new_stream(
         &self,
         integrity: IntegrityType,
+    ) -> Result<ProtofishStream<U::Stream>, ArbError> {
+        self.new_stream_with(integrity, StreamOptions::default())
+            .await
+    }
+
+    /// Opens a new stream on this context with the given options, such as a
+    /// per-stream rate limit or pacing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream cannot be opened or announced.
+    pub async fn new_stream_with(
+        &self,
+        integrity: IntegrityType,
+        options: StreamOptions,
     ) -> Result<ProtofishStream<U::Stream>, ArbError> {
         let stream = self.utp.new_stream(integrity.clone()).await?;
+        self.write_payload(Payload::StreamOpen(StreamOpen {
+            stream_id: stream.id(),
+            meta: StreamCreateMeta {
+                integrity_type: integrity,
+            },
+        }))
+        .await?;
+        Ok(ProtofishStream::new(
+            stream,
+            self.writer.clone(),
+            self.limits.clone(),
+            options,
+        ))
+    }
+
+    /// Waits for the next `StreamReport` sent by the receiver of a sequenced
+    /// stream opened on this context.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ArbError::UnexpectedData` if another control payload is
+    /// received, or `ArbError::Connection` if the read fails.
+    pub async fn recv_report(&self) -> Result<StreamReport, ArbError> {
+        match self.recv_payload(ContextQueue::Control).await? {
+            Payload::StreamReport(report) => Ok(report),
+            other => Err(ArbError::UnexpectedData(format!(
+                "expected StreamReport, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Returns a token that is cancelled when this context is cancelled,
+    /// either by the peer or locally with `cancel`.
+    ///
+    /// Handlers can `select!` on `cancelled()` of the token to stop working
+    /// on a request the peer has abandoned.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.reader.cancel.clone()
+    }
+
+    /// Completes once this context is cancelled.
+    pub async fn cancelled(&self) {
+        self.reader.cancel.cancelled().await
+    }
+
+    /// Returns whether this context has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.reader.cancel.is_cancelled()
+    }
+
+    /// Cancels this context on both sides.
+    ///
+    /// A `Cancel` payload carrying `reason` is sent to the peer, and pending
+    /// and future reads on this side fail with `ConnectionError::Cancelled`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `Cancel` payload cannot be written. The context
+    /// is cancelled locally regardless.
+    pub async fn cancel(&self, reason: &str) -> Result<(), ArbError> {
+        if self.reader.cancel.is_cancelled() {
+            return Ok(());
+        }
+
+        self.reader.cancel.cancel();
         self.writer
-            .write(Payload::StreamOpen(StreamOpen {
-                stream_id: stream.id(),
-                meta: StreamCreateMeta {
-                    integrity_type: integrity,
-                },
+            .write(Payload::Cancel(Cancel {
+                reason: reason.into(),
             }))
             .await?;
-        Ok(ProtofishStream::new(stream))
+
+        Ok(())
+    }
+
+    /// Marks this context as completed, so that dropping it does not cancel
+    /// it on the peer.
+    ///
+    /// The context stops receiving payloads.
+    pub fn finish(mut self) {
+        self.finished = true;
+        self.writer.release();
+    }
+
+    /// Marks this context as opened locally, enabling cancellation on drop.
+    pub(crate) fn into_initiator(mut self) -> Self {
+        self.initiator = true;
+        self
+    }
+
+    /// Writes a raw payload to this context.
+    ///
+    /// The first payload written on a locally opened context carries the
+    /// remaining budget of its deadline.
+    pub(crate) async fn write_payload(&self, payload: Payload) -> Result<(), ArbError> {
+        let first = !self.written.swap(true, Ordering::Relaxed);
+        let budget = if first && self.initiator {
+            self.remaining()
+        } else {
+            None
+        };
+
+        self.writer.write_with_budget(payload, budget).await?;
+        Ok(())
+    }
+
+    /// Reads the next control payload from this context.
+    pub(crate) async fn read_payload(&self) -> Result<Payload, ArbError> {
+        self.recv_payload(ContextQueue::Control).await
+    }
+
+    /// Reads the next payload from a queue, enforcing the deadline of the
+    /// context.
+    ///
+    /// A `Timeout` error from the peer is turned into `ArbError::Timeout`.
+    async fn recv_payload(&self, queue: ContextQueue) -> Result<Payload, ArbError> {
+        let read = self.reader.read_queue(queue);
+
+        let payload = match self.reader.deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, read).await {
+                Ok(payload) => payload?,
+                Err(_) => {
+                    self.expire().await;
+                    return Err(ArbError::Timeout);
+                }
+            },
+            None => read.await?,
+        };
+
+        if let Payload::Error(Error {
+            error_type: ErrorType::Timeout,
+            ..
+        }) = payload
+        {
+            self.timed_out.store(true, Ordering::Relaxed);
+            return Err(ArbError::Timeout);
+        }
+
+        Ok(payload)
+    }
+
+    /// Tells the peer, once, that the deadline of this context passed.
+    async fn expire(&self) {
+        if self.timed_out.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let payload = Payload::Error(Error {
+            error_type: ErrorType::Timeout,
+            message: "deadline exceeded".into(),
+        });
+
+        if let Err(e) = self.writer.write(payload).await {
+            tracing::debug!("failed to report timeout: {}", e);
+        }
     }
 }
 
@@ -122,5 +380,239 @@ pub fn make_arbitrary<U: UTP>(utp: Arc<U>, (writer, reader): Context<U::Stream>)
         utp,
         writer,
         reader,
+        limits: RateLimits::default(),
+        initiator: false,
+        written: AtomicBool::new(false),
+        finished: false,
+        timed_out: AtomicBool::new(false),
+    }
+}
+
+impl<U: UTP> Drop for ArbContext<U> {
+    fn drop(&mut self) {
+        self.writer.release();
+
+        if !self.initiator
+            || self.finished
+            || !self.written.load(Ordering::Relaxed)
+            || self.reader.cancel.is_cancelled()
+            || self.timed_out.load(Ordering::Relaxed)
+        {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let writer = self.writer.clone();
+
+        handle.spawn(async move {
+            let payload = Payload::Cancel(Cancel {
+                reason: "context dropped".into(),
+            });
+
+            if let Err(e) = writer.write(payload).await {
+                tracing::debug!("failed to cancel context {}: {}", writer.context_id, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::{
+        core::common::{arbitrary::ArbError, error::ConnectionError},
+        schema::IntegrityType,
+        utp::tests::utp::mock_connection_pair,
+    };
+
+    #[tokio::test]
+    async fn test_cancel_propagates() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        assert_eq!(server_arb.read().await.unwrap(), "muffin");
+
+        client_arb.cancel("no longer needed").await.unwrap();
+        assert!(client_arb.is_cancelled());
+
+        tokio::time::timeout(Duration::from_secs(1), server_arb.cancelled())
+            .await
+            .unwrap();
+        assert!(matches!(
+            server_arb.read().await,
+            Err(ArbError::Connection(ConnectionError::Cancelled))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_drop_cancels_initiated_context() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let token = server_arb.cancellation_token();
+        drop(client_arb);
+
+        tokio::time::timeout(Duration::from_secs(1), token.cancelled())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_finish_does_not_cancel() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        client_arb.finish();
+
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(100), server_arb.cancelled()).await;
+        assert!(cancelled.is_err());
+        assert_eq!(server_arb.read().await.unwrap(), "muffin");
+    }
+
+    #[tokio::test]
+    async fn test_drop_releases_context() {
+        let (client, server) = mock_connection_pair().await;
+        let (client_before, server_before) =
+            (client.pmc.context_count(), server.pmc.context_count());
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        let server_arb = server.next_arb().await.unwrap();
+
+        assert_eq!(client.pmc.context_count(), client_before + 1);
+        assert_eq!(server.pmc.context_count(), server_before + 1);
+
+        server_arb.finish();
+        drop(client_arb);
+
+        assert_eq!(client.pmc.context_count(), client_before);
+        assert_eq!(server.pmc.context_count(), server_before);
+
+        // the cancel sent on drop does not bring the context back
+        let next = tokio::time::timeout(Duration::from_millis(100), server.next_arb()).await;
+        assert!(next.is_err());
+        assert_eq!(server.pmc.context_count(), server_before);
+    }
+
+    #[tokio::test]
+    async fn test_late_payload_does_not_reopen_context() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        let server_arb = server.next_arb().await.unwrap();
+        let server_before = server.pmc.context_count();
+
+        server_arb.finish();
+        client_arb.write(Bytes::from_static(b"cute")).await.unwrap();
+
+        let next = tokio::time::timeout(Duration::from_millis(100), server.next_arb()).await;
+        assert!(next.is_err());
+        assert_eq!(server.pmc.context_count(), server_before - 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_expires_on_both_sides() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb().with_timeout(Duration::from_secs(1));
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        assert!(server_arb.remaining().unwrap() <= Duration::from_secs(1));
+        assert_eq!(server_arb.read().await.unwrap(), "muffin");
+
+        let (client_result, server_result) = tokio::join!(client_arb.read(), server_arb.read());
+        assert!(matches!(client_result, Err(ArbError::Timeout)));
+        assert!(matches!(server_result, Err(ArbError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_inherited_in_scope() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb().with_timeout(Duration::from_secs(30));
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let deadline = server_arb.deadline().unwrap();
+
+        let nested = server_arb.scope(async { server.new_arb() }).await;
+        assert_eq!(nested.deadline(), Some(deadline));
+        assert_eq!(server.new_arb().deadline(), None);
+    }
+
+    #[tokio::test]
+    async fn test_streams_and_data_in_any_order() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"first"))
+            .await
+            .unwrap();
+        let first = client_arb
+            .new_stream(IntegrityType::Reliable)
+            .await
+            .unwrap();
+        let second = client_arb
+            .new_stream(IntegrityType::Reliable)
+            .await
+            .unwrap();
+        client_arb
+            .write(Bytes::from_static(b"second"))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+
+        let (peer_first, peer_second) =
+            tokio::join!(async { server_arb.wait_stream().await.unwrap() }, async {
+                let stream = server_arb.wait_stream().await.unwrap();
+                (stream, server_arb.read().await.unwrap())
+            });
+        let (peer_second, data) = peer_second;
+
+        assert_eq!(peer_first.id(), first.id());
+        assert_eq!(peer_second.id(), second.id());
+        assert_eq!(data, "first");
+        assert_eq!(server_arb.read().await.unwrap(), "second");
     }
 }
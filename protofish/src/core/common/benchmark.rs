@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::Instant,
+};
+
+use crate::{
+    core::common::arbitrary::{ArbContext, ArbError},
+    schema::{BenchmarkStart, IntegrityType, Payload},
+    utp::{UTP, error::UTPError},
+};
+
+/// Size of the chunks written to a reliable benchmark stream.
+const RELIABLE_CHUNK: usize = 64 * 1024;
+
+/// Size of the messages sent over an unreliable benchmark stream.
+const UNRELIABLE_CHUNK: usize = 1024;
+
+/// Outcome of one side of a benchmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchmarkReport {
+    /// Bytes sent by the client, or received by the server
+    pub bytes: u64,
+    /// Time from opening the stream until the benchmark ended
+    pub elapsed: Duration,
+}
+
+impl BenchmarkReport {
+    /// Returns the throughput in bytes per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl<U: UTP> ArbContext<U> {
+    /// Runs a benchmark against the peer, which is expected to call
+    /// `recv_benchmark` and `serve_benchmark` on this context.
+    ///
+    /// A `BenchmarkStart` payload is sent, followed by a new stream of the
+    /// given integrity type carrying `byte_count` bytes, and `BenchmarkEnd`.
+    /// The benchmark completes once the peer acknowledges it with `Ok`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of bytes sent and the time until the peer's
+    /// acknowledgement.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream cannot be opened or written, or
+    /// `ArbError::UnexpectedData` if the peer answers with anything but `Ok`.
+    pub async fn run_benchmark(
+        &self,
+        integrity: IntegrityType,
+        byte_count: u64,
+    ) -> Result<BenchmarkReport, ArbError> {
+        self.write_payload(Payload::BenchmarkStart(BenchmarkStart {
+            integrity_type: integrity.clone(),
+            byte_count,
+        }))
+        .await?;
+
+        let mut stream = self.new_stream(integrity.clone()).await?;
+        let started = Instant::now();
+        let mut remaining = byte_count;
+
+        match integrity {
+            IntegrityType::Reliable => {
+                let chunk = vec![0u8; RELIABLE_CHUNK];
+                while remaining > 0 {
+                    let len = remaining.min(RELIABLE_CHUNK as u64) as usize;
+                    stream
+                        .write_all(&chunk[..len])
+                        .await
+                        .map_err(UTPError::from)?;
+                    remaining -= len as u64;
+                }
+                stream.shutdown().await.map_err(UTPError::from)?;
+            }
+            IntegrityType::Unreliable => {
+                let chunk = Bytes::from(vec![0u8; UNRELIABLE_CHUNK]);
+                while remaining > 0 {
+                    let len = remaining.min(UNRELIABLE_CHUNK as u64) as usize;
+                    stream.send_datagram(chunk.slice(..len)).await?;
+                    remaining -= len as u64;
+                }
+            }
+        }
+
+        self.write_payload(Payload::BenchmarkEnd).await?;
+
+        match self.read_payload().await? {
+            Payload::Ok => {}
+            other => {
+                return Err(ArbError::UnexpectedData(format!(
+                    "expected Ok, got {:?}",
+                    other
+                )));
+            }
+        }
+
+        Ok(BenchmarkReport {
+            bytes: byte_count,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Waits for the peer to start a benchmark on this context.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ArbError::UnexpectedData` if another control payload is
+    /// received, or `ArbError::Connection` if the read fails.
+    pub async fn recv_benchmark(&self) -> Result<BenchmarkStart, ArbError> {
+        match self.read_payload().await? {
+            Payload::BenchmarkStart(start) => Ok(start),
+            other => Err(ArbError::UnexpectedData(format!(
+                "expected BenchmarkStart, got {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Serves a benchmark announced by `start`, see `run_benchmark`.
+    ///
+    /// The stream of the benchmark is drained until `byte_count` bytes were
+    /// received, or until `BenchmarkEnd` arrives for unreliable streams, and
+    /// the benchmark is then acknowledged with `Ok`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of bytes received and the time it took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream cannot be accepted or read, or
+    /// `ArbError::UnexpectedData` if the peer sends another control payload.
+    pub async fn serve_benchmark(
+        &self,
+        start: BenchmarkStart,
+    ) -> Result<BenchmarkReport, ArbError> {
+        let mut stream = self.wait_stream().await?;
+        let started = Instant::now();
+        let mut received = 0u64;
+
+        match start.integrity_type {
+            IntegrityType::Reliable => {
+                let mut buf = vec![0u8; RELIABLE_CHUNK];
+                while received < start.byte_count {
+                    let n = stream.read(&mut buf).await.map_err(UTPError::from)?;
+                    if n == 0 {
+                        break;
+                    }
+                    received += n as u64;
+                }
+                self.recv_benchmark_end().await?;
+            }
+            IntegrityType::Unreliable => loop {
+                tokio::select! {
+                    message = stream.recv_datagram() => received += message?.len() as u64,
+                    end = self.recv_benchmark_end() => break end?,
+                }
+            },
+        }
+
+        let elapsed = started.elapsed();
+        self.write_payload(Payload::Ok).await?;
+
+        Ok(BenchmarkReport {
+            bytes: received,
+            elapsed,
+        })
+    }
+
+    /// Waits for `BenchmarkEnd`.
+    async fn recv_benchmark_end(&self) -> Result<(), ArbError> {
+        match self.read_payload().await? {
+            Payload::BenchmarkEnd => Ok(()),
+            other => Err(ArbError::UnexpectedData(format!(
+                "expected BenchmarkEnd, got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{client::connect, server::accept},
+        schema::IntegrityType,
+        utp::tests::utp::mock_utp_pairs,
+    };
+
+    #[tokio::test]
+    async fn test_reliable_benchmark() {
+        let (a, b) = mock_utp_pairs();
+
+        let server = tokio::spawn(async move {
+            let conn = accept(a.into()).await.unwrap();
+            let arb = conn.next_arb().await.unwrap();
+            let start = arb.recv_benchmark().await.unwrap();
+            assert_eq!(start.byte_count, 300_000);
+            arb.serve_benchmark(start).await.unwrap()
+        });
+
+        let conn = connect(b.into()).await.unwrap();
+        let arb = conn.new_arb();
+        let sent = arb
+            .run_benchmark(IntegrityType::Reliable, 300_000)
+            .await
+            .unwrap();
+        arb.finish();
+
+        let received = server.await.unwrap();
+        assert_eq!(sent.bytes, 300_000);
+        assert_eq!(received.bytes, 300_000);
+    }
+}
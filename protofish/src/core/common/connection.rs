@@ -1,13 +1,42 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::{sync::watch, task::JoinHandle};
 
 use crate::{
+    capture::{Capture, CaptureEvent, CaptureSlot, Direction},
     core::common::{
         arbitrary::{ArbContext, make_arbitrary},
+        deadline::current_deadline,
         pmc::PMC,
+        rate::RateLimiter,
     },
-    utp::UTP,
+    schema::ServerHello,
+    utp::{UTP, UTPEvent, UTPStats, error::UTPError},
 };
 
+/// Lifecycle state of a `Connection`, driven by the transport's events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The connection is usable
+    Open,
+
+    /// The connection was closed gracefully, by either side
+    Closed {
+        /// Reason given by the side that closed the connection
+        reason: String,
+    },
+
+    /// The transport was lost without a graceful close
+    Lost,
+}
+
+impl ConnectionState {
+    /// Returns `true` unless the connection is open.
+    pub fn is_closed(&self) -> bool {
+        !matches!(self, ConnectionState::Open)
+    }
+}
+
 /// Represents an established Protofish connection.
 ///
 /// A `Connection` provides access to the Primary Messaging Channel (PMC) and
@@ -18,6 +47,10 @@ where
     U: UTP,
 {
     utp: Arc<U>,
+    rate_limit: Option<RateLimiter>,
+    state: watch::Receiver<ConnectionState>,
+    watcher: JoinHandle<()>,
+    server_hello: Option<ServerHello>,
 
     /// The Primary Messaging Channel for this connection
     pub pmc: PMC<U::Stream>,
@@ -27,8 +60,73 @@ impl<U> Connection<U>
 where
     U: UTP,
 {
+    /// Wraps a transport and the PMC established on it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime, as a task following the
+    /// events of the transport is spawned.
     pub fn new(utp: Arc<U>, pmc: PMC<U::Stream>) -> Self {
-        Self { utp, pmc }
+        let (state_tx, state) = watch::channel(ConnectionState::Open);
+        let watcher = tokio::spawn(watch_events(utp.clone(), state_tx, pmc.capture_slot()));
+
+        Self {
+            utp,
+            rate_limit: None,
+            state,
+            watcher,
+            server_hello: None,
+            pmc,
+        }
+    }
+
+    /// Keeps the `ServerHello` the server answered the handshake with.
+    pub(crate) fn with_server_hello(mut self, server_hello: ServerHello) -> Self {
+        self.server_hello = Some(server_hello);
+        self
+    }
+
+    /// Returns the `ServerHello` received during the handshake, on the client
+    /// side of a connection.
+    pub fn server_hello(&self) -> Option<&ServerHello> {
+        self.server_hello.as_ref()
+    }
+
+    /// Returns the current state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    /// Waits until the connection is closed or lost.
+    ///
+    /// # Returns
+    ///
+    /// Returns the final state, which is never `ConnectionState::Open`.
+    pub async fn closed(&self) -> ConnectionState {
+        let mut state = self.state.clone();
+
+        match state.wait_for(ConnectionState::is_closed).await {
+            Ok(state) => state.clone(),
+            // the watcher only exits after publishing a final state
+            Err(_) => self.state(),
+        }
+    }
+
+    /// Records the traffic of this connection from now on, see the `capture`
+    /// module.
+    ///
+    /// Messages of the handshake, which happened before, are not recorded.
+    pub fn with_capture(self, capture: Capture) -> Self {
+        self.pmc.set_capture(capture);
+        self
+    }
+
+    /// Caps everything sent through contexts and streams of this connection.
+    ///
+    /// The limit applies to contexts created or accepted afterwards.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
     }
 
     /// Creates a new arbitrary data context for sending messages.
@@ -37,12 +135,22 @@ where
     /// parity rules (even for client-initiated, odd for server-initiated).
     /// Use this to initiate a new conversation.
     ///
+    /// If called while a deadline is in scope (see `ArbContext::scope`), the
+    /// new context inherits it.
+    ///
     /// # Returns
     ///
     /// Returns an `ArbContext` containing a writer and reader for arbitrary binary data.
     pub fn new_arb(&self) -> ArbContext<U> {
         let ctx = self.pmc.create_context();
-        make_arbitrary(self.utp.clone(), ctx)
+        let arb = make_arbitrary(self.utp.clone(), ctx).into_initiator();
+
+        let arb = match current_deadline() {
+            Some(deadline) => arb.with_deadline(deadline),
+            None => arb,
+        };
+
+        self.limited(arb)
     }
 
     /// Waits for the next incoming arbitrary data context from the peer.
@@ -56,6 +164,141 @@ where
     /// the connection is closed.
     pub async fn next_arb(&self) -> Option<ArbContext<U>> {
         let ctx = self.pmc.next_context().await?;
-        Some(make_arbitrary(self.utp.clone(), ctx))
+        Some(self.limited(make_arbitrary(self.utp.clone(), ctx)))
+    }
+
+    /// Closes the connection, telling the peer why.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transport fails to deliver the close.
+    pub async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        self.utp.close(code, reason).await
+    }
+
+    /// Returns the address of the peer, if the transport has one.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.utp.peer_addr()
+    }
+
+    /// Returns the local address of the transport, if it has one.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.utp.local_addr()
+    }
+
+    /// Returns a snapshot of the transport's statistics.
+    pub fn stats(&self) -> UTPStats {
+        self.utp.stats()
+    }
+
+    fn limited(&self, arb: ArbContext<U>) -> ArbContext<U> {
+        match &self.rate_limit {
+            Some(limiter) => arb.with_rate_limit(limiter.clone()),
+            None => arb,
+        }
+    }
+}
+
+impl<U: UTP> Drop for Connection<U> {
+    fn drop(&mut self) {
+        self.watcher.abort();
+    }
+}
+
+/// Follows the transport's events until it closes, publishing the resulting
+/// state.
+async fn watch_events<U: UTP>(
+    utp: Arc<U>,
+    state: watch::Sender<ConnectionState>,
+    capture: CaptureSlot,
+) {
+    loop {
+        let event = utp.next_event().await;
+
+        if let Some(capture) = capture.get() {
+            let recorded = match event {
+                UTPEvent::NewStream(id) => Some(CaptureEvent::StreamOpened(id)),
+                UTPEvent::StreamClosed(id) => Some(CaptureEvent::StreamClosed(id)),
+                UTPEvent::StreamReset { id, code } => Some(CaptureEvent::StreamReset { id, code }),
+                _ => None,
+            };
+            if let Some(recorded) = recorded {
+                capture.record(Direction::Received, recorded);
+            }
+        }
+
+        match event {
+            UTPEvent::Closed { reason } => {
+                tracing::debug!("connection closed: {}", reason);
+                state.send_replace(ConnectionState::Closed { reason });
+                break;
+            }
+            UTPEvent::UnexpectedClose => {
+                tracing::debug!("connection lost");
+                state.send_replace(ConnectionState::Lost);
+                break;
+            }
+            UTPEvent::StreamReset { id, code } => {
+                tracing::debug!("stream {} reset with code {}", id, code);
+            }
+            UTPEvent::PathChanged { remote } => {
+                tracing::debug!("peer address changed to {}", remote);
+            }
+            // streams are picked up through `StreamOpen` payloads on contexts
+            UTPEvent::NewStream(_) | UTPEvent::StreamClosed(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::common::connection::ConnectionState,
+        utp::{UTPEvent, tests::utp::mock_connection_pair},
+    };
+
+    #[tokio::test]
+    async fn test_client_keeps_server_hello() {
+        let (client, server) = mock_connection_pair().await;
+
+        let hello = client.server_hello().unwrap();
+        assert!(hello.ok);
+        assert!(hello.connection_token.is_some());
+        assert!(server.server_hello().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_closed_after_transport_close() {
+        let (_client, server) = mock_connection_pair().await;
+
+        assert_eq!(server.state(), ConnectionState::Open);
+
+        server
+            .utp
+            .add_event(UTPEvent::Closed {
+                reason: "shutting down".into(),
+            })
+            .await;
+
+        let expected = ConnectionState::Closed {
+            reason: "shutting down".into(),
+        };
+        assert_eq!(server.closed().await, expected);
+        assert_eq!(server.state(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_close_reaches_peer() {
+        let (client, server) = mock_connection_pair().await;
+
+        assert_eq!(client.peer_addr(), server.local_addr());
+
+        client.close(0, "bye").await.unwrap();
+
+        let expected = ConnectionState::Closed {
+            reason: "bye".into(),
+        };
+        assert_eq!(server.closed().await, expected);
+        assert_eq!(client.closed().await, expected);
     }
 }
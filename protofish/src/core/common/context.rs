@@ -1,11 +1,15 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::{
+    sync::{Mutex, mpsc::UnboundedReceiver},
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     core::common::error::ConnectionError,
-    internal::pmc_frame::PMCFrame,
-    schema::{ContextId, Message, Payload},
+    internal::pmc_frame::{ContextReceivers, PMCFrame},
+    schema::{ContextId, Message, Payload, StreamId},
     utp::UTPStream,
 };
 
@@ -29,43 +33,144 @@ impl<S: UTPStream> ContextWriter<S> {
     ///
     /// Returns an error if the underlying stream write fails.
     pub async fn write(&self, payload: Payload) -> Result<(), ConnectionError> {
+        self.write_with_budget(payload, None).await
+    }
+
+    /// Writes a payload carrying the remaining time budget of the context.
+    pub(crate) async fn write_with_budget(
+        &self,
+        payload: Payload,
+        budget: Option<Duration>,
+    ) -> Result<(), ConnectionError> {
         self.pmc_frame
             .send_frame(Message {
                 context_id: self.context_id,
                 payload,
+                budget,
             })
             .await
             .map_err(ConnectionError::UTP)
     }
+
+    /// Returns a token cancelled once the peer announces the close of a
+    /// stream of this context.
+    pub(crate) fn watch_stream(&self, stream_id: StreamId) -> CancellationToken {
+        self.pmc_frame.watch_stream(self.context_id, stream_id)
+    }
+
+    pub(crate) fn unwatch_stream(&self, stream_id: StreamId) {
+        self.pmc_frame.unwatch_stream(self.context_id, stream_id);
+    }
+
+    /// Stops routing incoming payloads of this context. Writing stays
+    /// possible.
+    pub(crate) fn release(&self) {
+        self.pmc_frame.release_context(self.context_id);
+    }
+}
+
+impl<S: UTPStream> Clone for ContextWriter<S> {
+    fn clone(&self) -> Self {
+        Self {
+            context_id: self.context_id,
+            pmc_frame: self.pmc_frame.clone(),
+        }
+    }
+}
+
+/// The queues a context's payloads are sorted into.
+///
+/// Each queue preserves the order of its own payloads, so data and stream
+/// opens can be consumed independently of each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContextQueue {
+    /// `ArbitaryData` payloads
+    Data,
+    /// `StreamOpen` payloads
+    Streams,
+    /// Everything else, such as `Ok`, `Error` and stream notices
+    Control,
+}
+
+impl ContextQueue {
+    pub(crate) fn of(payload: &Payload) -> Self {
+        match payload {
+            Payload::ArbitaryData(_) => ContextQueue::Data,
+            Payload::StreamOpen(_) => ContextQueue::Streams,
+            _ => ContextQueue::Control,
+        }
+    }
 }
 
 /// Reader half of a context, used to receive payloads within a specific context.
 ///
-/// Messages received on this context are delivered in order via an unbounded
-/// channel.
+/// Messages received on this context are sorted into data, stream-open and
+/// control queues, each delivered in order via an unbounded channel.
 pub struct ContextReader {
-    pub(crate) receiver: tokio::sync::Mutex<UnboundedReceiver<Payload>>,
+    data: Mutex<UnboundedReceiver<Payload>>,
+    streams: Mutex<UnboundedReceiver<Payload>>,
+    control: Mutex<UnboundedReceiver<Payload>>,
+    pub(crate) cancel: CancellationToken,
+    /// Deadline derived from the budget of the context's first message.
+    pub(crate) deadline: Option<Instant>,
 }
 
 impl ContextReader {
-    /// Reads the next payload from this context.
+    pub(crate) fn new(
+        receivers: ContextReceivers,
+        cancel: CancellationToken,
+        deadline: Option<Instant>,
+    ) -> Self {
+        Self {
+            data: receivers.data.into(),
+            streams: receivers.streams.into(),
+            control: receivers.control.into(),
+            cancel,
+            deadline,
+        }
+    }
+
+    /// Reads the next control payload from this context, i.e. anything other
+    /// than `ArbitaryData` and `StreamOpen`.
     ///
-    /// This method blocks until a message arrives on this context.
+    /// This method blocks until a matching message arrives on this context.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// Returns the next `Payload`, or an error if the context is closed.
+    /// See `read_queue`.
+    pub async fn read_control(&self) -> Result<Payload, ConnectionError> {
+        self.read_queue(ContextQueue::Control).await
+    }
+
+    /// Reads the next control payload from this context.
+    ///
+    /// Data and stream opens are no longer returned here since they got
+    /// queues of their own.
+    #[deprecated(note = "use `read_control`, or `ArbContext` for data and streams")]
+    pub async fn read(&self) -> Result<Payload, ConnectionError> {
+        self.read_control().await
+    }
+
+    /// Reads the next payload from one of the queues of this context.
     ///
     /// # Errors
     ///
-    /// Returns `ConnectionError::ClosedStream` if the context channel is closed.
-    pub async fn read(&self) -> Result<Payload, ConnectionError> {
-        self.receiver
-            .lock()
-            .await
-            .recv()
-            .await
-            .ok_or(ConnectionError::ClosedStream)
+    /// Returns `ConnectionError::ClosedStream` if the context channel is closed,
+    /// or `ConnectionError::Cancelled` once the context is cancelled and no
+    /// payloads received before the cancellation remain in the queue.
+    pub(crate) async fn read_queue(&self, queue: ContextQueue) -> Result<Payload, ConnectionError> {
+        let receiver = match queue {
+            ContextQueue::Data => &self.data,
+            ContextQueue::Streams => &self.streams,
+            ContextQueue::Control => &self.control,
+        };
+        let mut receiver = receiver.lock().await;
+
+        tokio::select! {
+            biased;
+            payload = receiver.recv() => payload.ok_or(ConnectionError::ClosedStream),
+            _ = self.cancel.cancelled() => Err(ConnectionError::Cancelled),
+        }
     }
 }
 
@@ -23,6 +23,12 @@ impl ContextCounter {
             self.counter - 2 // handshake compatible
         }
     }
+
+    /// Returns whether `context_id` is one this counter hands out, i.e. was
+    /// opened by this side.
+    pub fn is_own(&self, context_id: ContextId) -> bool {
+        (context_id % 2 == 1) == self.is_server
+    }
 }
 
 #[cfg(test)]
@@ -35,6 +41,8 @@ mod tests {
 
         assert_eq!(c.next_context_id(), 1);
         assert_eq!(c.next_context_id(), 3);
+        assert!(c.is_own(5));
+        assert!(!c.is_own(4));
     }
 
     #[test]
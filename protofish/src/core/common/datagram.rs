@@ -0,0 +1,88 @@
+use std::{
+    io::ErrorKind,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{Sink, Stream};
+
+use crate::{
+    core::common::stream::{ProtofishReadHalf, ProtofishWriteHalf},
+    utp::{UTPStream, error::UTPError},
+};
+
+/// `Sink` adapter sending each item as one message on a stream.
+///
+/// Created by `ProtofishStream::into_datagrams` or
+/// `ProtofishWriteHalf::into_sink`.
+pub struct DatagramSink {
+    inner: Pin<Box<dyn Sink<Bytes, Error = UTPError> + Send>>,
+}
+
+/// `Stream` adapter yielding each message received on a stream.
+///
+/// The stream ends when the peer closes the underlying UTP stream. After any
+/// other error is yielded, no more items are produced.
+pub struct DatagramStream {
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes, UTPError>> + Send>>,
+}
+
+impl DatagramSink {
+    pub(crate) fn new<S: UTPStream>(write: ProtofishWriteHalf<S>) -> Self {
+        let inner = futures::sink::unfold(write, |mut write, data: Bytes| async move {
+            write.send_datagram(data).await?;
+            Ok::<_, UTPError>(write)
+        });
+
+        Self {
+            inner: Box::pin(inner),
+        }
+    }
+}
+
+impl DatagramStream {
+    pub(crate) fn new<S: UTPStream>(read: ProtofishReadHalf<S>) -> Self {
+        let inner = futures::stream::unfold(Some(read), |read| async move {
+            let mut read = read?;
+
+            match read.recv_datagram().await {
+                Ok(data) => Some((Ok(data), Some(read))),
+                Err(UTPError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+
+        Self {
+            inner: Box::pin(inner),
+        }
+    }
+}
+
+impl Sink<Bytes> for DatagramSink {
+    type Error = UTPError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), UTPError>> {
+        self.inner.as_mut().poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), UTPError> {
+        self.inner.as_mut().start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), UTPError>> {
+        self.inner.as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), UTPError>> {
+        self.inner.as_mut().poll_close(cx)
+    }
+}
+
+impl Stream for DatagramStream {
+    type Item = Result<Bytes, UTPError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
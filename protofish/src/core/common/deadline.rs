@@ -0,0 +1,65 @@
+use std::future::Future;
+
+use tokio::time::Instant;
+
+tokio::task_local! {
+    static DEADLINE: Option<Instant>;
+}
+
+/// Returns the deadline of the request being handled by the current task, if
+/// any.
+///
+/// Contexts opened with `Connection::new_arb` while a deadline is in scope
+/// inherit it, so that it propagates along a chain of requests.
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok().flatten()
+}
+
+/// Runs `future` with `deadline` in scope for `current_deadline`.
+///
+/// Nested scopes can only shorten the deadline: the earlier of the enclosing
+/// deadline and `deadline` applies.
+pub async fn with_deadline<F: Future>(deadline: Option<Instant>, future: F) -> F::Output {
+    let deadline = earliest(current_deadline(), deadline);
+
+    DEADLINE.scope(deadline, future).await
+}
+
+pub(crate) fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::core::common::deadline::{current_deadline, with_deadline};
+
+    #[tokio::test]
+    async fn test_nested_deadline_scopes() {
+        let outer = Instant::now() + Duration::from_secs(5);
+        let inner = Instant::now() + Duration::from_secs(1);
+
+        assert_eq!(current_deadline(), None);
+
+        with_deadline(Some(outer), async {
+            assert_eq!(current_deadline(), Some(outer));
+
+            with_deadline(Some(inner), async {
+                assert_eq!(current_deadline(), Some(inner));
+            })
+            .await;
+
+            with_deadline(Some(outer + Duration::from_secs(1)), async {
+                assert_eq!(current_deadline(), Some(outer));
+            })
+            .await;
+        })
+        .await;
+    }
+}
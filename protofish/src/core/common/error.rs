@@ -13,6 +13,10 @@ pub enum ConnectionError {
     #[error("stream closed")]
     ClosedStream,
 
+    /// The context was cancelled by the peer or locally
+    #[error("context cancelled")]
+    Cancelled,
+
     /// The server rejected the handshake
     #[error("handshake rejected: {0}")]
     HandshakeReject(String),
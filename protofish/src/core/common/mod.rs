@@ -1,7 +1,12 @@
 pub mod arbitrary;
+pub mod benchmark;
 pub mod connection;
 pub mod context;
 pub mod counter;
+pub mod datagram;
+pub mod deadline;
 pub mod error;
 pub mod pmc;
+pub mod rate;
+pub mod sequence;
 pub mod stream;
@@ -1,15 +1,17 @@
 use std::sync::Arc;
 
 use parking_lot::Mutex;
+use tokio::time::Instant;
 
 use crate::{
+    capture::{Capture, CaptureSlot},
     core::common::{
         context::{Context, ContextReader, ContextWriter},
         counter::ContextCounter,
     },
     internal::pmc_frame::PMCFrame,
-    schema::Payload,
-    utp::UTPStream,
+    schema::Message,
+    utp::{UTPStream, error::UTPError},
 };
 
 pub struct PMC<U: UTPStream> {
@@ -24,36 +26,53 @@ where
     pub(crate) fn new(is_server: bool, utp_stream: S) -> Self {
         Self {
             counter: ContextCounter::new(is_server).into(),
-            frame: PMCFrame::new(utp_stream).into(),
+            frame: PMCFrame::new(utp_stream, ContextCounter::new(is_server)).into(),
         }
     }
 
     pub fn create_context(&self) -> Context<S> {
         let context_id = self.counter.lock().next_context_id();
-        self.make_context(context_id, None)
+        let (receivers, cancel) = self.frame.subscribe_context(context_id);
+
+        (
+            self.make_writer(context_id),
+            ContextReader::new(receivers, cancel, None),
+        )
     }
 
-    fn make_context(&self, context_id: u64, initial_payload: Option<Payload>) -> Context<S> {
-        let writer = ContextWriter {
+    fn make_writer(&self, context_id: u64) -> ContextWriter<S> {
+        ContextWriter {
             context_id,
             pmc_frame: self.frame.clone(),
-        };
+        }
+    }
+
+    /// Sends `message` as is, bypassing the context bookkeeping. Used to
+    /// replay captured traffic.
+    pub(crate) async fn send_raw(&self, message: Message) -> Result<(), UTPError> {
+        self.frame.send_frame(message).await
+    }
 
-        let receiver = self.frame.subscribe_context(context_id, initial_payload);
+    pub(crate) fn set_capture(&self, capture: Capture) {
+        self.frame.set_capture(capture);
+    }
 
-        let reader = ContextReader {
-            receiver: receiver.into(),
-        };
+    pub(crate) fn capture_slot(&self) -> CaptureSlot {
+        self.frame.capture_slot()
+    }
 
-        (writer, reader)
+    #[cfg(test)]
+    pub(crate) fn context_count(&self) -> usize {
+        self.frame.context_count()
     }
 
     pub async fn next_context(&self) -> Option<Context<S>> {
-        let msg = self.frame.next_context_message().await?;
+        let incoming = self.frame.next_context().await?;
 
-        let ctx = self.make_context(msg.context_id, Some(msg.payload));
+        let deadline = incoming.budget.map(|budget| Instant::now() + budget);
+        let reader = ContextReader::new(incoming.receivers, incoming.cancel, deadline);
 
-        Some(ctx)
+        Some((self.make_writer(incoming.context_id), reader))
     }
 }
 
@@ -61,7 +80,9 @@ where
 mod tests {
 
     use crate::{
-        core::common::pmc::PMC, schema::Payload, utp::tests::stream::mock_utp_stream_pairs,
+        core::common::pmc::PMC,
+        schema::{Cancel, Message, Payload},
+        utp::tests::stream::mock_utp_stream_pairs,
     };
 
     #[tokio::test]
@@ -75,12 +96,39 @@ mod tests {
         b_tx.write(Payload::Ok).await.unwrap();
 
         let (a_tx, rx) = pmc_a.next_context().await.unwrap();
-        let p = rx.read().await.unwrap();
+        let p = rx.read_control().await.unwrap();
 
         assert!(matches!(p, Payload::Ok));
 
         a_tx.write(Payload::Keepalive).await.unwrap();
-        let ba = b_rx.read().await.unwrap();
+        let ba = b_rx.read_control().await.unwrap();
         assert!(matches!(ba, Payload::Keepalive));
     }
+
+    #[tokio::test]
+    async fn test_cancel_for_unknown_context_is_dropped() {
+        let (a, b) = mock_utp_stream_pairs(0);
+
+        let pmc_a = PMC::new(true, a);
+        let pmc_b = PMC::new(false, b);
+
+        for context_id in 100..110 {
+            pmc_b
+                .send_raw(Message {
+                    context_id,
+                    payload: Payload::Cancel(Cancel {
+                        reason: "muffin".into(),
+                    }),
+                    budget: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let (b_tx, _b_rx) = pmc_b.create_context();
+        b_tx.write(Payload::Ok).await.unwrap();
+        pmc_a.next_context().await.unwrap();
+
+        assert_eq!(pmc_a.context_count(), 1);
+    }
 }
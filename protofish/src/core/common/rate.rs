@@ -0,0 +1,166 @@
+use std::{sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+/// A token-bucket rate limiter counting bytes.
+///
+/// Cloning a `RateLimiter` shares the bucket, so a single limiter can cap
+/// several streams or contexts together. Sends are admitted as long as the
+/// bucket is not in debt; each send then takes its size from the bucket, so a
+/// message larger than the burst is allowed through and paid for afterwards.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter refilling at `bytes_per_second`, holding at most
+    /// `burst` bytes of credit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    pub fn new(bytes_per_second: u64, burst: u64) -> Self {
+        assert!(bytes_per_second > 0, "rate must be greater than zero");
+
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate: bytes_per_second as f64,
+                burst: burst as f64,
+                tokens: burst as f64,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Creates a limiter without burst credit, spacing sends evenly so that
+    /// each one waits for the previous to be paid off.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    pub fn pacing(bytes_per_second: u64) -> Self {
+        Self::new(bytes_per_second, 0)
+    }
+
+    /// Takes `bytes` from the bucket and returns how long the caller must
+    /// wait before sending them.
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock();
+        bucket.refill();
+
+        let wait = if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        } else {
+            Duration::ZERO
+        };
+
+        bucket.tokens -= bytes as f64;
+
+        wait
+    }
+
+    /// Waits until `bytes` may be sent.
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Returns bytes reserved but not sent to the bucket.
+    pub(crate) fn refund(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock();
+        bucket.tokens = (bucket.tokens + bytes as f64).min(bucket.burst);
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+}
+
+/// The chain of limiters a sender is subject to, e.g. stream, context and
+/// connection.
+#[derive(Clone, Default)]
+pub(crate) struct RateLimits(Vec<RateLimiter>);
+
+impl RateLimits {
+    pub(crate) fn push(&mut self, limiter: RateLimiter) {
+        self.0.push(limiter);
+    }
+
+    pub(crate) fn extend(&mut self, other: RateLimits) {
+        self.0.extend(other.0);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reserves `bytes` on every limiter, returning the longest wait.
+    pub(crate) fn reserve(&self, bytes: usize) -> Duration {
+        self.0
+            .iter()
+            .map(|limiter| limiter.reserve(bytes))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    pub(crate) fn refund(&self, bytes: usize) {
+        if bytes > 0 {
+            self.0.iter().for_each(|limiter| limiter.refund(bytes));
+        }
+    }
+
+    pub(crate) async fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::core::common::rate::{RateLimiter, RateLimits};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_reserve() {
+        let limiter = RateLimiter::new(1000, 1000);
+
+        assert_eq!(limiter.reserve(1000), Duration::ZERO);
+        assert_eq!(limiter.reserve(500), Duration::ZERO);
+        assert_eq!(limiter.reserve(1), Duration::from_millis(500));
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(limiter.reserve(1), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limits_take_longest_wait() {
+        let mut limits = RateLimits::default();
+        limits.push(RateLimiter::pacing(1000));
+        limits.push(RateLimiter::pacing(100));
+
+        assert_eq!(limits.reserve(100), Duration::ZERO);
+        assert_eq!(limits.reserve(100), Duration::from_secs(1));
+    }
+}
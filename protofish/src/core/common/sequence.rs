@@ -0,0 +1,334 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::time::Instant;
+
+use crate::{
+    core::common::{
+        error::ConnectionError,
+        stream::{ProtofishReadHalf, ProtofishWriteHalf},
+    },
+    schema::{Payload, StreamId, StreamReport},
+    utp::{UTPStream, error::UTPError},
+};
+
+/// Size of the header prepended to every sequenced message.
+///
+/// Layout: `[sequence: u64 le][sent_at_micros: u64 le]`.
+pub const SEQUENCE_HEADER_LEN: usize = 16;
+
+/// Number of sequence numbers behind the highest one for which duplicates
+/// can still be detected.
+const REPLAY_WINDOW: u64 = 64;
+
+/// A message received on a sequenced stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequencedMessage {
+    /// Sequence number stamped by the sender, starting from zero.
+    pub sequence: u64,
+    /// Sender timestamp in microseconds since the sender started sequencing.
+    pub sent_at_micros: u64,
+    /// Message content.
+    pub content: Bytes,
+}
+
+/// Write half of a stream that stamps each message with a sequence number
+/// and a timestamp.
+///
+/// Created with `ProtofishWriteHalf::sequenced`.
+pub struct SequencedWriteHalf<S: UTPStream> {
+    inner: ProtofishWriteHalf<S>,
+    next_sequence: u64,
+    epoch: Instant,
+}
+
+/// Read half of a sequenced stream, tracking loss, duplicates, reordering
+/// and jitter of the messages it receives.
+///
+/// Created with `ProtofishReadHalf::sequenced`.
+pub struct SequencedReadHalf<S: UTPStream> {
+    inner: ProtofishReadHalf<S>,
+    tracker: SequenceTracker,
+}
+
+/// Receiver statistics, updated for every message that arrives.
+#[derive(Debug, Default)]
+struct SequenceTracker {
+    epoch: Option<Instant>,
+    highest: Option<u64>,
+    /// Bit `n` is set if `highest - n` has been received.
+    window: u64,
+    received: u64,
+    duplicates: u64,
+    reordered: u64,
+    last_transit: Option<i64>,
+    jitter: f64,
+}
+
+impl<S: UTPStream> SequencedWriteHalf<S> {
+    pub(crate) fn new(inner: ProtofishWriteHalf<S>) -> Self {
+        Self {
+            inner,
+            next_sequence: 0,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Returns the identifier of the underlying stream.
+    pub fn id(&self) -> StreamId {
+        self.inner.id()
+    }
+
+    /// Sends a message stamped with the next sequence number.
+    ///
+    /// # Returns
+    ///
+    /// Returns the sequence number assigned to the message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying `send_datagram` fails. The sequence
+    /// number is consumed either way, so a failed send shows up as a loss.
+    pub async fn send(&mut self, content: Bytes) -> Result<u64, UTPError> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let mut buf = BytesMut::with_capacity(SEQUENCE_HEADER_LEN + content.len());
+        buf.put_u64_le(sequence);
+        buf.put_u64_le(self.epoch.elapsed().as_micros() as u64);
+        buf.put_slice(&content);
+
+        self.inner.send_datagram(buf.freeze()).await?;
+
+        Ok(sequence)
+    }
+
+    /// Unwraps the underlying write half.
+    pub fn into_inner(self) -> ProtofishWriteHalf<S> {
+        self.inner
+    }
+}
+
+impl<S: UTPStream> SequencedReadHalf<S> {
+    pub(crate) fn new(inner: ProtofishReadHalf<S>) -> Self {
+        Self {
+            inner,
+            tracker: SequenceTracker::default(),
+        }
+    }
+
+    /// Returns the identifier of the underlying stream.
+    pub fn id(&self) -> StreamId {
+        self.inner.id()
+    }
+
+    /// Receives the next message and updates the statistics.
+    ///
+    /// Duplicates of recently received messages are counted and skipped.
+    /// Duplicates older than the 64-message detection window cannot be told
+    /// apart from late arrivals and are returned as reordered messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream is closed, the transport fails, or the
+    /// message is too short to carry a sequence header.
+    pub async fn recv(&mut self) -> Result<SequencedMessage, UTPError> {
+        loop {
+            let mut data = self.inner.recv_datagram().await?;
+
+            if data.len() < SEQUENCE_HEADER_LEN {
+                return Err(UTPError::Warn(format!(
+                    "sequenced message of {} bytes is shorter than its header",
+                    data.len()
+                )));
+            }
+
+            let sequence = data.get_u64_le();
+            let sent_at_micros = data.get_u64_le();
+
+            if self
+                .tracker
+                .record(sequence, sent_at_micros, Instant::now())
+            {
+                return Ok(SequencedMessage {
+                    sequence,
+                    sent_at_micros,
+                    content: data,
+                });
+            }
+        }
+    }
+
+    /// Returns a snapshot of the statistics collected so far.
+    pub fn report(&self) -> StreamReport {
+        self.tracker.report(self.inner.id())
+    }
+
+    /// Sends the current statistics to the peer on the owning context, as a
+    /// `StreamReport` payload.
+    ///
+    /// The sender picks it up with `ArbContext::recv_report`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the context write fails.
+    pub async fn send_report(&self) -> Result<(), ConnectionError> {
+        self.inner
+            .context()
+            .write(Payload::StreamReport(self.report()))
+            .await
+    }
+
+    /// Unwraps the underlying read half, discarding the statistics.
+    pub fn into_inner(self) -> ProtofishReadHalf<S> {
+        self.inner
+    }
+}
+
+impl SequenceTracker {
+    /// Records an arrival. Returns `false` if the message is a duplicate.
+    fn record(&mut self, sequence: u64, sent_at_micros: u64, now: Instant) -> bool {
+        match self.highest {
+            None => {
+                self.highest = Some(sequence);
+                self.window = 1;
+            }
+            Some(highest) if sequence > highest => {
+                let shift = sequence - highest;
+                self.window = if shift >= REPLAY_WINDOW {
+                    0
+                } else {
+                    self.window << shift
+                };
+                self.window |= 1;
+                self.highest = Some(sequence);
+            }
+            Some(highest) => {
+                let offset = highest - sequence;
+
+                if offset < REPLAY_WINDOW {
+                    if self.window & (1 << offset) != 0 {
+                        self.duplicates += 1;
+                        return false;
+                    }
+                    self.window |= 1 << offset;
+                }
+
+                self.reordered += 1;
+            }
+        }
+
+        self.received += 1;
+        self.update_jitter(sent_at_micros, now);
+
+        true
+    }
+
+    /// Interarrival jitter estimate as defined in RFC 3550, section 6.4.1.
+    ///
+    /// Only differences between transit times are used, so the sender and
+    /// receiver clocks do not need to be synchronized.
+    fn update_jitter(&mut self, sent_at_micros: u64, now: Instant) {
+        let epoch = *self.epoch.get_or_insert(now);
+        let arrival = now.duration_since(epoch).as_micros() as i64;
+        let transit = arrival - sent_at_micros as i64;
+
+        if let Some(last_transit) = self.last_transit {
+            let d = (transit - last_transit).abs() as f64;
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+
+        self.last_transit = Some(transit);
+    }
+
+    fn report(&self, stream_id: StreamId) -> StreamReport {
+        let expected = self.highest.map(|h| h + 1).unwrap_or(0);
+
+        StreamReport {
+            stream_id,
+            highest_sequence: self.highest.unwrap_or(0),
+            received: self.received,
+            lost: expected.saturating_sub(self.received),
+            duplicates: self.duplicates,
+            reordered: self.reordered,
+            jitter_micros: self.jitter as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::Instant;
+
+    use crate::{
+        core::common::sequence::SequenceTracker, schema::IntegrityType,
+        utp::tests::utp::mock_connection_pair,
+    };
+
+    #[test]
+    fn test_tracker_loss_duplicates_reorder() {
+        let mut tracker = SequenceTracker::default();
+        let now = Instant::now();
+
+        for sequence in [0, 1, 3, 2, 2, 6] {
+            tracker.record(sequence, 0, now);
+        }
+
+        let report = tracker.report(1);
+        assert_eq!(report.highest_sequence, 6);
+        assert_eq!(report.received, 5);
+        assert_eq!(report.lost, 2);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.reordered, 1);
+    }
+
+    #[test]
+    fn test_tracker_jitter() {
+        let mut tracker = SequenceTracker::default();
+        let start = Instant::now();
+
+        tracker.record(0, 0, start);
+        tracker.record(1, 20_000, start + Duration::from_millis(20));
+        assert_eq!(tracker.report(1).jitter_micros, 0);
+
+        tracker.record(2, 40_000, start + Duration::from_millis(56));
+        assert_eq!(tracker.report(1).jitter_micros, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_sequenced_stream_report() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let stream = client_arb
+            .new_stream(IntegrityType::Unreliable)
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let peer = server_arb.wait_stream().await.unwrap();
+
+        let (write, _read) = stream.split();
+        let (_peer_write, peer_read) = peer.split();
+        let mut write = write.sequenced();
+        let mut peer_read = peer_read.sequenced();
+
+        for content in [&b"muffin"[..], b"is", b"cute"] {
+            write.send(Bytes::copy_from_slice(content)).await.unwrap();
+        }
+
+        for (sequence, content) in [&b"muffin"[..], b"is", b"cute"].into_iter().enumerate() {
+            let message = peer_read.recv().await.unwrap();
+            assert_eq!(message.sequence, sequence as u64);
+            assert_eq!(message.content, content);
+        }
+
+        peer_read.send_report().await.unwrap();
+
+        let report = client_arb.recv_report().await.unwrap();
+        assert_eq!(report.stream_id, write.id());
+        assert_eq!(report.received, 3);
+        assert_eq!(report.lost, 0);
+    }
+}
@@ -1,16 +1,652 @@
-use crate::utp::UTPStream;
+use std::{
+    fmt,
+    future::Future,
+    io::Result as IoResult,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
 
-pub struct ProtofishStream<U: UTPStream> {
-    stream: U,
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    core::common::{
+        context::ContextWriter,
+        datagram::{DatagramSink, DatagramStream},
+        rate::{RateLimiter, RateLimits},
+        sequence::{SequencedReadHalf, SequencedWriteHalf},
+    },
+    schema::{IntegrityType, Payload, StreamClose, StreamId},
+    utp::{UTPStream, error::UTPError},
+};
+
+/// A Protofish stream opened within a context.
+///
+/// `ProtofishStream` implements `AsyncRead` and `AsyncWrite` directly, and can
+/// be split into owned halves that may be moved to different tasks and later
+/// reunited. Once the stream and all of its halves are dropped, a
+/// `StreamClose` payload is sent on the owning context, which completes
+/// `peer_closed` on the peer's side of the stream.
+pub struct ProtofishStream<S: UTPStream> {
+    write: ProtofishWriteHalf<S>,
+    read: ProtofishReadHalf<S>,
+}
+
+/// Owned write half of a `ProtofishStream`.
+pub struct ProtofishWriteHalf<S: UTPStream> {
+    inner: S::StreamWrite,
+    shared: Arc<StreamShared<S>>,
+    limits: RateLimits,
+    reserved: usize,
+    delay: Option<Pin<Box<Sleep>>>,
 }
 
-impl<U: UTPStream> ProtofishStream<U> {
-    pub(crate) fn new(stream: U) -> Self {
-        Self { stream }
+/// Largest number of bytes reserved from the rate limiters by a single
+/// `poll_write`, so that large writes are spread out rather than sent in one
+/// burst after a long wait.
+const MAX_WRITE_RESERVATION: usize = 16 * 1024;
+
+/// Options applied to a stream when it is opened.
+#[derive(Clone, Default)]
+pub struct StreamOptions {
+    rate_limit: Option<RateLimiter>,
+    pacing: Option<u64>,
+}
+
+impl StreamOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps what the stream sends, in addition to any context or connection
+    /// limits.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Spreads sends evenly at `bytes_per_second` instead of letting them
+    /// go out in bursts. Intended for `Unreliable` streams, where bursts
+    /// overflow queues along the path and the excess is silently dropped.
+    pub fn pacing(mut self, bytes_per_second: u64) -> Self {
+        self.pacing = Some(bytes_per_second);
+        self
+    }
+}
+
+/// Owned read half of a `ProtofishStream`.
+pub struct ProtofishReadHalf<S: UTPStream> {
+    inner: S::StreamRead,
+    shared: Arc<StreamShared<S>>,
+}
+
+/// State shared by both halves of a stream.
+///
+/// Dropping the last reference announces the close to the peer.
+struct StreamShared<S: UTPStream> {
+    id: StreamId,
+    integrity_type: IntegrityType,
+    context: ContextWriter<S>,
+    /// Cancelled when the peer announces the close of the stream
+    peer_closed: CancellationToken,
+}
+
+impl<S: UTPStream> ProtofishStream<S> {
+    pub(crate) fn new(
+        stream: S,
+        context: ContextWriter<S>,
+        inherited: RateLimits,
+        options: StreamOptions,
+    ) -> Self {
+        let shared = Arc::new(StreamShared {
+            id: stream.id(),
+            integrity_type: stream.integrity_type(),
+            peer_closed: context.watch_stream(stream.id()),
+            context,
+        });
+
+        let mut limits = RateLimits::default();
+        if let Some(bytes_per_second) = options.pacing {
+            limits.push(RateLimiter::pacing(bytes_per_second));
+        }
+        if let Some(limiter) = options.rate_limit {
+            limits.push(limiter);
+        }
+        limits.extend(inherited);
+
+        let (write, read) = stream.split();
+
+        Self {
+            write: ProtofishWriteHalf {
+                inner: write,
+                shared: shared.clone(),
+                limits,
+                reserved: 0,
+                delay: None,
+            },
+            read: ProtofishReadHalf {
+                inner: read,
+                shared,
+            },
+        }
     }
 
+    /// Returns the identifier of this stream.
+    pub fn id(&self) -> StreamId {
+        self.read.shared.id
+    }
+
+    /// Returns the integrity type this stream was opened with.
+    pub fn integrity_type(&self) -> IntegrityType {
+        self.read.shared.integrity_type.clone()
+    }
+
+    /// Splits the stream into owned write and read halves.
+    ///
+    /// The halves can be reunited with `reunite`. The stream is considered
+    /// closed only once both halves are dropped.
     #[inline(always)]
-    pub fn split(self) -> (U::StreamWrite, U::StreamRead) {
-        self.stream.split()
+    pub fn split(self) -> (ProtofishWriteHalf<S>, ProtofishReadHalf<S>) {
+        (self.write, self.read)
+    }
+
+    /// Sends a single message, preserving its boundary.
+    ///
+    /// See `ProtofishWriteHalf::send_datagram`.
+    pub async fn send_datagram(&mut self, data: Bytes) -> Result<(), UTPError> {
+        self.write.send_datagram(data).await
+    }
+
+    /// Receives a single message sent with `send_datagram`.
+    ///
+    /// See `ProtofishReadHalf::recv_datagram`.
+    pub async fn recv_datagram(&mut self) -> Result<Bytes, UTPError> {
+        self.read.recv_datagram().await
+    }
+
+    /// Completes once the peer has dropped its side of the stream.
+    ///
+    /// See `ProtofishReadHalf::peer_closed`.
+    pub async fn peer_closed(&self) {
+        self.read.peer_closed().await
+    }
+
+    /// Converts the stream into a message `Sink` and `Stream` pair.
+    pub fn into_datagrams(self) -> (DatagramSink, DatagramStream) {
+        (self.write.into_sink(), self.read.into_stream())
+    }
+
+    /// Adds a rate limit to what this stream sends.
+    ///
+    /// See `ProtofishWriteHalf::with_rate_limit`.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.write = self.write.with_rate_limit(limiter);
+        self
+    }
+}
+
+impl<S: UTPStream> ProtofishWriteHalf<S> {
+    /// Returns the identifier of the stream this half belongs to.
+    pub fn id(&self) -> StreamId {
+        self.shared.id
+    }
+
+    /// Returns the integrity type of the stream this half belongs to.
+    pub fn integrity_type(&self) -> IntegrityType {
+        self.shared.integrity_type.clone()
+    }
+
+    /// Sends a single message, preserving its boundary.
+    ///
+    /// This is the natural way to use `Unreliable` streams, where a partially
+    /// delivered byte range is meaningless: each message is either delivered
+    /// whole or not at all. On `Reliable` streams the boundary is kept by the
+    /// transport's framing. Mixing messages and raw `AsyncWrite` bytes on the
+    /// same stream is not supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message exceeds what the transport can carry
+    /// or the write fails.
+    pub async fn send_datagram(&mut self, data: Bytes) -> Result<(), UTPError> {
+        self.limits.acquire(data.len()).await;
+        S::send_datagram(&mut self.inner, data).await
+    }
+
+    /// Adds a rate limit to what this half sends, on top of the limits
+    /// inherited from its context and connection.
+    ///
+    /// The limiter may be shared with other streams to cap them together.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limits.push(limiter);
+        self
+    }
+
+    /// Converts this half into a `Sink` of messages.
+    pub fn into_sink(self) -> DatagramSink {
+        DatagramSink::new(self)
+    }
+
+    /// Wraps this half so that every message carries a sequence number and
+    /// a timestamp, for use with a `SequencedReadHalf` on the peer.
+    pub fn sequenced(self) -> SequencedWriteHalf<S> {
+        SequencedWriteHalf::new(self)
+    }
+
+    /// Reunites this half with the read half it was split from.
+    ///
+    /// # Errors
+    ///
+    /// Returns both halves back in a `ReuniteError` if they did not originate
+    /// from the same stream.
+    pub fn reunite(
+        self,
+        read: ProtofishReadHalf<S>,
+    ) -> Result<ProtofishStream<S>, ReuniteError<S>> {
+        if Arc::ptr_eq(&self.shared, &read.shared) {
+            Ok(ProtofishStream { write: self, read })
+        } else {
+            Err(ReuniteError(self, read))
+        }
+    }
+}
+
+impl<S: UTPStream> ProtofishReadHalf<S> {
+    /// Returns the identifier of the stream this half belongs to.
+    pub fn id(&self) -> StreamId {
+        self.shared.id
+    }
+
+    /// Returns the integrity type of the stream this half belongs to.
+    pub fn integrity_type(&self) -> IntegrityType {
+        self.shared.integrity_type.clone()
+    }
+
+    /// Receives a single message sent with `send_datagram`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream is closed or the transport fails.
+    pub async fn recv_datagram(&mut self) -> Result<Bytes, UTPError> {
+        S::recv_datagram(&mut self.inner).await
+    }
+
+    /// Completes once the peer has dropped its side of the stream, as
+    /// announced by its `StreamClose` payload.
+    ///
+    /// Data sent before the close may still be waiting to be read. A close
+    /// announced before this side of the stream was accepted is not seen.
+    pub async fn peer_closed(&self) {
+        self.shared.peer_closed.cancelled().await
+    }
+
+    /// Converts this half into a `Stream` of messages.
+    pub fn into_stream(self) -> DatagramStream {
+        DatagramStream::new(self)
+    }
+
+    /// Wraps this half to receive messages sent by a `SequencedWriteHalf`,
+    /// tracking loss, duplicates, reordering and jitter.
+    pub fn sequenced(self) -> SequencedReadHalf<S> {
+        SequencedReadHalf::new(self)
+    }
+
+    /// Returns the writer of the context this stream was opened on.
+    pub(crate) fn context(&self) -> &ContextWriter<S> {
+        &self.shared.context
+    }
+
+    /// Reunites this half with the write half it was split from.
+    ///
+    /// # Errors
+    ///
+    /// Returns both halves back in a `ReuniteError` if they did not originate
+    /// from the same stream.
+    pub fn reunite(
+        self,
+        write: ProtofishWriteHalf<S>,
+    ) -> Result<ProtofishStream<S>, ReuniteError<S>> {
+        write.reunite(self)
+    }
+}
+
+/// Error returned when reuniting halves that belong to different streams.
+pub struct ReuniteError<S: UTPStream>(pub ProtofishWriteHalf<S>, pub ProtofishReadHalf<S>);
+
+impl<S: UTPStream> fmt::Debug for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReuniteError")
+            .field(&self.0.id())
+            .field(&self.1.id())
+            .finish()
+    }
+}
+
+impl<S: UTPStream> fmt::Display for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tried to reunite halves of streams {} and {}",
+            self.0.id(),
+            self.1.id()
+        )
+    }
+}
+
+impl<S: UTPStream> std::error::Error for ReuniteError<S> {}
+
+impl<S: UTPStream> Drop for StreamShared<S> {
+    fn drop(&mut self) {
+        self.context.unwatch_stream(self.id);
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let context = self.context.clone();
+        let stream_id = self.id;
+
+        handle.spawn(async move {
+            if let Err(e) = context
+                .write(Payload::StreamClose(StreamClose { stream_id }))
+                .await
+            {
+                tracing::debug!("failed to announce close of stream {}: {}", stream_id, e);
+            }
+        });
+    }
+}
+
+impl<S: UTPStream> AsyncRead for ProtofishReadHalf<S> {
+    #[inline(always)]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: UTPStream> AsyncWrite for ProtofishWriteHalf<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+        let this = &mut *self;
+
+        if this.limits.is_empty() || buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+
+        if this.reserved == 0 {
+            this.reserved = buf.len().min(MAX_WRITE_RESERVATION);
+
+            let wait = this.limits.reserve(this.reserved);
+            if !wait.is_zero() {
+                this.delay = Some(Box::pin(tokio::time::sleep(wait)));
+            }
+        }
+
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+
+        let len = this.reserved.min(buf.len());
+        let result = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]));
+
+        let written = *result.as_ref().unwrap_or(&0);
+        this.limits.refund(this.reserved - written);
+        this.reserved = 0;
+
+        Poll::Ready(result)
+    }
+
+    #[inline(always)]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    #[inline(always)]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: UTPStream> AsyncRead for ProtofishStream<S> {
+    #[inline(always)]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.read).poll_read(cx, buf)
+    }
+}
+
+impl<S: UTPStream> AsyncWrite for ProtofishStream<S> {
+    #[inline(always)]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+        Pin::new(&mut self.write).poll_write(cx, buf)
+    }
+
+    #[inline(always)]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.write).poll_flush(cx)
+    }
+
+    #[inline(always)]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Pin::new(&mut self.write).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::Instant,
+    };
+
+    use crate::{
+        core::common::stream::{ProtofishStream, ReuniteError, StreamOptions},
+        schema::IntegrityType,
+        utp::tests::utp::mock_connection_pair,
+    };
+
+    #[tokio::test]
+    async fn test_stream_read_write() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let mut stream: ProtofishStream<_> = client_arb
+            .new_stream(IntegrityType::Reliable)
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let mut peer = server_arb.wait_stream().await.unwrap();
+
+        assert_eq!(stream.id(), peer.id());
+        assert_eq!(peer.integrity_type(), IntegrityType::Reliable);
+
+        stream.write_all(b"muffin").await.unwrap();
+
+        let mut got = [0u8; 6];
+        peer.read_exact(&mut got).await.unwrap();
+        assert_eq!(&got, b"muffin");
+    }
+
+    #[tokio::test]
+    async fn test_split_reunite() {
+        let (client, _server) = mock_connection_pair().await;
+
+        let arb = client.new_arb();
+        let first = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+        let second = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+        let (first_write, first_read) = first.split();
+        let (second_write, second_read) = second.split();
+
+        let Err(ReuniteError(first_write, second_read)) = first_write.reunite(second_read) else {
+            panic!("reunited halves of different streams");
+        };
+
+        assert!(first_write.reunite(first_read).is_ok());
+        assert!(second_read.reunite(second_write).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_close_on_drop() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let stream = client_arb
+            .new_stream(IntegrityType::Reliable)
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let peer = server_arb.wait_stream().await.unwrap();
+
+        let (write, read) = stream.split();
+        drop(write);
+        drop(read);
+
+        tokio::time::timeout(Duration::from_secs(1), peer.peer_closed())
+            .await
+            .unwrap();
+
+        // the notice is consumed by the stream, not queued on the context
+        let control =
+            tokio::time::timeout(Duration::from_millis(50), server_arb.read_payload()).await;
+        assert!(control.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_close_of_unwatched_stream_dropped() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        client_arb
+            .write(Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        let server_arb = server.next_arb().await.unwrap();
+
+        // never accepted by the peer
+        drop(
+            client_arb
+                .new_stream(IntegrityType::Reliable)
+                .await
+                .unwrap(),
+        );
+
+        let control =
+            tokio::time::timeout(Duration::from_millis(50), server_arb.read_payload()).await;
+        assert!(control.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_datagrams() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let mut stream = client_arb
+            .new_stream(IntegrityType::Unreliable)
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let mut peer = server_arb.wait_stream().await.unwrap();
+
+        let messages = [
+            Bytes::from_static(b"muffin"),
+            Bytes::new(),
+            Bytes::from(vec![7u8; 4000]),
+        ];
+
+        let sent = messages.clone();
+        tokio::spawn(async move {
+            for message in sent {
+                stream.send_datagram(message).await.unwrap();
+            }
+        });
+
+        for message in messages.iter() {
+            assert_eq!(&peer.recv_datagram().await.unwrap(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_datagram_sink_stream() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let stream = client_arb
+            .new_stream(IntegrityType::Unreliable)
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let peer = server_arb.wait_stream().await.unwrap();
+
+        let (mut sink, _) = stream.into_datagrams();
+        let (_, mut incoming) = peer.into_datagrams();
+
+        sink.send(Bytes::from_static(b"muffin")).await.unwrap();
+        sink.send(Bytes::from_static(b"is")).await.unwrap();
+
+        assert_eq!(
+            incoming.next().await.unwrap().unwrap(),
+            Bytes::from_static(b"muffin")
+        );
+        assert_eq!(
+            incoming.next().await.unwrap().unwrap(),
+            Bytes::from_static(b"is")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_paced_write() {
+        let (client, server) = mock_connection_pair().await;
+
+        let client_arb = client.new_arb();
+        let mut stream = client_arb
+            .new_stream_with(IntegrityType::Unreliable, StreamOptions::new().pacing(1000))
+            .await
+            .unwrap();
+
+        let server_arb = server.next_arb().await.unwrap();
+        let mut peer = server_arb.wait_stream().await.unwrap();
+
+        let reader = tokio::spawn(async move {
+            let mut got = vec![0u8; 3000];
+            peer.read_exact(&mut got).await.unwrap();
+        });
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            stream.write_all(&[1u8; 1000]).await.unwrap();
+        }
+        reader.await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(3));
     }
 }
@@ -1,5 +1,6 @@
 pub mod client;
 pub mod common;
+pub mod pubsub;
 pub mod server;
 
 #[cfg(test)]
@@ -0,0 +1,362 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::future::join_all;
+
+use crate::{
+    core::{
+        common::{arbitrary::ArbContext, connection::Connection},
+        pubsub::{
+            error::PubSubError,
+            request::{PubSubRequest, validate_topic},
+        },
+    },
+    schema::{Error, ErrorType, Payload},
+    utp::UTP,
+};
+
+type SubscriberMap<U> = DashMap<u64, Arc<ArbContext<U>>>;
+
+/// A topic broker that fans published messages out to subscribers.
+///
+/// A single broker can serve any number of connections. Every subscription
+/// is bound to the context it was requested on, so delivery to one
+/// subscriber never blocks behind another subscriber's context.
+pub struct Broker<U: UTP> {
+    topics: DashMap<String, SubscriberMap<U>>,
+    next_subscriber_id: AtomicU64,
+}
+
+impl<U: UTP> Default for Broker<U> {
+    fn default() -> Self {
+        Self {
+            topics: DashMap::new(),
+            next_subscriber_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<U: UTP> Broker<U> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves pub/sub requests arriving on a connection.
+    ///
+    /// Every incoming context on the connection is treated as a pub/sub
+    /// request and handled on its own task. This method returns once the
+    /// connection stops yielding contexts.
+    pub async fn serve(self: &Arc<Self>, conn: &Connection<U>) {
+        while let Some(arb) = conn.next_arb().await {
+            let broker = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(e) = broker.handle(arb).await {
+                    tracing::warn!("pub/sub request failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Handles a single pub/sub request context.
+    ///
+    /// For subscriptions, this method keeps running until the subscriber
+    /// unsubscribes or its context is closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is malformed or rejected. In both
+    /// cases an `Error` payload has already been sent back to the peer.
+    pub async fn handle(&self, arb: ArbContext<U>) -> Result<(), PubSubError> {
+        let request = match PubSubRequest::decode(arb.read().await?) {
+            Ok(request) => request,
+            Err(e) => return reject(&arb, e).await,
+        };
+
+        match request {
+            PubSubRequest::Subscribe { topic } => {
+                if let Err(e) = validate_topic(&topic) {
+                    return reject(&arb, e).await;
+                }
+
+                let arb = Arc::new(arb);
+                let id = self.insert(&topic, arb.clone());
+
+                let result = arb.write_payload(Payload::Ok).await;
+                if result.is_ok() {
+                    watch_subscription(&arb).await;
+                }
+
+                self.remove(&topic, id);
+                Ok(result?)
+            }
+            PubSubRequest::Publish { topic, content } => {
+                if let Err(e) = validate_topic(&topic) {
+                    return reject(&arb, e).await;
+                }
+
+                self.publish(&topic, content).await?;
+                arb.write_payload(Payload::Ok).await?;
+
+                Ok(())
+            }
+            PubSubRequest::Unsubscribe => {
+                reject(
+                    &arb,
+                    PubSubError::Malformed("unsubscribe outside of a subscription".into()),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Publishes a message to every current subscriber of a topic.
+    ///
+    /// The message is written to all subscribers concurrently, so a slow
+    /// subscriber delays the return of this method but not delivery to the
+    /// others. Subscribers whose contexts can no longer be written to are
+    /// dropped.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of subscribers the message was delivered to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PubSubError::InvalidTopic` if the topic is not valid.
+    pub async fn publish(&self, topic: &str, content: Bytes) -> Result<usize, PubSubError> {
+        validate_topic(topic)?;
+
+        let subscribers: Vec<(u64, Arc<ArbContext<U>>)> = match self.topics.get(topic) {
+            Some(map) => map.iter().map(|e| (*e.key(), e.value().clone())).collect(),
+            None => return Ok(0),
+        };
+
+        let results = join_all(subscribers.into_iter().map(|(id, arb)| {
+            let content = content.clone();
+            async move { (id, arb.write(content).await) }
+        }))
+        .await;
+
+        let mut delivered = 0;
+        for (id, result) in results {
+            if let Err(e) = result {
+                tracing::warn!("dropping subscriber of {}: {}", topic, e);
+                self.remove(topic, id);
+            } else {
+                delivered += 1;
+            }
+        }
+
+        Ok(delivered)
+    }
+
+    /// Returns the number of active subscriptions to a topic.
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.topics.get(topic).map(|map| map.len()).unwrap_or(0)
+    }
+
+    fn insert(&self, topic: &str, arb: Arc<ArbContext<U>>) -> u64 {
+        let id = self.next_subscriber_id.fetch_add(1, Ordering::Relaxed);
+        self.topics
+            .entry(topic.to_string())
+            .or_default()
+            .insert(id, arb);
+        id
+    }
+
+    fn remove(&self, topic: &str, id: u64) {
+        if let Some(map) = self.topics.get(topic) {
+            map.remove(&id);
+        }
+        self.topics.remove_if(topic, |_, map| map.is_empty());
+    }
+}
+
+/// Waits until the subscriber unsubscribes or its context goes away.
+async fn watch_subscription<U: UTP>(arb: &ArbContext<U>) {
+    loop {
+        match arb.read().await.map(PubSubRequest::decode) {
+            Ok(Ok(PubSubRequest::Unsubscribe)) | Err(_) => break,
+            Ok(other) => {
+                tracing::warn!("ignoring request on subscription context: {:?}", other);
+            }
+        }
+    }
+}
+
+async fn reject<U: UTP>(arb: &ArbContext<U>, error: PubSubError) -> Result<(), PubSubError> {
+    arb.write_payload(Payload::Error(Error {
+        error_type: ErrorType::Unspecified,
+        message: error.to_string(),
+    }))
+    .await?;
+
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bytes::Bytes;
+
+    use crate::{
+        core::{
+            client::connect,
+            common::connection::Connection,
+            common::rate::RateLimiter,
+            pubsub::{broker::Broker, error::PubSubError},
+            server::accept,
+        },
+        utp::tests::utp::{MockUTP, mock_utp_pairs},
+    };
+
+    async fn setup() -> (Arc<Broker<MockUTP>>, Connection<MockUTP>) {
+        let (a, b) = mock_utp_pairs();
+        let broker = Arc::new(Broker::new());
+
+        let server_broker = broker.clone();
+        tokio::spawn(async move {
+            let conn = accept(a.into()).await.unwrap();
+            server_broker.serve(&conn).await;
+        });
+
+        let conn = connect(b.into()).await.unwrap();
+        (broker, conn)
+    }
+
+    async fn wait_subscribers(broker: &Broker<MockUTP>, topic: &str, count: usize) {
+        while broker.subscriber_count(topic) != count {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_fan_out() {
+        let (broker, conn) = setup().await;
+
+        let first = conn.subscribe("voice.state").await.unwrap();
+        let second = conn.subscribe("voice.state").await.unwrap();
+        let other = conn.subscribe("voice.other").await.unwrap();
+        assert_eq!(broker.subscriber_count("voice.state"), 2);
+
+        conn.publish("voice.state", Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+
+        assert_eq!(first.recv().await.unwrap(), Bytes::from_static(b"muffin"));
+        assert_eq!(second.recv().await.unwrap(), Bytes::from_static(b"muffin"));
+
+        let delivered = broker
+            .publish("voice.other", Bytes::from_static(b"local"))
+            .await
+            .unwrap();
+        assert_eq!(delivered, 1);
+        assert_eq!(other.recv().await.unwrap(), Bytes::from_static(b"local"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_does_not_stall_others() {
+        let (a, b) = mock_utp_pairs();
+        let broker = Arc::new(Broker::new());
+        let limiter = RateLimiter::new(1, 0);
+
+        // the first subscription is rate limited, the others are not
+        let server_broker = broker.clone();
+        let server_limiter = limiter.clone();
+        tokio::spawn(async move {
+            let conn = accept(a.into()).await.unwrap();
+
+            let mut limiter = Some(server_limiter);
+            while let Some(arb) = conn.next_arb().await {
+                let arb = match limiter.take() {
+                    Some(limiter) => arb.with_rate_limit(limiter),
+                    None => arb,
+                };
+                let broker = server_broker.clone();
+                tokio::spawn(async move { broker.handle(arb).await });
+            }
+        });
+
+        let conn = connect(b.into()).await.unwrap();
+        let slow = conn.subscribe("voice.state").await.unwrap();
+        let mut fast = Vec::new();
+        for _ in 0..8 {
+            fast.push(conn.subscribe("voice.state").await.unwrap());
+        }
+
+        // a minute of debt holds back delivery to the slow subscriber
+        limiter.reserve(60);
+
+        let publish = tokio::spawn({
+            let broker = broker.clone();
+            async move {
+                broker
+                    .publish("voice.state", Bytes::from_static(b"muffin"))
+                    .await
+            }
+        });
+
+        for subscription in &fast {
+            let received = tokio::time::timeout(Duration::from_secs(1), subscription.recv())
+                .await
+                .expect("delivery stalled behind the slow subscriber")
+                .unwrap();
+            assert_eq!(received, Bytes::from_static(b"muffin"));
+        }
+
+        let held_back = tokio::time::timeout(Duration::from_millis(50), slow.recv()).await;
+        assert!(held_back.is_err());
+
+        publish.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe() {
+        let (broker, conn) = setup().await;
+
+        let sub = conn.subscribe("voice.state").await.unwrap();
+        assert_eq!(sub.topic(), "voice.state");
+        assert_eq!(broker.subscriber_count("voice.state"), 1);
+
+        sub.unsubscribe().await.unwrap();
+        wait_subscribers(&broker, "voice.state", 0).await;
+
+        let delivered = broker
+            .publish("voice.state", Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        assert_eq!(delivered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_topic() {
+        let (_broker, conn) = setup().await;
+
+        let local = conn.subscribe("voice state").await;
+        assert!(matches!(local, Err(PubSubError::InvalidTopic(_))));
+
+        let publish = conn.publish("", Bytes::new()).await;
+        assert!(matches!(publish, Err(PubSubError::InvalidTopic(_))));
+    }
+
+    #[tokio::test]
+    async fn test_broker_rejects_bad_topic() {
+        let (broker, conn) = setup().await;
+
+        let arb = conn.new_arb();
+        let request = crate::core::pubsub::request::PubSubRequest::Subscribe {
+            topic: "bad topic".into(),
+        };
+        arb.write(request.encode()).await.unwrap();
+
+        let reply = arb.read_payload().await.unwrap();
+        assert!(matches!(reply, crate::schema::Payload::Error(_)));
+        assert_eq!(broker.subscriber_count("bad topic"), 0);
+    }
+}
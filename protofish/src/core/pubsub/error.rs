@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+use crate::core::common::arbitrary::ArbError;
+
+/// Errors that can occur during publish/subscribe operations.
+#[derive(Error, Debug)]
+pub enum PubSubError {
+    /// Error from the underlying arbitrary data context
+    #[error("context error: {0}")]
+    Arb(#[from] ArbError),
+
+    /// The topic name is not acceptable
+    #[error("invalid topic: {0}")]
+    InvalidTopic(String),
+
+    /// The peer rejected the request with an `Error` payload
+    #[error("rejected by peer: {0}")]
+    Rejected(String),
+
+    /// Received a malformed pub/sub request or reply
+    #[error("malformed pub/sub message: {0}")]
+    Malformed(String),
+}
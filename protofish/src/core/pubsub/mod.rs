@@ -0,0 +1,6 @@
+pub mod broker;
+pub mod error;
+pub mod subscription;
+
+mod request;
+pub use request::{MAX_TOPIC_LEN, validate_topic};
@@ -0,0 +1,125 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::core::pubsub::error::PubSubError;
+
+/// Maximum length of a topic name in bytes.
+pub const MAX_TOPIC_LEN: usize = 256;
+
+const OP_SUBSCRIBE: u8 = 1;
+const OP_UNSUBSCRIBE: u8 = 2;
+const OP_PUBLISH: u8 = 3;
+
+/// A pub/sub request, carried as the content of an `ArbitaryData` payload.
+///
+/// Wire layout: `[op: u8][topic_len: u16 le][topic: utf-8][content]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PubSubRequest {
+    Subscribe { topic: String },
+    Unsubscribe,
+    Publish { topic: String, content: Bytes },
+}
+
+impl PubSubRequest {
+    pub(crate) fn encode(&self) -> Bytes {
+        let (op, topic, content) = match self {
+            PubSubRequest::Subscribe { topic } => (OP_SUBSCRIBE, topic.as_str(), &[][..]),
+            PubSubRequest::Unsubscribe => (OP_UNSUBSCRIBE, "", &[][..]),
+            PubSubRequest::Publish { topic, content } => (OP_PUBLISH, topic.as_str(), &content[..]),
+        };
+
+        let mut buf = BytesMut::with_capacity(3 + topic.len() + content.len());
+        buf.put_u8(op);
+        buf.put_u16_le(topic.len() as u16);
+        buf.put_slice(topic.as_bytes());
+        buf.put_slice(content);
+
+        buf.freeze()
+    }
+
+    pub(crate) fn decode(mut buf: Bytes) -> Result<Self, PubSubError> {
+        if buf.len() < 3 {
+            return Err(PubSubError::Malformed("request header is truncated".into()));
+        }
+
+        let op = buf.get_u8();
+        let topic_len = buf.get_u16_le() as usize;
+
+        if buf.len() < topic_len {
+            return Err(PubSubError::Malformed("topic is truncated".into()));
+        }
+
+        let topic = String::from_utf8(buf.split_to(topic_len).to_vec())
+            .map_err(|_| PubSubError::Malformed("topic is not valid UTF-8".into()))?;
+
+        match op {
+            OP_SUBSCRIBE => Ok(PubSubRequest::Subscribe { topic }),
+            OP_UNSUBSCRIBE => Ok(PubSubRequest::Unsubscribe),
+            OP_PUBLISH => Ok(PubSubRequest::Publish {
+                topic,
+                content: buf,
+            }),
+            _ => Err(PubSubError::Malformed(format!("unknown operation {}", op))),
+        }
+    }
+}
+
+/// Checks that a topic name is non-empty, at most `MAX_TOPIC_LEN` bytes long
+/// and free of whitespace and control characters.
+pub fn validate_topic(topic: &str) -> Result<(), PubSubError> {
+    if topic.is_empty() {
+        Err(PubSubError::InvalidTopic("topic is empty".into()))
+    } else if topic.len() > MAX_TOPIC_LEN {
+        Err(PubSubError::InvalidTopic(format!(
+            "topic is longer than {} bytes",
+            MAX_TOPIC_LEN
+        )))
+    } else if topic.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Err(PubSubError::InvalidTopic(format!(
+            "topic {:?} contains whitespace or control characters",
+            topic
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::core::pubsub::request::{MAX_TOPIC_LEN, PubSubRequest, validate_topic};
+
+    #[test]
+    fn test_request_roundtrip() {
+        let requests = [
+            PubSubRequest::Subscribe {
+                topic: "voice.state".into(),
+            },
+            PubSubRequest::Unsubscribe,
+            PubSubRequest::Publish {
+                topic: "voice.state".into(),
+                content: Bytes::from_static(b"muffin"),
+            },
+        ];
+
+        for request in requests {
+            let decoded = PubSubRequest::decode(request.encode()).unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+
+    #[test]
+    fn test_request_decode_malformed() {
+        assert!(PubSubRequest::decode(Bytes::from_static(&[1])).is_err());
+        assert!(PubSubRequest::decode(Bytes::from_static(&[1, 10, 0, b'a'])).is_err());
+        assert!(PubSubRequest::decode(Bytes::from_static(&[9, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_validate_topic() {
+        assert!(validate_topic("voice.state").is_ok());
+        assert!(validate_topic("").is_err());
+        assert!(validate_topic("voice state").is_err());
+        assert!(validate_topic(&"a".repeat(MAX_TOPIC_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use bytes::Bytes;
+
+use crate::{
+    core::{
+        common::{arbitrary::ArbContext, connection::Connection},
+        pubsub::{
+            error::PubSubError,
+            request::{PubSubRequest, validate_topic},
+        },
+    },
+    schema::{ArbitaryData, Payload},
+    utp::UTP,
+};
+
+/// A subscription to a single topic.
+///
+/// Each subscription owns its own context, so messages published to the
+/// topic are delivered in order and independently of other subscriptions.
+/// Dropping a subscription cancels its context, which also ends it on the
+/// broker.
+pub struct Subscription<U: UTP> {
+    topic: String,
+    arb: ArbContext<U>,
+}
+
+impl<U: UTP> Subscription<U> {
+    /// Returns the topic this subscription is bound to.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Receives the next message published to the topic.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the context is closed or the peer sends
+    /// something other than `ArbitaryData`.
+    pub async fn recv(&self) -> Result<Bytes, PubSubError> {
+        Ok(self.arb.read().await?)
+    }
+
+    /// Cancels the subscription.
+    ///
+    /// The broker stops delivering to this subscription once it processes
+    /// the request; messages already in flight are discarded with the context.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request cannot be written.
+    pub async fn unsubscribe(self) -> Result<(), PubSubError> {
+        send_request(&self.arb, PubSubRequest::Unsubscribe).await?;
+        self.arb.finish();
+
+        Ok(())
+    }
+}
+
+impl<U: UTP> Connection<U> {
+    /// Subscribes to a topic on the peer's broker.
+    ///
+    /// A new context is opened for the subscription, and the broker answers
+    /// with `Ok` on success or an `Error` payload if the topic is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PubSubError::InvalidTopic` if the topic fails local validation,
+    /// `PubSubError::Rejected` if the broker refuses it, or another error if the
+    /// exchange fails.
+    pub async fn subscribe(&self, topic: &str) -> Result<Subscription<U>, PubSubError> {
+        validate_topic(topic)?;
+
+        let arb = self.new_arb();
+        send_request(
+            &arb,
+            PubSubRequest::Subscribe {
+                topic: topic.into(),
+            },
+        )
+        .await?;
+        expect_ok(&arb).await?;
+
+        Ok(Subscription {
+            topic: topic.into(),
+            arb,
+        })
+    }
+
+    /// Publishes a message to a topic on the peer's broker.
+    ///
+    /// The call completes once the broker has fanned the message out to its
+    /// current subscribers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PubSubError::InvalidTopic` if the topic fails local validation,
+    /// `PubSubError::Rejected` if the broker refuses it, or another error if the
+    /// exchange fails.
+    pub async fn publish(&self, topic: &str, content: Bytes) -> Result<(), PubSubError> {
+        validate_topic(topic)?;
+
+        let arb = self.new_arb();
+        send_request(
+            &arb,
+            PubSubRequest::Publish {
+                topic: topic.into(),
+                content,
+            },
+        )
+        .await?;
+
+        expect_ok(&arb).await?;
+        arb.finish();
+
+        Ok(())
+    }
+}
+
+async fn send_request<U: UTP>(
+    arb: &ArbContext<U>,
+    request: PubSubRequest,
+) -> Result<(), PubSubError> {
+    arb.write_payload(Payload::ArbitaryData(ArbitaryData {
+        content: request.encode(),
+    }))
+    .await?;
+
+    Ok(())
+}
+
+async fn expect_ok<U: UTP>(arb: &ArbContext<U>) -> Result<(), PubSubError> {
+    match arb.read_payload().await? {
+        Payload::Ok => Ok(()),
+        Payload::Error(error) => Err(PubSubError::Rejected(error.message)),
+        other => Err(PubSubError::Malformed(format!(
+            "expected Ok or Error, got {:?}",
+            other
+        ))),
+    }
+}
@@ -76,7 +76,7 @@ mod tests {
             };
 
             tx.write(Payload::ClientHello(client_hello)).await.unwrap();
-            let r = rx.read().await.unwrap();
+            let r = rx.read_control().await.unwrap();
 
             if let Payload::ServerHello(server_hello) = r {
                 assert_eq!(server_hello.ok, assert_ok);
@@ -13,7 +13,7 @@ use crate::{
 
 pub async fn server_handshake<S: UTPStream>(pmc: &PMC<S>) -> Result<(), ProtofishError> {
     let ctx = get_client_hello(pmc).await?;
-    let payload = ctx.1.read().await?;
+    let payload = ctx.1.read_control().await?;
 
     if let Payload::ClientHello(client_hello) = payload {
         if client_hello.resume_connection_token.is_some() {
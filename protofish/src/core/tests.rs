@@ -1,6 +1,14 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::io::AsyncWriteExt;
+
 use crate::{
+    constant::VERSION,
     core::{client::connect, server::accept},
-    utp::tests::utp::mock_utp_pairs,
+    dissect::encode_frame,
+    schema::{ClientHello, IntegrityType, Message, Payload},
+    testkit::{LinkConfig, pair},
+    utp::{UTP, UTPStream, tests::utp::mock_utp_pairs},
 };
 
 #[tokio::test]
@@ -13,3 +21,54 @@ async fn test_bi_handshake() {
 
     connect(a.into()).await.unwrap();
 }
+
+/// Writes raw bytes on the PMC stream of a server and returns the result of
+/// its handshake.
+async fn accept_raw(bytes: Vec<u8>) -> bool {
+    let (server, client) = pair(LinkConfig::default());
+    let accepting = tokio::spawn(accept(Arc::new(server)));
+
+    let (mut write, _read) = client
+        .new_stream(IntegrityType::Reliable)
+        .await
+        .unwrap()
+        .split();
+    write.write_all(&bytes).await.unwrap();
+    write.shutdown().await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), accepting)
+        .await
+        .expect("handshake hung")
+        .unwrap()
+        .is_ok()
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_malformed_frame_closes_pmc() {
+    // used to panic the PMC reader, leaving the handshake hanging
+    let client_hello = encode_frame(Message {
+        context_id: 0,
+        payload: Payload::ClientHello(ClientHello {
+            version: VERSION,
+            resume_connection_token: None,
+        }),
+        budget: None,
+    });
+    assert!(accept_raw(client_hello.to_vec()).await);
+
+    // a message without a payload
+    let mut bytes = vec![2, 0, 0, 0, 0, 0, 0, 0, 0x08, 0x05];
+    bytes.extend_from_slice(&client_hello);
+    assert!(!accept_raw(bytes).await);
+
+    // a stream open without meta
+    let mut bytes = vec![6, 0, 0, 0, 0, 0, 0, 0, 0x12, 0x04, 0x2a, 0x02, 0x08, 0x01];
+    bytes.extend_from_slice(&client_hello);
+    assert!(!accept_raw(bytes).await);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_oversized_frame_closes_pmc() {
+    // used to abort on allocating the announced length
+    assert!(!accept_raw(u64::MAX.to_le_bytes().to_vec()).await);
+}
@@ -0,0 +1,424 @@
+//! Decoding raw bytes of a Primary Messaging Channel, for debugging.
+//!
+//! A PMC stream is a sequence of frames, each a little-endian `u64` length
+//! followed by a `payload.v1.Message` of that length. `dissect` splits a
+//! byte dump of such a stream into `Frame`s, which print as one line of
+//! text each:
+//!
+//! ```
+//! use protofish::dissect::{dissect, encode_frame};
+//! use protofish::{Message, Payload};
+//!
+//! let dump = encode_frame(Message {
+//!     context_id: 2,
+//!     payload: Payload::Keepalive,
+//!     budget: None,
+//! });
+//!
+//! for frame in dissect(&dump) {
+//!     println!("{}", frame);
+//! }
+//! ```
+//!
+//! Frames whose body is not a valid message are reported as
+//! `FrameContent::Corrupt`, and decoding continues after them. A dump that
+//! ends within a frame yields a final `FrameContent::Truncated`.
+
+use std::fmt;
+
+use bytes::{Bytes, BytesMut};
+use prost::Message as _;
+
+use crate::{
+    internal::serialize::{deserialize_message, serialize_message},
+    prost_generated::payload_ext::v1 as payload_ext_v1,
+    schema::{ContextId, IntegrityType, Message, Payload, SchemaError},
+};
+
+/// Size of the length prefix of a frame.
+const LENGTH_PREFIX: usize = 8;
+
+/// A frame of a PMC byte dump.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Position of the frame in the dump, starting at 0
+    pub index: usize,
+    /// Byte offset of the frame's length prefix in the dump
+    pub offset: usize,
+    /// Length of the frame's body, unless the prefix itself is cut short
+    pub length: Option<u64>,
+    pub content: FrameContent,
+}
+
+/// What a frame turned out to contain.
+#[derive(Debug, Clone)]
+pub enum FrameContent {
+    /// A well-formed message
+    Message(Message),
+
+    /// The body is not a valid `payload.v1.Message`
+    Corrupt {
+        /// Context ID, if the body decoded far enough to tell
+        context_id: Option<ContextId>,
+        reason: String,
+    },
+
+    /// The dump ends before the frame does
+    Truncated {
+        /// Bytes of the frame present in the dump, including its prefix
+        available: usize,
+    },
+}
+
+impl Frame {
+    /// Returns the context ID of the frame, if known.
+    pub fn context_id(&self) -> Option<ContextId> {
+        match &self.content {
+            FrameContent::Message(message) => Some(message.context_id),
+            FrameContent::Corrupt { context_id, .. } => *context_id,
+            FrameContent::Truncated { .. } => None,
+        }
+    }
+
+    /// Returns the name of the payload variant of the frame, if known.
+    pub fn variant(&self) -> Option<&'static str> {
+        match &self.content {
+            FrameContent::Message(message) => Some(message.payload.name()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} @{}", self.index, self.offset)?;
+        if let Some(length) = self.length {
+            write!(f, " len={}", length)?;
+        }
+
+        match &self.content {
+            FrameContent::Message(message) => {
+                write!(f, " ctx={} {}", message.context_id, message.payload.name())?;
+                if let Some(budget) = message.budget {
+                    write!(f, " budget={:?}", budget)?;
+                }
+                for (name, value) in payload_fields(&message.payload) {
+                    write!(f, " {}={}", name, value)?;
+                }
+                Ok(())
+            }
+            FrameContent::Corrupt { context_id, reason } => {
+                if let Some(context_id) = context_id {
+                    write!(f, " ctx={}", context_id)?;
+                }
+                write!(f, " CORRUPT: {}", reason)
+            }
+            FrameContent::Truncated { available } => {
+                write!(f, " TRUNCATED: {} bytes available", available)
+            }
+        }
+    }
+}
+
+/// Value of a payload field, see `payload_fields`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Uint(u64),
+    Bool(bool),
+    Text(String),
+    Bytes(Bytes),
+    /// An optional field that is not set
+    None,
+}
+
+/// Bytes fields print at most this many bytes in hex.
+const BYTES_SHOWN: usize = 32;
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Uint(value) => write!(f, "{}", value),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::Text(value) => write!(f, "{:?}", value),
+            FieldValue::Bytes(value) => {
+                for byte in value.iter().take(BYTES_SHOWN) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if value.len() > BYTES_SHOWN {
+                    write!(f, "..({} bytes)", value.len())?;
+                }
+                Ok(())
+            }
+            FieldValue::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Lists the fields of a payload by name, in schema order.
+pub fn payload_fields(payload: &Payload) -> Vec<(&'static str, FieldValue)> {
+    let version = |v: &crate::schema::Version| {
+        FieldValue::Text(format!("{}.{}.{}", v.major, v.minor, v.patch))
+    };
+    let integrity = |integrity: &IntegrityType| {
+        FieldValue::Text(
+            match integrity {
+                IntegrityType::Reliable => "reliable",
+                IntegrityType::Unreliable => "unreliable",
+            }
+            .into(),
+        )
+    };
+
+    match payload {
+        Payload::ClientHello(hello) => vec![
+            ("version", version(&hello.version)),
+            (
+                "resume_connection_token",
+                hello
+                    .resume_connection_token
+                    .clone()
+                    .map_or(FieldValue::None, |token| FieldValue::Bytes(token.into())),
+            ),
+        ],
+        Payload::ServerHello(hello) => vec![
+            ("version", version(&hello.version)),
+            ("ok", FieldValue::Bool(hello.ok)),
+            (
+                "connection_token",
+                hello
+                    .connection_token
+                    .clone()
+                    .map_or(FieldValue::None, FieldValue::Bytes),
+            ),
+            (
+                "message",
+                hello
+                    .message
+                    .clone()
+                    .map_or(FieldValue::None, FieldValue::Text),
+            ),
+        ],
+        Payload::Error(error) => vec![
+            (
+                "error_type",
+                FieldValue::Text(format!("{:?}", error.error_type).to_lowercase()),
+            ),
+            ("message", FieldValue::Text(error.message.clone())),
+        ],
+        Payload::StreamOpen(open) => vec![
+            ("stream_id", FieldValue::Uint(open.stream_id)),
+            ("integrity_type", integrity(&open.meta.integrity_type)),
+        ],
+        Payload::StreamClose(close) => vec![("stream_id", FieldValue::Uint(close.stream_id))],
+        Payload::ArbitaryData(data) => vec![("content", FieldValue::Bytes(data.content.clone()))],
+        Payload::BenchmarkStart(start) => vec![
+            ("integrity_type", integrity(&start.integrity_type)),
+            ("byte_count", FieldValue::Uint(start.byte_count)),
+        ],
+        Payload::StreamReport(report) => vec![
+            ("stream_id", FieldValue::Uint(report.stream_id)),
+            (
+                "highest_sequence",
+                FieldValue::Uint(report.highest_sequence),
+            ),
+            ("received", FieldValue::Uint(report.received)),
+            ("lost", FieldValue::Uint(report.lost)),
+            ("duplicates", FieldValue::Uint(report.duplicates)),
+            ("reordered", FieldValue::Uint(report.reordered)),
+            ("jitter_micros", FieldValue::Uint(report.jitter_micros)),
+        ],
+        Payload::Cancel(cancel) => vec![("reason", FieldValue::Text(cancel.reason.clone()))],
+        Payload::Ok | Payload::Keepalive | Payload::Close | Payload::BenchmarkEnd => Vec::new(),
+    }
+}
+
+/// Splits a byte dump of a PMC stream into frames.
+///
+/// Decoding never fails: bodies that do not decode are returned as
+/// `FrameContent::Corrupt`, and a dump ending within a frame ends with a
+/// `FrameContent::Truncated` frame.
+pub fn dissect(bytes: &[u8]) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let index = frames.len();
+        let rest = &bytes[offset..];
+
+        let Some(prefix) = rest.first_chunk::<LENGTH_PREFIX>() else {
+            frames.push(Frame {
+                index,
+                offset,
+                length: None,
+                content: FrameContent::Truncated {
+                    available: rest.len(),
+                },
+            });
+            break;
+        };
+
+        let length = u64::from_le_bytes(*prefix);
+        let body = &rest[LENGTH_PREFIX..];
+
+        let body = match usize::try_from(length) {
+            Ok(length) if length <= body.len() => &body[..length],
+            _ => {
+                frames.push(Frame {
+                    index,
+                    offset,
+                    length: Some(length),
+                    content: FrameContent::Truncated {
+                        available: rest.len(),
+                    },
+                });
+                break;
+            }
+        };
+
+        frames.push(Frame {
+            index,
+            offset,
+            length: Some(length),
+            content: decode_body(body),
+        });
+        offset += LENGTH_PREFIX + body.len();
+    }
+
+    frames
+}
+
+/// Frames a message the way it is written to a PMC stream.
+pub fn encode_frame(message: Message) -> Bytes {
+    let body = serialize_message(message);
+
+    let mut frame = BytesMut::with_capacity(LENGTH_PREFIX + body.len());
+    frame.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    frame.extend_from_slice(&body);
+    frame.freeze()
+}
+
+/// Decodes the body of a single frame, checked the way a connection checks
+/// the frames it receives.
+///
+/// # Errors
+///
+/// Returns an error if `body` is not a protobuf `Message`, or lacks fields
+/// the schema requires.
+pub fn decode_message(body: &[u8]) -> Result<Message, SchemaError> {
+    deserialize_message(body)
+}
+
+fn decode_body(body: &[u8]) -> FrameContent {
+    let message = match payload_ext_v1::Message::decode(body) {
+        Ok(message) => message,
+        Err(e) => {
+            return FrameContent::Corrupt {
+                context_id: None,
+                reason: e.to_string(),
+            };
+        }
+    };
+    let context_id = message.context_id;
+
+    match Message::try_from(message) {
+        Ok(message) => FrameContent::Message(message),
+        Err(e) => FrameContent::Corrupt {
+            context_id: Some(context_id),
+            reason: e.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{FrameContent, dissect, encode_frame};
+    use crate::schema::{ArbitaryData, Message, Payload};
+
+    fn message(context_id: u64, payload: Payload) -> Message {
+        Message {
+            context_id,
+            payload,
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn test_dissect_frames() {
+        let mut dump = encode_frame(message(0, Payload::Keepalive)).to_vec();
+        dump.extend_from_slice(&encode_frame(message(
+            4,
+            Payload::ArbitaryData(ArbitaryData {
+                content: Bytes::from_static(b"\x01\x02"),
+            }),
+        )));
+
+        let frames = dissect(&dump);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].context_id(), Some(4));
+        assert_eq!(frames[1].variant(), Some("ArbitaryData"));
+        assert_eq!(frames[1].offset, frames[0].length.unwrap() as usize + 8);
+        assert!(
+            frames[1]
+                .to_string()
+                .ends_with("ctx=4 ArbitaryData content=0102")
+        );
+    }
+
+    #[test]
+    fn test_corrupt_frame_is_skipped() {
+        let mut dump = Vec::new();
+        // an invalid protobuf body
+        dump.extend_from_slice(&3u64.to_le_bytes());
+        dump.extend_from_slice(&[0xff, 0xff, 0xff]);
+        // a valid message without a payload
+        dump.extend_from_slice(&0u64.to_le_bytes());
+        dump.extend_from_slice(&encode_frame(message(2, Payload::Close)));
+
+        let frames = dissect(&dump);
+        assert_eq!(frames.len(), 3);
+        assert!(matches!(
+            frames[0].content,
+            FrameContent::Corrupt {
+                context_id: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &frames[1].content,
+            FrameContent::Corrupt { context_id: Some(0), reason } if reason == "missing payload"
+        ));
+        assert_eq!(frames[2].variant(), Some("Close"));
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let frame = encode_frame(message(2, Payload::Ok));
+        let mut dump = frame.to_vec();
+        dump.extend_from_slice(&frame[..frame.len() - 1]);
+
+        let frames = dissect(&dump);
+        assert_eq!(frames.len(), 2);
+        assert!(
+            matches!(frames[1].content, FrameContent::Truncated { available } if available == frame.len() - 1)
+        );
+
+        let frames = dissect(&frame[..5]);
+        assert!(matches!(
+            frames[0],
+            super::Frame {
+                length: None,
+                content: FrameContent::Truncated { available: 5 },
+                ..
+            }
+        ));
+
+        // a length too large for the platform is treated as truncation too
+        let frames = dissect(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            frames[0].content,
+            FrameContent::Truncated { available: 8 }
+        ));
+    }
+}
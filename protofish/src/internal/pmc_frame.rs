@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{io, sync::Arc, time::Duration};
 
 use bytes::Bytes;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
     sync::{
@@ -10,22 +10,131 @@ use tokio::{
     },
     task::JoinHandle,
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
+    capture::{Capture, CaptureEvent, CaptureSlot, Direction},
+    constant::MAX_FRAME_LEN,
+    core::common::{context::ContextQueue, counter::ContextCounter},
     internal::serialize::{deserialize_message, serialize_message},
-    schema::{ContextId, Message, Payload},
+    schema::{ContextId, Message, Payload, StreamId},
     utp::{UTPStream, error::UTPError},
 };
 
-type SenderMap = Arc<DashMap<ContextId, UnboundedSender<Payload>>>;
+type SenderMap = Arc<DashMap<ContextId, ContextSlot>>;
+/// Contexts opened by the peer and released here, whose late payloads are
+/// dropped instead of opening them again.
+type ReleasedSet = Arc<DashSet<ContextId>>;
+
+/// Routing state of a single context.
+struct ContextSlot {
+    senders: ContextSenders,
+    cancel: CancellationToken,
+    /// Streams of the context that are still used locally, cancelled when
+    /// the peer announces their close
+    streams: DashMap<StreamId, CancellationToken>,
+}
+
+impl ContextSlot {
+    fn new(senders: ContextSenders, cancel: CancellationToken) -> Self {
+        Self {
+            senders,
+            cancel,
+            streams: DashMap::new(),
+        }
+    }
+}
+
+/// Sending ends of the queues of a context.
+#[derive(Clone)]
+struct ContextSenders {
+    data: UnboundedSender<Payload>,
+    streams: UnboundedSender<Payload>,
+    control: UnboundedSender<Payload>,
+}
+
+/// A context opened by the peer, registered when its first message arrived.
+pub struct IncomingContext {
+    pub context_id: ContextId,
+    pub budget: Option<Duration>,
+    pub receivers: ContextReceivers,
+    pub cancel: CancellationToken,
+}
+
+/// Receiving ends of the queues of a context.
+pub struct ContextReceivers {
+    pub data: UnboundedReceiver<Payload>,
+    pub streams: UnboundedReceiver<Payload>,
+    pub control: UnboundedReceiver<Payload>,
+}
+
+impl ContextSenders {
+    fn channel() -> (Self, ContextReceivers) {
+        let (data, data_rx) = mpsc::unbounded_channel();
+        let (streams, streams_rx) = mpsc::unbounded_channel();
+        let (control, control_rx) = mpsc::unbounded_channel();
+
+        (
+            Self {
+                data,
+                streams,
+                control,
+            },
+            ContextReceivers {
+                data: data_rx,
+                streams: streams_rx,
+                control: control_rx,
+            },
+        )
+    }
+
+    fn route(&self, payload: Payload) {
+        let sender = match ContextQueue::of(&payload) {
+            ContextQueue::Data => &self.data,
+            ContextQueue::Streams => &self.streams,
+            ContextQueue::Control => &self.control,
+        };
+
+        send_curried(sender.clone())(payload);
+    }
+}
+
+/// Sending side of the frame.
+enum FrameWriter<W> {
+    /// Messages are serialized and length-prefixed on a byte stream
+    Bytes(Mutex<W>),
+    /// Messages are passed as values, see `UTPStream::into_messages`
+    Messages(UnboundedSender<Message>),
+}
+
+/// Receiving side of the frame.
+enum FrameReader<R> {
+    Bytes(R),
+    Messages(UnboundedReceiver<Message>),
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    async fn next(&mut self) -> Result<Option<Message>, UTPError> {
+        match self {
+            FrameReader::Bytes(stream) => recv_frame(stream).await,
+            FrameReader::Messages(rx) => match rx.recv().await {
+                Some(message) => Ok(Some(message)),
+                None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream closed").into()),
+            },
+        }
+    }
+}
 
 pub struct PMCFrame<U>
 where
     U: UTPStream,
 {
     senders: SenderMap,
-    context_rx: Mutex<UnboundedReceiver<Message>>,
-    writer: Mutex<U::StreamWrite>,
+    released: ReleasedSet,
+    counter: Arc<ContextCounter>,
+    context_rx: Mutex<UnboundedReceiver<IncomingContext>>,
+    writer: FrameWriter<U::StreamWrite>,
+    capture: CaptureSlot,
     shutdown_notify: Arc<Notify>,
     _task: JoinHandle<()>,
 }
@@ -34,16 +143,36 @@ impl<U> PMCFrame<U>
 where
     U: UTPStream,
 {
-    pub fn new(stream: U) -> Self {
+    /// Starts reading frames from `stream`. `counter` tells contexts opened
+    /// locally apart from the peer's.
+    pub fn new(stream: U, counter: ContextCounter) -> Self {
         let senders: SenderMap = Default::default();
+        let released: ReleasedSet = Default::default();
+        let counter = Arc::new(counter);
         let (context_tx, context_rx) = mpsc::unbounded_channel();
         let shutdown_notify = Arc::new(Notify::new());
+        let capture = CaptureSlot::default();
 
-        let (writer, mut reader) = stream.split();
+        let (writer, mut reader) = match stream.into_messages() {
+            Ok(channel) => (
+                FrameWriter::Messages(channel.tx),
+                FrameReader::Messages(channel.rx),
+            ),
+            Err(stream) => {
+                let (writer, reader) = stream.split();
+                (
+                    FrameWriter::Bytes(Mutex::new(writer)),
+                    FrameReader::Bytes(reader),
+                )
+            }
+        };
 
         let _task = {
             let senders = senders.clone();
+            let released = released.clone();
+            let counter = counter.clone();
             let notify = shutdown_notify.clone();
+            let capture = capture.clone();
 
             tokio::spawn(async move {
                 loop {
@@ -51,7 +180,7 @@ where
                         _ = notify.notified() => {
                             break;
                         }
-                        success = match_frame(&mut reader, senders.clone(), context_tx.clone()) => {
+                        success = match_frame(&mut reader, &counter, senders.clone(), &released, context_tx.clone(), &capture) => {
                             if !success {break;}
                         }
                     }
@@ -61,41 +190,119 @@ where
 
         Self {
             senders,
+            released,
+            counter,
             context_rx: Mutex::new(context_rx),
             shutdown_notify,
-            writer: Mutex::new(writer),
+            writer,
+            capture,
             _task,
         }
     }
 
+    /// Starts recording every message sent and received from now on.
+    ///
+    /// Only the first capture set is used.
+    pub fn set_capture(&self, capture: Capture) {
+        if self.capture.set(capture).is_err() {
+            tracing::warn!("PMC is already being captured");
+        }
+    }
+
+    /// Returns the slot holding the capture, if one gets set.
+    pub fn capture_slot(&self) -> CaptureSlot {
+        self.capture.clone()
+    }
+
+    /// Starts routing payloads of a locally opened context to a new set of
+    /// queues.
+    ///
+    /// Returns the receiving ends along with the context's cancellation
+    /// token.
     pub fn subscribe_context(
         &self,
         context_id: ContextId,
-        initial_item: Option<Payload>,
-    ) -> UnboundedReceiver<Payload> {
-        let (tx, rx) = mpsc::unbounded_channel();
+    ) -> (ContextReceivers, CancellationToken) {
+        let (senders, receivers) = ContextSenders::channel();
+        let cancel = CancellationToken::new();
+
+        self.senders
+            .insert(context_id, ContextSlot::new(senders, cancel.clone()));
+
+        (receivers, cancel)
+    }
 
-        if let Some(item) = initial_item {
-            send_curried(tx.clone())(item);
+    /// Returns a token cancelled once the peer announces the close of a
+    /// stream of the context.
+    ///
+    /// `StreamClose` payloads are only delivered this way, and dropped for
+    /// streams nobody watches.
+    pub fn watch_stream(&self, context_id: ContextId, stream_id: StreamId) -> CancellationToken {
+        match self.senders.get(&context_id) {
+            Some(slot) => slot.streams.entry(stream_id).or_default().clone(),
+            None => CancellationToken::new(),
         }
+    }
+
+    /// Stops watching a stream, see `watch_stream`.
+    pub fn unwatch_stream(&self, context_id: ContextId, stream_id: StreamId) {
+        if let Some(slot) = self.senders.get(&context_id) {
+            slot.streams.remove(&stream_id);
+        }
+    }
 
-        self.senders.insert(context_id, tx);
+    /// Stops routing payloads of a context, once nothing reads them anymore.
+    ///
+    /// Later payloads of the context are dropped. The IDs of released
+    /// contexts opened by the peer are remembered for this.
+    pub fn release_context(&self, context_id: ContextId) {
+        if self.senders.remove(&context_id).is_some() && !self.counter.is_own(context_id) {
+            self.released.insert(context_id);
+        }
+    }
 
-        rx
+    /// Number of contexts currently being routed.
+    #[cfg(test)]
+    pub(crate) fn context_count(&self) -> usize {
+        self.senders.len()
     }
 
-    pub async fn next_context_message(&self) -> Option<Message> {
+    /// Waits for the peer to open a new context.
+    ///
+    /// Payloads sent on the context before it is picked up here are already
+    /// queued on its receivers.
+    pub async fn next_context(&self) -> Option<IncomingContext> {
         self.context_rx.lock().await.recv().await
     }
 
     pub async fn send_frame(&self, message: Message) -> Result<(), UTPError> {
+        if let Some(capture) = self.capture.get() {
+            capture.record(Direction::Sent, CaptureEvent::Message(message.clone()));
+        }
+
+        let writer = match &self.writer {
+            FrameWriter::Bytes(writer) => writer,
+            FrameWriter::Messages(tx) => {
+                return tx.send(message).map_err(|_| {
+                    io::Error::new(io::ErrorKind::BrokenPipe, "stream closed").into()
+                });
+            }
+        };
+
         let buf = serialize_message(message);
 
         let len: u64 = buf.len() as u64;
+        if len > MAX_FRAME_LEN {
+            return Err(UTPError::Warn(format!(
+                "message of {} bytes exceeds the frame limit of {} bytes",
+                len, MAX_FRAME_LEN
+            )));
+        }
+
         let len_bytes = len.to_le_bytes();
         let len_bytes = Bytes::copy_from_slice(&len_bytes);
 
-        let mut writer = self.writer.lock().await;
+        let mut writer = writer.lock().await;
         writer.write_all(&len_bytes).await?;
         writer.write_all(&buf).await?;
 
@@ -110,17 +317,68 @@ impl<U: UTPStream> Drop for PMCFrame<U> {
 }
 
 async fn match_frame<R: AsyncRead + Unpin>(
-    stream: &mut R,
+    reader: &mut FrameReader<R>,
+    counter: &ContextCounter,
     senders: SenderMap,
-    context_tx: UnboundedSender<Message>,
+    released: &ReleasedSet,
+    context_tx: UnboundedSender<IncomingContext>,
+    capture: &CaptureSlot,
 ) -> bool {
-    match recv_frame(stream).await {
+    match reader.next().await {
         Ok(message_option) => {
             if let Some(message) = message_option {
-                if let Some(sender) = senders.get(&message.context_id) {
-                    send_curried(sender.clone())(message.payload);
+                if let Some(capture) = capture.get() {
+                    capture.record(Direction::Received, CaptureEvent::Message(message.clone()));
+                }
+
+                if let Payload::Cancel(cancel) = &message.payload {
+                    match senders.get(&message.context_id) {
+                        Some(slot) => {
+                            tracing::debug!(
+                                "context {} cancelled by peer: {}",
+                                message.context_id,
+                                cancel.reason
+                            );
+                            slot.cancel.cancel();
+                        }
+                        None => {
+                            // never seen, so there is nothing to cancel
+                            tracing::debug!(
+                                "dropping cancel for unknown context {}",
+                                message.context_id
+                            );
+                        }
+                    }
+                } else if let Payload::StreamClose(close) = &message.payload {
+                    // consumed by the stream it closes, if it is still used
+                    if let Some(slot) = senders.get(&message.context_id)
+                        && let Some((_, closed)) = slot.streams.remove(&close.stream_id)
+                    {
+                        closed.cancel();
+                    }
+                } else if let Some(slot) = senders.get(&message.context_id) {
+                    slot.senders.route(message.payload);
+                } else if counter.is_own(message.context_id)
+                    || released.contains(&message.context_id)
+                {
+                    // already released
+                    tracing::debug!(
+                        "dropping payload for released context {}",
+                        message.context_id
+                    );
                 } else {
-                    send_curried(context_tx)(message);
+                    let (queues, receivers) = ContextSenders::channel();
+                    let cancel = CancellationToken::new();
+
+                    queues.route(message.payload);
+                    senders.insert(message.context_id, ContextSlot::new(queues, cancel.clone()));
+
+                    send_curried(context_tx)(IncomingContext {
+                        context_id: message.context_id,
+                        budget: message.budget,
+                        receivers,
+                        cancel,
+                    });
                 }
 
                 true
@@ -145,13 +403,19 @@ async fn match_frame<R: AsyncRead + Unpin>(
 
 async fn recv_frame<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<Message>, UTPError> {
     let len = stream.read_u64_le().await?;
+    if len > MAX_FRAME_LEN {
+        return Err(UTPError::Fatal(format!(
+            "frame of {} bytes exceeds the limit of {} bytes",
+            len, MAX_FRAME_LEN
+        )));
+    }
 
     let mut buf = vec![0; len as usize];
     stream.read_exact(&mut buf).await?;
 
-    let message = deserialize_message(&buf);
-
-    Ok(message)
+    deserialize_message(&buf)
+        .map(Some)
+        .map_err(|e| UTPError::Fatal(format!("malformed frame: {}", e)))
 }
 
 fn send_curried<T>(sender: impl Into<UnboundedSender<T>>) -> impl Fn(T) {
@@ -1,7 +1,10 @@
 use bytes::Bytes;
 use prost::Message;
 
-use crate::{prost_generated::payload::v1, schema};
+use crate::{
+    prost_generated::payload_ext::v1,
+    schema::{self, SchemaError},
+};
 
 pub fn serialize_message(message: schema::Message) -> Bytes {
     let message_prost: v1::Message = message.into();
@@ -11,16 +14,19 @@ pub fn serialize_message(message: schema::Message) -> Bytes {
     Bytes::copy_from_slice(&v)
 }
 
-pub fn deserialize_message(buf: &[u8]) -> Option<schema::Message> {
-    v1::Message::decode(buf).ok().map(Into::into)
+pub fn deserialize_message(buf: &[u8]) -> Result<schema::Message, SchemaError> {
+    v1::Message::decode(buf)?.try_into()
 }
 
 #[cfg(test)]
 mod tests {
+    use prost::Message as _;
+
     use crate::{
         constant::VERSION,
         internal::serialize::{deserialize_message, serialize_message},
-        schema::{ClientHello, Message, Payload},
+        prost_generated::payload::v1 as published,
+        schema::{ClientHello, Message, Payload, SchemaError},
     };
 
     #[test]
@@ -31,6 +37,7 @@ mod tests {
                 version: VERSION,
                 resume_connection_token: None,
             }),
+            budget: None,
         };
 
         let bytes = serialize_message(d.clone());
@@ -39,4 +46,55 @@ mod tests {
 
         assert_eq!(value.context_id, d.context_id);
     }
+
+    #[test]
+    fn test_deserialize_incomplete_message() {
+        // context 1 without a payload
+        assert_eq!(
+            deserialize_message(&[0x08, 0x01]).unwrap_err(),
+            SchemaError::MissingField("payload")
+        );
+        // a client hello without a version
+        assert_eq!(
+            deserialize_message(&[0x12, 0x02, 0x0a, 0x00]).unwrap_err(),
+            SchemaError::MissingField("version")
+        );
+        // a benchmark start with integrity type 9
+        assert_eq!(
+            deserialize_message(&[0x12, 0x04, 0x52, 0x02, 0x08, 0x09]).unwrap_err(),
+            SchemaError::UnknownEnumValue {
+                name: "integrity type",
+                value: 9
+            }
+        );
+        assert!(matches!(
+            deserialize_message(&[0xff]),
+            Err(SchemaError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_published_schema_compatible() {
+        let published = published::Message {
+            context_id: 7,
+            payload: Some(published::Payload {
+                payload: Some(published::payload::Payload::Ok(published::Ok {})),
+            }),
+        };
+
+        let value = deserialize_message(&published.encode_to_vec()).unwrap();
+        assert_eq!(value.context_id, 7);
+        assert!(matches!(value.payload, Payload::Ok));
+
+        let bytes = serialize_message(Message {
+            context_id: 7,
+            payload: Payload::Keepalive,
+            budget: None,
+        });
+        let decoded = published::Message::decode(bytes.as_ref()).unwrap();
+        assert!(matches!(
+            decoded.payload.unwrap().payload,
+            Some(published::payload::Payload::Keepalive(_))
+        ));
+    }
 }
@@ -57,18 +57,42 @@ mod prost_generated {
             ));
         }
     }
+
+    pub mod payload_ext {
+        pub mod v1 {
+            include!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/prost_generated/payload_ext.v1.rs"
+            ));
+        }
+    }
 }
 
+pub mod capture;
 mod constant;
 mod core;
+pub mod dissect;
 mod error;
 mod internal;
+pub mod local;
 pub mod schema;
 pub use schema::*;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
 pub mod utp;
 
+pub use constant::MAX_FRAME_LEN;
 pub use core::client::connect;
 pub use core::common::arbitrary::*;
+pub use core::common::benchmark::*;
 pub use core::common::connection::*;
+pub use core::common::datagram::*;
+pub use core::common::deadline::{current_deadline, with_deadline};
+pub use core::common::rate::RateLimiter;
+pub use core::common::sequence::*;
+pub use core::common::stream::*;
+pub use core::pubsub::{
+    MAX_TOPIC_LEN, broker::Broker, error::PubSubError, subscription::Subscription, validate_topic,
+};
 pub use core::server::accept;
 pub use utp::UTP;
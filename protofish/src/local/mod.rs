@@ -0,0 +1,134 @@
+//! In-process transport for components living in the same process.
+//!
+//! `pair` creates two connected `LocalUTP`s backed by channels. Nothing is
+//! encoded: the Primary Messaging Channel passes `Message` values as they
+//! are, and `Bytes` written with `ArbContext::write` or sent as datagrams
+//! reach the peer without being copied. The rest of the API is unchanged, so
+//! the same code keeps working once the components are split across
+//! processes and talk over a network transport.
+//!
+//! ```no_run
+//! use bytes::Bytes;
+//! use protofish::local::connection_pair;
+//!
+//! # async fn example() {
+//! let (client, server) = connection_pair().await.unwrap();
+//!
+//! let arb = client.new_arb();
+//! arb.write(Bytes::from_static(b"muffin")).await.unwrap();
+//!
+//! let peer = server.next_arb().await.unwrap();
+//! let data = peer.read().await.unwrap();
+//! # }
+//! ```
+
+mod stream;
+mod utp;
+
+use std::sync::Arc;
+
+pub use stream::{LocalStream, LocalStreamRead, LocalStreamWrite};
+pub use utp::{LocalUTP, pair};
+
+use crate::{
+    core::{client::connect, common::connection::Connection, server::accept},
+    error::ProtofishError,
+};
+
+/// Creates a client and a server `Connection` over a `pair` of in-process
+/// transports, completing the handshake.
+///
+/// # Returns
+///
+/// Returns `(client, server)`.
+///
+/// # Errors
+///
+/// Returns an error if the handshake fails.
+pub async fn connection_pair()
+-> Result<(Connection<LocalUTP>, Connection<LocalUTP>), ProtofishError> {
+    let (a, b) = pair();
+
+    let (server, client) = tokio::try_join!(accept(Arc::new(a)), connect(Arc::new(b)))?;
+
+    Ok((client, server))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::{
+        core::common::connection::ConnectionState,
+        local::{LocalStream, connection_pair, pair},
+        schema::IntegrityType,
+        utp::{UTP, UTPStream},
+    };
+
+    #[tokio::test]
+    async fn test_arbitrary_data_is_not_copied() {
+        let (client, server) = connection_pair().await.unwrap();
+
+        let data = Bytes::from(vec![42u8; 1024]);
+        let arb = client.new_arb();
+        arb.write(data.clone()).await.unwrap();
+
+        let peer = server.next_arb().await.unwrap();
+        let received = peer.read().await.unwrap();
+
+        assert_eq!(received, data);
+        assert_eq!(received.as_ptr(), data.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn test_reliable_stream() {
+        let (client, server) = connection_pair().await.unwrap();
+
+        let arb = client.new_arb();
+        let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+        let peer_arb = server.next_arb().await.unwrap();
+        let mut peer = peer_arb.wait_stream().await.unwrap();
+
+        stream.write_all(b"muffin").await.unwrap();
+        let mut buf = [0; 6];
+        peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"muffin");
+    }
+
+    #[tokio::test]
+    async fn test_datagrams_are_not_copied() {
+        let (a, b) = pair();
+
+        for integrity in [IntegrityType::Reliable, IntegrityType::Unreliable] {
+            let stream = b.new_stream(integrity.clone()).await.unwrap();
+            let peer = a.wait_stream(stream.id(), integrity).await.unwrap();
+
+            let (mut write, _) = stream.split();
+            let (_, mut read) = peer.split();
+
+            let data = Bytes::from_static(b"muffin");
+            LocalStream::send_datagram(&mut write, data.clone())
+                .await
+                .unwrap();
+            let received = LocalStream::recv_datagram(&mut read).await.unwrap();
+
+            assert_eq!(received.as_ptr(), data.as_ptr());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_reaches_peer() {
+        let (client, server) = connection_pair().await.unwrap();
+
+        client.close(0, "bye").await.unwrap();
+
+        assert_eq!(
+            server.closed().await,
+            ConnectionState::Closed {
+                reason: "bye".into()
+            }
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc,
+};
+use tokio_util::sync::PollSender;
+
+use crate::{
+    schema::{IntegrityType, StreamId},
+    utp::{MessageChannel, UTPStream, error::UTPError},
+};
+
+/// Number of writes or messages a stream buffers before the writer waits.
+const QUEUE_LEN: usize = 64;
+
+/// A stream of a `LocalUTP` pair.
+///
+/// Messages sent with `send_datagram` are handed to the peer as the same
+/// `Bytes`, on streams of either integrity type. Reliable streams can also be
+/// turned into a `Message` channel, which the Primary Messaging Channel uses
+/// instead of encoding messages.
+pub struct LocalStream {
+    id: StreamId,
+    integrity: IntegrityType,
+    writer: LocalStreamWrite,
+    reader: LocalStreamRead,
+    messages: Option<MessageChannel>,
+}
+
+/// Write half of a `LocalStream`.
+pub struct LocalStreamWrite {
+    tx: PollSender<Bytes>,
+}
+
+/// Read half of a `LocalStream`.
+pub struct LocalStreamRead {
+    rx: mpsc::Receiver<Bytes>,
+    pending: Bytes,
+}
+
+/// Creates both ends of a stream.
+pub(crate) fn pair(id: StreamId, integrity: IntegrityType) -> (LocalStream, LocalStream) {
+    let (a_tx, a_rx) = mpsc::channel(QUEUE_LEN);
+    let (b_tx, b_rx) = mpsc::channel(QUEUE_LEN);
+
+    let (a_messages, b_messages) = if integrity == IntegrityType::Reliable {
+        let (a_tx, a_rx) = mpsc::unbounded_channel();
+        let (b_tx, b_rx) = mpsc::unbounded_channel();
+
+        (
+            Some(MessageChannel { tx: a_tx, rx: b_rx }),
+            Some(MessageChannel { tx: b_tx, rx: a_rx }),
+        )
+    } else {
+        (None, None)
+    };
+
+    (
+        LocalStream::new(id, integrity.clone(), a_tx, b_rx, a_messages),
+        LocalStream::new(id, integrity, b_tx, a_rx, b_messages),
+    )
+}
+
+impl LocalStream {
+    fn new(
+        id: StreamId,
+        integrity: IntegrityType,
+        tx: mpsc::Sender<Bytes>,
+        rx: mpsc::Receiver<Bytes>,
+        messages: Option<MessageChannel>,
+    ) -> Self {
+        Self {
+            id,
+            integrity,
+            writer: LocalStreamWrite {
+                tx: PollSender::new(tx),
+            },
+            reader: LocalStreamRead {
+                rx,
+                pending: Bytes::new(),
+            },
+            messages,
+        }
+    }
+}
+
+#[async_trait]
+impl UTPStream for LocalStream {
+    type StreamRead = LocalStreamRead;
+    type StreamWrite = LocalStreamWrite;
+
+    fn id(&self) -> StreamId {
+        self.id
+    }
+
+    fn integrity_type(&self) -> IntegrityType {
+        self.integrity.clone()
+    }
+
+    fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
+        (self.writer, self.reader)
+    }
+
+    /// Hands `data` to the peer as is, without framing or copying.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        let tx = writer.tx.get_ref().ok_or_else(broken_pipe)?;
+        tx.send(data).await.map_err(|_| broken_pipe())?;
+
+        Ok(())
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        // left over from a partial `AsyncRead`
+        if !reader.pending.is_empty() {
+            return Ok(std::mem::take(&mut reader.pending));
+        }
+
+        match reader.rx.recv().await {
+            Some(data) => Ok(data),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream closed").into()),
+        }
+    }
+
+    fn into_messages(mut self) -> Result<MessageChannel, Self> {
+        match self.messages.take() {
+            Some(messages) => Ok(messages),
+            None => Err(self),
+        }
+    }
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "stream closed")
+}
+
+impl AsyncWrite for LocalStreamWrite {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        ready!(self.tx.poll_reserve(cx)).map_err(|_| broken_pipe())?;
+        self.tx
+            .send_item(Bytes::copy_from_slice(buf))
+            .map_err(|_| broken_pipe())?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.tx.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for LocalStreamRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.pending.is_empty() {
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(data) => self.pending = data,
+                // end of stream
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.pending.len().min(buf.remaining());
+        let data = self.pending.split_to(len);
+        buf.put_slice(&data);
+
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use tokio::sync::{
+    Mutex, Notify,
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    local::stream::{self, LocalStream},
+    schema::{IntegrityType, StreamId},
+    utp::{UTP, UTPEvent, error::UTPError},
+};
+
+/// One side of an in-process transport pair, created with `pair`.
+///
+/// Closing or dropping either side closes the pair. Since it lives in
+/// memory, it has no addresses and reports no statistics.
+pub struct LocalUTP {
+    local: Arc<Side>,
+    peer: Arc<Side>,
+    stream_ids: Arc<AtomicU64>,
+    shutdown: CancellationToken,
+}
+
+struct Side {
+    events_tx: UnboundedSender<UTPEvent>,
+    events_rx: Mutex<UnboundedReceiver<UTPEvent>>,
+    /// Set once a terminal event has been returned by `next_event`.
+    finished: AtomicBool,
+    streams: DashMap<StreamId, LocalStream>,
+    notify: Notify,
+}
+
+impl Side {
+    fn new() -> Arc<Self> {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        Arc::new(Self {
+            events_tx,
+            events_rx: Mutex::new(events_rx),
+            finished: AtomicBool::new(false),
+            streams: Default::default(),
+            notify: Notify::new(),
+        })
+    }
+
+    fn add_event(&self, event: UTPEvent) {
+        let _ = self.events_tx.send(event);
+    }
+}
+
+/// Creates a connected pair of in-process transports.
+///
+/// The first transport is meant for `accept`, the second for `connect`.
+pub fn pair() -> (LocalUTP, LocalUTP) {
+    let a = Side::new();
+    let b = Side::new();
+    let stream_ids = Arc::new(AtomicU64::new(0));
+    let shutdown = CancellationToken::new();
+
+    (
+        LocalUTP {
+            local: a.clone(),
+            peer: b.clone(),
+            stream_ids: stream_ids.clone(),
+            shutdown: shutdown.clone(),
+        },
+        LocalUTP {
+            local: b,
+            peer: a,
+            stream_ids,
+            shutdown,
+        },
+    )
+}
+
+impl LocalUTP {
+    fn take_down(&self, event: UTPEvent) {
+        if !self.shutdown.is_cancelled() {
+            self.shutdown.cancel();
+            self.local.add_event(event.clone());
+            self.peer.add_event(event);
+        }
+    }
+
+    fn ensure_up(&self) -> Result<(), UTPError> {
+        if self.shutdown.is_cancelled() {
+            Err(UTPError::Fatal("transport is closed".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for LocalUTP {
+    fn drop(&mut self) {
+        self.take_down(UTPEvent::UnexpectedClose);
+    }
+}
+
+#[async_trait]
+impl UTP for LocalUTP {
+    type Stream = LocalStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.ensure_up()
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        if self.local.finished.load(Ordering::Acquire) {
+            return UTPEvent::UnexpectedClose;
+        }
+
+        // the side holds its own sender, so the channel never closes
+        let event = self
+            .local
+            .events_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .unwrap_or(UTPEvent::UnexpectedClose);
+
+        if event.is_terminal() {
+            self.local.finished.store(true, Ordering::Release);
+        }
+
+        event
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<LocalStream, UTPError> {
+        self.ensure_up()?;
+
+        let id = self.stream_ids.fetch_add(1, Ordering::Relaxed);
+        let (local, peer) = stream::pair(id, integrity);
+
+        self.peer.streams.insert(id, peer);
+        self.peer.notify.notify_waiters();
+        self.peer.add_event(UTPEvent::NewStream(id));
+
+        Ok(local)
+    }
+
+    async fn wait_stream(&self, id: StreamId, _: IntegrityType) -> Result<LocalStream, UTPError> {
+        loop {
+            let notified = self.local.notify.notified();
+            tokio::pin!(notified);
+            // register before checking, so an insert in between is not missed
+            notified.as_mut().enable();
+
+            if let Some((_, stream)) = self.local.streams.remove(&id) {
+                return Ok(stream);
+            }
+
+            tokio::select! {
+                _ = notified => {}
+                _ = self.shutdown.cancelled() => self.ensure_up()?,
+            }
+        }
+    }
+
+    async fn close(&self, _code: u64, reason: &str) -> Result<(), UTPError> {
+        self.take_down(UTPEvent::Closed {
+            reason: reason.to_string(),
+        });
+
+        Ok(())
+    }
+}
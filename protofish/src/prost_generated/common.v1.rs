@@ -0,0 +1,70 @@
+// This file is @generated by prost-build.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Version {
+    #[prost(uint32, tag = "1")]
+    pub major: u32,
+    #[prost(uint32, tag = "2")]
+    pub minor: u32,
+    #[prost(uint32, tag = "3")]
+    pub patch: u32,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct StreamCreateMeta {
+    #[prost(enumeration = "IntegrityType", tag = "1")]
+    pub stream_integrity: i32,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum IntegrityType {
+    Unspecified = 0,
+    Reliable = 1,
+    Unreliable = 2,
+}
+impl IntegrityType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "INTEGRITY_TYPE_UNSPECIFIED",
+            Self::Reliable => "INTEGRITY_TYPE_RELIABLE",
+            Self::Unreliable => "INTEGRITY_TYPE_UNRELIABLE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "INTEGRITY_TYPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "INTEGRITY_TYPE_RELIABLE" => Some(Self::Reliable),
+            "INTEGRITY_TYPE_UNRELIABLE" => Some(Self::Unreliable),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorType {
+    Unspecified = 0,
+    Timeout = 1,
+}
+impl ErrorType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "ERROR_TYPE_UNSPECIFIED",
+            Self::Timeout => "ERROR_TYPE_TIMEOUT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ERROR_TYPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "ERROR_TYPE_TIMEOUT" => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Message {
+    #[prost(uint64, tag = "1")]
+    pub context_id: u64,
+    #[prost(message, optional, tag = "2")]
+    pub payload: ::core::option::Option<Payload>,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Payload {
+    #[prost(oneof = "payload::Payload", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11")]
+    pub payload: ::core::option::Option<payload::Payload>,
+}
+/// Nested message and enum types in `Payload`.
+pub mod payload {
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum Payload {
+        #[prost(message, tag = "1")]
+        ClientHello(super::ClientHello),
+        #[prost(message, tag = "2")]
+        ServerHello(super::ServerHello),
+        #[prost(message, tag = "3")]
+        Ok(super::Ok),
+        #[prost(message, tag = "4")]
+        Error(super::Error),
+        #[prost(message, tag = "5")]
+        StreamOpen(super::StreamOpen),
+        #[prost(message, tag = "6")]
+        StreamClose(super::StreamClose),
+        #[prost(message, tag = "7")]
+        ArbitaryData(super::ArbitaryData),
+        #[prost(message, tag = "8")]
+        Keepalive(super::Keepalive),
+        #[prost(message, tag = "9")]
+        Close(super::Close),
+        #[prost(message, tag = "10")]
+        BenchmarkStart(super::BenchmarkStart),
+        #[prost(message, tag = "11")]
+        BenchmarkEnd(super::BenchmarkEnd),
+    }
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ClientHello {
+    #[prost(message, optional, tag = "1")]
+    pub version: ::core::option::Option<super::super::common::v1::Version>,
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub resume_connection_token: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ServerHello {
+    #[prost(message, optional, tag = "1")]
+    pub version: ::core::option::Option<super::super::common::v1::Version>,
+    #[prost(bool, tag = "2")]
+    pub ok: bool,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub connection_token: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(string, optional, tag = "4")]
+    pub message: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Ok {}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Error {
+    #[prost(enumeration = "super::super::common::v1::ErrorType", tag = "1")]
+    pub error_type: i32,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct StreamOpen {
+    #[prost(uint64, tag = "1")]
+    pub stream_id: u64,
+    #[prost(message, optional, tag = "2")]
+    pub meta: ::core::option::Option<super::super::common::v1::StreamCreateMeta>,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct StreamClose {
+    #[prost(uint64, tag = "1")]
+    pub stream_id: u64,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct ArbitaryData {
+    #[prost(bytes = "vec", tag = "1")]
+    pub content: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Keepalive {}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Close {}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct BenchmarkStart {
+    #[prost(enumeration = "super::super::common::v1::IntegrityType", tag = "1")]
+    pub integrity_type: i32,
+    #[prost(uint64, tag = "2")]
+    pub byte_count: u64,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct BenchmarkEnd {}
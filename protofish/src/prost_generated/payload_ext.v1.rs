@@ -0,0 +1,73 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Message {
+    #[prost(uint64, tag = "1")]
+    pub context_id: u64,
+    #[prost(message, optional, tag = "2")]
+    pub payload: ::core::option::Option<Payload>,
+    /// Remaining time budget of the context, set on its first message only.
+    #[prost(uint64, optional, tag = "3")]
+    pub budget_millis: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Payload {
+    #[prost(
+        oneof = "payload::Payload",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13"
+    )]
+    pub payload: ::core::option::Option<payload::Payload>,
+}
+/// Nested message and enum types in `Payload`.
+pub mod payload {
+    #[derive(Clone, PartialEq, Eq, Hash, ::prost::Oneof)]
+    pub enum Payload {
+        #[prost(message, tag = "1")]
+        ClientHello(super::super::super::payload::v1::ClientHello),
+        #[prost(message, tag = "2")]
+        ServerHello(super::super::super::payload::v1::ServerHello),
+        #[prost(message, tag = "3")]
+        Ok(super::super::super::payload::v1::Ok),
+        #[prost(message, tag = "4")]
+        Error(super::super::super::payload::v1::Error),
+        #[prost(message, tag = "5")]
+        StreamOpen(super::super::super::payload::v1::StreamOpen),
+        #[prost(message, tag = "6")]
+        StreamClose(super::super::super::payload::v1::StreamClose),
+        #[prost(message, tag = "7")]
+        ArbitaryData(super::super::super::payload::v1::ArbitaryData),
+        #[prost(message, tag = "8")]
+        Keepalive(super::super::super::payload::v1::Keepalive),
+        #[prost(message, tag = "9")]
+        Close(super::super::super::payload::v1::Close),
+        #[prost(message, tag = "10")]
+        BenchmarkStart(super::super::super::payload::v1::BenchmarkStart),
+        #[prost(message, tag = "11")]
+        BenchmarkEnd(super::super::super::payload::v1::BenchmarkEnd),
+        #[prost(message, tag = "12")]
+        StreamReport(super::StreamReport),
+        #[prost(message, tag = "13")]
+        Cancel(super::Cancel),
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct StreamReport {
+    #[prost(uint64, tag = "1")]
+    pub stream_id: u64,
+    #[prost(uint64, tag = "2")]
+    pub highest_sequence: u64,
+    #[prost(uint64, tag = "3")]
+    pub received: u64,
+    #[prost(uint64, tag = "4")]
+    pub lost: u64,
+    #[prost(uint64, tag = "5")]
+    pub duplicates: u64,
+    #[prost(uint64, tag = "6")]
+    pub reordered: u64,
+    #[prost(uint64, tag = "7")]
+    pub jitter_micros: u64,
+}
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Cancel {
+    #[prost(string, tag = "1")]
+    pub reason: ::prost::alloc::string::String,
+}
@@ -1,3 +1,31 @@
+//! Protocol messages, converted to and from their protobuf form on the wire.
+//!
+//! # JSON representation
+//!
+//! With the `serde` feature, every type here implements `Serialize` and
+//! `Deserialize`. The representation is stable across releases:
+//!
+//! - Structs are objects keyed by their field names.
+//! - Enums without data, such as [`IntegrityType`] and [`ErrorType`], are
+//!   snake_case strings, e.g. `"reliable"`.
+//! - [`Payload`] is an object tagged by a snake_case `"type"` field, next to
+//!   the fields of the variant, e.g. `{"type": "stream_close", "stream_id": 1}`
+//!   or `{"type": "ok"}`.
+//! - Byte strings are standard, padded base64.
+//! - [`Message::budget`] is the integer `budget_millis`.
+//! - Unset optional fields are `null`, and may be left out when deserializing.
+//!
+//! ```json
+//! {
+//!   "context_id": 2,
+//!   "payload": { "type": "arbitary_data", "content": "aGk=" },
+//!   "budget_millis": null
+//! }
+//! ```
+
+mod error;
+pub use error::SchemaError;
+
 mod common {
     mod schema;
     mod transform;
@@ -12,3 +40,6 @@ mod payload {
 
 pub use common::*;
 pub use payload::*;
+
+#[cfg(feature = "serde")]
+mod serde_helpers;
@@ -1,6 +1,9 @@
 use crate::{
     prost_generated::common::{self},
-    schema::common::schema::{ErrorType, IntegrityType, StreamCreateMeta, Version},
+    schema::{
+        SchemaError,
+        common::schema::{ErrorType, IntegrityType, StreamCreateMeta, Version},
+    },
 };
 
 impl From<common::v1::Version> for Version {
@@ -23,13 +26,37 @@ impl From<Version> for common::v1::Version {
     }
 }
 
-impl From<common::v1::StreamCreateMeta> for StreamCreateMeta {
-    fn from(value: common::v1::StreamCreateMeta) -> Self {
-        StreamCreateMeta {
-            integrity_type: common::v1::IntegrityType::try_from(value.stream_integrity)
-                .unwrap()
-                .into(),
-        }
+impl TryFrom<common::v1::StreamCreateMeta> for StreamCreateMeta {
+    type Error = SchemaError;
+
+    fn try_from(value: common::v1::StreamCreateMeta) -> Result<Self, SchemaError> {
+        Ok(StreamCreateMeta {
+            integrity_type: IntegrityType::from_i32(value.stream_integrity)?,
+        })
+    }
+}
+
+impl IntegrityType {
+    /// Converts the wire value of an integrity type.
+    pub(crate) fn from_i32(value: i32) -> Result<Self, SchemaError> {
+        common::v1::IntegrityType::try_from(value)
+            .map(Into::into)
+            .map_err(|_| SchemaError::UnknownEnumValue {
+                name: "integrity type",
+                value,
+            })
+    }
+}
+
+impl ErrorType {
+    /// Converts the wire value of an error type.
+    pub(crate) fn from_i32(value: i32) -> Result<Self, SchemaError> {
+        common::v1::ErrorType::try_from(value)
+            .map(Into::into)
+            .map_err(|_| SchemaError::UnknownEnumValue {
+                name: "error type",
+                value,
+            })
     }
 }
 
@@ -96,8 +123,22 @@ mod tests {
         let proto_meta = common::v1::StreamCreateMeta {
             stream_integrity: common::v1::IntegrityType::Reliable.into(),
         };
-        let schema_meta: StreamCreateMeta = proto_meta.clone().into();
-        assert!(matches!(schema_meta.integrity_type, IntegrityType::Reliable));
+        let schema_meta: StreamCreateMeta = proto_meta.try_into().unwrap();
+        assert!(matches!(
+            schema_meta.integrity_type,
+            IntegrityType::Reliable
+        ));
+
+        let proto_meta = common::v1::StreamCreateMeta {
+            stream_integrity: 42,
+        };
+        assert_eq!(
+            StreamCreateMeta::try_from(proto_meta).unwrap_err(),
+            SchemaError::UnknownEnumValue {
+                name: "integrity type",
+                value: 42
+            }
+        );
 
         // The into() call for StreamCreateMeta is not implemented, so we skip that part of the test
     }
@@ -137,10 +178,7 @@ mod tests {
         assert!(matches!(schema_timeout, ErrorType::Timeout));
 
         let schema_unspecified_back: common::v1::ErrorType = ErrorType::Unspecified.into();
-        assert_eq!(
-            schema_unspecified_back,
-            common::v1::ErrorType::Unspecified
-        );
+        assert_eq!(schema_unspecified_back, common::v1::ErrorType::Unspecified);
 
         let schema_timeout_back: common::v1::ErrorType = ErrorType::Timeout.into();
         assert_eq!(schema_timeout_back, common::v1::ErrorType::Timeout);
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+/// Reasons bytes received from a peer are not a valid schema message.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The bytes are not a protobuf `Message`
+    #[error("invalid protobuf: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    /// A field the schema requires is not set
+    #[error("missing {0}")]
+    MissingField(&'static str),
+
+    /// An enum field holds a value this version does not know
+    #[error("unknown {name} {value}")]
+    UnknownEnumValue { name: &'static str, value: i32 },
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 
 use crate::schema::{IntegrityType, Version};
@@ -6,12 +8,25 @@ pub type ContextId = u64;
 pub type StreamId = u64;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
     pub context_id: ContextId,
     pub payload: Payload,
+    /// Remaining time budget of the context, carried by its first message.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "budget_millis",
+            default,
+            with = "crate::schema::serde_helpers::millis"
+        )
+    )]
+    pub budget: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
 pub enum Payload {
     ClientHello(ClientHello),
     ServerHello(ServerHello),
@@ -24,46 +39,109 @@ pub enum Payload {
     Close,
     BenchmarkStart(BenchmarkStart),
     BenchmarkEnd,
+    StreamReport(StreamReport),
+    Cancel(Cancel),
+}
+
+impl Payload {
+    /// Returns the name of the variant, e.g. `"StreamOpen"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Payload::ClientHello(_) => "ClientHello",
+            Payload::ServerHello(_) => "ServerHello",
+            Payload::Ok => "Ok",
+            Payload::Error(_) => "Error",
+            Payload::StreamOpen(_) => "StreamOpen",
+            Payload::StreamClose(_) => "StreamClose",
+            Payload::ArbitaryData(_) => "ArbitaryData",
+            Payload::Keepalive => "Keepalive",
+            Payload::Close => "Close",
+            Payload::BenchmarkStart(_) => "BenchmarkStart",
+            Payload::BenchmarkEnd => "BenchmarkEnd",
+            Payload::StreamReport(_) => "StreamReport",
+            Payload::Cancel(_) => "Cancel",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClientHello {
     pub version: Version,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::schema::serde_helpers::base64_option")
+    )]
     pub resume_connection_token: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServerHello {
     pub version: Version,
     pub ok: bool,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "crate::schema::serde_helpers::base64_option")
+    )]
     pub connection_token: Option<Bytes>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub message: Option<String>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Error {
     pub error_type: crate::schema::ErrorType,
     pub message: String,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StreamOpen {
     pub stream_id: StreamId,
     pub meta: crate::schema::StreamCreateMeta,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StreamClose {
     pub stream_id: StreamId,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArbitaryData {
-    pub content: Vec<u8>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::schema::serde_helpers::base64")
+    )]
+    pub content: Bytes,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BenchmarkStart {
     pub integrity_type: IntegrityType,
     pub byte_count: u64,
 }
+
+/// Receiver-side statistics of a sequenced stream, sent back to the sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamReport {
+    pub stream_id: StreamId,
+    pub highest_sequence: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub duplicates: u64,
+    pub reordered: u64,
+    pub jitter_micros: u64,
+}
+
+/// Tells the peer that the sender is no longer interested in a context.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cancel {
+    pub reason: String,
+}
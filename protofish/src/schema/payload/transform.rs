@@ -1,102 +1,139 @@
+use std::time::Duration;
+
 use crate::{
-    prost_generated::common::v1 as common_v1, prost_generated::payload::v1 as payload_v1,
-    schema as common_schema, schema::payload::schema as payload_schema,
+    prost_generated::common::v1 as common_v1,
+    prost_generated::payload::v1 as payload_v1,
+    prost_generated::payload_ext::v1 as payload_ext_v1,
+    schema as common_schema,
+    schema::{SchemaError, payload::schema as payload_schema},
 };
 
-impl From<payload_v1::Message> for payload_schema::Message {
-    fn from(value: payload_v1::Message) -> Self {
-        payload_schema::Message {
+impl TryFrom<payload_ext_v1::Message> for payload_schema::Message {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_ext_v1::Message) -> Result<Self, SchemaError> {
+        Ok(payload_schema::Message {
             context_id: value.context_id,
-            payload: value.payload.unwrap().into(),
-        }
+            payload: value
+                .payload
+                .ok_or(SchemaError::MissingField("payload"))?
+                .try_into()?,
+            budget: value.budget_millis.map(Duration::from_millis),
+        })
     }
 }
 
-impl From<payload_schema::Message> for payload_v1::Message {
+impl From<payload_schema::Message> for payload_ext_v1::Message {
     fn from(value: payload_schema::Message) -> Self {
-        payload_v1::Message {
+        payload_ext_v1::Message {
             context_id: value.context_id,
             payload: Some(value.payload.into()),
+            budget_millis: value.budget.map(|budget| budget.as_millis() as u64),
         }
     }
 }
 
-impl From<payload_v1::Payload> for payload_schema::Payload {
-    fn from(value: payload_v1::Payload) -> Self {
-        match value.payload.unwrap() {
-            payload_v1::payload::Payload::ClientHello(v) => {
-                payload_schema::Payload::ClientHello(v.into())
+impl TryFrom<payload_ext_v1::Payload> for payload_schema::Payload {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_ext_v1::Payload) -> Result<Self, SchemaError> {
+        let payload = value.payload.ok_or(SchemaError::MissingField("payload"))?;
+
+        Ok(match payload {
+            payload_ext_v1::payload::Payload::ClientHello(v) => {
+                payload_schema::Payload::ClientHello(v.try_into()?)
+            }
+            payload_ext_v1::payload::Payload::Ok(_) => payload_schema::Payload::Ok,
+            payload_ext_v1::payload::Payload::Error(v) => {
+                payload_schema::Payload::Error(v.try_into()?)
             }
-            payload_v1::payload::Payload::Ok(_) => payload_schema::Payload::Ok,
-            payload_v1::payload::Payload::Error(v) => payload_schema::Payload::Error(v.into()),
-            payload_v1::payload::Payload::StreamOpen(v) => {
-                payload_schema::Payload::StreamOpen(v.into())
+            payload_ext_v1::payload::Payload::StreamOpen(v) => {
+                payload_schema::Payload::StreamOpen(v.try_into()?)
             }
-            payload_v1::payload::Payload::StreamClose(v) => {
+            payload_ext_v1::payload::Payload::StreamClose(v) => {
                 payload_schema::Payload::StreamClose(v.into())
             }
-            payload_v1::payload::Payload::ArbitaryData(v) => {
+            payload_ext_v1::payload::Payload::ArbitaryData(v) => {
                 payload_schema::Payload::ArbitaryData(v.into())
             }
-            payload_v1::payload::Payload::Keepalive(_) => payload_schema::Payload::Keepalive,
-            payload_v1::payload::Payload::ServerHello(v) => {
-                payload_schema::Payload::ServerHello(v.into())
+            payload_ext_v1::payload::Payload::Keepalive(_) => payload_schema::Payload::Keepalive,
+            payload_ext_v1::payload::Payload::ServerHello(v) => {
+                payload_schema::Payload::ServerHello(v.try_into()?)
             }
-            payload_v1::payload::Payload::Close(_) => payload_schema::Payload::Close,
-            payload_v1::payload::Payload::BenchmarkStart(v) => {
-                payload_schema::Payload::BenchmarkStart(v.into())
+            payload_ext_v1::payload::Payload::Close(_) => payload_schema::Payload::Close,
+            payload_ext_v1::payload::Payload::BenchmarkStart(v) => {
+                payload_schema::Payload::BenchmarkStart(v.try_into()?)
             }
-            payload_v1::payload::Payload::BenchmarkEnd(_) => payload_schema::Payload::BenchmarkEnd,
-        }
+            payload_ext_v1::payload::Payload::BenchmarkEnd(_) => {
+                payload_schema::Payload::BenchmarkEnd
+            }
+            payload_ext_v1::payload::Payload::StreamReport(v) => {
+                payload_schema::Payload::StreamReport(v.into())
+            }
+            payload_ext_v1::payload::Payload::Cancel(v) => {
+                payload_schema::Payload::Cancel(v.into())
+            }
+        })
     }
 }
 
-impl From<payload_schema::Payload> for payload_v1::Payload {
+impl From<payload_schema::Payload> for payload_ext_v1::Payload {
     fn from(value: payload_schema::Payload) -> Self {
         let payload = match value {
             payload_schema::Payload::ClientHello(v) => {
-                payload_v1::payload::Payload::ClientHello(v.into())
+                payload_ext_v1::payload::Payload::ClientHello(v.into())
             }
-            payload_schema::Payload::Ok => payload_v1::payload::Payload::Ok(payload_v1::Ok {}),
-            payload_schema::Payload::Error(v) => payload_v1::payload::Payload::Error(v.into()),
+            payload_schema::Payload::Ok => payload_ext_v1::payload::Payload::Ok(payload_v1::Ok {}),
+            payload_schema::Payload::Error(v) => payload_ext_v1::payload::Payload::Error(v.into()),
             payload_schema::Payload::StreamOpen(v) => {
-                payload_v1::payload::Payload::StreamOpen(v.into())
+                payload_ext_v1::payload::Payload::StreamOpen(v.into())
             }
             payload_schema::Payload::StreamClose(v) => {
-                payload_v1::payload::Payload::StreamClose(v.into())
+                payload_ext_v1::payload::Payload::StreamClose(v.into())
             }
             payload_schema::Payload::ArbitaryData(v) => {
-                payload_v1::payload::Payload::ArbitaryData(v.into())
+                payload_ext_v1::payload::Payload::ArbitaryData(v.into())
             }
             payload_schema::Payload::Keepalive => {
-                payload_v1::payload::Payload::Keepalive(payload_v1::Keepalive {})
+                payload_ext_v1::payload::Payload::Keepalive(payload_v1::Keepalive {})
             }
             payload_schema::Payload::ServerHello(v) => {
-                payload_v1::payload::Payload::ServerHello(v.into())
+                payload_ext_v1::payload::Payload::ServerHello(v.into())
             }
             payload_schema::Payload::Close => {
-                payload_v1::payload::Payload::Close(payload_v1::Close {})
+                payload_ext_v1::payload::Payload::Close(payload_v1::Close {})
             }
             payload_schema::Payload::BenchmarkStart(v) => {
-                payload_v1::payload::Payload::BenchmarkStart(v.into())
+                payload_ext_v1::payload::Payload::BenchmarkStart(v.into())
             }
             payload_schema::Payload::BenchmarkEnd => {
-                payload_v1::payload::Payload::BenchmarkEnd(payload_v1::BenchmarkEnd {})
+                payload_ext_v1::payload::Payload::BenchmarkEnd(payload_v1::BenchmarkEnd {})
+            }
+            payload_schema::Payload::StreamReport(v) => {
+                payload_ext_v1::payload::Payload::StreamReport(v.into())
+            }
+            payload_schema::Payload::Cancel(v) => {
+                payload_ext_v1::payload::Payload::Cancel(v.into())
             }
         };
 
-        payload_v1::Payload {
+        payload_ext_v1::Payload {
             payload: Some(payload),
         }
     }
 }
 
-impl From<payload_v1::ClientHello> for payload_schema::ClientHello {
-    fn from(value: payload_v1::ClientHello) -> Self {
-        payload_schema::ClientHello {
-            version: value.version.unwrap().into(),
+impl TryFrom<payload_v1::ClientHello> for payload_schema::ClientHello {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_v1::ClientHello) -> Result<Self, SchemaError> {
+        Ok(payload_schema::ClientHello {
+            version: value
+                .version
+                .ok_or(SchemaError::MissingField("version"))?
+                .into(),
             resume_connection_token: value.resume_connection_token,
-        }
+        })
     }
 }
 
@@ -109,14 +146,19 @@ impl From<payload_schema::ClientHello> for payload_v1::ClientHello {
     }
 }
 
-impl From<payload_v1::ServerHello> for payload_schema::ServerHello {
-    fn from(value: payload_v1::ServerHello) -> Self {
-        payload_schema::ServerHello {
-            version: value.version.unwrap().into(),
+impl TryFrom<payload_v1::ServerHello> for payload_schema::ServerHello {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_v1::ServerHello) -> Result<Self, SchemaError> {
+        Ok(payload_schema::ServerHello {
+            version: value
+                .version
+                .ok_or(SchemaError::MissingField("version"))?
+                .into(),
             ok: value.ok,
             connection_token: value.connection_token.map(Into::into),
             message: value.message,
-        }
+        })
     }
 }
 
@@ -131,14 +173,14 @@ impl From<payload_schema::ServerHello> for payload_v1::ServerHello {
     }
 }
 
-impl From<payload_v1::Error> for payload_schema::Error {
-    fn from(value: payload_v1::Error) -> Self {
-        payload_schema::Error {
-            error_type: common_v1::ErrorType::try_from(value.error_type)
-                .unwrap()
-                .into(),
+impl TryFrom<payload_v1::Error> for payload_schema::Error {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_v1::Error) -> Result<Self, SchemaError> {
+        Ok(payload_schema::Error {
+            error_type: common_schema::ErrorType::from_i32(value.error_type)?,
             message: value.message,
-        }
+        })
     }
 }
 
@@ -151,12 +193,17 @@ impl From<payload_schema::Error> for payload_v1::Error {
     }
 }
 
-impl From<payload_v1::StreamOpen> for payload_schema::StreamOpen {
-    fn from(value: payload_v1::StreamOpen) -> Self {
-        payload_schema::StreamOpen {
+impl TryFrom<payload_v1::StreamOpen> for payload_schema::StreamOpen {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_v1::StreamOpen) -> Result<Self, SchemaError> {
+        Ok(payload_schema::StreamOpen {
             stream_id: value.stream_id,
-            meta: value.meta.unwrap().into(),
-        }
+            meta: value
+                .meta
+                .ok_or(SchemaError::MissingField("stream meta"))?
+                .try_into()?,
+        })
     }
 }
 
@@ -188,7 +235,7 @@ impl From<payload_schema::StreamClose> for payload_v1::StreamClose {
 impl From<payload_v1::ArbitaryData> for payload_schema::ArbitaryData {
     fn from(value: payload_v1::ArbitaryData) -> Self {
         payload_schema::ArbitaryData {
-            content: value.content,
+            content: value.content.into(),
         }
     }
 }
@@ -196,19 +243,19 @@ impl From<payload_v1::ArbitaryData> for payload_schema::ArbitaryData {
 impl From<payload_schema::ArbitaryData> for payload_v1::ArbitaryData {
     fn from(value: payload_schema::ArbitaryData) -> Self {
         payload_v1::ArbitaryData {
-            content: value.content,
+            content: value.content.into(),
         }
     }
 }
 
-impl From<payload_v1::BenchmarkStart> for payload_schema::BenchmarkStart {
-    fn from(value: payload_v1::BenchmarkStart) -> Self {
-        payload_schema::BenchmarkStart {
-            integrity_type: common_v1::IntegrityType::try_from(value.integrity_type)
-                .unwrap()
-                .into(),
+impl TryFrom<payload_v1::BenchmarkStart> for payload_schema::BenchmarkStart {
+    type Error = SchemaError;
+
+    fn try_from(value: payload_v1::BenchmarkStart) -> Result<Self, SchemaError> {
+        Ok(payload_schema::BenchmarkStart {
+            integrity_type: common_schema::IntegrityType::from_i32(value.integrity_type)?,
             byte_count: value.byte_count,
-        }
+        })
     }
 }
 
@@ -221,6 +268,50 @@ impl From<payload_schema::BenchmarkStart> for payload_v1::BenchmarkStart {
     }
 }
 
+impl From<payload_ext_v1::StreamReport> for payload_schema::StreamReport {
+    fn from(value: payload_ext_v1::StreamReport) -> Self {
+        payload_schema::StreamReport {
+            stream_id: value.stream_id,
+            highest_sequence: value.highest_sequence,
+            received: value.received,
+            lost: value.lost,
+            duplicates: value.duplicates,
+            reordered: value.reordered,
+            jitter_micros: value.jitter_micros,
+        }
+    }
+}
+
+impl From<payload_schema::StreamReport> for payload_ext_v1::StreamReport {
+    fn from(value: payload_schema::StreamReport) -> Self {
+        payload_ext_v1::StreamReport {
+            stream_id: value.stream_id,
+            highest_sequence: value.highest_sequence,
+            received: value.received,
+            lost: value.lost,
+            duplicates: value.duplicates,
+            reordered: value.reordered,
+            jitter_micros: value.jitter_micros,
+        }
+    }
+}
+
+impl From<payload_ext_v1::Cancel> for payload_schema::Cancel {
+    fn from(value: payload_ext_v1::Cancel) -> Self {
+        payload_schema::Cancel {
+            reason: value.reason,
+        }
+    }
+}
+
+impl From<payload_schema::Cancel> for payload_ext_v1::Cancel {
+    fn from(value: payload_schema::Cancel) -> Self {
+        payload_ext_v1::Cancel {
+            reason: value.reason,
+        }
+    }
+}
+
 impl From<common_schema::StreamCreateMeta> for common_v1::StreamCreateMeta {
     fn from(value: common_schema::StreamCreateMeta) -> Self {
         common_v1::StreamCreateMeta {
@@ -256,20 +347,22 @@ mod tests {
 
     #[test]
     fn test_message_conversion() {
-        let proto_message = payload_v1::Message {
+        let proto_message = payload_ext_v1::Message {
             context_id: 123,
-            payload: Some(payload_v1::Payload {
-                payload: Some(payload_v1::payload::Payload::Ok(payload_v1::Ok {})),
+            payload: Some(payload_ext_v1::Payload {
+                payload: Some(payload_ext_v1::payload::Payload::Ok(payload_v1::Ok {})),
             }),
+            budget_millis: Some(1500),
         };
-        let schema_message: payload_schema::Message = proto_message.clone().into();
+        let schema_message: payload_schema::Message = proto_message.clone().try_into().unwrap();
         assert_eq!(schema_message.context_id, 123);
+        assert_eq!(schema_message.budget, Some(Duration::from_millis(1500)));
         assert!(matches!(
             schema_message.payload,
             payload_schema::Payload::Ok
         ));
 
-        let converted_proto: payload_v1::Message = schema_message.into();
+        let converted_proto: payload_ext_v1::Message = schema_message.into();
         assert_eq!(converted_proto, proto_message);
     }
 
@@ -284,22 +377,22 @@ mod tests {
             }),
             resume_connection_token: None,
         };
-        let payload = payload_v1::Payload {
-            payload: Some(payload_v1::payload::Payload::ClientHello(
+        let payload = payload_ext_v1::Payload {
+            payload: Some(payload_ext_v1::payload::Payload::ClientHello(
                 proto_client_hello,
             )),
         };
-        let schema_payload: payload_schema::Payload = payload.into();
+        let schema_payload: payload_schema::Payload = payload.try_into().unwrap();
         assert!(matches!(
             schema_payload,
             payload_schema::Payload::ClientHello(_)
         ));
 
         // Test OK
-        let payload = payload_v1::Payload {
-            payload: Some(payload_v1::payload::Payload::Ok(payload_v1::Ok {})),
+        let payload = payload_ext_v1::Payload {
+            payload: Some(payload_ext_v1::payload::Payload::Ok(payload_v1::Ok {})),
         };
-        let schema_payload: payload_schema::Payload = payload.into();
+        let schema_payload: payload_schema::Payload = payload.try_into().unwrap();
         assert!(matches!(schema_payload, payload_schema::Payload::Ok));
     }
 
@@ -313,7 +406,8 @@ mod tests {
             }),
             resume_connection_token: Some(vec![1, 2, 3]),
         };
-        let schema_client_hello: payload_schema::ClientHello = proto_client_hello.clone().into();
+        let schema_client_hello: payload_schema::ClientHello =
+            proto_client_hello.clone().try_into().unwrap();
         assert_eq!(schema_client_hello.version.major, 1);
         assert_eq!(
             schema_client_hello.resume_connection_token,
@@ -336,7 +430,8 @@ mod tests {
             connection_token: Some(vec![4, 5, 6]),
             message: Some("hi".into()),
         };
-        let schema_server_hello: payload_schema::ServerHello = proto_server_hello.clone().into();
+        let schema_server_hello: payload_schema::ServerHello =
+            proto_server_hello.clone().try_into().unwrap();
         assert_eq!(schema_server_hello.version.major, 1);
         assert!(schema_server_hello.ok);
         assert!(schema_server_hello.connection_token.is_some());
@@ -351,7 +446,7 @@ mod tests {
             error_type: common_v1::ErrorType::Timeout.into(),
             message: "Request timed out".to_string(),
         };
-        let schema_error: payload_schema::Error = proto_error.clone().into();
+        let schema_error: payload_schema::Error = proto_error.clone().try_into().unwrap();
         assert!(matches!(schema_error.error_type, ErrorType::Timeout));
         assert_eq!(schema_error.message, "Request timed out");
 
@@ -367,7 +462,7 @@ mod tests {
                 stream_integrity: common_v1::IntegrityType::Reliable.into(),
             }),
         };
-        let schema_stream_open: payload_schema::StreamOpen = proto_stream_open.clone().into();
+        let schema_stream_open: payload_schema::StreamOpen = proto_stream_open.try_into().unwrap();
         assert_eq!(schema_stream_open.stream_id, 12345);
         assert!(matches!(
             schema_stream_open.meta.integrity_type,
@@ -407,7 +502,7 @@ mod tests {
             byte_count: 1024,
         };
         let schema_benchmark_start: payload_schema::BenchmarkStart =
-            proto_benchmark_start.clone().into();
+            proto_benchmark_start.try_into().unwrap();
         assert!(matches!(
             schema_benchmark_start.integrity_type,
             IntegrityType::Unreliable
@@ -417,4 +512,23 @@ mod tests {
         let converted_proto: payload_v1::BenchmarkStart = schema_benchmark_start.into();
         assert_eq!(converted_proto, proto_benchmark_start);
     }
+
+    #[test]
+    fn test_stream_report_conversion() {
+        let proto_stream_report = payload_ext_v1::StreamReport {
+            stream_id: 7,
+            highest_sequence: 99,
+            received: 95,
+            lost: 5,
+            duplicates: 1,
+            reordered: 2,
+            jitter_micros: 1500,
+        };
+        let schema_stream_report: payload_schema::StreamReport = proto_stream_report.into();
+        assert_eq!(schema_stream_report.highest_sequence, 99);
+        assert_eq!(schema_stream_report.lost, 5);
+
+        let converted_proto: payload_ext_v1::StreamReport = schema_stream_report.into();
+        assert_eq!(converted_proto, proto_stream_report);
+    }
 }
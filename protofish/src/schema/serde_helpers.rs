@@ -0,0 +1,156 @@
+//! `serde(with = ...)` adapters for fields without a natural JSON form.
+
+/// Byte strings as standard, padded base64.
+pub(crate) mod base64 {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(encoded)
+            .map(T::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Optional byte strings as base64, or `null`.
+pub(crate) mod base64_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(
+        bytes: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => super::base64::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        #[derive(Deserialize)]
+        struct Encoded(#[serde(with = "super::base64")] Vec<u8>);
+
+        Ok(Option::<Encoded>::deserialize(deserializer)?.map(|Encoded(bytes)| T::from(bytes)))
+    }
+}
+
+/// Optional durations as whole milliseconds, or `null`.
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => {
+                serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use crate::schema::{
+        ArbitaryData, ClientHello, IntegrityType, Message, Payload, ServerHello, StreamCreateMeta,
+        StreamOpen, Version,
+    };
+
+    #[test]
+    fn test_message_json_representation() {
+        let message = Message {
+            context_id: 3,
+            payload: Payload::StreamOpen(StreamOpen {
+                stream_id: 7,
+                meta: StreamCreateMeta {
+                    integrity_type: IntegrityType::Unreliable,
+                },
+            }),
+            budget: Some(Duration::from_millis(1500)),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            json!({
+                "context_id": 3,
+                "payload": {
+                    "type": "stream_open",
+                    "stream_id": 7,
+                    "meta": { "integrity_type": "unreliable" }
+                },
+                "budget_millis": 1500
+            })
+        );
+
+        let ok: Message =
+            serde_json::from_value(json!({ "context_id": 1, "payload": { "type": "ok" } }))
+                .unwrap();
+        assert!(matches!(ok.payload, Payload::Ok));
+        assert_eq!(ok.budget, None);
+    }
+
+    #[test]
+    fn test_bytes_as_base64() {
+        let data = Payload::ArbitaryData(ArbitaryData {
+            content: Bytes::from_static(b"hi"),
+        });
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value, json!({ "type": "arbitary_data", "content": "aGk=" }));
+
+        let Payload::ArbitaryData(data) = serde_json::from_value(value).unwrap() else {
+            panic!("wrong variant");
+        };
+        assert_eq!(data.content, "hi");
+
+        let hello = Payload::ServerHello(ServerHello {
+            version: Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            },
+            ok: true,
+            connection_token: Some(Bytes::from_static(&[0xff])),
+            message: None,
+        });
+        let value = serde_json::to_value(&hello).unwrap();
+        assert_eq!(value["connection_token"], "/w==");
+        assert_eq!(value["message"], serde_json::Value::Null);
+
+        let hello: ClientHello =
+            serde_json::from_value(json!({ "version": { "major": 1, "minor": 0, "patch": 0 } }))
+                .unwrap();
+        assert_eq!(hello.resume_connection_token, None);
+
+        assert!(
+            serde_json::from_value::<ArbitaryData>(json!({ "content": "not base64!" })).is_err()
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Characteristics of one direction of a simulated link.
+///
+/// Loss and reordering only apply to unreliable streams; reliable streams are
+/// delayed but always delivered in order, like QUIC streams.
+#[derive(Clone, Debug)]
+pub struct LinkConfig {
+    /// One-way delay added to every message
+    pub latency: Duration,
+
+    /// Throughput of the link in bytes per second, shared by all streams in
+    /// the same direction. `None` means unlimited.
+    pub bandwidth: Option<u64>,
+
+    /// Probability in `[0, 1]` that an unreliable message is dropped
+    pub loss: f64,
+
+    /// Probability in `[0, 1]` that an unreliable message is held back by
+    /// `reorder_delay`, letting later messages overtake it
+    pub reorder: f64,
+
+    /// Extra delay of reordered messages
+    pub reorder_delay: Duration,
+
+    /// Largest message an unreliable stream accepts
+    pub max_datagram_size: usize,
+
+    /// Seed of the random number generator deciding loss and reordering
+    pub seed: u64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            bandwidth: None,
+            loss: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            max_datagram_size: 1200,
+            seed: 0,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Sets the one-way delay.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Limits the throughput to `bytes_per_second`.
+    pub fn with_bandwidth(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Sets the probability that an unreliable message is dropped.
+    pub fn with_loss(mut self, probability: f64) -> Self {
+        self.loss = probability;
+        self
+    }
+
+    /// Holds back unreliable messages by `delay` with the given probability.
+    pub fn with_reorder(mut self, probability: f64, delay: Duration) -> Self {
+        self.reorder = probability;
+        self.reorder_delay = delay;
+        self
+    }
+
+    /// Sets the largest message an unreliable stream accepts.
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// Sets the seed deciding loss and reordering.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// State shared by both sides of a link.
+pub(crate) struct Link {
+    /// Cancelled when the link goes down, stopping all deliveries.
+    pub(crate) shutdown: CancellationToken,
+    down: AtomicBool,
+    /// `true` while the link is partitioned, holding back all deliveries.
+    partitioned: watch::Sender<bool>,
+}
+
+impl Link {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            shutdown: CancellationToken::new(),
+            down: AtomicBool::new(false),
+            partitioned: watch::Sender::new(false),
+        })
+    }
+
+    /// Partitions or heals the link.
+    ///
+    /// While partitioned, unreliable messages are dropped, and reliable ones
+    /// are held until the link heals, as if retransmitted then.
+    pub(crate) fn set_partitioned(&self, partitioned: bool) {
+        self.partitioned.send_replace(partitioned);
+    }
+
+    pub(crate) fn is_partitioned(&self) -> bool {
+        *self.partitioned.borrow()
+    }
+
+    /// Takes the link down. Returns `false` if it was already down.
+    pub(crate) fn take_down(&self) -> bool {
+        let first = !self.down.swap(true, Ordering::AcqRel);
+        self.shutdown.cancel();
+        first
+    }
+
+    pub(crate) fn is_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+}
+
+/// One direction of a link, deciding when and whether messages arrive.
+pub(crate) struct Direction {
+    link: Arc<Link>,
+    state: Mutex<DirectionState>,
+    pub(crate) bytes_sent: AtomicU64,
+    pub(crate) bytes_delivered: Arc<AtomicU64>,
+    pub(crate) lost: AtomicU64,
+}
+
+struct DirectionState {
+    config: LinkConfig,
+    rng: StdRng,
+    /// When the link finishes transmitting what has been sent so far.
+    busy_until: Instant,
+    /// Arrival of the last reliable message, which later ones may not
+    /// overtake even if the latency drops.
+    last_reliable: Instant,
+}
+
+impl Direction {
+    pub(crate) fn new(config: LinkConfig, link: Arc<Link>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(DirectionState {
+                rng: StdRng::seed_from_u64(config.seed),
+                busy_until: Instant::now(),
+                last_reliable: Instant::now(),
+                config,
+            }),
+            link,
+            bytes_sent: AtomicU64::new(0),
+            bytes_delivered: Default::default(),
+            lost: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the current characteristics of the direction.
+    pub(crate) fn config(&self) -> LinkConfig {
+        self.state.lock().config.clone()
+    }
+
+    /// Changes the one-way delay of messages sent from now on.
+    pub(crate) fn set_latency(&self, latency: Duration) {
+        self.state.lock().config.latency = latency;
+    }
+
+    /// Decides when a message of `len` bytes arrives.
+    ///
+    /// Returns `None` if the message is lost.
+    pub(crate) fn schedule(&self, len: usize, reliable: bool) -> Option<Instant> {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+
+        if !reliable && self.link.is_partitioned() {
+            self.lost.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let transmission = match state.config.bandwidth {
+            Some(bandwidth) => Duration::from_secs_f64(len as f64 / bandwidth as f64),
+            None => Duration::ZERO,
+        };
+        state.busy_until = state.busy_until.max(Instant::now()) + transmission;
+
+        let mut arrival = state.busy_until + state.config.latency;
+
+        if reliable {
+            arrival = arrival.max(state.last_reliable);
+            state.last_reliable = arrival;
+        } else {
+            if state.rng.random::<f64>() < state.config.loss {
+                self.lost.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+
+            if state.rng.random::<f64>() < state.config.reorder {
+                arrival += state.config.reorder_delay;
+            }
+        }
+
+        Some(arrival)
+    }
+
+    /// Creates a pipe carrying messages in this direction.
+    ///
+    /// A task delivers each message at its scheduled time, or once the link
+    /// heals if it is partitioned then, and closes the receiving end once the
+    /// sending end is dropped and everything in flight has arrived, or as
+    /// soon as the link goes down.
+    pub(crate) fn pipe(self: &Arc<Self>) -> (Pipe, UnboundedReceiver<Bytes>) {
+        let (in_tx, in_rx) = mpsc::unbounded_channel();
+        let (out_tx, out_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(deliver(
+            in_rx,
+            out_tx,
+            self.link.shutdown.clone(),
+            self.link.partitioned.subscribe(),
+            self.bytes_delivered.clone(),
+        ));
+
+        (
+            Pipe {
+                direction: self.clone(),
+                tx: in_tx,
+            },
+            out_rx,
+        )
+    }
+}
+
+/// Sending end of a pipe.
+pub(crate) struct Pipe {
+    direction: Arc<Direction>,
+    tx: UnboundedSender<(Instant, Bytes)>,
+}
+
+impl Pipe {
+    pub(crate) fn direction(&self) -> &Direction {
+        &self.direction
+    }
+
+    /// Sends a message, which may be delayed or dropped.
+    ///
+    /// Returns `false` if the link is down.
+    pub(crate) fn send(&self, data: Bytes, reliable: bool) -> bool {
+        if self.direction.link.is_down() {
+            return false;
+        }
+
+        match self.direction.schedule(data.len(), reliable) {
+            Some(arrival) => self.tx.send((arrival, data)).is_ok(),
+            None => true,
+        }
+    }
+}
+
+async fn deliver(
+    mut rx: UnboundedReceiver<(Instant, Bytes)>,
+    tx: UnboundedSender<Bytes>,
+    shutdown: CancellationToken,
+    mut partitioned: watch::Receiver<bool>,
+    delivered: Arc<AtomicU64>,
+) {
+    // ordered by arrival time, then by send order
+    let mut in_flight = BinaryHeap::new();
+    let mut sequence = 0u64;
+    let mut open = true;
+    // stops once the link is dropped, which cannot change the partition
+    let mut watching = true;
+
+    while open || !in_flight.is_empty() {
+        let next = in_flight
+            .peek()
+            .map(|Reverse((arrival, _, _)): &Reverse<(Instant, u64, Bytes)>| *arrival)
+            .filter(|_| !*partitioned.borrow());
+
+        tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => break,
+            changed = partitioned.changed(), if watching => watching = changed.is_ok(),
+            message = rx.recv(), if open => match message {
+                Some((arrival, data)) => {
+                    in_flight.push(Reverse((arrival, sequence, data)));
+                    sequence += 1;
+                }
+                None => open = false,
+            },
+            _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                if let Some(Reverse((_, _, data))) = in_flight.pop() {
+                    delivered.fetch_add(data.len() as u64, Ordering::Relaxed);
+                    let _ = tx.send(data);
+                }
+            }
+        }
+    }
+}
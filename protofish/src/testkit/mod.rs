@@ -0,0 +1,201 @@
+//! In-memory transport for testing code built on protofish.
+//!
+//! `pair` creates two connected `TestUTP`s whose link can add latency, limit
+//! bandwidth, drop or reorder messages of unreliable streams, and be cut on
+//! demand with `TestUTP::disconnect`. Loss and reordering are drawn from a
+//! seeded random number generator, so combined with tokio's paused clock
+//! (`#[tokio::test(start_paused = true)]`) a test behaves the same on every
+//! run.
+//!
+//! `sim` builds on these links to simulate a network of many nodes, with
+//! partitions, latency changes and crashes played from seeded scenarios.
+//!
+//! Enabled with the `testkit` feature.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use protofish::testkit::{LinkConfig, connection_pair};
+//!
+//! # async fn example() {
+//! let config = LinkConfig::default()
+//!     .with_latency(Duration::from_millis(40))
+//!     .with_loss(0.1)
+//!     .with_seed(7);
+//!
+//! let (client, server) = connection_pair(config).await.unwrap();
+//! # }
+//! ```
+
+mod link;
+pub mod sim;
+mod stream;
+mod utp;
+
+use std::sync::Arc;
+
+pub use link::LinkConfig;
+pub use stream::{TestStream, TestStreamRead, TestStreamWrite};
+pub use utp::{TestUTP, pair, pair_with};
+
+use crate::{
+    core::{client::connect, common::connection::Connection, server::accept},
+    error::ProtofishError,
+};
+
+/// Creates a client and a server `Connection` over a `pair` of in-memory
+/// transports, completing the handshake.
+///
+/// # Returns
+///
+/// Returns `(client, server)`.
+///
+/// # Errors
+///
+/// Returns an error if the handshake fails.
+pub async fn connection_pair(
+    config: LinkConfig,
+) -> Result<(Connection<TestUTP>, Connection<TestUTP>), ProtofishError> {
+    let (a, b) = pair(config);
+
+    let (server, client) = tokio::try_join!(accept(Arc::new(a)), connect(Arc::new(b)))?;
+
+    Ok((client, server))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::ErrorKind, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::Instant,
+    };
+
+    use crate::{
+        core::common::connection::ConnectionState,
+        schema::IntegrityType,
+        testkit::{LinkConfig, TestUTP, connection_pair, pair},
+        utp::{UTP, UTPEvent, UTPStream, error::UTPError},
+    };
+
+    async fn open(
+        a: &TestUTP,
+        b: &TestUTP,
+        integrity: IntegrityType,
+    ) -> (<TestUTP as UTP>::Stream, <TestUTP as UTP>::Stream) {
+        let local = a.new_stream(integrity.clone()).await.unwrap();
+        let remote = b.wait_stream(local.id(), integrity).await.unwrap();
+
+        (local, remote)
+    }
+
+    /// Sends 100 numbered datagrams and returns the numbers that arrived, in
+    /// arrival order.
+    async fn send_numbered(config: LinkConfig) -> Vec<u8> {
+        let (a, b) = pair(config);
+        let (local, remote) = open(&a, &b, IntegrityType::Unreliable).await;
+
+        let (mut write, _) = local.split();
+        let (_, mut read) = remote.split();
+
+        for n in 0..100u8 {
+            <TestUTP as UTP>::Stream::send_datagram(&mut write, Bytes::from(vec![n]))
+                .await
+                .unwrap();
+        }
+        write.shutdown().await.unwrap();
+
+        let mut received = vec![];
+        while let Ok(data) = <TestUTP as UTP>::Stream::recv_datagram(&mut read).await {
+            received.push(data[0]);
+        }
+
+        received
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_latency_and_bandwidth() {
+        let config = LinkConfig::default()
+            .with_latency(Duration::from_millis(50))
+            .with_bandwidth(1000);
+        let (a, b) = pair(config);
+        let (local, remote) = open(&a, &b, IntegrityType::Reliable).await;
+
+        let (mut write, _) = local.split();
+        let (_, mut read) = remote.split();
+
+        let start = Instant::now();
+        write.write_all(&[1; 2000]).await.unwrap();
+
+        let mut buf = vec![0; 2000];
+        read.read_exact(&mut buf).await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(2050));
+        assert_eq!(a.stats().bytes_sent, Some(2000));
+        assert_eq!(b.stats().bytes_received, Some(2000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_loss_is_deterministic() {
+        let config = LinkConfig::default().with_loss(0.3).with_seed(7);
+
+        let first = send_numbered(config.clone()).await;
+        let second = send_numbered(config).await;
+
+        assert!(first.len() < 100 && !first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reorder_delivers_everything() {
+        let config = LinkConfig::default()
+            .with_latency(Duration::from_millis(5))
+            .with_reorder(0.2, Duration::from_millis(20))
+            .with_seed(3);
+
+        let received = send_numbered(config).await;
+        let mut sorted = received.clone();
+        sorted.sort();
+
+        assert_ne!(received, sorted);
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_disconnect() {
+        let (a, b) = pair(LinkConfig::default());
+        let (local, _remote) = open(&a, &b, IntegrityType::Reliable).await;
+        let (_, mut read) = local.split();
+
+        b.disconnect();
+
+        let err = read.read_u8().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+
+        assert!(matches!(a.next_event().await, UTPEvent::UnexpectedClose));
+        assert!(matches!(
+            a.new_stream(IntegrityType::Reliable).await,
+            Err(UTPError::Fatal(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connection_pair() {
+        let config = LinkConfig::default().with_latency(Duration::from_millis(20));
+        let (client, server) = connection_pair(config).await.unwrap();
+
+        let arb = client.new_arb();
+        arb.write(Bytes::from_static(b"muffin")).await.unwrap();
+
+        let peer = server.next_arb().await.unwrap();
+        assert_eq!(peer.read().await.unwrap(), Bytes::from_static(b"muffin"));
+
+        client.close(0, "done").await.unwrap();
+
+        let expected = ConnectionState::Closed {
+            reason: "done".into(),
+        };
+        assert_eq!(server.closed().await, expected);
+    }
+}
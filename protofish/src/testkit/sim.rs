@@ -0,0 +1,591 @@
+//! Deterministic simulation of a network of protofish nodes.
+//!
+//! A `Network` connects named nodes with in-memory links and injects faults
+//! into them: partitions, latency changes and crashes, applied directly or
+//! played from a timed `Scenario`. Simulations run on a paused tokio clock
+//! and draw every random decision from the seed of the network, so a failing
+//! run is reproduced exactly by running it again with the same seed.
+//!
+//! `simulate_seeds` runs a test for many seeds and reports the one that
+//! failed; setting `PROTOFISH_SIM_SEED` runs only that seed:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use protofish::testkit::sim::{Fault, Scenario, simulate_seeds};
+//!
+//! simulate_seeds(0..100, |network| async move {
+//!     let server = network.add_node("server");
+//!     let client = network.add_node("client");
+//!     let (connection, _server_connection) = network.connect(client, server).await.unwrap();
+//!
+//!     network.play(
+//!         Scenario::new()
+//!             .at(Duration::from_millis(10), Fault::Partition(client, server))
+//!             .at(Duration::from_millis(500), Fault::Heal(client, server)),
+//!     );
+//!
+//!     let arb = connection.new_arb();
+//!     arb.write(bytes::Bytes::from_static(b"muffin")).await.unwrap();
+//! });
+//! ```
+//!
+//! Panics in spawned tasks are caught by tokio, so assertions belong in the
+//! future passed to `simulate`. With `--cfg tokio_unstable`, the scheduler's
+//! own random choices, such as the branch order of `tokio::select!`, are
+//! seeded too; without it they may still vary between runs.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    net::{Ipv4Addr, SocketAddr},
+    ops::Range,
+    panic::AssertUnwindSafe,
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::{
+    core::{client::connect, common::connection::Connection, server::accept},
+    error::ProtofishError,
+    testkit::{LinkConfig, TestUTP, utp::pair_between},
+};
+
+/// Environment variable that makes `simulate_seeds` run only the given seed.
+pub const SEED_VAR: &str = "PROTOFISH_SIM_SEED";
+
+/// A node of a `Network`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "node {}", self.0)
+    }
+}
+
+/// A change to the network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Stops traffic between two nodes: reliable data is held until they
+    /// heal, unreliable data is dropped
+    Partition(NodeId, NodeId),
+    /// Ends a partition between two nodes
+    Heal(NodeId, NodeId),
+    /// Partitions a node from every other node
+    Isolate(NodeId),
+    /// Ends all partitions of a node
+    Rejoin(NodeId),
+    /// Sets the one-way latency between two nodes, in both directions
+    Latency(NodeId, NodeId, Duration),
+    /// Drops every link of a node without a graceful close; new links to it
+    /// fail until it restarts
+    Crash(NodeId),
+    /// Lets a crashed node be connected to again
+    Restart(NodeId),
+}
+
+/// Faults to apply at given times, played with `Network::play`.
+#[derive(Clone, Debug, Default)]
+pub struct Scenario {
+    steps: Vec<(Duration, Fault)>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `fault` once `after` has passed since the scenario started.
+    pub fn at(mut self, after: Duration, fault: Fault) -> Self {
+        self.steps.push((after, fault));
+        self
+    }
+
+    /// Returns the faults in the order they are applied.
+    pub fn steps(&self) -> Vec<(Duration, Fault)> {
+        let mut steps = self.steps.clone();
+        // stable, so faults at the same time keep their order
+        steps.sort_by_key(|(after, _)| *after);
+        steps
+    }
+}
+
+/// A simulated network of nodes. Cloning it gives another handle to the
+/// same network.
+#[derive(Clone)]
+pub struct Network {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    seed: u64,
+    rng: StdRng,
+    start: Instant,
+    nodes: Vec<Node>,
+    /// Characteristics of new links, by pair of nodes
+    configs: HashMap<(NodeId, NodeId), LinkConfig>,
+    partitions: HashSet<(NodeId, NodeId)>,
+    links: Vec<NetworkLink>,
+    log: Vec<(Duration, Fault)>,
+}
+
+struct Node {
+    name: String,
+    crashed: bool,
+}
+
+struct NetworkLink {
+    nodes: (NodeId, NodeId),
+    utp: TestUTP,
+}
+
+/// Orders a pair of nodes, as links are not directional.
+fn key(a: NodeId, b: NodeId) -> (NodeId, NodeId) {
+    (a.min(b), a.max(b))
+}
+
+impl Network {
+    /// Creates an empty network whose random decisions are drawn from
+    /// `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                seed,
+                rng: StdRng::seed_from_u64(seed),
+                start: Instant::now(),
+                nodes: Vec::new(),
+                configs: HashMap::new(),
+                partitions: HashSet::new(),
+                links: Vec::new(),
+                log: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.state.lock().seed
+    }
+
+    /// Adds a node, whose transports get the address `10.0.0.x`.
+    pub fn add_node(&self, name: &str) -> NodeId {
+        let mut state = self.state.lock();
+        state.nodes.push(Node {
+            name: name.to_string(),
+            crashed: false,
+        });
+
+        NodeId(state.nodes.len() - 1)
+    }
+
+    /// Returns the name a node was added with.
+    pub fn name(&self, node: NodeId) -> String {
+        self.state.lock().nodes[node.0].name.clone()
+    }
+
+    /// Sets the characteristics of links created from now on between two
+    /// nodes. Their seed is replaced by one drawn from the network.
+    pub fn set_link(&self, a: NodeId, b: NodeId, config: LinkConfig) {
+        self.state.lock().configs.insert(key(a, b), config);
+    }
+
+    /// Creates a pair of transports between two nodes, like
+    /// `testkit::pair`: the first is meant for `accept` on `server`, the
+    /// second for `connect` on `client`.
+    ///
+    /// The link starts out partitioned if the nodes are, and down if either
+    /// node has crashed.
+    pub fn transports(&self, server: NodeId, client: NodeId) -> (TestUTP, TestUTP) {
+        let mut state = self.state.lock();
+        let config = state
+            .configs
+            .get(&key(server, client))
+            .cloned()
+            .unwrap_or_default();
+        let to_client = config.clone().with_seed(state.rng.random());
+        let to_server = config.with_seed(state.rng.random());
+
+        let (a, b) = pair_between(address(server), address(client), to_client, to_server);
+
+        if state.nodes[server.0].crashed || state.nodes[client.0].crashed {
+            a.disconnect();
+        } else {
+            a.link()
+                .set_partitioned(state.partitions.contains(&key(server, client)));
+            state.links.push(NetworkLink {
+                nodes: (server, client),
+                utp: a.handle(),
+            });
+        }
+
+        (a, b)
+    }
+
+    /// Connects `client` to `server` and completes the handshake.
+    ///
+    /// # Returns
+    ///
+    /// Returns `(client, server)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handshake fails.
+    pub async fn connect(
+        &self,
+        client: NodeId,
+        server: NodeId,
+    ) -> Result<(Connection<TestUTP>, Connection<TestUTP>), ProtofishError> {
+        let (a, b) = self.transports(server, client);
+
+        let (server, client) = tokio::try_join!(accept(Arc::new(a)), connect(Arc::new(b)))?;
+
+        Ok((client, server))
+    }
+
+    /// Applies a fault now.
+    pub fn apply(&self, fault: Fault) {
+        let mut state = self.state.lock();
+        let elapsed = state.start.elapsed();
+        tracing::debug!("simulation at {:?}: {:?}", elapsed, fault);
+
+        match &fault {
+            Fault::Partition(a, b) => {
+                state.partitions.insert(key(*a, *b));
+            }
+            Fault::Heal(a, b) => {
+                state.partitions.remove(&key(*a, *b));
+            }
+            Fault::Isolate(node) => {
+                for other in 0..state.nodes.len() {
+                    if other != node.0 {
+                        state.partitions.insert(key(*node, NodeId(other)));
+                    }
+                }
+            }
+            Fault::Rejoin(node) => {
+                state.partitions.retain(|(a, b)| a != node && b != node);
+            }
+            Fault::Latency(a, b, latency) => {
+                let config = state.configs.entry(key(*a, *b)).or_default();
+                config.latency = *latency;
+
+                for link in state.links.iter().filter(|link| link.between(*a, *b)) {
+                    for direction in link.utp.directions() {
+                        direction.set_latency(*latency);
+                    }
+                }
+            }
+            Fault::Crash(node) => {
+                state.nodes[node.0].crashed = true;
+                state.links.retain(|link| {
+                    let involved = link.nodes.0 == *node || link.nodes.1 == *node;
+                    if involved {
+                        link.utp.disconnect();
+                    }
+                    !involved
+                });
+            }
+            Fault::Restart(node) => {
+                state.nodes[node.0].crashed = false;
+            }
+        }
+
+        state.links.retain(|link| !link.utp.is_down());
+        for link in &state.links {
+            let partitioned = state.partitions.contains(&key(link.nodes.0, link.nodes.1));
+            link.utp.link().set_partitioned(partitioned);
+        }
+
+        state.log.push((elapsed, fault));
+    }
+
+    /// Plays a scenario, with its times counted from now.
+    pub fn play(&self, scenario: Scenario) -> JoinHandle<()> {
+        let network = self.clone();
+        let start = Instant::now();
+
+        tokio::spawn(async move {
+            for (after, fault) in scenario.steps() {
+                tokio::time::sleep_until(start + after).await;
+                network.apply(fault);
+            }
+        })
+    }
+
+    /// Draws a scenario of `count` faults between `nodes` within `duration`
+    /// from the network's seed: partitions that heal again, and latency
+    /// changes of up to 200 ms.
+    pub fn chaos(&self, nodes: &[NodeId], duration: Duration, count: usize) -> Scenario {
+        let mut state = self.state.lock();
+        let rng = &mut state.rng;
+        let mut scenario = Scenario::new();
+
+        if nodes.len() < 2 {
+            return scenario;
+        }
+
+        for _ in 0..count {
+            let a = nodes[rng.random_range(0..nodes.len())];
+            let mut b = nodes[rng.random_range(0..nodes.len() - 1)];
+            if b == a {
+                b = nodes[nodes.len() - 1];
+            }
+            let at = duration.mul_f64(rng.random());
+
+            if rng.random_bool(0.5) {
+                let heal = at + (duration - at).mul_f64(rng.random());
+                scenario = scenario
+                    .at(at, Fault::Partition(a, b))
+                    .at(heal, Fault::Heal(a, b));
+            } else {
+                let latency = Duration::from_millis(rng.random_range(0..=200));
+                scenario = scenario.at(at, Fault::Latency(a, b, latency));
+            }
+        }
+
+        scenario
+    }
+
+    /// Returns the faults applied so far, with the time since the network
+    /// was created.
+    pub fn log(&self) -> Vec<(Duration, Fault)> {
+        self.state.lock().log.clone()
+    }
+}
+
+impl NetworkLink {
+    fn between(&self, a: NodeId, b: NodeId) -> bool {
+        key(self.nodes.0, self.nodes.1) == key(a, b)
+    }
+}
+
+fn address(node: NodeId) -> SocketAddr {
+    let host = u32::from(Ipv4Addr::new(10, 0, 0, 1)) + node.0 as u32;
+    SocketAddr::from((Ipv4Addr::from(host), 4433))
+}
+
+/// Runs `test` with a `Network` seeded with `seed`, on a new single-threaded
+/// runtime whose clock is paused.
+pub fn simulate<F, Fut>(seed: u64, test: F) -> Fut::Output
+where
+    F: FnOnce(Network) -> Fut,
+    Fut: Future,
+{
+    let mut builder = tokio::runtime::Builder::new_current_thread();
+    builder.enable_all().start_paused(true);
+    #[cfg(tokio_unstable)]
+    builder.rng_seed(tokio::runtime::RngSeed::from_bytes(&seed.to_le_bytes()));
+
+    let runtime = builder
+        .build()
+        .expect("failed to build the simulation runtime");
+    runtime.block_on(async move { test(Network::new(seed)).await })
+}
+
+/// Runs `test` with `simulate` once for every seed, or only for the seed in
+/// `PROTOFISH_SIM_SEED` if it is set.
+///
+/// # Panics
+///
+/// Panics if `test` panics for a seed, after printing the seed to stderr.
+pub fn simulate_seeds<F, Fut>(seeds: Range<u64>, test: F)
+where
+    F: Fn(Network) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let seeds = match std::env::var(SEED_VAR) {
+        Ok(seed) => {
+            let seed: u64 = seed
+                .parse()
+                .unwrap_or_else(|_| panic!("{} is not a seed: {:?}", SEED_VAR, seed));
+            seed..seed + 1
+        }
+        Err(_) => seeds,
+    };
+
+    for seed in seeds {
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| simulate(seed, &test)));
+
+        if let Err(panic) = result {
+            eprintln!(
+                "simulation failed with seed {}, rerun it with {}={}",
+                seed, SEED_VAR, seed
+            );
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::{Instant, timeout};
+
+    use super::{Fault, Network, Scenario, simulate, simulate_seeds};
+    use crate::{
+        core::common::connection::ConnectionState,
+        schema::IntegrityType,
+        testkit::{LinkConfig, TestUTP},
+        utp::{UTP, UTPStream},
+    };
+
+    #[test]
+    fn test_partition_holds_reliable_data() {
+        simulate(1, |network| async move {
+            let server = network.add_node("server");
+            let client = network.add_node("client");
+            let (connection, server_connection) = network.connect(client, server).await.unwrap();
+
+            network.apply(Fault::Partition(client, server));
+            let start = Instant::now();
+            network.play(Scenario::new().at(Duration::from_secs(2), Fault::Heal(client, server)));
+
+            let arb = connection.new_arb();
+            arb.write(Bytes::from_static(b"muffin")).await.unwrap();
+
+            let peer = server_connection.next_arb().await.unwrap();
+            assert_eq!(peer.read().await.unwrap(), Bytes::from_static(b"muffin"));
+            assert!(start.elapsed() >= Duration::from_secs(2));
+            assert_eq!(network.log().len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_crash_and_restart() {
+        simulate(2, |network| async move {
+            let server = network.add_node("server");
+            let client = network.add_node("client");
+            let (connection, _server_connection) = network.connect(client, server).await.unwrap();
+
+            network.apply(Fault::Crash(server));
+            assert_eq!(connection.closed().await, ConnectionState::Lost);
+
+            let attempt = timeout(Duration::from_secs(1), network.connect(client, server)).await;
+            assert!(!matches!(attempt, Ok(Ok(_))));
+
+            network.apply(Fault::Restart(server));
+            network.connect(client, server).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_handshake_completes_after_heal() {
+        simulate(3, |network| async move {
+            let server = network.add_node("server");
+            let client = network.add_node("client");
+            network.set_link(
+                client,
+                server,
+                LinkConfig::default().with_latency(Duration::from_millis(30)),
+            );
+
+            network.apply(Fault::Isolate(server));
+            network.play(Scenario::new().at(Duration::from_millis(300), Fault::Rejoin(server)));
+
+            let start = Instant::now();
+            let (connection, _) = network.connect(client, server).await.unwrap();
+            assert!(start.elapsed() >= Duration::from_millis(300));
+            assert_eq!(connection.stats().rtt, Some(Duration::from_millis(60)));
+        });
+    }
+
+    /// Sends numbered datagrams through a lossy, chaotic network and returns
+    /// those that arrived, with the faults applied.
+    async fn lossy_run(network: Network) -> (Vec<u8>, Vec<(Duration, Fault)>) {
+        let a = network.add_node("a");
+        let b = network.add_node("b");
+        network.set_link(a, b, LinkConfig::default().with_loss(0.2));
+        let (server, client) = network.transports(a, b);
+
+        network.play(network.chaos(&[a, b], Duration::from_millis(100), 5));
+
+        let local = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+        let remote = server
+            .wait_stream(local.id(), IntegrityType::Unreliable)
+            .await
+            .unwrap();
+        let (mut write, _) = local.split();
+        let (_, mut read) = remote.split();
+
+        tokio::spawn(async move {
+            for n in 0..100u8 {
+                let _ =
+                    <TestUTP as UTP>::Stream::send_datagram(&mut write, Bytes::from(vec![n])).await;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+
+        let mut received = vec![];
+        while let Ok(Ok(data)) = timeout(
+            Duration::from_secs(1),
+            <TestUTP as UTP>::Stream::recv_datagram(&mut read),
+        )
+        .await
+        {
+            received.push(data[0]);
+        }
+
+        (received, network.log())
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let first = simulate(7, lossy_run);
+        let second = simulate(7, lossy_run);
+        let other = simulate(8, lossy_run);
+
+        assert!(!first.0.is_empty() && first.0.len() < 100);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_many_connections_under_chaos() {
+        simulate_seeds(0..5, |network| async move {
+            let server = network.add_node("server");
+            let clients: Vec<_> = (0..4)
+                .map(|i| network.add_node(&format!("client {}", i)))
+                .collect();
+
+            let mut nodes = clients.clone();
+            nodes.push(server);
+            network.play(network.chaos(&nodes, Duration::from_secs(1), 10));
+
+            let mut tasks = vec![];
+            for client in clients {
+                let (connection, server_connection) =
+                    network.connect(client, server).await.unwrap();
+
+                tasks.push(tokio::spawn(async move {
+                    let arb = connection.new_arb();
+                    arb.write(Bytes::from(vec![1; 100_000])).await.unwrap();
+
+                    let peer = server_connection.next_arb().await.unwrap();
+                    peer.read().await.unwrap().len()
+                }));
+            }
+
+            for task in tasks {
+                let received = timeout(Duration::from_secs(10), task).await;
+                assert_eq!(received.unwrap().unwrap(), 100_000);
+            }
+        });
+    }
+
+    #[test]
+    fn test_simulate_seeds_stops_at_failing_seed() {
+        let result = std::panic::catch_unwind(|| {
+            simulate_seeds(0..10, |network| async move {
+                assert_ne!(network.seed(), 4);
+            })
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::mpsc::UnboundedReceiver,
+};
+
+use crate::{
+    schema::{IntegrityType, StreamId},
+    testkit::link::{Link, Pipe},
+    utp::{UTPStream, error::UTPError, read_framed_datagram, write_framed_datagram},
+};
+
+/// Largest chunk a reliable stream hands to the link at once.
+///
+/// Bigger writes are split, so that bandwidth limits pace them.
+const MAX_RELIABLE_CHUNK: usize = 16 * 1024;
+
+/// A stream of a `TestUTP` pair.
+///
+/// Writes never block: they are queued on the simulated link without limit,
+/// and only their delivery is delayed.
+pub struct TestStream {
+    id: StreamId,
+    integrity: IntegrityType,
+    writer: TestStreamWrite,
+    reader: TestStreamRead,
+}
+
+/// Write half of a `TestStream`.
+pub struct TestStreamWrite {
+    pipe: Option<Pipe>,
+    reliable: bool,
+}
+
+/// Read half of a `TestStream`.
+pub struct TestStreamRead {
+    rx: UnboundedReceiver<Bytes>,
+    pending: Bytes,
+    reliable: bool,
+    link: Arc<Link>,
+}
+
+impl TestStream {
+    pub(crate) fn new(
+        id: StreamId,
+        integrity: IntegrityType,
+        pipe: Pipe,
+        rx: UnboundedReceiver<Bytes>,
+        link: Arc<Link>,
+    ) -> Self {
+        let reliable = integrity == IntegrityType::Reliable;
+
+        Self {
+            id,
+            integrity,
+            writer: TestStreamWrite {
+                pipe: Some(pipe),
+                reliable,
+            },
+            reader: TestStreamRead {
+                rx,
+                pending: Bytes::new(),
+                reliable,
+                link,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl UTPStream for TestStream {
+    type StreamRead = TestStreamRead;
+    type StreamWrite = TestStreamWrite;
+
+    fn id(&self) -> StreamId {
+        self.id
+    }
+
+    fn integrity_type(&self) -> IntegrityType {
+        self.integrity.clone()
+    }
+
+    fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
+        (self.writer, self.reader)
+    }
+
+    /// Sends one message as a single unit on unreliable streams, so it is
+    /// either delivered whole or lost. Reliable streams use the
+    /// length-prefixed fallback.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        if writer.reliable {
+            return write_framed_datagram(writer, data).await;
+        }
+
+        let max = writer.pipe()?.direction().config().max_datagram_size;
+        if data.len() > max {
+            return Err(UTPError::Warn(format!(
+                "datagram of {} bytes exceeds {} bytes",
+                data.len(),
+                max
+            )));
+        }
+
+        writer.send(data)?;
+
+        Ok(())
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        if reader.reliable {
+            return read_framed_datagram(reader).await;
+        }
+
+        // left over from a partial `AsyncRead`
+        if !reader.pending.is_empty() {
+            return Ok(std::mem::take(&mut reader.pending));
+        }
+
+        match reader.rx.recv().await {
+            Some(data) => Ok(data),
+            None => Err(reader.closed_error().into()),
+        }
+    }
+}
+
+impl TestStreamWrite {
+    fn pipe(&self) -> io::Result<&Pipe> {
+        self.pipe
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "stream shut down"))
+    }
+
+    fn send(&self, data: Bytes) -> io::Result<()> {
+        if self.pipe()?.send(data, self.reliable) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "link is down"))
+        }
+    }
+}
+
+impl AsyncWrite for TestStreamWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let limit = if self.reliable {
+            MAX_RELIABLE_CHUNK
+        } else {
+            self.pipe()?.direction().config().max_datagram_size
+        };
+        let len = buf.len().min(limit);
+
+        self.send(Bytes::copy_from_slice(&buf[..len]))?;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // the peer sees the end of the stream once everything in flight arrives
+        self.pipe.take();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl TestStreamRead {
+    fn closed_error(&self) -> io::Error {
+        if self.link.is_down() {
+            io::Error::new(io::ErrorKind::ConnectionReset, "link is down")
+        } else {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "stream closed")
+        }
+    }
+}
+
+impl AsyncRead for TestStreamRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.pending.is_empty() {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(data)) => self.pending = data,
+                Poll::Ready(None) if self.link.is_down() => {
+                    return Poll::Ready(Err(self.closed_error()));
+                }
+                // end of stream
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let len = self.pending.len().min(buf.remaining());
+        let data = self.pending.split_to(len);
+        buf.put_slice(&data);
+
+        Poll::Ready(Ok(()))
+    }
+}
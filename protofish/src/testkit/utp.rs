@@ -0,0 +1,271 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use tokio::sync::{
+    Mutex, Notify,
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+
+use crate::{
+    schema::{IntegrityType, StreamId},
+    testkit::{
+        link::{Direction, Link, LinkConfig},
+        stream::TestStream,
+    },
+    utp::{UTP, UTPEvent, UTPStats, error::UTPError},
+};
+
+/// One side of an in-memory transport pair, created with `pair` or
+/// `pair_with`.
+///
+/// Both sides share the simulated link: closing or disconnecting either one
+/// takes the link down for both.
+pub struct TestUTP {
+    local: Arc<Side>,
+    peer: Arc<Side>,
+    outgoing: Arc<Direction>,
+    incoming: Arc<Direction>,
+    link: Arc<Link>,
+    stream_ids: Arc<AtomicU64>,
+}
+
+struct Side {
+    addr: SocketAddr,
+    events_tx: UnboundedSender<UTPEvent>,
+    events_rx: Mutex<UnboundedReceiver<UTPEvent>>,
+    /// Set once a terminal event has been returned by `next_event`.
+    finished: AtomicBool,
+    streams: DashMap<StreamId, TestStream>,
+    notify: Notify,
+}
+
+impl Side {
+    fn new(addr: SocketAddr) -> Arc<Self> {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        Arc::new(Self {
+            addr,
+            events_tx,
+            events_rx: Mutex::new(events_rx),
+            finished: AtomicBool::new(false),
+            streams: Default::default(),
+            notify: Notify::new(),
+        })
+    }
+
+    fn add_event(&self, event: UTPEvent) {
+        let _ = self.events_tx.send(event);
+    }
+}
+
+/// Creates a connected pair of in-memory transports, with the same link
+/// characteristics in both directions.
+///
+/// The first transport is meant for `accept`, the second for `connect`.
+pub fn pair(config: LinkConfig) -> (TestUTP, TestUTP) {
+    let seed = config.seed.wrapping_add(1);
+    pair_with(config.clone(), config.with_seed(seed))
+}
+
+/// Creates a connected pair of in-memory transports, with separate link
+/// characteristics for each direction.
+pub fn pair_with(a_to_b: LinkConfig, b_to_a: LinkConfig) -> (TestUTP, TestUTP) {
+    pair_between(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 1)),
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 2)),
+        a_to_b,
+        b_to_a,
+    )
+}
+
+/// Creates a pair like `pair_with`, with the given addresses for its sides.
+pub(crate) fn pair_between(
+    a: SocketAddr,
+    b: SocketAddr,
+    a_to_b: LinkConfig,
+    b_to_a: LinkConfig,
+) -> (TestUTP, TestUTP) {
+    let link = Link::new();
+    let stream_ids = Arc::new(AtomicU64::new(0));
+
+    let a = Side::new(a);
+    let b = Side::new(b);
+    let a_to_b = Direction::new(a_to_b, link.clone());
+    let b_to_a = Direction::new(b_to_a, link.clone());
+
+    (
+        TestUTP {
+            local: a.clone(),
+            peer: b.clone(),
+            outgoing: a_to_b.clone(),
+            incoming: b_to_a.clone(),
+            link: link.clone(),
+            stream_ids: stream_ids.clone(),
+        },
+        TestUTP {
+            local: b,
+            peer: a,
+            outgoing: b_to_a,
+            incoming: a_to_b,
+            link,
+            stream_ids,
+        },
+    )
+}
+
+impl TestUTP {
+    /// Drops the link without a graceful close, as if the network failed.
+    ///
+    /// Both sides observe `UTPEvent::UnexpectedClose`, and all streams fail.
+    pub fn disconnect(&self) {
+        self.take_down(UTPEvent::UnexpectedClose);
+    }
+
+    /// Returns `true` if the link was closed or disconnected.
+    pub fn is_down(&self) -> bool {
+        self.link.is_down()
+    }
+
+    /// Returns another handle to this side, through which the simulated
+    /// network changes the link.
+    pub(crate) fn handle(&self) -> Self {
+        Self {
+            local: self.local.clone(),
+            peer: self.peer.clone(),
+            outgoing: self.outgoing.clone(),
+            incoming: self.incoming.clone(),
+            link: self.link.clone(),
+            stream_ids: self.stream_ids.clone(),
+        }
+    }
+
+    pub(crate) fn link(&self) -> &Link {
+        &self.link
+    }
+
+    /// Returns both directions of the link, outgoing first.
+    pub(crate) fn directions(&self) -> [&Direction; 2] {
+        [&self.outgoing, &self.incoming]
+    }
+
+    fn take_down(&self, event: UTPEvent) {
+        if self.link.take_down() {
+            self.local.add_event(event.clone());
+            self.peer.add_event(event);
+        }
+    }
+
+    fn ensure_up(&self) -> Result<(), UTPError> {
+        if self.link.is_down() {
+            Err(UTPError::Fatal("link is down".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl UTP for TestUTP {
+    type Stream = TestStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.ensure_up()
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        if self.local.finished.load(Ordering::Acquire) {
+            return UTPEvent::UnexpectedClose;
+        }
+
+        // the side holds its own sender, so the channel never closes
+        let event = self
+            .local
+            .events_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .unwrap_or(UTPEvent::UnexpectedClose);
+
+        if event.is_terminal() {
+            self.local.finished.store(true, Ordering::Release);
+        }
+
+        event
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<TestStream, UTPError> {
+        self.ensure_up()?;
+
+        let id = self.stream_ids.fetch_add(1, Ordering::Relaxed);
+
+        let (local_pipe, peer_rx) = self.outgoing.pipe();
+        let (peer_pipe, local_rx) = self.incoming.pipe();
+
+        let local = TestStream::new(
+            id,
+            integrity.clone(),
+            local_pipe,
+            local_rx,
+            self.link.clone(),
+        );
+        let peer = TestStream::new(id, integrity, peer_pipe, peer_rx, self.link.clone());
+
+        self.peer.streams.insert(id, peer);
+        self.peer.notify.notify_waiters();
+        self.peer.add_event(UTPEvent::NewStream(id));
+
+        Ok(local)
+    }
+
+    async fn wait_stream(&self, id: StreamId, _: IntegrityType) -> Result<TestStream, UTPError> {
+        loop {
+            let notified = self.local.notify.notified();
+            tokio::pin!(notified);
+            // register before checking, so an insert in between is not missed
+            notified.as_mut().enable();
+
+            if let Some((_, stream)) = self.local.streams.remove(&id) {
+                return Ok(stream);
+            }
+
+            tokio::select! {
+                _ = notified => {}
+                _ = self.link.shutdown.cancelled() => self.ensure_up()?,
+            }
+        }
+    }
+
+    async fn close(&self, _code: u64, reason: &str) -> Result<(), UTPError> {
+        self.take_down(UTPEvent::Closed {
+            reason: reason.to_string(),
+        });
+
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        Some(self.peer.addr)
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(self.local.addr)
+    }
+
+    fn stats(&self) -> UTPStats {
+        UTPStats {
+            rtt: Some(self.outgoing.config().latency + self.incoming.config().latency),
+            congestion_window: None,
+            bytes_sent: Some(self.outgoing.bytes_sent.load(Ordering::Relaxed)),
+            bytes_received: Some(self.incoming.bytes_delivered.load(Ordering::Relaxed)),
+            lost_packets: Some(self.outgoing.lost.load(Ordering::Relaxed)),
+        }
+    }
+}
@@ -1,16 +1,26 @@
+use std::{net::SocketAddr, time::Duration};
+
 use async_trait::async_trait;
-use tokio::io::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+};
 
 use crate::{
-    schema::{IntegrityType, StreamId},
+    schema::{IntegrityType, Message, StreamId},
     utp::error::UTPError,
 };
 
+/// Maximum size of a single message sent with the framed datagram fallback.
+pub const MAX_FRAMED_DATAGRAM_LEN: usize = 16 * 1024 * 1024;
+
 /// Trait defining the interface for a UTP stream.
 ///
 /// A UTP stream is an independent logical channel for binary data transmission.
 /// Streams can be either reliable (lossless) or unreliable (lossy) based on
 /// the `IntegrityType` used when opening the stream.
+#[async_trait]
 pub trait UTPStream: Send + Sync + 'static {
     type StreamRead: AsyncRead + Unpin + Send;
     type StreamWrite: AsyncWrite + Unpin + Send;
@@ -22,6 +32,95 @@ pub trait UTPStream: Send + Sync + 'static {
     fn integrity_type(&self) -> IntegrityType;
 
     fn split(self) -> (Self::StreamWrite, Self::StreamRead);
+
+    /// Sends a single message on the write half, preserving its boundary.
+    ///
+    /// The default implementation prefixes the message with its length and
+    /// writes it to the byte stream. Transports with native message support,
+    /// such as datagrams, should override this together with `recv_datagram`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is too large or the write fails.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        write_framed_datagram(writer, data).await
+    }
+
+    /// Receives a single message from the read half, as sent by `send_datagram`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stream is closed or the framing is corrupt.
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        read_framed_datagram(reader).await
+    }
+
+    /// Turns the stream into a channel of whole `Message` values, if the
+    /// transport can carry them without encoding.
+    ///
+    /// The Primary Messaging Channel uses this, when available, instead of
+    /// framing serialized messages on the byte stream. Both ends of a stream
+    /// must agree. The default implementation returns the stream unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns the stream itself if it only carries bytes.
+    fn into_messages(self) -> Result<MessageChannel, Self>
+    where
+        Self: Sized,
+    {
+        Err(self)
+    }
+}
+
+/// Both directions of a stream that carries `Message` values, as returned by
+/// `UTPStream::into_messages`.
+///
+/// The stream ends when `rx` yields `None`.
+pub struct MessageChannel {
+    pub tx: UnboundedSender<Message>,
+    pub rx: UnboundedReceiver<Message>,
+}
+
+/// Writes a length-prefixed message to a byte stream.
+///
+/// This is the framing used by the default `UTPStream::send_datagram`, exposed
+/// so that transports overriding it can still fall back to it.
+pub async fn write_framed_datagram<W: AsyncWrite + Unpin + Send>(
+    writer: &mut W,
+    data: Bytes,
+) -> Result<(), UTPError> {
+    if data.len() > MAX_FRAMED_DATAGRAM_LEN {
+        return Err(UTPError::Warn(format!(
+            "datagram of {} bytes exceeds {} bytes",
+            data.len(),
+            MAX_FRAMED_DATAGRAM_LEN
+        )));
+    }
+
+    writer.write_u32_le(data.len() as u32).await?;
+    writer.write_all(&data).await?;
+
+    Ok(())
+}
+
+/// Reads a length-prefixed message written by `write_framed_datagram`.
+pub async fn read_framed_datagram<R: AsyncRead + Unpin + Send>(
+    reader: &mut R,
+) -> Result<Bytes, UTPError> {
+    let len = reader.read_u32_le().await? as usize;
+
+    if len > MAX_FRAMED_DATAGRAM_LEN {
+        return Err(UTPError::Fatal(format!(
+            "framed datagram of {} bytes exceeds {} bytes",
+            len, MAX_FRAMED_DATAGRAM_LEN
+        )));
+    }
+
+    let mut buf = vec![0; len];
+    reader.read_exact(&mut buf).await?;
+
+    Ok(buf.into())
 }
 
 /// Trait defining the Upstream Transport Protocol (UTP) interface.
@@ -34,7 +133,8 @@ pub trait UTPStream: Send + Sync + 'static {
 /// - Stream open/close operations
 /// - Send/receive operations
 /// - Primary stream management
-/// - Event notification for incoming streams
+/// - Event notification for incoming streams, stream and connection closes,
+///   and path changes
 #[async_trait]
 pub trait UTP: Send + Sync + 'static {
     /// The stream type used by this UTP implementation
@@ -50,7 +150,9 @@ pub trait UTP: Send + Sync + 'static {
     /// Waits for the next UTP event.
     ///
     /// This method blocks until an event occurs, such as a new incoming
-    /// stream or an unexpected connection close.
+    /// stream or a connection close. Once a terminal event (see
+    /// `UTPEvent::is_terminal`) has been returned, implementations should keep
+    /// returning `UnexpectedClose`.
     async fn next_event(&self) -> UTPEvent;
 
     /// Opens a new stream with the specified integrity type.
@@ -82,6 +184,63 @@ pub trait UTP: Send + Sync + 'static {
         id: StreamId,
         integrity: IntegrityType,
     ) -> Result<Self::Stream, UTPError>;
+
+    /// Closes the transport, telling the peer why.
+    ///
+    /// The peer observes a `UTPEvent::Closed` carrying `reason`. The default
+    /// implementation does nothing, leaving the transport to close when it is
+    /// dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Application-defined close code
+    /// * `reason` - Human-readable reason for the close
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the close cannot be delivered.
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        let _ = (code, reason);
+        Ok(())
+    }
+
+    /// Returns the address of the peer, if the transport has one.
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Returns the local address of the transport, if it has one.
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Returns a snapshot of the transport's statistics.
+    ///
+    /// The default implementation reports nothing.
+    fn stats(&self) -> UTPStats {
+        UTPStats::default()
+    }
+}
+
+/// Transport statistics reported by `UTP::stats`.
+///
+/// Fields a transport cannot measure are left as `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UTPStats {
+    /// Current round-trip time estimate
+    pub rtt: Option<Duration>,
+
+    /// Current congestion window, in bytes
+    pub congestion_window: Option<u64>,
+
+    /// Bytes sent on the wire, including transport overhead
+    pub bytes_sent: Option<u64>,
+
+    /// Bytes received on the wire, including transport overhead
+    pub bytes_received: Option<u64>,
+
+    /// Packets detected as lost
+    pub lost_packets: Option<u64>,
 }
 
 /// Events that can occur on a UTP connection.
@@ -90,6 +249,36 @@ pub enum UTPEvent {
     /// The connection was unexpectedly closed
     UnexpectedClose,
 
+    /// The connection was closed gracefully, by either side
+    Closed {
+        /// Reason given by the side that closed the connection
+        reason: String,
+    },
+
     /// A new stream with the given ID has been opened by the peer
     NewStream(StreamId),
+
+    /// The peer finished sending on the stream with the given ID
+    StreamClosed(StreamId),
+
+    /// The stream with the given ID was abruptly terminated
+    StreamReset {
+        /// The reset stream
+        id: StreamId,
+        /// Transport-specific error code
+        code: u64,
+    },
+
+    /// The address of the peer changed, e.g. after a connection migration
+    PathChanged {
+        /// The new address of the peer
+        remote: SocketAddr,
+    },
+}
+
+impl UTPEvent {
+    /// Returns `true` if the connection cannot be used after this event.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, UTPEvent::UnexpectedClose | UTPEvent::Closed { .. })
+    }
 }
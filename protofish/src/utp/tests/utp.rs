@@ -1,6 +1,10 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicU64, Ordering},
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 
 use async_trait::async_trait;
@@ -10,11 +14,13 @@ use tokio::sync::{
     mpsc::{self, Receiver, Sender},
 };
 
+#[cfg(test)]
+use crate::core::{client::connect, common::connection::Connection, server::accept};
 use crate::{
     schema::{IntegrityType, StreamId},
     utp::{
         error::UTPError,
-        protocol::{UTP, UTPEvent},
+        protocol::{UTP, UTPEvent, UTPStats},
         tests::stream::{MockUTPStream, mock_utp_stream_pairs},
     },
 };
@@ -29,6 +35,7 @@ struct PeerStreamStore {
 pub struct MockUTP {
     peer: Option<Arc<MockUTP>>,
     id_counter: Arc<AtomicU64>,
+    addr: SocketAddr,
 
     event_receiver: Arc<Mutex<Receiver<UTPEvent>>>,
     event_sender: Sender<UTPEvent>,
@@ -43,17 +50,23 @@ impl MockUTP {
         Self {
             peer: None,
             id_counter,
+            addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
             event_receiver: Mutex::new(rx).into(),
             event_sender: tx,
             peer_streams: Default::default(),
         }
     }
 
+    pub fn with_addr(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
     pub fn set_peer(&mut self, peer: Arc<MockUTP>) {
         self.peer.replace(peer);
     }
 
-    pub(self) async fn add_event(&self, event: UTPEvent) {
+    pub(crate) async fn add_event(&self, event: UTPEvent) {
         if let Err(e) = self.event_sender.send(event).await {
             tracing::warn!("test send error: {:?}", e);
         }
@@ -103,13 +116,41 @@ impl UTP for MockUTP {
             }
         }
     }
+
+    async fn close(&self, _code: u64, reason: &str) -> Result<(), UTPError> {
+        let event = UTPEvent::Closed {
+            reason: reason.to_string(),
+        };
+
+        if let Some(ref peer) = self.peer {
+            peer.add_event(event.clone()).await;
+        }
+        self.add_event(event).await;
+
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer.as_ref().map(|peer| peer.addr)
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        Some(self.addr)
+    }
+
+    fn stats(&self) -> UTPStats {
+        UTPStats {
+            rtt: Some(Duration::ZERO),
+            ..Default::default()
+        }
+    }
 }
 
 pub fn mock_utp_pairs() -> (MockUTP, MockUTP) {
     let counter = Arc::new(AtomicU64::new(0));
 
-    let mut a = MockUTP::new(counter.clone());
-    let mut b = MockUTP::new(counter.clone());
+    let mut a = MockUTP::new(counter.clone()).with_addr(SocketAddr::from((Ipv4Addr::LOCALHOST, 1)));
+    let mut b = MockUTP::new(counter.clone()).with_addr(SocketAddr::from((Ipv4Addr::LOCALHOST, 2)));
 
     a.set_peer(Arc::new(b.clone()));
     b.set_peer(Arc::new(a.clone()));
@@ -117,6 +158,17 @@ pub fn mock_utp_pairs() -> (MockUTP, MockUTP) {
     (a, b)
 }
 
+/// Connects a client and a server `Connection` over a mock UTP pair.
+#[cfg(test)]
+pub(crate) async fn mock_connection_pair() -> (Connection<MockUTP>, Connection<MockUTP>) {
+    let (a, b) = mock_utp_pairs();
+
+    let server = tokio::spawn(async move { accept(a.into()).await.unwrap() });
+    let client = connect(b.into()).await.unwrap();
+
+    (client, server.await.unwrap())
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -174,4 +226,19 @@ mod tests {
         let ba = open_stream_ab((pairs.1, pairs.0)).await;
         check_stream_uni(ba).await;
     }
+
+    #[tokio::test]
+    async fn test_mock_utp_close() {
+        let (a, b) = mock_utp_pairs();
+
+        assert_eq!(a.peer_addr(), b.local_addr());
+        assert_eq!(b.peer_addr(), a.local_addr());
+
+        a.close(0, "done").await.unwrap();
+
+        for utp in [&a, &b] {
+            let event = utp.next_event().await;
+            assert!(matches!(&event, UTPEvent::Closed { reason } if reason == "done"));
+        }
+    }
 }
@@ -1,12 +1,13 @@
 use async_trait::async_trait;
 use dashmap::DashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::{Mutex, mpsc};
 
 use protofish::utp::error::UTPError;
-use protofish::utp::{UTP, UTPEvent};
+use protofish::utp::{UTP, UTPEvent, UTPStats};
 use protofish::{IntegrityType, StreamId};
 
 use crate::datagram::DatagramRouter;
@@ -19,6 +20,7 @@ pub struct QuicUTP {
     event_tx: mpsc::UnboundedSender<UTPEvent>,
     event_rx: Arc<Mutex<mpsc::UnboundedReceiver<UTPEvent>>>,
     datagram_router: DatagramRouter,
+    local_addr: Option<SocketAddr>,
 }
 
 impl QuicUTP {
@@ -37,6 +39,7 @@ impl QuicUTP {
             event_tx,
             event_rx: Arc::new(Mutex::new(event_rx)),
             datagram_router: datagram_router.clone(),
+            local_addr: None,
         };
 
         instance.spawn_stream_listener();
@@ -45,6 +48,16 @@ impl QuicUTP {
         instance
     }
 
+    /// Sets the address reported by `local_addr`, usually that of the
+    /// endpoint the connection was made on.
+    ///
+    /// QUIC connections only know their local IP, so this is not filled in
+    /// automatically.
+    pub fn with_local_addr(mut self, addr: SocketAddr) -> Self {
+        self.local_addr = Some(addr);
+        self
+    }
+
     fn add_unreliable_stream(&self, stream_id: StreamId) -> QuicUTPStream {
         let stream = QuicUTPStream::new_unreliable(stream_id, self.datagram_router.clone());
 
@@ -61,6 +74,8 @@ impl QuicUTP {
         let streams: Arc<DashMap<StreamId, QuicUTPStream>> = Arc::clone(&self.streams);
 
         tokio::spawn(async move {
+            let mut remote = connection.remote_address();
+
             loop {
                 match connection.accept_bi().await {
                     Ok((send, mut recv)) => match recv.read_u64_le().await {
@@ -73,20 +88,44 @@ impl QuicUTP {
                                 break;
                             }
                         }
-                        Err(_) => {
-                            let _ = event_tx.send(UTPEvent::UnexpectedClose);
+                        Err(e) => {
+                            tracing::warn!("failed to read stream header: {}", e);
                         }
                     },
-                    Err(_) => {
-                        let _ = event_tx.send(UTPEvent::UnexpectedClose);
+                    Err(e) => {
+                        let _ = event_tx.send(close_event(e));
                         break;
                     }
                 }
+
+                // quinn has no migration event, so check whenever the
+                // listener wakes up
+                let current = connection.remote_address();
+                if current != remote {
+                    remote = current;
+                    let _ = event_tx.send(UTPEvent::PathChanged { remote });
+                }
             }
         });
     }
 }
 
+/// Maps the reason a QUIC connection ended to a UTP event.
+fn close_event(error: quinn::ConnectionError) -> UTPEvent {
+    match error {
+        quinn::ConnectionError::ApplicationClosed(close) => UTPEvent::Closed {
+            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+        },
+        quinn::ConnectionError::ConnectionClosed(close) => UTPEvent::Closed {
+            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+        },
+        quinn::ConnectionError::LocallyClosed => UTPEvent::Closed {
+            reason: "closed locally".into(),
+        },
+        _ => UTPEvent::UnexpectedClose,
+    }
+}
+
 #[async_trait]
 impl UTP for QuicUTP {
     type Stream = QuicUTPStream;
@@ -140,4 +179,33 @@ impl UTP for QuicUTP {
             IntegrityType::Unreliable => Ok(self.add_unreliable_stream(id)),
         }
     }
+
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        let code = quinn::VarInt::from_u64(code)
+            .map_err(|e| UTPError::Warn(format!("invalid close code: {}", e)))?;
+
+        self.connection.close(code, reason.as_bytes());
+
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        Some(self.connection.remote_address())
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    fn stats(&self) -> UTPStats {
+        let stats = self.connection.stats();
+
+        UTPStats {
+            rtt: Some(stats.path.rtt),
+            congestion_window: Some(stats.path.cwnd),
+            bytes_sent: Some(stats.udp_tx.bytes),
+            bytes_received: Some(stats.udp_rx.bytes),
+            lost_packets: Some(stats.path.lost_packets),
+        }
+    }
 }
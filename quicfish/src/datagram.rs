@@ -1,18 +1,36 @@
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 
 use bytes::{Bytes, BytesMut};
 use dashmap::DashMap;
 use protofish::StreamId;
-use tokio::io::{AsyncWriteExt, ReadHalf, SimplexStream, WriteHalf};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc::{self, Receiver, Sender, error::TrySendError};
+
+/// Number of datagrams buffered per unreliable stream before new ones are dropped.
+const DATAGRAM_QUEUE_LEN: usize = 1024;
+/// Number of unreliable streams the peer may send datagrams on before they are
+/// registered locally; datagrams on further streams are dropped.
+const MAX_PENDING_STREAMS: usize = 64;
 
 #[derive(Clone)]
 pub struct DatagramRouter {
     conn: Arc<quinn::Connection>,
-    channels: Arc<DashMap<StreamId, WriteHalf<SimplexStream>>>,
-    pending_readers: Arc<DashMap<StreamId, ReadHalf<SimplexStream>>>,
+    channels: Arc<DashMap<StreamId, Sender<Bytes>>>,
+    pending_readers: Arc<DashMap<StreamId, Receiver<Bytes>>>,
     datagram_chunk_size: usize,
 }
 
+/// Receiving side of an unreliable stream.
+///
+/// Each received datagram is kept whole, so it can be consumed either as a
+/// message with `recv` or as bytes through `AsyncRead`.
+pub struct DatagramReader {
+    rx: Receiver<Bytes>,
+    pending: Bytes,
+}
+
 impl DatagramRouter {
     pub fn new(conn: Arc<quinn::Connection>, datagram_chunk_size: usize) -> Self {
         Self {
@@ -23,28 +41,46 @@ impl DatagramRouter {
         }
     }
 
-    pub fn register(&self, stream_id: StreamId) -> ReadHalf<SimplexStream> {
-        if let Some((_, read_half)) = self.pending_readers.remove(&stream_id) {
-            read_half
+    pub fn register(&self, stream_id: StreamId) -> DatagramReader {
+        let rx = if let Some((_, rx)) = self.pending_readers.remove(&stream_id) {
+            rx
         } else {
-            let (read_half, write_half) = tokio::io::simplex(1024);
+            let (tx, rx) = mpsc::channel(DATAGRAM_QUEUE_LEN);
 
-            self.channels.insert(stream_id, write_half);
+            self.channels.insert(stream_id, tx);
+
+            rx
+        };
 
-            read_half
+        DatagramReader {
+            rx,
+            pending: Bytes::new(),
         }
     }
 
-    fn register_lazy_writer(&self, stream_id: StreamId) {
+    fn register_lazy_writer(&self, stream_id: StreamId) -> crate::error::Result<()> {
         if !self.channels.contains_key(&stream_id) {
-            let (read_half, write_half) = tokio::io::simplex(1024);
-            self.channels.insert(stream_id, write_half);
-            self.pending_readers.insert(stream_id, read_half);
+            if self.pending_readers.len() >= MAX_PENDING_STREAMS {
+                return Err(crate::error::Error::Datagram(
+                    "too many unregistered streams".to_string(),
+                ));
+            }
+
+            let (tx, rx) = mpsc::channel(DATAGRAM_QUEUE_LEN);
+            self.channels.insert(stream_id, tx);
+            self.pending_readers.insert(stream_id, rx);
         }
+
+        Ok(())
+    }
+
+    /// Maximum payload that fits in a single datagram of this stream.
+    pub fn max_payload_size(&self) -> usize {
+        self.datagram_chunk_size - std::mem::size_of::<StreamId>()
     }
 
     pub fn write(&self, stream_id: StreamId, data: Bytes) -> crate::error::Result<()> {
-        let actual_chunk_size = self.datagram_chunk_size - std::mem::size_of::<StreamId>();
+        let actual_chunk_size = self.max_payload_size();
 
         (0..data.len())
             .step_by(actual_chunk_size)
@@ -56,6 +92,19 @@ impl DatagramRouter {
             .collect()
     }
 
+    /// Sends `data` as exactly one datagram.
+    pub fn write_datagram(&self, stream_id: StreamId, data: Bytes) -> crate::error::Result<()> {
+        if data.len() > self.max_payload_size() {
+            return Err(crate::error::Error::Datagram(format!(
+                "message of {} bytes exceeds the datagram payload limit of {} bytes",
+                data.len(),
+                self.max_payload_size()
+            )));
+        }
+
+        self.write_chunk(stream_id, &data)
+    }
+
     fn write_chunk(&self, stream_id: StreamId, data: &Bytes) -> crate::error::Result<()> {
         let id_bytes = stream_id.to_le_bytes();
 
@@ -67,15 +116,24 @@ impl DatagramRouter {
         Ok(())
     }
 
-    async fn route_datagram(&self, stream_id: StreamId, data: &Bytes) -> crate::error::Result<()> {
-        self.register_lazy_writer(stream_id);
-        let Some(mut channel) = self.channels.get_mut(&stream_id) else {
+    fn route_datagram(&self, stream_id: StreamId, data: Bytes) -> crate::error::Result<()> {
+        self.register_lazy_writer(stream_id)?;
+        let Some(channel) = self.channels.get(&stream_id) else {
             return Err(crate::error::Error::StreamClosed);
         };
 
-        channel.write_all(data).await?;
-
-        Ok(())
+        match channel.try_send(data) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                tracing::trace!("dropping datagram for stream {}: queue full", stream_id);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => {
+                drop(channel);
+                self.channels.remove(&stream_id);
+                Err(crate::error::Error::StreamClosed)
+            }
+        }
     }
 
     async fn run_listener(&self) -> crate::error::Result<()> {
@@ -91,7 +149,9 @@ impl DatagramRouter {
                     let stream_id = u64::from_le_bytes(id_bytes);
 
                     let payload = data.slice(8..);
-                    self.route_datagram(stream_id, &payload).await?;
+                    if let Err(e) = self.route_datagram(stream_id, payload) {
+                        tracing::debug!("datagram for stream {} not routed: {}", stream_id, e);
+                    }
                 }
                 Err(err) => {
                     break Err(crate::error::Error::from(err));
@@ -103,11 +163,43 @@ impl DatagramRouter {
     pub fn spawn_listener(&self) {
         let self_c = self.clone();
         tokio::spawn(async move {
-            loop {
-                if let Err(e) = self_c.run_listener().await {
-                    tracing::warn!("datagram listener error: {:?}", e);
-                }
+            if let Err(e) = self_c.run_listener().await {
+                tracing::debug!("datagram listener stopped: {:?}", e);
             }
         });
     }
 }
+
+impl DatagramReader {
+    /// Receives the next whole datagram, or `None` once the connection is gone.
+    ///
+    /// Bytes left over from a partial `AsyncRead` are returned first.
+    pub async fn recv(&mut self) -> Option<Bytes> {
+        if !self.pending.is_empty() {
+            return Some(std::mem::take(&mut self.pending));
+        }
+
+        self.rx.recv().await
+    }
+}
+
+impl AsyncRead for DatagramReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        while self.pending.is_empty() {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(data)) => self.pending = data,
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let len = buf.remaining().min(self.pending.len());
+        buf.put_slice(&self.pending.split_to(len));
+
+        Poll::Ready(Ok(()))
+    }
+}
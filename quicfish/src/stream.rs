@@ -1,12 +1,14 @@
+use async_trait::async_trait;
 use bytes::Bytes;
 use std::io::ErrorKind;
 use std::task::Poll;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, SimplexStream};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use protofish::utp::UTPStream;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTPStream, read_framed_datagram, write_framed_datagram};
 use protofish::{IntegrityType, StreamId};
 
-use crate::datagram::DatagramRouter;
+use crate::datagram::{DatagramReader, DatagramRouter};
 
 pub struct QuicUTPStream {
     id: StreamId,
@@ -22,7 +24,7 @@ pub enum StreamWriteInner {
 
 pub enum StreamReadInner {
     Reliable(quinn::RecvStream),
-    Unreliable(ReadHalf<SimplexStream>),
+    Unreliable(DatagramReader),
 }
 
 impl QuicUTPStream {
@@ -47,6 +49,7 @@ impl QuicUTPStream {
     }
 }
 
+#[async_trait]
 impl UTPStream for QuicUTPStream {
     type StreamRead = StreamReadInner;
     type StreamWrite = StreamWriteInner;
@@ -62,6 +65,28 @@ impl UTPStream for QuicUTPStream {
     fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
         (self.writer, self.reader)
     }
+
+    /// Sends one message as a single QUIC datagram on unreliable streams, so
+    /// it is either delivered whole or lost. Reliable streams use the
+    /// length-prefixed fallback.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        match writer {
+            StreamWriteInner::Reliable(reliable) => write_framed_datagram(reliable, data).await,
+            StreamWriteInner::Unreliable(router, stream_id) => {
+                Ok(router.write_datagram(*stream_id, data)?)
+            }
+        }
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        match reader {
+            StreamReadInner::Reliable(reliable) => read_framed_datagram(reliable).await,
+            StreamReadInner::Unreliable(unreliable) => unreliable
+                .recv()
+                .await
+                .ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+        }
+    }
 }
 
 impl AsyncRead for StreamReadInner {
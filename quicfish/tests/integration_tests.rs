@@ -5,7 +5,8 @@ use tokio::time::timeout;
 
 use bytes::Bytes;
 use protofish::IntegrityType;
-use quicfish::{QuicConfig, QuicEndpoint, QuicUTP};
+use protofish::utp::{UTP, UTPStream};
+use quicfish::{QuicConfig, QuicEndpoint, QuicUTP, QuicUTPStream};
 
 mod common;
 use common::create_test_certs;
@@ -194,6 +195,149 @@ async fn test_unreliable_stream() {
     assert!(server_result, "Server should have processed datagram");
 }
 
+#[tokio::test]
+async fn test_unreliable_datagrams() {
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let server_config = QuicConfig::server_default().with_server_crypto(server_crypto);
+    let server_endpoint = QuicEndpoint::server("127.0.0.1:0".parse().unwrap(), server_config)
+        .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+
+    let server_handle = tokio::spawn(async move {
+        if let Some(conn) = server_endpoint.accept().await {
+            let utp = Arc::new(QuicUTP::new(conn, true));
+            let conn = protofish::accept(utp).await.expect("failed to accept");
+
+            let arb = conn.next_arb().await.unwrap();
+            let mut stream = arb.wait_stream().await.unwrap();
+
+            for _ in 0..3 {
+                let message = timeout(Duration::from_secs(2), stream.recv_datagram())
+                    .await
+                    .expect("Receive timeout")
+                    .unwrap();
+                stream.send_datagram(message).await.unwrap();
+            }
+
+            ready_rx.await.unwrap();
+
+            return true;
+        }
+        false
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_config = QuicConfig::client_default().with_client_crypto(client_crypto);
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), client_config)
+        .expect("Failed to create client endpoint");
+
+    let conn = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .unwrap();
+    let client_utp = Arc::new(QuicUTP::new(conn, false));
+    let client_conn = protofish::connect(client_utp).await.unwrap();
+    let arb = client_conn.new_arb();
+
+    let mut stream = arb.new_stream(IntegrityType::Unreliable).await.unwrap();
+
+    let too_large = Bytes::from(vec![0u8; 64 * 1024]);
+    assert!(stream.send_datagram(too_large).await.is_err());
+
+    let messages = [
+        Bytes::from_static(b"a"),
+        Bytes::from_static(b"bb"),
+        Bytes::from_static(b"ccc"),
+    ];
+    for message in &messages {
+        stream.send_datagram(message.clone()).await.unwrap();
+    }
+
+    let mut received = Vec::new();
+    for _ in 0..messages.len() {
+        let message = timeout(Duration::from_secs(2), stream.recv_datagram())
+            .await
+            .expect("Receive timeout")
+            .unwrap();
+        received.push(message);
+    }
+
+    ready_tx.send(()).unwrap();
+
+    received.sort_by_key(|message| message.len());
+    assert_eq!(received, messages);
+
+    let server_result = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .expect("Server task failed");
+
+    assert!(server_result, "Server should have echoed datagrams");
+}
+
+#[tokio::test]
+async fn test_unregistered_datagram_streams_are_capped() {
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let server_config = QuicConfig::server_default().with_server_crypto(server_crypto);
+    let server_endpoint = QuicEndpoint::server("127.0.0.1:0".parse().unwrap(), server_config)
+        .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move { server_endpoint.accept().await.unwrap() });
+
+    let client_config = QuicConfig::client_default().with_client_crypto(client_crypto);
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), client_config)
+        .expect("Failed to create client endpoint");
+    let conn = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .unwrap();
+
+    let client = QuicUTP::new(conn, false);
+    let server = QuicUTP::new(server_handle.await.unwrap(), true);
+
+    // one more stream than the server keeps datagrams for
+    let mut ids = Vec::new();
+    for _ in 0..65 {
+        let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+        ids.push(stream.id());
+
+        let (mut write, _) = stream.split();
+        QuicUTPStream::send_datagram(&mut write, Bytes::from_static(b"muffin"))
+            .await
+            .unwrap();
+        // keeps the datagrams from being dropped by the socket buffers
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut received = 0;
+    for id in ids {
+        let stream = server
+            .wait_stream(id, IntegrityType::Unreliable)
+            .await
+            .unwrap();
+        let (_, mut read) = stream.split();
+        if timeout(
+            Duration::from_millis(50),
+            QuicUTPStream::recv_datagram(&mut read),
+        )
+        .await
+        .is_ok()
+        {
+            received += 1;
+        }
+    }
+
+    assert_eq!(received, 64);
+}
+
 #[tokio::test]
 async fn test_multiple_streams() {
     let (server_crypto, client_crypto) = create_test_certs();
@@ -272,3 +416,91 @@ async fn test_multiple_streams() {
 
     assert!(server_result);
 }
+
+#[tokio::test]
+async fn test_graceful_close_event() {
+    use protofish::utp::{UTP, UTPEvent};
+
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let server_config = QuicConfig::server_default().with_server_crypto(server_crypto);
+    let server_endpoint = QuicEndpoint::server("127.0.0.1:0".parse().unwrap(), server_config)
+        .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move {
+        let conn = server_endpoint.accept().await.expect("No connection");
+        let utp = QuicUTP::new(conn, true);
+
+        utp.next_event().await
+    });
+
+    let client_config = QuicConfig::client_default().with_client_crypto(client_crypto);
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), client_config)
+        .expect("Failed to create client endpoint");
+
+    let conn = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .expect("Failed to connect");
+
+    conn.close(0u32.into(), b"bye");
+
+    let event = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .expect("Server task failed");
+
+    assert!(
+        matches!(&event, UTPEvent::Closed { reason } if reason == "bye"),
+        "unexpected event: {:?}",
+        event
+    );
+}
+
+#[tokio::test]
+async fn test_close_addresses_and_stats() {
+    use protofish::utp::{UTP, UTPEvent};
+
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let server_config = QuicConfig::server_default().with_server_crypto(server_crypto);
+    let server_endpoint = QuicEndpoint::server("127.0.0.1:0".parse().unwrap(), server_config)
+        .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move {
+        let conn = server_endpoint.accept().await.expect("No connection");
+        let utp = QuicUTP::new(conn, true);
+
+        utp.next_event().await
+    });
+
+    let client_config = QuicConfig::client_default().with_client_crypto(client_crypto);
+    let client_endpoint = QuicEndpoint::client("127.0.0.1:0".parse().unwrap(), client_config)
+        .expect("Failed to create client endpoint");
+    let client_addr = client_endpoint.local_addr().unwrap();
+
+    let conn = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .expect("Failed to connect");
+    let utp = QuicUTP::new(conn, false).with_local_addr(client_addr);
+
+    assert_eq!(utp.peer_addr(), Some(server_addr));
+    assert_eq!(utp.local_addr(), Some(client_addr));
+    assert!(utp.stats().rtt.is_some());
+
+    utp.close(7, "maintenance").await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .expect("Server task failed");
+
+    assert!(
+        matches!(&event, UTPEvent::Closed { reason } if reason == "maintenance"),
+        "unexpected event: {:?}",
+        event
+    );
+}
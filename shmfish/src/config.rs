@@ -0,0 +1,58 @@
+use protofish::IntegrityType;
+
+use crate::ring::RingLayout;
+
+/// Settings of a `ShmEndpoint`.
+///
+/// They apply to streams opened by this side; streams opened by the peer use
+/// the peer's settings.
+#[derive(Clone, Debug)]
+pub struct ShmConfig {
+    /// Bytes buffered in each direction of a reliable stream before writers
+    /// wait
+    pub ring_capacity: usize,
+    /// Messages buffered in each direction of an unreliable stream before
+    /// the oldest is overwritten
+    pub slot_count: usize,
+    /// Largest message of an unreliable stream
+    pub slot_size: usize,
+}
+
+impl Default for ShmConfig {
+    fn default() -> Self {
+        Self {
+            ring_capacity: 1024 * 1024,
+            slot_count: 256,
+            slot_size: 4096,
+        }
+    }
+}
+
+impl ShmConfig {
+    pub fn with_ring_capacity(mut self, capacity: usize) -> Self {
+        self.ring_capacity = capacity;
+        self
+    }
+
+    pub fn with_slot_count(mut self, count: usize) -> Self {
+        self.slot_count = count;
+        self
+    }
+
+    pub fn with_slot_size(mut self, size: usize) -> Self {
+        self.slot_size = size;
+        self
+    }
+
+    pub(crate) fn layout(&self, integrity: &IntegrityType) -> RingLayout {
+        match integrity {
+            IntegrityType::Reliable => RingLayout::Bytes {
+                capacity: self.ring_capacity,
+            },
+            IntegrityType::Unreliable => RingLayout::Slots {
+                count: self.slot_count,
+                slot_size: self.slot_size,
+            },
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+
+use bytes::{Buf, BufMut, BytesMut};
+use protofish::StreamId;
+use tokio::io::Interest;
+use tokio::net::UnixStream;
+use tokio::sync::Mutex;
+
+use crate::ring::RingLayout;
+
+/// Length of the `[type u8][len u32 le]` header of a control frame.
+const HEADER_LEN: usize = 5;
+/// Largest body of a control frame.
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// Number of descriptors attached to an `Open` frame.
+pub(crate) const OPEN_FDS: usize = 5;
+
+const OPEN: u8 = 0;
+const CLOSE: u8 = 1;
+
+const LAYOUT_BYTES: u8 = 0;
+const LAYOUT_SLOTS: u8 = 1;
+
+/// A message on the Unix socket that sets up the shared memory channels.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Control {
+    /// Opens a stream. The channel's descriptors are attached to the frame.
+    Open { id: StreamId, layout: RingLayout },
+    /// Closes the connection gracefully.
+    Close { code: u64, reason: String },
+}
+
+impl Control {
+    pub fn encode(&self) -> BytesMut {
+        let mut body = BytesMut::new();
+
+        let kind = match self {
+            Control::Open { id, layout } => {
+                body.put_u64_le(*id);
+                match *layout {
+                    RingLayout::Bytes { capacity } => {
+                        body.put_u8(LAYOUT_BYTES);
+                        body.put_u64_le(capacity as u64);
+                        body.put_u64_le(0);
+                    }
+                    RingLayout::Slots { count, slot_size } => {
+                        body.put_u8(LAYOUT_SLOTS);
+                        body.put_u64_le(count as u64);
+                        body.put_u64_le(slot_size as u64);
+                    }
+                }
+                OPEN
+            }
+            Control::Close { code, reason } => {
+                body.put_u64_le(*code);
+                // keep the frame within bounds, at a character boundary
+                let mut end = reason.len().min(MAX_BODY_LEN - 8);
+                while !reason.is_char_boundary(end) {
+                    end -= 1;
+                }
+                body.put_slice(&reason.as_bytes()[..end]);
+                CLOSE
+            }
+        };
+
+        let mut frame = BytesMut::with_capacity(HEADER_LEN + body.len());
+        frame.put_u8(kind);
+        frame.put_u32_le(body.len() as u32);
+        frame.put_slice(&body);
+        frame
+    }
+
+    fn decode(kind: u8, mut body: &[u8]) -> io::Result<Self> {
+        match kind {
+            OPEN => {
+                if body.len() != 25 {
+                    return Err(protocol_error("malformed Open frame"));
+                }
+
+                let id = body.get_u64_le();
+                let layout = body.get_u8();
+                let a = usize::try_from(body.get_u64_le()).map_err(|_| invalid_layout())?;
+                let b = usize::try_from(body.get_u64_le()).map_err(|_| invalid_layout())?;
+
+                let layout = match layout {
+                    LAYOUT_BYTES => RingLayout::Bytes { capacity: a },
+                    LAYOUT_SLOTS => RingLayout::Slots {
+                        count: a,
+                        slot_size: b,
+                    },
+                    _ => return Err(invalid_layout()),
+                };
+
+                Ok(Control::Open { id, layout })
+            }
+            CLOSE => {
+                if body.len() < 8 {
+                    return Err(protocol_error("malformed Close frame"));
+                }
+
+                let code = body.get_u64_le();
+                let reason = String::from_utf8_lossy(body).into_owned();
+
+                Ok(Control::Close { code, reason })
+            }
+            other => Err(protocol_error(&format!("unknown control frame {}", other))),
+        }
+    }
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn invalid_layout() -> io::Error {
+    protocol_error("invalid ring layout")
+}
+
+/// Sending side of the control socket.
+pub(crate) struct ControlWriter {
+    lock: Mutex<()>,
+}
+
+impl ControlWriter {
+    pub fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Sends `control`, attaching `fds` to its first byte.
+    pub async fn send(
+        &self,
+        socket: &UnixStream,
+        control: &Control,
+        fds: &[BorrowedFd<'_>],
+    ) -> io::Result<()> {
+        let frame = control.encode();
+        let fds: Vec<RawFd> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+
+        // frames must not interleave
+        let _guard = self.lock.lock().await;
+
+        let mut sent = 0;
+        while sent < frame.len() {
+            let attached = if sent == 0 { &fds[..] } else { &[] };
+            sent += socket
+                .async_io(Interest::WRITABLE, || {
+                    send_with_fds(socket.as_raw_fd(), &frame[sent..], attached)
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receiving side of the control socket.
+pub(crate) struct ControlReader {
+    buf: BytesMut,
+    /// Received descriptors not yet claimed by a frame.
+    fds: VecDeque<OwnedFd>,
+}
+
+impl ControlReader {
+    pub fn new() -> Self {
+        Self {
+            buf: BytesMut::new(),
+            fds: VecDeque::new(),
+        }
+    }
+
+    /// Waits for the next frame and the descriptors attached to it.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` when the peer closed the socket between frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket fails, the frame is malformed, or the
+    /// socket ends mid-frame.
+    pub async fn next(
+        &mut self,
+        socket: &UnixStream,
+    ) -> io::Result<Option<(Control, Vec<OwnedFd>)>> {
+        loop {
+            if let Some(frame) = self.parse()? {
+                return Ok(Some(frame));
+            }
+
+            let mut chunk = [0; 4096];
+            let read = socket
+                .async_io(Interest::READABLE, || {
+                    recv_with_fds(socket.as_raw_fd(), &mut chunk, &mut self.fds)
+                })
+                .await?;
+
+            if read == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "control socket ended mid-frame",
+                ));
+            }
+
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    fn parse(&mut self) -> io::Result<Option<(Control, Vec<OwnedFd>)>> {
+        if self.buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+
+        let kind = self.buf[0];
+        let len = u32::from_le_bytes(self.buf[1..HEADER_LEN].try_into().unwrap()) as usize;
+        if len > MAX_BODY_LEN {
+            return Err(protocol_error("control frame too large"));
+        }
+        if self.buf.len() < HEADER_LEN + len {
+            return Ok(None);
+        }
+
+        let frame = self.buf.split_to(HEADER_LEN + len);
+        let control = Control::decode(kind, &frame[HEADER_LEN..])?;
+
+        // descriptors arrive with the first byte of their frame, so they are
+        // queued by now
+        let fds = match control {
+            Control::Open { .. } => {
+                if self.fds.len() < OPEN_FDS {
+                    return Err(protocol_error("Open frame without descriptors"));
+                }
+                self.fds.drain(..OPEN_FDS).collect()
+            }
+            Control::Close { .. } => Vec::new(),
+        };
+
+        Ok(Some((control, fds)))
+    }
+}
+
+fn send_with_fds(socket: RawFd, data: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+
+    let fds_len = size_of_val(fds) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+
+    let mut message = unsafe { std::mem::zeroed::<libc::msghdr>() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+
+    if !fds.is_empty() {
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = control.len() as _;
+
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&message);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+        }
+    }
+
+    let sent = unsafe { libc::sendmsg(socket, &message, libc::MSG_NOSIGNAL | libc::MSG_DONTWAIT) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(sent as usize)
+}
+
+fn recv_with_fds(socket: RawFd, buf: &mut [u8], fds: &mut VecDeque<OwnedFd>) -> io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+
+    // room for a few frames' worth of descriptors
+    let max_fds = (4 * OPEN_FDS * size_of::<RawFd>()) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(max_fds) } as usize];
+
+    let mut message = unsafe { std::mem::zeroed::<libc::msghdr>() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = control.len() as _;
+
+    let read = unsafe {
+        libc::recvmsg(
+            socket,
+            &mut message,
+            libc::MSG_CMSG_CLOEXEC | libc::MSG_DONTWAIT,
+        )
+    };
+    if read < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&message);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let data: *const RawFd = libc::CMSG_DATA(cmsg).cast();
+
+                for i in 0..len / size_of::<RawFd>() {
+                    fds.push_back(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&message, cmsg);
+        }
+    }
+
+    if message.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(protocol_error("control message truncated"));
+    }
+
+    Ok(read as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsFd;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_with_descriptors() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let writer = ControlWriter::new();
+        let mut reader = ControlReader::new();
+
+        let open = Control::Open {
+            id: 7,
+            layout: RingLayout::Slots {
+                count: 4,
+                slot_size: 1024,
+            },
+        };
+        let close = Control::Close {
+            code: 3,
+            reason: "bye".into(),
+        };
+
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let fd = file.as_fd();
+        writer.send(&a, &open, &[fd; OPEN_FDS]).await.unwrap();
+        writer.send(&a, &close, &[]).await.unwrap();
+        drop(a);
+
+        let (control, fds) = reader.next(&b).await.unwrap().unwrap();
+        assert_eq!(control, open);
+        assert_eq!(fds.len(), OPEN_FDS);
+
+        let (control, fds) = reader.next(&b).await.unwrap().unwrap();
+        assert_eq!(control, close);
+        assert!(fds.is_empty());
+
+        assert!(reader.next(&b).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_without_descriptors_rejected() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let writer = ControlWriter::new();
+        let mut reader = ControlReader::new();
+
+        let open = Control::Open {
+            id: 7,
+            layout: RingLayout::Bytes { capacity: 1024 },
+        };
+        writer.send(&a, &open, &[]).await.unwrap();
+
+        let err = reader.next(&b).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
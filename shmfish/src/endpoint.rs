@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::config::ShmConfig;
+use crate::error::{Error, Result};
+use crate::utp::ShmUTP;
+
+/// Opens or accepts shared memory connections, set up over a Unix domain
+/// socket at a well-known path.
+///
+/// A server endpoint removes its socket file when dropped.
+pub struct ShmEndpoint {
+    listener: Option<(UnixListener, PathBuf)>,
+    config: ShmConfig,
+}
+
+impl ShmEndpoint {
+    pub fn client(config: ShmConfig) -> Self {
+        Self {
+            listener: None,
+            config,
+        }
+    }
+
+    /// Binds a listening socket at `path`. Must be called within a tokio
+    /// runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be bound, e.g. because `path`
+    /// already exists.
+    pub fn server(path: impl AsRef<Path>, config: ShmConfig) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let listener = UnixListener::bind(&path)?;
+
+        Ok(Self {
+            listener: Some((listener, path)),
+            config,
+        })
+    }
+
+    /// Connects to the server listening at `path`.
+    pub async fn connect(&self, path: impl AsRef<Path>) -> Result<ShmUTP> {
+        if self.listener.is_some() {
+            return Err(Error::Config(
+                "Cannot connect from server endpoint".to_string(),
+            ));
+        }
+
+        let socket = UnixStream::connect(path).await?;
+
+        Ok(ShmUTP::new(socket, false, self.config.clone()))
+    }
+
+    /// Waits for the next client.
+    ///
+    /// Returns `None` if this is a client endpoint or the listener fails.
+    pub async fn accept(&self) -> Option<ShmUTP> {
+        let (listener, _) = self.listener.as_ref()?;
+
+        match listener.accept().await {
+            Ok((socket, _)) => Some(ShmUTP::new(socket, true, self.config.clone())),
+            Err(e) => {
+                tracing::warn!("Unix socket accept failure: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Returns the path the server endpoint is bound to.
+    pub fn local_path(&self) -> Result<&Path> {
+        match &self.listener {
+            Some((_, path)) => Ok(path),
+            None => Err(Error::Config("Client endpoint is not bound".to_string())),
+        }
+    }
+}
+
+impl Drop for ShmEndpoint {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.listener {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub struct ShmEndpointBuilder {
+    config: ShmConfig,
+    path: Option<PathBuf>,
+}
+
+impl ShmEndpointBuilder {
+    pub fn new_client() -> Self {
+        Self {
+            config: ShmConfig::default(),
+            path: None,
+        }
+    }
+
+    pub fn new_server(path: impl AsRef<Path>) -> Self {
+        Self {
+            config: ShmConfig::default(),
+            path: Some(path.as_ref().to_path_buf()),
+        }
+    }
+
+    pub fn with_config(mut self, config: ShmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> Result<ShmEndpoint> {
+        match self.path {
+            Some(path) => ShmEndpoint::server(path, self.config),
+            None => Ok(ShmEndpoint::client(self.config)),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::task::{Context, Poll, ready};
+
+use tokio::io::unix::AsyncFd;
+
+/// An eventfd used to wake the other side of a ring.
+///
+/// Both processes hold the same eventfd. `notify` makes it readable, and
+/// `poll_wait` waits for that and resets it.
+pub(crate) struct EventFd {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl EventFd {
+    /// Creates a new eventfd. Must be called within a tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::from_fd(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Wraps an eventfd received from another process. Must be called
+    /// within a tokio runtime.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        // the flag belongs to the open file, so it normally survives the
+        // transfer, but a peer could have cleared it
+        let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+        if flags < 0
+            || unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    pub fn fd(&self) -> &OwnedFd {
+        self.fd.get_ref()
+    }
+
+    /// Wakes the waiter, if any. Never blocks.
+    pub fn notify(&self) {
+        let value: u64 = 1;
+        let written = unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                (&value as *const u64).cast(),
+                size_of::<u64>(),
+            )
+        };
+
+        // EAGAIN means the counter is saturated, so the waiter wakes anyway
+        if written < 0 {
+            tracing::trace!("eventfd notify failed: {}", io::Error::last_os_error());
+        }
+    }
+
+    /// Waits until `notify` was called since the last wait.
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+
+            match guard.try_io(|fd| {
+                let mut value: u64 = 0;
+                let read = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        (&mut value as *mut u64).cast(),
+                        size_of::<u64>(),
+                    )
+                };
+
+                if read < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Shared memory transport for processes on the same Linux host.
+//!
+//! Streams are ring buffers in memory shared by both processes, so data is
+//! copied once into the ring and once out of it, with no system call per
+//! write unless the other side is asleep. A Unix domain socket is used to
+//! set up streams and to notice when the peer goes away.
+
+#[cfg(not(target_os = "linux"))]
+compile_error!("shmfish only supports Linux");
+
+pub mod config;
+mod control;
+pub mod endpoint;
+pub mod error;
+mod event;
+mod mem;
+mod ring;
+pub mod stream;
+pub mod utp;
+
+pub type Connection = protofish::Connection<ShmUTP>;
+pub type ArbContext = protofish::ArbContext<ShmUTP>;
+
+pub use config::ShmConfig;
+pub use endpoint::{ShmEndpoint, ShmEndpointBuilder};
+pub use error::{Error, Result};
+pub use stream::{ShmStream, ShmStreamRead, ShmStreamWrite};
+pub use utp::ShmUTP;
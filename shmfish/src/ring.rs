@@ -0,0 +1,722 @@
+use std::io;
+use std::os::fd::{BorrowedFd, OwnedFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+use std::task::{Context, Poll, ready};
+
+use bytes::Bytes;
+use tokio::io::ReadBuf;
+
+use crate::event::EventFd;
+use crate::mem::SharedMemory;
+
+/// Bytes reserved for the header at the start of each ring.
+const HEADER_LEN: usize = 256;
+/// Bytes of each slot taken by its sequence number and length.
+const SLOT_HEADER_LEN: usize = 16;
+
+/// Value of `Header::writer_closed`.
+const OPEN: u32 = 0;
+const CLOSED: u32 = 1;
+const RESET: u32 = 2;
+
+/// Control block at the start of a ring, shared by both processes.
+///
+/// `head` and `tail` count bytes (or messages) ever written and read, so
+/// they never wrap in practice.
+#[repr(C)]
+struct Header {
+    head: AtomicU64,
+    _pad0: [u64; 7],
+    /// Unused by slot rings, whose readers keep their position locally
+    tail: AtomicU64,
+    _pad1: [u64; 7],
+    writer_closed: AtomicU32,
+    reader_closed: AtomicU32,
+    /// Set while the reader sleeps, so the writer knows to wake it
+    reader_waiting: AtomicU32,
+    /// Set while the writer sleeps, so the reader knows to wake it
+    writer_waiting: AtomicU32,
+}
+
+const _: () = assert!(size_of::<Header>() <= HEADER_LEN);
+
+/// Shape of the data area of a ring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RingLayout {
+    /// Byte ring of a reliable stream. Writers wait while it is full.
+    Bytes { capacity: usize },
+    /// Message slots of an unreliable stream. Writers overwrite the oldest
+    /// message when the reader falls behind.
+    Slots { count: usize, slot_size: usize },
+}
+
+impl RingLayout {
+    /// Size of one ring, header included, or `None` if the layout is empty
+    /// or too large.
+    pub fn len(&self) -> Option<usize> {
+        let data = match *self {
+            RingLayout::Bytes { capacity } if capacity > 0 => capacity,
+            RingLayout::Slots { count, slot_size } if count > 0 && slot_size > 0 => {
+                count.checked_mul(slot_stride(slot_size)?)?
+            }
+            _ => return None,
+        };
+
+        // keep both rings of a channel 64-byte aligned
+        HEADER_LEN.checked_add(data)?.checked_next_multiple_of(64)
+    }
+}
+
+fn slot_stride(slot_size: usize) -> Option<usize> {
+    if slot_size > u32::MAX as usize {
+        return None;
+    }
+
+    SLOT_HEADER_LEN
+        .checked_add(slot_size)?
+        .checked_next_multiple_of(8)
+}
+
+/// One direction of a channel: a ring and the eventfds waking each side.
+struct Ring {
+    mem: Arc<SharedMemory>,
+    offset: usize,
+    /// Signalled by the writer after adding data or closing
+    data: EventFd,
+    /// Signalled by the reader after freeing space or closing
+    space: EventFd,
+}
+
+impl Ring {
+    fn header(&self) -> &Header {
+        // the offset is within the mapping and 64-byte aligned
+        unsafe { &*(self.mem.as_ptr().add(self.offset) as *const Header) }
+    }
+
+    fn data_ptr(&self) -> *mut u8 {
+        unsafe { self.mem.as_ptr().add(self.offset + HEADER_LEN) }
+    }
+
+    /// Fails both ends of the ring after the peer left its header
+    /// inconsistent.
+    fn corrupt(&self) -> io::Error {
+        let header = self.header();
+        header.writer_closed.store(RESET, Ordering::SeqCst);
+        header.reader_closed.store(1, Ordering::SeqCst);
+        self.data.notify();
+        self.space.notify();
+
+        io::Error::new(io::ErrorKind::InvalidData, "corrupt ring header")
+    }
+}
+
+/// Both directions of a stream, in one shared memory region.
+///
+/// The creator writes to the first ring and reads from the second; the
+/// peer that opens the region does the opposite.
+pub(crate) struct Channel {
+    layout: RingLayout,
+    rings: [Ring; 2],
+    /// Index of the ring this side writes to.
+    side: usize,
+}
+
+impl Channel {
+    /// Allocates a new channel. Must be called within a tokio runtime.
+    pub fn create(layout: RingLayout) -> io::Result<Self> {
+        let len = layout.len().ok_or_else(|| invalid_layout(layout))?;
+        let mem = Arc::new(SharedMemory::create(2 * len)?);
+
+        Ok(Self {
+            layout,
+            rings: [
+                Ring {
+                    mem: mem.clone(),
+                    offset: 0,
+                    data: EventFd::new()?,
+                    space: EventFd::new()?,
+                },
+                Ring {
+                    mem,
+                    offset: len,
+                    data: EventFd::new()?,
+                    space: EventFd::new()?,
+                },
+            ],
+            side: 0,
+        })
+    }
+
+    /// Opens a channel created by the peer, from the descriptors returned by
+    /// its `fds`. Must be called within a tokio runtime.
+    pub fn open(layout: RingLayout, fds: [OwnedFd; 5]) -> io::Result<Self> {
+        let len = layout.len().ok_or_else(|| invalid_layout(layout))?;
+        let [mem, data0, space0, data1, space1] = fds;
+
+        let mem = Arc::new(SharedMemory::from_fd(mem)?);
+        if mem.len() < 2 * len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "shared memory of {} bytes is too small for {:?}",
+                    mem.len(),
+                    layout
+                ),
+            ));
+        }
+
+        Ok(Self {
+            layout,
+            rings: [
+                Ring {
+                    mem: mem.clone(),
+                    offset: 0,
+                    data: EventFd::from_fd(data0)?,
+                    space: EventFd::from_fd(space0)?,
+                },
+                Ring {
+                    mem,
+                    offset: len,
+                    data: EventFd::from_fd(data1)?,
+                    space: EventFd::from_fd(space1)?,
+                },
+            ],
+            side: 1,
+        })
+    }
+
+    pub fn layout(&self) -> RingLayout {
+        self.layout
+    }
+
+    /// Descriptors the peer needs to open the channel, in the order
+    /// `open` expects.
+    pub fn fds(&self) -> [BorrowedFd<'_>; 5] {
+        use std::os::fd::AsFd;
+
+        [
+            self.rings[0].mem.fd().as_fd(),
+            self.rings[0].data.fd().as_fd(),
+            self.rings[0].space.fd().as_fd(),
+            self.rings[1].data.fd().as_fd(),
+            self.rings[1].space.fd().as_fd(),
+        ]
+    }
+
+    fn outgoing(&self) -> &Ring {
+        &self.rings[self.side]
+    }
+
+    fn incoming(&self) -> &Ring {
+        &self.rings[1 - self.side]
+    }
+
+    /// Fails both local halves, as if the peer had vanished. Used when the
+    /// connection is lost, to wake anything waiting on the rings.
+    pub fn reset(&self) {
+        let incoming = self.incoming();
+        let _ = incoming.header().writer_closed.compare_exchange(
+            OPEN,
+            RESET,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        incoming.data.notify();
+
+        let outgoing = self.outgoing();
+        outgoing.header().reader_closed.store(1, Ordering::SeqCst);
+        outgoing.space.notify();
+    }
+}
+
+fn invalid_layout(layout: RingLayout) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid ring layout {:?}", layout),
+    )
+}
+
+/// Bytes waiting in a byte ring, or `None` if `head` and `tail` cannot both
+/// be right. The peer writes one of them, so neither is trusted.
+fn used(head: u64, tail: u64, capacity: usize) -> Option<usize> {
+    head.checked_sub(tail)
+        .filter(|&used| used <= capacity as u64)
+        .map(|used| used as usize)
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "reader is gone")
+}
+
+/// Sleeps on `event` until `ready` holds, announcing the wait in `flag` so
+/// the other side knows to signal.
+fn poll_until(
+    flag: &AtomicU32,
+    event: &EventFd,
+    cx: &mut Context<'_>,
+    ready: impl Fn() -> bool,
+) -> Poll<io::Result<()>> {
+    loop {
+        if ready() {
+            flag.store(0, Ordering::SeqCst);
+            return Poll::Ready(Ok(()));
+        }
+
+        flag.store(1, Ordering::SeqCst);
+        // the other side may have moved before it could see the flag
+        if ready() {
+            flag.store(0, Ordering::SeqCst);
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(event.poll_wait(cx))?;
+    }
+}
+
+fn wake(flag: &AtomicU32, event: &EventFd) {
+    if flag.load(Ordering::SeqCst) != 0 {
+        event.notify();
+    }
+}
+
+/// Writing end of a channel's outgoing ring. Closes it when dropped.
+pub(crate) struct RingWriter {
+    channel: Arc<Channel>,
+}
+
+impl RingWriter {
+    pub fn new(channel: Arc<Channel>) -> Self {
+        Self { channel }
+    }
+
+    pub fn layout(&self) -> RingLayout {
+        self.channel.layout
+    }
+
+    /// Copies as much of `buf` as fits into a byte ring, waiting while it
+    /// is full.
+    pub fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let RingLayout::Bytes { capacity } = self.channel.layout else {
+            unreachable!("byte write on a slot ring");
+        };
+        let ring = self.channel.outgoing();
+        let header = ring.header();
+
+        let pending = || {
+            used(
+                header.head.load(Ordering::Relaxed),
+                header.tail.load(Ordering::SeqCst),
+                capacity,
+            )
+        };
+        let closed = || header.reader_closed.load(Ordering::SeqCst) != 0;
+
+        ready!(poll_until(&header.writer_waiting, &ring.space, cx, || {
+            closed() || pending().is_none_or(|used| used < capacity)
+        }))?;
+        if closed() {
+            return Poll::Ready(Err(broken_pipe()));
+        }
+
+        let head = header.head.load(Ordering::Relaxed);
+        let tail = header.tail.load(Ordering::SeqCst);
+        let Some(used) = used(head, tail, capacity) else {
+            return Poll::Ready(Err(ring.corrupt()));
+        };
+        // at most `capacity`, so both copies stay inside the ring
+        let len = buf.len().min(capacity - used).min(capacity);
+        let pos = (head % capacity as u64) as usize;
+        let first = len.min(capacity - pos);
+
+        unsafe {
+            let data = ring.data_ptr();
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), data.add(pos), first);
+            std::ptr::copy_nonoverlapping(buf.as_ptr().add(first), data, len - first);
+        }
+
+        header.head.store(head + len as u64, Ordering::SeqCst);
+        wake(&header.reader_waiting, &ring.data);
+
+        Poll::Ready(Ok(len))
+    }
+
+    /// Writes one message into a slot ring, overwriting the oldest one if
+    /// the reader fell behind. Never waits.
+    pub fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let RingLayout::Slots { count, slot_size } = self.channel.layout else {
+            unreachable!("message write on a byte ring");
+        };
+        if message.len() > slot_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message of {} bytes exceeds {} bytes",
+                    message.len(),
+                    slot_size
+                ),
+            ));
+        }
+
+        let ring = self.channel.outgoing();
+        let header = ring.header();
+        if header.reader_closed.load(Ordering::SeqCst) != 0 {
+            return Err(broken_pipe());
+        }
+
+        let index = header.head.load(Ordering::Relaxed);
+        let slot = slot_ptr(ring, count, slot_size, index);
+
+        // odd while being written, so a concurrent reader discards the slot
+        let (seq, len) = unsafe { slot_header(slot) };
+        seq.store(2 * index + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        len.store(message.len() as u32, Ordering::Relaxed);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                message.as_ptr(),
+                slot.add(SLOT_HEADER_LEN),
+                message.len(),
+            );
+        }
+
+        seq.store(2 * index + 2, Ordering::Release);
+        header.head.store(index + 1, Ordering::SeqCst);
+        wake(&header.reader_waiting, &ring.data);
+
+        Ok(())
+    }
+
+    /// Marks the end of the stream for the reader.
+    pub fn close(&mut self) {
+        let ring = self.channel.outgoing();
+        let _ = ring.header().writer_closed.compare_exchange(
+            OPEN,
+            CLOSED,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        ring.data.notify();
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Reading end of a channel's incoming ring. Closes it when dropped, which
+/// fails further writes of the peer.
+pub(crate) struct RingReader {
+    channel: Arc<Channel>,
+    /// Index of the next message of a slot ring.
+    next: u64,
+    /// Messages of a slot ring that were overwritten before being read.
+    lost: u64,
+}
+
+impl RingReader {
+    pub fn new(channel: Arc<Channel>) -> Self {
+        Self {
+            channel,
+            next: 0,
+            lost: 0,
+        }
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Result of reading past the end of the ring.
+    fn end(header: &Header) -> io::Result<()> {
+        if header.writer_closed.load(Ordering::SeqCst) == RESET {
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "connection lost",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Copies available bytes of a byte ring into `buf`, waiting while it is
+    /// empty. Reads nothing at the end of the stream.
+    pub fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let RingLayout::Bytes { capacity } = self.channel.layout else {
+            unreachable!("byte read on a slot ring");
+        };
+        let ring = self.channel.incoming();
+        let header = ring.header();
+
+        ready!(poll_until(&header.reader_waiting, &ring.data, cx, || {
+            header.head.load(Ordering::SeqCst) != header.tail.load(Ordering::Relaxed)
+                || header.writer_closed.load(Ordering::SeqCst) != OPEN
+        }))?;
+
+        let head = header.head.load(Ordering::SeqCst);
+        let tail = header.tail.load(Ordering::Relaxed);
+        let Some(used) = used(head, tail, capacity) else {
+            return Poll::Ready(Err(ring.corrupt()));
+        };
+        if used == 0 {
+            return Poll::Ready(Self::end(header));
+        }
+
+        // at most `capacity`, so both copies stay inside the ring
+        let len = used.min(buf.remaining()).min(capacity);
+        let pos = (tail % capacity as u64) as usize;
+        let first = len.min(capacity - pos);
+
+        unsafe {
+            let data = ring.data_ptr();
+            let out = buf.initialize_unfilled_to(len);
+            std::ptr::copy_nonoverlapping(data.add(pos), out.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, out.as_mut_ptr().add(first), len - first);
+        }
+        buf.advance(len);
+
+        header.tail.store(tail + len as u64, Ordering::SeqCst);
+        wake(&header.writer_waiting, &ring.space);
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Receives the next message of a slot ring, skipping overwritten ones.
+    /// Returns `None` at the end of the stream.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Bytes>>> {
+        let RingLayout::Slots { count, slot_size } = self.channel.layout else {
+            unreachable!("message read on a byte ring");
+        };
+        let channel = self.channel.clone();
+        let ring = channel.incoming();
+        let header = ring.header();
+
+        loop {
+            let next = self.next;
+            ready!(poll_until(&header.reader_waiting, &ring.data, cx, || {
+                header.head.load(Ordering::SeqCst) > next
+                    || header.writer_closed.load(Ordering::SeqCst) != OPEN
+            }))?;
+
+            let head = header.head.load(Ordering::SeqCst);
+            if head <= self.next {
+                return Poll::Ready(Self::end(header).map(|_| None));
+            }
+
+            if head - self.next > count as u64 {
+                let skipped = head - count as u64 - self.next;
+                self.lost += skipped;
+                self.next += skipped;
+            }
+
+            let index = self.next;
+            self.next += 1;
+
+            let slot = slot_ptr(ring, count, slot_size, index);
+            let (seq, len) = unsafe { slot_header(slot) };
+
+            let before = seq.load(Ordering::Acquire);
+            if before != 2 * index + 2 {
+                // overwritten since `head` was read
+                self.lost += 1;
+                continue;
+            }
+
+            let len = (len.load(Ordering::Relaxed) as usize).min(slot_size);
+            let mut message = vec![0; len];
+            unsafe {
+                std::ptr::copy_nonoverlapping(slot.add(SLOT_HEADER_LEN), message.as_mut_ptr(), len);
+            }
+
+            fence(Ordering::Acquire);
+            if seq.load(Ordering::Relaxed) != before {
+                // overwritten while being copied
+                self.lost += 1;
+                continue;
+            }
+
+            return Poll::Ready(Ok(Some(Bytes::from(message))));
+        }
+    }
+}
+
+impl Drop for RingReader {
+    fn drop(&mut self) {
+        let ring = self.channel.incoming();
+        ring.header().reader_closed.store(1, Ordering::SeqCst);
+        ring.space.notify();
+    }
+}
+
+fn slot_ptr(ring: &Ring, count: usize, slot_size: usize, index: u64) -> *mut u8 {
+    // the layout was validated when the channel was created or opened
+    let stride = slot_stride(slot_size).unwrap();
+    unsafe {
+        ring.data_ptr()
+            .add((index % count as u64) as usize * stride)
+    }
+}
+
+/// Sequence number and length at the start of a slot.
+unsafe fn slot_header<'a>(slot: *mut u8) -> (&'a AtomicU64, &'a AtomicU32) {
+    unsafe {
+        (
+            &*(slot as *const AtomicU64),
+            &*(slot.add(8) as *const AtomicU32),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Opens the same channel a second time, as the peer process would.
+    fn pair(layout: RingLayout) -> (Arc<Channel>, Arc<Channel>) {
+        let a = Channel::create(layout).unwrap();
+        let fds = a.fds().map(|fd| fd.try_clone_to_owned().unwrap());
+        let b = Channel::open(layout, fds).unwrap();
+
+        (Arc::new(a), Arc::new(b))
+    }
+
+    async fn read(reader: &mut RingReader, len: usize) -> Vec<u8> {
+        let mut out = vec![0; len];
+        let mut buf = ReadBuf::new(&mut out);
+        poll_fn(|cx| reader.poll_read(cx, &mut buf)).await.unwrap();
+        let filled = buf.filled().len();
+        out.truncate(filled);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_byte_ring_waits_when_full() {
+        let (a, b) = pair(RingLayout::Bytes { capacity: 8 });
+        let mut writer = RingWriter::new(a);
+        let mut reader = RingReader::new(b);
+
+        let written = poll_fn(|cx| writer.poll_write(cx, b"muffin is cute"))
+            .await
+            .unwrap();
+        assert_eq!(written, 8);
+
+        let blocked = tokio::spawn(async move {
+            poll_fn(|cx| writer.poll_write(cx, b"cute")).await.unwrap();
+            writer
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!blocked.is_finished());
+
+        assert_eq!(read(&mut reader, 6).await, b"muffin");
+        let mut writer = blocked.await.unwrap();
+
+        // wraps around the end of the ring
+        assert_eq!(read(&mut reader, 16).await, b" icute");
+
+        writer.close();
+        assert_eq!(read(&mut reader, 16).await, b"");
+    }
+
+    #[tokio::test]
+    async fn test_slot_ring_overwrites_oldest() {
+        let (a, b) = pair(RingLayout::Slots {
+            count: 4,
+            slot_size: 16,
+        });
+        let mut writer = RingWriter::new(a);
+        let mut reader = RingReader::new(b);
+
+        for i in 0..10u8 {
+            writer.send(&[i; 3]).unwrap();
+        }
+        assert!(writer.send(&[0; 17]).is_err());
+        writer.close();
+
+        let mut received = Vec::new();
+        while let Some(message) = poll_fn(|cx| reader.poll_recv(cx)).await.unwrap() {
+            received.push(message[0]);
+        }
+
+        assert_eq!(received, [6, 7, 8, 9]);
+        assert_eq!(reader.lost(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_write_after_reader_dropped() {
+        let (a, b) = pair(RingLayout::Bytes { capacity: 8 });
+        let mut writer = RingWriter::new(a);
+        drop(RingReader::new(b));
+
+        let err = poll_fn(|cx| writer.poll_write(cx, b"muffin"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn test_reset_wakes_reader() {
+        let (a, b) = pair(RingLayout::Bytes { capacity: 8 });
+        let _writer = RingWriter::new(a);
+        let mut reader = RingReader::new(b.clone());
+
+        let blocked = tokio::spawn(async move {
+            let mut out = [0; 4];
+            let mut buf = ReadBuf::new(&mut out);
+            poll_fn(|cx| reader.poll_read(cx, &mut buf)).await
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        b.reset();
+
+        let err = blocked.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_header() {
+        let (a, b) = pair(RingLayout::Bytes { capacity: 8 });
+        let mut writer = RingWriter::new(a.clone());
+        let mut reader = RingReader::new(b);
+
+        poll_fn(|cx| writer.poll_write(cx, b"muffin"))
+            .await
+            .unwrap();
+
+        // the peer moves `tail` past `head`
+        a.outgoing().header().tail.store(7, Ordering::SeqCst);
+
+        let err = poll_fn(|cx| writer.poll_write(cx, b"cute"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut out = [0; 16];
+        let mut buf = ReadBuf::new(&mut out);
+        let err = poll_fn(|cx| reader.poll_read(cx, &mut buf))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(buf.filled().is_empty());
+
+        // or claims more than the ring holds
+        a.outgoing().header().tail.store(0, Ordering::SeqCst);
+        a.outgoing().header().head.store(100, Ordering::SeqCst);
+
+        let mut buf = ReadBuf::new(&mut out);
+        let err = poll_fn(|cx| reader.poll_read(cx, &mut buf))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,187 @@
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTPStream, read_framed_datagram, write_framed_datagram};
+use protofish::{IntegrityType, StreamId};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::ring::{Channel, RingLayout, RingReader, RingWriter};
+
+/// A stream over a shared memory channel.
+///
+/// Reliable streams are byte rings whose writers wait while the ring is
+/// full. Unreliable streams are rings of message slots whose writers never
+/// wait, overwriting the oldest message instead.
+pub struct ShmStream {
+    id: StreamId,
+    integrity_type: IntegrityType,
+    writer: ShmStreamWrite,
+    reader: ShmStreamRead,
+}
+
+/// Write half of a `ShmStream`. Ends the stream for the peer when dropped.
+pub struct ShmStreamWrite {
+    ring: RingWriter,
+    reliable: bool,
+}
+
+/// Read half of a `ShmStream`. Fails further writes of the peer when
+/// dropped.
+pub struct ShmStreamRead {
+    ring: RingReader,
+    reliable: bool,
+    /// Unread part of the last message of an unreliable stream.
+    pending: Bytes,
+}
+
+impl ShmStream {
+    pub(crate) fn new(id: StreamId, channel: Arc<Channel>) -> Self {
+        let reliable = matches!(channel.layout(), RingLayout::Bytes { .. });
+
+        Self {
+            id,
+            integrity_type: if reliable {
+                IntegrityType::Reliable
+            } else {
+                IntegrityType::Unreliable
+            },
+            writer: ShmStreamWrite {
+                ring: RingWriter::new(channel.clone()),
+                reliable,
+            },
+            reader: ShmStreamRead {
+                ring: RingReader::new(channel),
+                reliable,
+                pending: Bytes::new(),
+            },
+        }
+    }
+}
+
+impl ShmStreamRead {
+    /// Returns the number of messages of an unreliable stream that were
+    /// overwritten before they could be read.
+    pub fn lost(&self) -> u64 {
+        self.ring.lost()
+    }
+}
+
+#[async_trait]
+impl UTPStream for ShmStream {
+    type StreamRead = ShmStreamRead;
+    type StreamWrite = ShmStreamWrite;
+
+    fn id(&self) -> StreamId {
+        self.id
+    }
+
+    fn integrity_type(&self) -> IntegrityType {
+        self.integrity_type.clone()
+    }
+
+    fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
+        (self.writer, self.reader)
+    }
+
+    /// Writes one message into a single slot on unreliable streams, so it is
+    /// either read whole or overwritten. Reliable streams use the
+    /// length-prefixed fallback.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        if writer.reliable {
+            return write_framed_datagram(writer, data).await;
+        }
+
+        writer.ring.send(&data).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidInput => UTPError::Warn(e.to_string()),
+            _ => e.into(),
+        })
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        if reader.reliable {
+            return read_framed_datagram(reader).await;
+        }
+
+        // left over from a partial `AsyncRead`
+        if !reader.pending.is_empty() {
+            return Ok(std::mem::take(&mut reader.pending));
+        }
+
+        match poll_fn(|cx| reader.ring.poll_recv(cx)).await? {
+            Some(data) => Ok(data),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream closed").into()),
+        }
+    }
+}
+
+impl AsyncWrite for ShmStreamWrite {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if self.reliable {
+            return self.ring.poll_write(cx, buf);
+        }
+
+        // one message per write, cut to the slot size
+        let len = match self.ring.send(buf) {
+            Ok(()) => buf.len(),
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                let RingLayout::Slots { slot_size, .. } = self.ring.layout() else {
+                    unreachable!();
+                };
+                self.ring.send(&buf[..slot_size])?;
+                slot_size
+            }
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.ring.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for ShmStreamRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.reliable {
+            return self.ring.poll_read(cx, buf);
+        }
+
+        if self.pending.is_empty() {
+            match ready!(self.ring.poll_recv(cx))? {
+                Some(data) => self.pending = data,
+                // end of stream
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.pending.len().min(buf.remaining());
+        let data = self.pending.split_to(len);
+        buf.put_slice(&data);
+
+        Poll::Ready(Ok(()))
+    }
+}
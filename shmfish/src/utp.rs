@@ -0,0 +1,255 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTP, UTPEvent};
+use protofish::{IntegrityType, StreamId};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::ShmConfig;
+use crate::control::{Control, ControlReader, ControlWriter, OPEN_FDS};
+use crate::ring::Channel;
+use crate::stream::ShmStream;
+
+/// UTP over shared memory, for processes on the same Linux host.
+///
+/// Each stream gets its own shared memory channel. A Unix socket carries
+/// only the setup: the channel's memory and eventfds are passed to the peer
+/// when the stream opens, and the socket closing ends the connection.
+pub struct ShmUTP {
+    shared: Arc<Shared>,
+    next_stream_id: AtomicU64,
+    task: JoinHandle<()>,
+}
+
+struct Shared {
+    socket: UnixStream,
+    writer: ControlWriter,
+    config: ShmConfig,
+    events_tx: UnboundedSender<UTPEvent>,
+    events_rx: Mutex<UnboundedReceiver<UTPEvent>>,
+    /// Set once a terminal event has been returned by `next_event`.
+    finished: AtomicBool,
+    /// Streams opened by the peer, until picked up by `wait_stream`.
+    accepted: DashMap<StreamId, ShmStream>,
+    accepted_notify: Notify,
+    /// Channels of live streams, reset when the connection ends.
+    channels: DashMap<StreamId, Weak<Channel>>,
+    terminated: AtomicBool,
+    closed: CancellationToken,
+}
+
+impl ShmUTP {
+    /// Runs the transport over a connected Unix socket. Must be called
+    /// within a tokio runtime.
+    ///
+    /// Stream IDs are odd on the server side and even on the client side,
+    /// so both sides can open streams.
+    pub fn new(socket: UnixStream, is_server: bool, config: ShmConfig) -> Self {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        let shared = Arc::new(Shared {
+            socket,
+            writer: ControlWriter::new(),
+            config,
+            events_tx,
+            events_rx: Mutex::new(events_rx),
+            finished: AtomicBool::new(false),
+            accepted: DashMap::new(),
+            accepted_notify: Notify::new(),
+            channels: DashMap::new(),
+            terminated: AtomicBool::new(false),
+            closed: CancellationToken::new(),
+        });
+
+        Self {
+            task: tokio::spawn(read_loop(shared.clone())),
+            shared,
+            next_stream_id: AtomicU64::new(if is_server { 1 } else { 0 }),
+        }
+    }
+
+    /// Returns the credentials of the peer process, if they could be read.
+    pub fn peer_cred(&self) -> Option<tokio::net::unix::UCred> {
+        self.shared.socket.peer_cred().ok()
+    }
+}
+
+impl Drop for ShmUTP {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.shared.terminate(UTPEvent::UnexpectedClose);
+    }
+}
+
+impl Shared {
+    fn register(&self, id: StreamId, channel: &Arc<Channel>) {
+        self.channels
+            .retain(|_, channel| channel.strong_count() > 0);
+        self.channels.insert(id, Arc::downgrade(channel));
+    }
+
+    /// Ends the connection: wakes everything waiting on the channels and
+    /// reports `event`. Only the first call has an effect.
+    fn terminate(&self, event: UTPEvent) {
+        if self.terminated.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.closed.cancel();
+        for entry in self.channels.iter() {
+            if let Some(channel) = entry.value().upgrade() {
+                channel.reset();
+            }
+        }
+
+        let _ = self.events_tx.send(event);
+    }
+
+    fn ensure_open(&self) -> Result<(), UTPError> {
+        if self.terminated.load(Ordering::SeqCst) {
+            Err(UTPError::Fatal("connection closed".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+async fn read_loop(shared: Arc<Shared>) {
+    let mut reader = ControlReader::new();
+
+    let event = loop {
+        let frame = tokio::select! {
+            _ = shared.closed.cancelled() => return,
+            frame = reader.next(&shared.socket) => frame,
+        };
+
+        match frame {
+            Ok(Some((Control::Open { id, layout }, fds))) => {
+                let Ok(fds) = <[_; OPEN_FDS]>::try_from(fds) else {
+                    unreachable!("the reader attaches exactly {} descriptors", OPEN_FDS);
+                };
+
+                match Channel::open(layout, fds) {
+                    Ok(channel) => {
+                        let channel = Arc::new(channel);
+                        shared.register(id, &channel);
+                        shared.accepted.insert(id, ShmStream::new(id, channel));
+                        shared.accepted_notify.notify_waiters();
+                        let _ = shared.events_tx.send(UTPEvent::NewStream(id));
+                    }
+                    Err(e) => tracing::warn!("failed to open stream {}: {}", id, e),
+                }
+            }
+            Ok(Some((Control::Close { reason, .. }, _))) => break UTPEvent::Closed { reason },
+            Ok(None) => break UTPEvent::UnexpectedClose,
+            Err(e) => {
+                tracing::warn!("shared memory control failure: {}", e);
+                break UTPEvent::UnexpectedClose;
+            }
+        }
+    };
+
+    shared.terminate(event);
+}
+
+#[async_trait]
+impl UTP for ShmUTP {
+    type Stream = ShmStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.shared.ensure_open()
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        if self.shared.finished.load(Ordering::Acquire) {
+            return UTPEvent::UnexpectedClose;
+        }
+
+        // the shared state holds a sender, so the channel never closes
+        let event = self
+            .shared
+            .events_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .unwrap_or(UTPEvent::UnexpectedClose);
+
+        if event.is_terminal() {
+            self.shared.finished.store(true, Ordering::Release);
+        }
+
+        event
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<Self::Stream, UTPError> {
+        self.shared.ensure_open()?;
+
+        let id = self.next_stream_id.fetch_add(2, Ordering::Relaxed);
+        let layout = self.shared.config.layout(&integrity);
+        let channel = Arc::new(Channel::create(layout)?);
+
+        self.shared
+            .writer
+            .send(
+                &self.shared.socket,
+                &Control::Open { id, layout },
+                &channel.fds(),
+            )
+            .await?;
+        self.shared.register(id, &channel);
+
+        Ok(ShmStream::new(id, channel))
+    }
+
+    async fn wait_stream(
+        &self,
+        id: StreamId,
+        _integrity: IntegrityType,
+    ) -> Result<Self::Stream, UTPError> {
+        loop {
+            let notified = self.shared.accepted_notify.notified();
+            tokio::pin!(notified);
+            // register before checking, so an insert in between is not missed
+            notified.as_mut().enable();
+
+            if let Some((_, stream)) = self.shared.accepted.remove(&id) {
+                return Ok(stream);
+            }
+
+            tokio::select! {
+                _ = notified => {}
+                _ = self.shared.closed.cancelled() => self.shared.ensure_open()?,
+            }
+        }
+    }
+
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        if self.shared.terminated.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let control = Control::Close {
+            code,
+            reason: reason.to_string(),
+        };
+        let result = self
+            .shared
+            .writer
+            .send(&self.shared.socket, &control, &[])
+            .await;
+
+        self.shared.terminate(UTPEvent::Closed {
+            reason: reason.to_string(),
+        });
+
+        Ok(result?)
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+use bytes::Bytes;
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPEvent, UTPStream};
+use shmfish::{ShmConfig, ShmEndpoint, ShmEndpointBuilder, ShmStream, ShmUTP};
+
+fn socket_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "shmfish-{}-{}.sock",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+async fn connected_pair(config: ShmConfig) -> (ShmUTP, ShmUTP) {
+    let path = socket_path();
+    let server_endpoint = ShmEndpoint::server(&path, config.clone()).unwrap();
+    let client_endpoint = ShmEndpoint::client(config);
+
+    let (server, client) = tokio::join!(server_endpoint.accept(), client_endpoint.connect(&path));
+    (server.unwrap(), client.unwrap())
+}
+
+#[tokio::test]
+async fn test_protofish_over_shared_memory() {
+    let path = socket_path();
+    // a small ring, so the transfer has to wait for the reader many times
+    let config = ShmConfig::default().with_ring_capacity(4096);
+    let server_endpoint = ShmEndpointBuilder::new_server(&path)
+        .with_config(config.clone())
+        .build()
+        .expect("Failed to create server endpoint");
+
+    let server_handle = tokio::spawn(async move {
+        let utp = server_endpoint.accept().await.expect("Failed to accept");
+        protofish::accept(Arc::new(utp)).await
+    });
+
+    let client_endpoint = ShmEndpoint::client(config);
+    let client = protofish::connect(Arc::new(client_endpoint.connect(&path).await.unwrap()))
+        .await
+        .unwrap();
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    let data: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let expected = data.clone();
+
+    let writer = tokio::spawn(async move {
+        stream.write_all(&data).await.unwrap();
+        stream
+    });
+
+    let mut received = vec![0; expected.len()];
+    timeout(Duration::from_secs(10), peer.read_exact(&mut received))
+        .await
+        .expect("Transfer timeout")
+        .unwrap();
+    assert_eq!(received, expected);
+
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unreliable_overwrites_oldest() {
+    let config = ShmConfig::default().with_slot_count(4).with_slot_size(64);
+    let (server, client) = connected_pair(config).await;
+
+    let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+    let id = stream.id();
+    let (mut writer, _reader) = stream.split();
+
+    // the writer never waits, even with nobody reading
+    for i in 0..10u8 {
+        ShmStream::send_datagram(&mut writer, Bytes::from(vec![i; 8]))
+            .await
+            .unwrap();
+    }
+
+    let peer = timeout(
+        Duration::from_secs(2),
+        server.wait_stream(id, IntegrityType::Unreliable),
+    )
+    .await
+    .expect("Server timeout")
+    .unwrap();
+    let (_writer, mut reader) = peer.split();
+
+    let mut received = Vec::new();
+    for _ in 0..4 {
+        let data = ShmStream::recv_datagram(&mut reader).await.unwrap();
+        received.push(data[0]);
+    }
+
+    assert_eq!(received, vec![6, 7, 8, 9]);
+    assert_eq!(reader.lost(), 6);
+}
+
+#[tokio::test]
+async fn test_graceful_close() {
+    let (server, client) = connected_pair(ShmConfig::default()).await;
+
+    client.close(0, "done").await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), server.next_event())
+        .await
+        .expect("Server timeout");
+    assert!(matches!(event, UTPEvent::Closed { reason } if reason == "done"));
+}
+
+#[tokio::test]
+async fn test_peer_dropped() {
+    let (server, client) = connected_pair(ShmConfig::default()).await;
+
+    let stream = client.new_stream(IntegrityType::Reliable).await.unwrap();
+    let id = stream.id();
+    let (_writer, mut reader) = stream.split();
+
+    // keep the peer's end of the stream alive, so it is not closed cleanly
+    let _peer = server
+        .wait_stream(id, IntegrityType::Reliable)
+        .await
+        .unwrap();
+    drop(server);
+
+    let event = timeout(Duration::from_secs(2), client.next_event())
+        .await
+        .expect("Client timeout");
+    assert!(matches!(event, UTPEvent::UnexpectedClose));
+
+    // a read blocked on the dead peer fails instead of waiting forever
+    let mut buf = [0; 8];
+    let result = timeout(Duration::from_secs(2), reader.read(&mut buf))
+        .await
+        .expect("Read timeout");
+    assert!(result.is_err());
+}
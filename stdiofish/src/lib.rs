@@ -0,0 +1,9 @@
+pub mod error;
+pub mod utp;
+
+pub type Connection = protofish::Connection<StdioUTP>;
+pub type ArbContext = protofish::ArbContext<StdioUTP>;
+
+pub use error::{Error, Result};
+pub use tcpfish::MuxConfig;
+pub use utp::StdioUTP;
@@ -0,0 +1,111 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTP, UTPEvent, UTPStats};
+use protofish::{IntegrityType, StreamId};
+use tcpfish::{MuxConfig, MuxStream, MuxUTP};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::Child;
+
+use crate::error::Error;
+
+/// UTP over a pair of pipes, such as a child process's stdin and stdout.
+///
+/// All streams share the pipes through `MuxUTP`. Pipes have no addresses,
+/// so `peer_addr` and `local_addr` are always `None`.
+///
+/// The process that spawns the other is expected to act as the client, so
+/// [`from_child`](Self::from_child) connects and [`stdio`](Self::stdio)
+/// accepts.
+pub struct StdioUTP {
+    mux: MuxUTP,
+}
+
+impl StdioUTP {
+    /// Multiplexes streams over `reader` and `writer`. Must be called within
+    /// a tokio runtime.
+    ///
+    /// Both sides must agree on who is the server, which decides the parity
+    /// of the stream IDs each side allocates.
+    pub fn new<R, W>(reader: R, writer: W, is_server: bool, config: MuxConfig) -> Self
+    where
+        R: AsyncRead + Send + 'static,
+        W: AsyncWrite + Send + 'static,
+    {
+        Self {
+            mux: MuxUTP::new(tokio::io::join(reader, writer), is_server, config),
+        }
+    }
+
+    /// Talks to `child` over its stdin and stdout, as the client side.
+    ///
+    /// The pipes are taken out of `child`, which stays with the caller so it
+    /// can be waited on or killed. Dropping the connection closes the
+    /// child's stdin.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child was not spawned with piped stdin and
+    /// stdout, or they were already taken.
+    pub fn from_child(child: &mut Child, config: MuxConfig) -> crate::Result<Self> {
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(Error::Config(
+                "Child stdin and stdout must be piped".to_string(),
+            ));
+        };
+
+        Ok(Self::new(stdout, stdin, false, config))
+    }
+
+    /// Talks to the parent process over this process's stdin and stdout, as
+    /// the server side.
+    ///
+    /// Nothing else may write to stdout while the connection is in use; log
+    /// to stderr instead. Reading stdin blocks a thread that tokio cannot
+    /// interrupt, so runtime shutdown may wait until the parent closes it.
+    pub fn stdio(config: MuxConfig) -> Self {
+        Self::new(tokio::io::stdin(), tokio::io::stdout(), true, config)
+    }
+}
+
+#[async_trait]
+impl UTP for StdioUTP {
+    type Stream = MuxStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.mux.connect().await
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        self.mux.next_event().await
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<Self::Stream, UTPError> {
+        self.mux.new_stream(integrity).await
+    }
+
+    async fn wait_stream(
+        &self,
+        id: StreamId,
+        integrity: IntegrityType,
+    ) -> Result<Self::Stream, UTPError> {
+        self.mux.wait_stream(id, integrity).await
+    }
+
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        self.mux.close(code, reason).await
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn stats(&self) -> UTPStats {
+        self.mux.stats()
+    }
+}
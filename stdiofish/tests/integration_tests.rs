@@ -0,0 +1,130 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio::time::timeout;
+
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPEvent};
+use stdiofish::{Error, MuxConfig, StdioUTP};
+
+fn pipe_pair() -> (StdioUTP, StdioUTP) {
+    let (client_read, server_write) = tokio::io::duplex(64 * 1024);
+    let (server_read, client_write) = tokio::io::duplex(64 * 1024);
+
+    let client = StdioUTP::new(client_read, client_write, false, MuxConfig::default());
+    let server = StdioUTP::new(server_read, server_write, true, MuxConfig::default());
+    (client, server)
+}
+
+fn spawn_cat() -> Child {
+    Command::new("cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("Failed to spawn cat")
+}
+
+#[tokio::test]
+async fn test_protofish_over_pipes() {
+    let (client, server) = pipe_pair();
+
+    let server_handle = tokio::spawn(protofish::accept(Arc::new(server)));
+    let client = protofish::connect(Arc::new(client)).await.unwrap();
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    stream.write_all(b"muffin").await.unwrap();
+    let mut buf = [0; 6];
+    peer.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"muffin");
+}
+
+#[tokio::test]
+async fn test_protofish_through_child_processes() {
+    // each direction is relayed by a child process, so the traffic goes
+    // through real child stdin and stdout pipes
+    let mut forward = spawn_cat();
+    let mut backward = spawn_cat();
+
+    let client = StdioUTP::new(
+        backward.stdout.take().unwrap(),
+        forward.stdin.take().unwrap(),
+        false,
+        MuxConfig::default(),
+    );
+    let server = StdioUTP::new(
+        forward.stdout.take().unwrap(),
+        backward.stdin.take().unwrap(),
+        true,
+        MuxConfig::default(),
+    );
+
+    let server_handle = tokio::spawn(protofish::accept(Arc::new(server)));
+    let client = protofish::connect(Arc::new(client)).await.unwrap();
+    let server = timeout(Duration::from_secs(5), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    let data: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+        stream.write_all(&data).await.unwrap();
+        stream
+    });
+
+    let mut received = vec![0; expected.len()];
+    timeout(Duration::from_secs(10), peer.read_exact(&mut received))
+        .await
+        .expect("Transfer timeout")
+        .unwrap();
+    assert_eq!(received, expected);
+
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_child_exit_closes_connection() {
+    let mut child = spawn_cat();
+    let utp = StdioUTP::from_child(&mut child, MuxConfig::default()).unwrap();
+
+    child.kill().await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), utp.next_event())
+        .await
+        .expect("Client timeout");
+    assert!(matches!(event, UTPEvent::UnexpectedClose));
+}
+
+#[tokio::test]
+async fn test_child_without_pipes_rejected() {
+    let mut child = Command::new("true")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to spawn true");
+
+    let result = StdioUTP::from_child(&mut child, MuxConfig::default());
+    assert!(matches!(result, Err(Error::Config(_))));
+
+    child.wait().await.unwrap();
+}
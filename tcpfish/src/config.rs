@@ -0,0 +1,129 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Initial credit, in bytes, of each direction of a reliable stream.
+///
+/// Both sides assume this value, so it is part of the wire protocol rather
+/// than a setting.
+pub const INITIAL_WINDOW: u32 = 256 * 1024;
+
+/// Settings of the stream multiplexer.
+#[derive(Clone, Debug)]
+pub struct MuxConfig {
+    /// Largest payload of a single `Data` frame
+    pub max_frame_size: usize,
+    /// Largest message an unreliable stream accepts
+    pub max_datagram_size: usize,
+    /// Number of messages buffered per unreliable stream, on each side,
+    /// before new ones are dropped
+    pub datagram_queue_len: usize,
+    /// Number of unreliable streams the peer may send messages on before
+    /// they are registered locally; messages on further streams are dropped
+    pub max_pending_datagram_streams: usize,
+    /// Number of reliable streams opened by the peer that may wait for
+    /// `wait_stream`; further streams are reset
+    pub max_pending_accepts: usize,
+    /// Number of reliable frames queued for sending before writers wait
+    pub send_queue_len: usize,
+}
+
+impl Default for MuxConfig {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 16 * 1024,
+            max_datagram_size: 64 * 1024,
+            datagram_queue_len: 1024,
+            max_pending_datagram_streams: 64,
+            max_pending_accepts: 64,
+            send_queue_len: 64,
+        }
+    }
+}
+
+impl MuxConfig {
+    pub fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    pub fn with_max_datagram_size(mut self, size: usize) -> Self {
+        self.max_datagram_size = size;
+        self
+    }
+
+    pub fn with_datagram_queue_len(mut self, len: usize) -> Self {
+        self.datagram_queue_len = len;
+        self
+    }
+
+    pub fn with_max_pending_datagram_streams(mut self, count: usize) -> Self {
+        self.max_pending_datagram_streams = count;
+        self
+    }
+
+    pub fn with_max_pending_accepts(mut self, count: usize) -> Self {
+        self.max_pending_accepts = count;
+        self
+    }
+
+    pub fn with_send_queue_len(mut self, len: usize) -> Self {
+        self.send_queue_len = len;
+        self
+    }
+}
+
+pub enum TlsConfig {
+    Client { crypto: Arc<rustls::ClientConfig> },
+    Server { crypto: Arc<rustls::ServerConfig> },
+}
+
+/// Settings of a `TcpEndpoint`.
+pub struct TcpConfig {
+    pub nodelay: bool,
+    pub mux: MuxConfig,
+    pub tls: Option<TlsConfig>,
+    /// Time a server gives each client to complete the TLS handshake
+    pub handshake_timeout: Duration,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            mux: MuxConfig::default(),
+            tls: None,
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TcpConfig {
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn with_mux(mut self, mux: MuxConfig) -> Self {
+        self.mux = mux;
+        self
+    }
+
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    pub fn with_client_tls(mut self, crypto: rustls::ClientConfig) -> Self {
+        self.tls = Some(TlsConfig::Client {
+            crypto: Arc::new(crypto),
+        });
+        self
+    }
+
+    pub fn with_server_tls(mut self, crypto: rustls::ServerConfig) -> Self {
+        self.tls = Some(TlsConfig::Server {
+            crypto: Arc::new(crypto),
+        });
+        self
+    }
+}
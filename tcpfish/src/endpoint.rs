@@ -0,0 +1,221 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::pki_types::ServerName;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::config::{MuxConfig, TcpConfig, TlsConfig};
+use crate::error::{Error, Result};
+use crate::mux::MuxUTP;
+
+/// Opens or accepts TCP connections and wraps them in a `MuxUTP`, with TLS
+/// when configured.
+pub struct TcpEndpoint {
+    listener: Option<TcpListener>,
+    config: TcpConfig,
+    /// TLS handshakes of accepted clients, run concurrently so that a slow
+    /// client does not hold up the others
+    handshakes: Mutex<JoinSet<Option<MuxUTP>>>,
+}
+
+/// Next event of the accept loop.
+enum Accepted {
+    Client(TcpStream, SocketAddr),
+    Handshake(Option<MuxUTP>),
+    Failed(std::io::Error),
+}
+
+impl TcpEndpoint {
+    pub fn client(config: TcpConfig) -> Result<Self> {
+        if let Some(TlsConfig::Server { .. }) = config.tls {
+            return Err(Error::Config(
+                "Server TLS config provided for client".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            listener: None,
+            config,
+            handshakes: Default::default(),
+        })
+    }
+
+    /// Binds a listening socket. Must be called within a tokio runtime.
+    pub fn server(bind_addr: SocketAddr, config: TcpConfig) -> Result<Self> {
+        if let Some(TlsConfig::Client { .. }) = config.tls {
+            return Err(Error::Config(
+                "Client TLS config provided for server".to_string(),
+            ));
+        }
+
+        let listener = std::net::TcpListener::bind(bind_addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener: Some(TcpListener::from_std(listener)?),
+            config,
+            handshakes: Default::default(),
+        })
+    }
+
+    /// Connects to a server.
+    ///
+    /// `server_name` is checked against the server's certificate when TLS is
+    /// enabled, and ignored otherwise.
+    pub async fn connect(&self, server_addr: SocketAddr, server_name: &str) -> Result<MuxUTP> {
+        if self.listener.is_some() {
+            return Err(Error::Config(
+                "Cannot connect from server endpoint".to_string(),
+            ));
+        }
+
+        let stream = TcpStream::connect(server_addr).await?;
+        stream.set_nodelay(self.config.nodelay)?;
+        let addrs = (stream.local_addr().ok(), stream.peer_addr().ok());
+
+        let utp = match &self.config.tls {
+            Some(TlsConfig::Client { crypto }) => {
+                let server_name = ServerName::try_from(server_name.to_string())
+                    .map_err(|e| Error::Config(e.to_string()))?;
+                let stream = TlsConnector::from(crypto.clone())
+                    .connect(server_name, stream)
+                    .await?;
+
+                self.wrap(stream, false)
+            }
+            _ => self.wrap(stream, false),
+        };
+
+        Ok(utp.with_addrs(addrs.0, addrs.1))
+    }
+
+    /// Waits for the next client.
+    ///
+    /// TLS handshakes run concurrently, each within the configured
+    /// `handshake_timeout`, and keep running when this future is dropped.
+    /// Clients whose handshake fails or times out are logged and skipped.
+    /// Returns `None` if this is a client endpoint or the listener fails.
+    pub async fn accept(&self) -> Option<MuxUTP> {
+        let listener = self.listener.as_ref()?;
+        let mut handshakes = self.handshakes.lock().await;
+
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => Accepted::Client(stream, peer),
+                    Err(e) => Accepted::Failed(e),
+                },
+                Some(joined) = handshakes.join_next() => {
+                    Accepted::Handshake(joined.unwrap_or_default())
+                }
+            };
+
+            let (stream, peer) = match accepted {
+                Accepted::Client(stream, peer) => (stream, peer),
+                Accepted::Handshake(Some(utp)) => return Some(utp),
+                Accepted::Handshake(None) => continue,
+                Accepted::Failed(e) => {
+                    tracing::warn!("TCP accept failure: {}", e);
+                    return None;
+                }
+            };
+
+            if let Err(e) = stream.set_nodelay(self.config.nodelay) {
+                tracing::warn!("failed to set TCP_NODELAY: {}", e);
+            }
+            let local = stream.local_addr().ok();
+
+            match &self.config.tls {
+                Some(TlsConfig::Server { crypto }) => {
+                    handshakes.spawn(tls_accept(
+                        stream,
+                        peer,
+                        crypto.clone(),
+                        self.config.handshake_timeout,
+                        self.config.mux.clone(),
+                    ));
+                }
+                _ => return Some(self.wrap(stream, true).with_addrs(local, Some(peer))),
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match &self.listener {
+            Some(listener) => Ok(listener.local_addr()?),
+            None => Err(Error::Config("Client endpoint is not bound".to_string())),
+        }
+    }
+
+    fn wrap<T>(&self, io: T, is_server: bool) -> MuxUTP
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+    {
+        MuxUTP::new(io, is_server, self.config.mux.clone())
+    }
+}
+
+/// Runs the server side of the TLS handshake with an accepted client.
+async fn tls_accept(
+    stream: TcpStream,
+    peer: SocketAddr,
+    crypto: Arc<rustls::ServerConfig>,
+    timeout: Duration,
+    mux: MuxConfig,
+) -> Option<MuxUTP> {
+    let local = stream.local_addr().ok();
+
+    match tokio::time::timeout(timeout, TlsAcceptor::from(crypto).accept(stream)).await {
+        Ok(Ok(stream)) => Some(MuxUTP::new(stream, true, mux).with_addrs(local, Some(peer))),
+        Ok(Err(e)) => {
+            tracing::warn!("TLS handshake with {} failed: {}", peer, e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("TLS handshake with {} timed out", peer);
+            None
+        }
+    }
+}
+
+pub struct TcpEndpointBuilder {
+    config: TcpConfig,
+    bind_addr: Option<SocketAddr>,
+}
+
+impl TcpEndpointBuilder {
+    pub fn new_client() -> Self {
+        Self {
+            config: TcpConfig::default(),
+            bind_addr: None,
+        }
+    }
+
+    pub fn new_server(bind_addr: SocketAddr) -> Self {
+        Self {
+            config: TcpConfig::default(),
+            bind_addr: Some(bind_addr),
+        }
+    }
+
+    pub fn with_config(mut self, config: TcpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_mux_config(mut self, mux: MuxConfig) -> Self {
+        self.config.mux = mux;
+        self
+    }
+
+    pub fn build(self) -> Result<TcpEndpoint> {
+        match self.bind_addr {
+            Some(bind_addr) => TcpEndpoint::server(bind_addr, self.config),
+            None => TcpEndpoint::client(self.config),
+        }
+    }
+}
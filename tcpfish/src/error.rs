@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("TLS error: {0}")]
+    Tls(#[from] rustls::Error),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Datagram error: {0}")]
+    Datagram(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("Connection closed")]
+    Closed,
+}
+
+impl From<Error> for protofish::utp::error::UTPError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => protofish::utp::error::UTPError::Io(e),
+            Error::Datagram(e) => protofish::utp::error::UTPError::Warn(e),
+            other => protofish::utp::error::UTPError::Fatal(other.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,206 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use protofish::StreamId;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::{Error, Result};
+
+/// Size of the header in front of every frame.
+///
+/// Layout: `[type: u8][stream_id: u64 le][length: u32 le]`.
+pub const FRAME_HEADER_LEN: usize = 13;
+
+/// Largest frame payload accepted from the peer.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const TYPE_OPEN: u8 = 0x01;
+const TYPE_DATA: u8 = 0x02;
+const TYPE_WINDOW: u8 = 0x03;
+const TYPE_FIN: u8 = 0x04;
+const TYPE_RESET: u8 = 0x05;
+const TYPE_DATAGRAM: u8 = 0x06;
+const TYPE_CLOSE: u8 = 0x07;
+
+/// A unit of the multiplexing protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// Opens a reliable stream.
+    Open { id: StreamId },
+
+    /// Carries bytes of a reliable stream, counted against its window.
+    Data { id: StreamId, data: Bytes },
+
+    /// Grants the peer `increment` more bytes of credit on a reliable stream.
+    Window { id: StreamId, increment: u32 },
+
+    /// The sender finished writing to a reliable stream.
+    Fin { id: StreamId },
+
+    /// The sender abandoned a reliable stream.
+    Reset { id: StreamId, code: u64 },
+
+    /// Carries one message of an unreliable stream.
+    Datagram { id: StreamId, data: Bytes },
+
+    /// Closes the whole connection.
+    Close { code: u64, reason: String },
+}
+
+impl Frame {
+    /// Serializes the frame, header included.
+    pub fn encode(&self) -> Bytes {
+        let (kind, id, payload) = match self {
+            Frame::Open { id } => (TYPE_OPEN, *id, Bytes::new()),
+            Frame::Data { id, data } => (TYPE_DATA, *id, data.clone()),
+            Frame::Window { id, increment } => (
+                TYPE_WINDOW,
+                *id,
+                Bytes::copy_from_slice(&increment.to_le_bytes()),
+            ),
+            Frame::Fin { id } => (TYPE_FIN, *id, Bytes::new()),
+            Frame::Reset { id, code } => {
+                (TYPE_RESET, *id, Bytes::copy_from_slice(&code.to_le_bytes()))
+            }
+            Frame::Datagram { id, data } => (TYPE_DATAGRAM, *id, data.clone()),
+            Frame::Close { code, reason } => {
+                let mut payload = BytesMut::with_capacity(8 + reason.len());
+                payload.put_u64_le(*code);
+                payload.put_slice(reason.as_bytes());
+                (TYPE_CLOSE, 0, payload.freeze())
+            }
+        };
+
+        let mut buf = BytesMut::with_capacity(FRAME_HEADER_LEN + payload.len());
+        buf.put_u8(kind);
+        buf.put_u64_le(id);
+        buf.put_u32_le(payload.len() as u32);
+        buf.put_slice(&payload);
+
+        buf.freeze()
+    }
+
+    fn decode(kind: u8, id: StreamId, mut payload: Bytes) -> Result<Self> {
+        let frame = match kind {
+            TYPE_OPEN => Frame::Open { id },
+            TYPE_DATA => Frame::Data { id, data: payload },
+            TYPE_WINDOW => Frame::Window {
+                id,
+                increment: take_u32(&mut payload)?,
+            },
+            TYPE_FIN => Frame::Fin { id },
+            TYPE_RESET => Frame::Reset {
+                id,
+                code: take_u64(&mut payload)?,
+            },
+            TYPE_DATAGRAM => Frame::Datagram { id, data: payload },
+            TYPE_CLOSE => Frame::Close {
+                code: take_u64(&mut payload)?,
+                reason: String::from_utf8_lossy(&payload).into_owned(),
+            },
+            other => return Err(Error::Protocol(format!("unknown frame type {}", other))),
+        };
+
+        Ok(frame)
+    }
+
+    /// Returns the number of bytes the frame takes on the wire.
+    pub fn wire_len(&self) -> usize {
+        FRAME_HEADER_LEN
+            + match self {
+                Frame::Open { .. } | Frame::Fin { .. } => 0,
+                Frame::Data { data, .. } | Frame::Datagram { data, .. } => data.len(),
+                Frame::Window { .. } => 4,
+                Frame::Reset { .. } => 8,
+                Frame::Close { reason, .. } => 8 + reason.len(),
+            }
+    }
+}
+
+/// Reads the next frame.
+///
+/// # Errors
+///
+/// Returns an error if the stream fails or ends, or if the frame is malformed
+/// or larger than `MAX_FRAME_LEN`.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let kind = reader.read_u8().await?;
+    let id = reader.read_u64_le().await?;
+    let len = reader.read_u32_le().await? as usize;
+
+    if len > MAX_FRAME_LEN {
+        return Err(Error::Protocol(format!(
+            "frame of {} bytes exceeds {} bytes",
+            len, MAX_FRAME_LEN
+        )));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+
+    Frame::decode(kind, id, payload.into())
+}
+
+fn take_u32(payload: &mut Bytes) -> Result<u32> {
+    if payload.len() < 4 {
+        return Err(Error::Protocol("truncated frame".into()));
+    }
+    Ok(payload.get_u32_le())
+}
+
+fn take_u64(payload: &mut Bytes) -> Result<u64> {
+    if payload.len() < 8 {
+        return Err(Error::Protocol("truncated frame".into()));
+    }
+    Ok(payload.get_u64_le())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Frame, read_frame};
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let frames = vec![
+            Frame::Open { id: 2 },
+            Frame::Data {
+                id: 2,
+                data: Bytes::from_static(b"muffin"),
+            },
+            Frame::Window {
+                id: 2,
+                increment: 4096,
+            },
+            Frame::Fin { id: 2 },
+            Frame::Reset { id: 3, code: 9 },
+            Frame::Datagram {
+                id: 5,
+                data: Bytes::from_static(b"cute"),
+            },
+            Frame::Close {
+                code: 1,
+                reason: "bye".into(),
+            },
+        ];
+
+        let mut wire = Vec::new();
+        for frame in &frames {
+            let encoded = frame.encode();
+            assert_eq!(encoded.len(), frame.wire_len());
+            wire.extend_from_slice(&encoded);
+        }
+
+        let mut reader = &wire[..];
+        for frame in frames {
+            assert_eq!(read_frame(&mut reader).await.unwrap(), frame);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_truncated_window_frame() {
+        let mut wire = Frame::Fin { id: 1 }.encode().to_vec();
+        wire[0] = 0x03;
+
+        assert!(read_frame(&mut &wire[..]).await.is_err());
+    }
+}
@@ -0,0 +1,19 @@
+pub mod config;
+pub mod endpoint;
+pub mod error;
+pub mod frame;
+pub mod mux;
+pub mod stream;
+
+pub type Connection = protofish::Connection<MuxUTP>;
+pub type ArbContext = protofish::ArbContext<MuxUTP>;
+
+/// The UTP of a TCP connection, which is a `MuxUTP` over the TCP stream.
+pub type TcpUTP = MuxUTP;
+
+pub use config::{MuxConfig, TcpConfig};
+pub use endpoint::{TcpEndpoint, TcpEndpointBuilder};
+pub use error::{Error, Result};
+pub use mux::MuxUTP;
+pub use stream::MuxStream;
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
@@ -0,0 +1,792 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use dashmap::DashMap;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTP, UTPEvent, UTPStats};
+use protofish::{IntegrityType, StreamId};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, Notify, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{INITIAL_WINDOW, MuxConfig};
+use crate::frame::{Frame, read_frame};
+use crate::stream::MuxStream;
+
+/// Code of the `Close` frame sent when the peer breaks the protocol.
+const PROTOCOL_ERROR: u64 = 1;
+
+/// A UTP implementation multiplexing streams over a single ordered byte
+/// stream, such as a TCP connection.
+///
+/// Reliable streams get per-stream flow control. Unreliable streams are
+/// carried as messages that are dropped, rather than queued, when either side
+/// cannot keep up, so they never hold back reliable traffic for long.
+pub struct MuxUTP {
+    shared: Arc<Shared>,
+    events_rx: Mutex<UnboundedReceiver<UTPEvent>>,
+    finished: AtomicBool,
+    next_stream_id: AtomicU64,
+    local_addr: Option<SocketAddr>,
+    peer_addr: Option<SocketAddr>,
+    tasks: [JoinHandle<()>; 2],
+}
+
+/// State shared by a `MuxUTP`, its streams and its I/O tasks.
+pub(crate) struct Shared {
+    pub(crate) config: MuxConfig,
+    /// Parity of the stream IDs the peer allocates.
+    peer_parity: StreamId,
+    /// Receiving ends of reliable streams that are still read.
+    receivers: DashMap<StreamId, Receiver>,
+    /// Send credit of reliable streams that are still written.
+    credits: DashMap<StreamId, Arc<Semaphore>>,
+    /// Reliable streams opened by the peer, waiting for `wait_stream`.
+    accepted: DashMap<StreamId, MuxStream>,
+    accepted_notify: Notify,
+    datagrams: DashMap<StreamId, mpsc::Sender<Bytes>>,
+    /// Messages of unreliable streams not registered locally yet.
+    pending_datagrams: DashMap<StreamId, mpsc::Receiver<Bytes>>,
+    events_tx: UnboundedSender<UTPEvent>,
+    terminated: AtomicBool,
+    closed: CancellationToken,
+    /// Ordered frames: stream opens, data, finishes and the final close.
+    pub(crate) data_tx: mpsc::Sender<Frame>,
+    /// Window updates, sent ahead of everything else.
+    control_tx: UnboundedSender<Frame>,
+    pub(crate) datagram_tx: mpsc::Sender<Frame>,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+/// Receiving end of a reliable stream.
+struct Receiver {
+    tx: UnboundedSender<io::Result<Bytes>>,
+    /// Credit granted to the peer and not used yet, which bounds what the
+    /// unbounded channel holds.
+    window: usize,
+}
+
+impl MuxUTP {
+    /// Starts multiplexing over `io`.
+    ///
+    /// Both sides must agree on who is the server, which decides the parity
+    /// of the stream IDs each side allocates.
+    pub fn new<T>(io: T, is_server: bool, config: MuxConfig) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (data_tx, data_rx) = mpsc::channel(config.send_queue_len);
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let (datagram_tx, datagram_rx) = mpsc::channel(config.datagram_queue_len);
+
+        let shared = Arc::new(Shared {
+            config,
+            peer_parity: if is_server { 0 } else { 1 },
+            receivers: Default::default(),
+            credits: Default::default(),
+            accepted: Default::default(),
+            accepted_notify: Notify::new(),
+            datagrams: Default::default(),
+            pending_datagrams: Default::default(),
+            events_tx,
+            terminated: AtomicBool::new(false),
+            closed: CancellationToken::new(),
+            data_tx,
+            control_tx,
+            datagram_tx,
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+        });
+
+        let (reader, writer) = tokio::io::split(io);
+        let tasks = [
+            tokio::spawn(read_loop(reader, shared.clone())),
+            tokio::spawn(write_loop(
+                writer,
+                data_rx,
+                control_rx,
+                datagram_rx,
+                shared.clone(),
+            )),
+        ];
+
+        Self {
+            shared,
+            events_rx: Mutex::new(events_rx),
+            finished: AtomicBool::new(false),
+            next_stream_id: AtomicU64::new(if is_server { 1 } else { 0 }),
+            local_addr: None,
+            peer_addr: None,
+            tasks,
+        }
+    }
+
+    /// Sets the addresses reported by `local_addr` and `peer_addr`.
+    pub fn with_addrs(mut self, local: Option<SocketAddr>, peer: Option<SocketAddr>) -> Self {
+        self.local_addr = local;
+        self.peer_addr = peer;
+        self
+    }
+
+    fn next_id(&self) -> StreamId {
+        self.next_stream_id.fetch_add(2, Ordering::Relaxed)
+    }
+
+    fn ensure_open(&self) -> Result<(), UTPError> {
+        if self.shared.is_closed() {
+            Err(UTPError::Fatal("connection closed".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for MuxUTP {
+    fn drop(&mut self) {
+        self.tasks.iter().for_each(JoinHandle::abort);
+    }
+}
+
+#[async_trait]
+impl UTP for MuxUTP {
+    type Stream = MuxStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.ensure_open()
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        if self.finished.load(Ordering::Acquire) {
+            return UTPEvent::UnexpectedClose;
+        }
+
+        // the shared state holds the sender, so the channel never closes
+        let event = self
+            .events_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .unwrap_or(UTPEvent::UnexpectedClose);
+
+        if event.is_terminal() {
+            self.finished.store(true, Ordering::Release);
+        }
+
+        event
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<MuxStream, UTPError> {
+        self.ensure_open()?;
+        let id = self.next_id();
+
+        match integrity {
+            IntegrityType::Reliable => {
+                let stream = self.shared.register_reliable(id);
+
+                self.shared
+                    .data_tx
+                    .send(Frame::Open { id })
+                    .await
+                    .map_err(|_| UTPError::Fatal("connection closed".into()))?;
+
+                Ok(stream)
+            }
+            IntegrityType::Unreliable => Ok(self.shared.register_unreliable(id)),
+        }
+    }
+
+    async fn wait_stream(
+        &self,
+        id: StreamId,
+        integrity: IntegrityType,
+    ) -> Result<MuxStream, UTPError> {
+        match integrity {
+            IntegrityType::Reliable => loop {
+                let notified = self.shared.accepted_notify.notified();
+                tokio::pin!(notified);
+                // register before checking, so an insert in between is not missed
+                notified.as_mut().enable();
+
+                if let Some((_, stream)) = self.shared.accepted.remove(&id) {
+                    return Ok(stream);
+                }
+                self.ensure_open()?;
+
+                notified.await;
+            },
+            IntegrityType::Unreliable => Ok(self.shared.register_unreliable(id)),
+        }
+    }
+
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        if self.shared.is_closed() {
+            return Ok(());
+        }
+
+        // queued behind pending data, which is flushed first
+        let _ = self
+            .shared
+            .data_tx
+            .send(Frame::Close {
+                code,
+                reason: reason.to_string(),
+            })
+            .await;
+
+        self.shared.terminate(UTPEvent::Closed {
+            reason: reason.to_string(),
+        });
+
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    fn stats(&self) -> UTPStats {
+        UTPStats {
+            bytes_sent: Some(self.shared.bytes_sent.load(Ordering::Relaxed)),
+            bytes_received: Some(self.shared.bytes_received.load(Ordering::Relaxed)),
+            ..Default::default()
+        }
+    }
+}
+
+impl Shared {
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.is_cancelled()
+    }
+
+    fn register_reliable(self: &Arc<Self>, id: StreamId) -> MuxStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let credit = Arc::new(Semaphore::new(INITIAL_WINDOW as usize));
+
+        self.receivers.insert(
+            id,
+            Receiver {
+                tx,
+                window: INITIAL_WINDOW as usize,
+            },
+        );
+        self.credits.insert(id, credit.clone());
+
+        MuxStream::new_reliable(id, self.clone(), rx, credit)
+    }
+
+    fn register_unreliable(self: &Arc<Self>, id: StreamId) -> MuxStream {
+        let rx = match self.pending_datagrams.remove(&id) {
+            Some((_, rx)) => rx,
+            None => {
+                let (tx, rx) = mpsc::channel(self.config.datagram_queue_len);
+                self.datagrams.insert(id, tx);
+                rx
+            }
+        };
+
+        MuxStream::new_unreliable(id, self.clone(), rx)
+    }
+
+    pub(crate) fn remove_receiver(&self, id: StreamId) {
+        self.receivers.remove(&id);
+    }
+
+    pub(crate) fn remove_credit(&self, id: StreamId) {
+        self.credits.remove(&id);
+    }
+
+    pub(crate) fn remove_datagrams(&self, id: StreamId) {
+        self.datagrams.remove(&id);
+    }
+
+    /// Grants the peer `bytes` more credit on a stream.
+    pub(crate) fn grant(&self, id: StreamId, bytes: usize) {
+        if let Some(mut receiver) = self.receivers.get_mut(&id) {
+            receiver.window += bytes;
+        }
+
+        if bytes > 0 && !self.is_closed() {
+            let _ = self.control_tx.send(Frame::Window {
+                id,
+                increment: bytes as u32,
+            });
+        }
+    }
+
+    fn emit(&self, event: UTPEvent) {
+        let _ = self.events_tx.send(event);
+    }
+
+    /// Handles a frame from the peer. Returns `false` once the connection is
+    /// closed.
+    fn dispatch(self: &Arc<Self>, frame: Frame) -> bool {
+        match frame {
+            Frame::Open { id } => {
+                if id % 2 != self.peer_parity {
+                    return self.protocol_error(format!("stream {} has the wrong parity", id));
+                }
+                if self.receivers.contains_key(&id)
+                    || self.credits.contains_key(&id)
+                    || self.accepted.contains_key(&id)
+                {
+                    return self.protocol_error(format!("stream {} is already open", id));
+                }
+                if self.accepted.len() >= self.config.max_pending_accepts {
+                    tracing::debug!("too many streams waiting to be accepted, resetting {}", id);
+                    let _ = self.control_tx.send(Frame::Reset { id, code: 0 });
+                    return true;
+                }
+
+                let stream = self.register_reliable(id);
+                self.accepted.insert(id, stream);
+                self.accepted_notify.notify_waiters();
+                self.emit(UTPEvent::NewStream(id));
+            }
+            Frame::Data { id, data } => {
+                let len = data.len();
+                let delivered = match self.receivers.get_mut(&id) {
+                    Some(mut receiver) => {
+                        let Some(window) = receiver.window.checked_sub(len) else {
+                            drop(receiver);
+                            return self
+                                .protocol_error(format!("stream {} overran its window", id));
+                        };
+                        receiver.window = window;
+                        receiver.tx.send(Ok(data)).is_ok()
+                    }
+                    None => false,
+                };
+
+                // nobody reads the stream any more, return the credit
+                if !delivered {
+                    self.grant(id, len);
+                }
+            }
+            Frame::Window { id, increment } => {
+                if let Some(credit) = self.credits.get(&id) {
+                    credit.add_permits(increment as usize);
+                }
+            }
+            Frame::Fin { id } => {
+                self.receivers.remove(&id);
+                self.emit(UTPEvent::StreamClosed(id));
+            }
+            Frame::Reset { id, code } => {
+                if let Some((_, receiver)) = self.receivers.remove(&id) {
+                    let _ = receiver.tx.send(Err(io::Error::new(
+                        io::ErrorKind::ConnectionReset,
+                        format!("stream reset by peer with code {}", code),
+                    )));
+                }
+                // the peer will not read what is still written either
+                if let Some((_, credit)) = self.credits.remove(&id) {
+                    credit.close();
+                }
+                self.emit(UTPEvent::StreamReset { id, code });
+            }
+            Frame::Datagram { id, data } => self.route_datagram(id, data),
+            Frame::Close { reason, .. } => {
+                self.terminate(UTPEvent::Closed { reason });
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Closes the connection after the peer broke the protocol. Returns
+    /// `false`, like `dispatch` does once the connection is closed.
+    fn protocol_error(&self, reason: String) -> bool {
+        tracing::debug!("mux protocol error: {}", reason);
+
+        // control frames are sent ahead of the close below stopping the writer
+        let _ = self.control_tx.send(Frame::Close {
+            code: PROTOCOL_ERROR,
+            reason,
+        });
+        self.terminate(UTPEvent::UnexpectedClose);
+
+        false
+    }
+
+    fn route_datagram(&self, id: StreamId, data: Bytes) {
+        let tx = match self.datagrams.get(&id) {
+            Some(tx) => tx.clone(),
+            None => {
+                if self.pending_datagrams.len() >= self.config.max_pending_datagram_streams {
+                    tracing::debug!(
+                        "too many unregistered streams, dropping datagram on stream {}",
+                        id
+                    );
+                    return;
+                }
+
+                let (tx, rx) = mpsc::channel(self.config.datagram_queue_len);
+                self.datagrams.insert(id, tx.clone());
+                self.pending_datagrams.insert(id, rx);
+                tx
+            }
+        };
+
+        if tx.try_send(data).is_err() {
+            tracing::trace!("receive queue full, dropping datagram on stream {}", id);
+        }
+    }
+
+    /// Ends the connection, failing all streams, and reports `event` if it
+    /// was not ended already.
+    fn terminate(&self, event: UTPEvent) {
+        if self.terminated.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        self.emit(event);
+
+        for receiver in self.receivers.iter() {
+            let _ = receiver.tx.send(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection closed",
+            )));
+        }
+        self.receivers.clear();
+
+        self.credits.iter().for_each(|credit| credit.close());
+        self.credits.clear();
+
+        self.datagrams.clear();
+        self.pending_datagrams.clear();
+
+        self.closed.cancel();
+        self.accepted_notify.notify_waiters();
+    }
+}
+
+async fn read_loop<R: AsyncRead + Unpin>(reader: R, shared: Arc<Shared>) {
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        tokio::select! {
+            _ = shared.closed.cancelled() => break,
+            frame = read_frame(&mut reader) => match frame {
+                Ok(frame) => {
+                    shared
+                        .bytes_received
+                        .fetch_add(frame.wire_len() as u64, Ordering::Relaxed);
+
+                    if !shared.dispatch(frame) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("mux receive failure: {}", e);
+                    shared.terminate(UTPEvent::UnexpectedClose);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn write_loop<W: AsyncWrite + Unpin>(
+    writer: W,
+    mut data_rx: mpsc::Receiver<Frame>,
+    mut control_rx: UnboundedReceiver<Frame>,
+    mut datagram_rx: mpsc::Receiver<Frame>,
+    shared: Arc<Shared>,
+) {
+    let mut writer = BufWriter::new(writer);
+
+    loop {
+        let frame = tokio::select! {
+            biased;
+            Some(frame) = control_rx.recv() => frame,
+            Some(frame) = data_rx.recv() => frame,
+            Some(frame) = datagram_rx.recv() => frame,
+            _ = shared.closed.cancelled() => break,
+        };
+
+        let closing = matches!(frame, Frame::Close { .. });
+        let idle = control_rx.is_empty() && data_rx.is_empty() && datagram_rx.is_empty();
+
+        let result = async {
+            writer.write_all(&frame.encode()).await?;
+            if closing || idle {
+                writer.flush().await?;
+            }
+            if closing {
+                writer.shutdown().await?;
+            }
+            io::Result::Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::debug!("mux send failure: {}", e);
+            shared.terminate(UTPEvent::UnexpectedClose);
+            break;
+        }
+
+        shared
+            .bytes_sent
+            .fetch_add(frame.wire_len() as u64, Ordering::Relaxed);
+
+        if closing {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use protofish::IntegrityType;
+    use protofish::utp::{UTP, UTPEvent, UTPStream};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::config::{INITIAL_WINDOW, MuxConfig};
+    use crate::frame::{Frame, read_frame};
+    use crate::mux::{MuxUTP, PROTOCOL_ERROR};
+
+    fn pair() -> (MuxUTP, MuxUTP) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+
+        (
+            MuxUTP::new(a, true, MuxConfig::default()),
+            MuxUTP::new(b, false, MuxConfig::default()),
+        )
+    }
+
+    /// A server and the raw client end of its connection, to send frames a
+    /// well-behaved peer never would.
+    fn raw_pair(config: MuxConfig) -> (MuxUTP, tokio::io::DuplexStream) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        (MuxUTP::new(a, true, config), b)
+    }
+
+    async fn send_frames(raw: &mut tokio::io::DuplexStream, frames: &[Frame]) {
+        for frame in frames {
+            raw.write_all(&frame.encode()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flow_control_beyond_window() {
+        let (server, client) = pair();
+
+        let stream = client.new_stream(IntegrityType::Reliable).await.unwrap();
+        let UTPEvent::NewStream(id) = server.next_event().await else {
+            panic!("expected NewStream");
+        };
+        let peer = server
+            .wait_stream(id, IntegrityType::Reliable)
+            .await
+            .unwrap();
+
+        let (mut write, _) = stream.split();
+        let (_, mut read) = peer.split();
+
+        let len = INITIAL_WINDOW as usize * 4;
+        let writer = tokio::spawn(async move {
+            write.write_all(&vec![7; len]).await.unwrap();
+            write.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        read.read_to_end(&mut received).await.unwrap();
+        writer.await.unwrap();
+
+        assert_eq!(received.len(), len);
+        assert!(matches!(
+            server.next_event().await,
+            UTPEvent::StreamClosed(closed) if closed == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_datagrams_before_registration() {
+        let (server, client) = pair();
+
+        let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+        let (mut write, _) = stream.split();
+
+        for message in [&b"muffin"[..], b"is", b"cute"] {
+            <MuxUTP as UTP>::Stream::send_datagram(&mut write, Bytes::copy_from_slice(message))
+                .await
+                .unwrap();
+        }
+
+        // give the messages time to arrive before the stream is registered
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let peer = server
+            .wait_stream(0, IntegrityType::Unreliable)
+            .await
+            .unwrap();
+        let (_, mut read) = peer.split();
+
+        for message in [&b"muffin"[..], b"is", b"cute"] {
+            let data = <MuxUTP as UTP>::Stream::recv_datagram(&mut read)
+                .await
+                .unwrap();
+            assert_eq!(data, message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_datagram_streams_are_capped() {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let server = MuxUTP::new(
+            a,
+            true,
+            MuxConfig::default().with_max_pending_datagram_streams(2),
+        );
+        let client = MuxUTP::new(b, false, MuxConfig::default());
+
+        for _ in 0..3 {
+            let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+            let (mut write, _) = stream.split();
+            <MuxUTP as UTP>::Stream::send_datagram(&mut write, Bytes::from_static(b"muffin"))
+                .await
+                .unwrap();
+        }
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        for (id, kept) in [(0, true), (2, true), (4, false)] {
+            let peer = server
+                .wait_stream(id, IntegrityType::Unreliable)
+                .await
+                .unwrap();
+            let (_, mut read) = peer.split();
+            let received = tokio::time::timeout(
+                std::time::Duration::from_millis(50),
+                <MuxUTP as UTP>::Stream::recv_datagram(&mut read),
+            )
+            .await;
+            assert_eq!(received.is_ok(), kept, "stream {}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_datagram_stream_is_removed() {
+        let (server, client) = pair();
+
+        let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+        let id = stream.id();
+        assert!(client.shared.datagrams.contains_key(&id));
+
+        let (write, read) = stream.split();
+        drop(read);
+        assert!(!client.shared.datagrams.contains_key(&id));
+        drop(write);
+
+        let peer = server
+            .wait_stream(id, IntegrityType::Unreliable)
+            .await
+            .unwrap();
+        drop(peer);
+        assert!(server.shared.datagrams.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_open_with_local_parity_closes() {
+        let (server, mut raw) = raw_pair(MuxConfig::default());
+
+        send_frames(&mut raw, &[Frame::Open { id: 1 }]).await;
+
+        assert!(matches!(
+            server.next_event().await,
+            UTPEvent::UnexpectedClose
+        ));
+        assert!(matches!(
+            read_frame(&mut raw).await.unwrap(),
+            Frame::Close {
+                code: PROTOCOL_ERROR,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_open_closes() {
+        let (server, mut raw) = raw_pair(MuxConfig::default());
+
+        send_frames(&mut raw, &[Frame::Open { id: 0 }, Frame::Open { id: 0 }]).await;
+
+        assert!(matches!(server.next_event().await, UTPEvent::NewStream(0)));
+        assert!(matches!(
+            server.next_event().await,
+            UTPEvent::UnexpectedClose
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pending_accepts_are_capped() {
+        let (server, mut raw) = raw_pair(MuxConfig::default().with_max_pending_accepts(2));
+
+        let opens: Vec<_> = [0, 2, 4].map(|id| Frame::Open { id }).into();
+        send_frames(&mut raw, &opens).await;
+
+        assert_eq!(
+            read_frame(&mut raw).await.unwrap(),
+            Frame::Reset { id: 4, code: 0 }
+        );
+        for id in [0, 2] {
+            assert!(
+                matches!(server.next_event().await, UTPEvent::NewStream(opened) if opened == id)
+            );
+            server
+                .wait_stream(id, IntegrityType::Reliable)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window_overrun_closes() {
+        let (server, mut raw) = raw_pair(MuxConfig::default());
+
+        let data = Bytes::from(vec![7; INITIAL_WINDOW as usize + 1]);
+        send_frames(
+            &mut raw,
+            &[Frame::Open { id: 0 }, Frame::Data { id: 0, data }],
+        )
+        .await;
+
+        assert!(matches!(server.next_event().await, UTPEvent::NewStream(0)));
+        assert!(matches!(
+            server.next_event().await,
+            UTPEvent::UnexpectedClose
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_close_reaches_peer() {
+        let (server, client) = pair();
+
+        client.close(3, "bye").await.unwrap();
+
+        assert!(matches!(
+            server.next_event().await,
+            UTPEvent::Closed { reason } if reason == "bye"
+        ));
+        assert!(matches!(
+            client.next_event().await,
+            UTPEvent::Closed { reason } if reason == "bye"
+        ));
+        assert!(client.new_stream(IntegrityType::Reliable).await.is_err());
+    }
+}
@@ -0,0 +1,444 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTPStream, read_framed_datagram, write_framed_datagram};
+use protofish::{IntegrityType, StreamId};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::Semaphore;
+use tokio::sync::mpsc::{self, UnboundedReceiver, error::TrySendError};
+use tokio_util::sync::{PollSemaphore, PollSender};
+
+use crate::config::INITIAL_WINDOW;
+use crate::frame::Frame;
+use crate::mux::Shared;
+
+/// A stream multiplexed over a `MuxUTP` connection.
+pub struct MuxStream {
+    id: StreamId,
+    integrity_type: IntegrityType,
+    writer: MuxStreamWrite,
+    reader: MuxStreamRead,
+}
+
+pub enum MuxStreamWrite {
+    Reliable(ReliableWrite),
+    Unreliable(DatagramWrite),
+}
+
+pub enum MuxStreamRead {
+    Reliable(ReliableRead),
+    Unreliable(DatagramRead),
+}
+
+/// Write half of a reliable stream.
+///
+/// Each write waits for credit granted by the peer, so a slow reader
+/// eventually stalls the writer instead of growing its buffers.
+pub struct ReliableWrite {
+    id: StreamId,
+    shared: Arc<Shared>,
+    tx: PollSender<Frame>,
+    credit: PollSemaphore,
+    /// Credit acquired but not yet used.
+    held: usize,
+    finished: bool,
+}
+
+/// Read half of a reliable stream.
+pub struct ReliableRead {
+    id: StreamId,
+    shared: Arc<Shared>,
+    rx: UnboundedReceiver<io::Result<Bytes>>,
+    pending: Bytes,
+    /// Bytes read since the last window update.
+    consumed: usize,
+}
+
+/// Write half of an unreliable stream. Messages that do not fit in the send
+/// queue are dropped.
+pub struct DatagramWrite {
+    id: StreamId,
+    shared: Arc<Shared>,
+}
+
+/// Read half of an unreliable stream.
+///
+/// Each received message is kept whole, so it can be consumed either as a
+/// message with `recv` or as bytes through `AsyncRead`.
+pub struct DatagramRead {
+    id: StreamId,
+    shared: Arc<Shared>,
+    rx: mpsc::Receiver<Bytes>,
+    pending: Bytes,
+}
+
+impl MuxStream {
+    pub(crate) fn new_reliable(
+        id: StreamId,
+        shared: Arc<Shared>,
+        rx: UnboundedReceiver<io::Result<Bytes>>,
+        credit: Arc<Semaphore>,
+    ) -> Self {
+        Self {
+            id,
+            integrity_type: IntegrityType::Reliable,
+            writer: MuxStreamWrite::Reliable(ReliableWrite {
+                id,
+                shared: shared.clone(),
+                tx: PollSender::new(shared.data_tx.clone()),
+                credit: PollSemaphore::new(credit),
+                held: 0,
+                finished: false,
+            }),
+            reader: MuxStreamRead::Reliable(ReliableRead {
+                id,
+                shared,
+                rx,
+                pending: Bytes::new(),
+                consumed: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn new_unreliable(
+        id: StreamId,
+        shared: Arc<Shared>,
+        rx: mpsc::Receiver<Bytes>,
+    ) -> Self {
+        Self {
+            id,
+            integrity_type: IntegrityType::Unreliable,
+            writer: MuxStreamWrite::Unreliable(DatagramWrite {
+                id,
+                shared: shared.clone(),
+            }),
+            reader: MuxStreamRead::Unreliable(DatagramRead {
+                id,
+                shared,
+                rx,
+                pending: Bytes::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl UTPStream for MuxStream {
+    type StreamRead = MuxStreamRead;
+    type StreamWrite = MuxStreamWrite;
+
+    fn id(&self) -> StreamId {
+        self.id
+    }
+
+    fn integrity_type(&self) -> IntegrityType {
+        self.integrity_type.clone()
+    }
+
+    fn split(self) -> (Self::StreamWrite, Self::StreamRead) {
+        (self.writer, self.reader)
+    }
+
+    /// Sends one message as a single `Datagram` frame on unreliable streams,
+    /// so it is either delivered whole or dropped. Reliable streams use the
+    /// length-prefixed fallback.
+    async fn send_datagram(writer: &mut Self::StreamWrite, data: Bytes) -> Result<(), UTPError> {
+        match writer {
+            MuxStreamWrite::Reliable(reliable) => write_framed_datagram(reliable, data).await,
+            MuxStreamWrite::Unreliable(unreliable) => {
+                let max = unreliable.shared.config.max_datagram_size;
+                if data.len() > max {
+                    return Err(UTPError::Warn(format!(
+                        "datagram of {} bytes exceeds {} bytes",
+                        data.len(),
+                        max
+                    )));
+                }
+
+                Ok(unreliable.send(data)?)
+            }
+        }
+    }
+
+    async fn recv_datagram(reader: &mut Self::StreamRead) -> Result<Bytes, UTPError> {
+        match reader {
+            MuxStreamRead::Reliable(reliable) => read_framed_datagram(reliable).await,
+            MuxStreamRead::Unreliable(unreliable) => unreliable
+                .recv()
+                .await
+                .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "stream closed").into()),
+        }
+    }
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "connection closed")
+}
+
+impl ReliableWrite {
+    /// Takes as much credit as is available, up to `want` bytes, waiting
+    /// until at least one byte is granted.
+    fn poll_credit(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<io::Result<()>> {
+        if self.held > 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        match ready!(self.credit.poll_acquire(cx)) {
+            Some(permit) => permit.forget(),
+            None => return Poll::Ready(Err(broken_pipe())),
+        }
+        self.held = 1;
+
+        let extra = (want - 1).min(self.credit.available_permits());
+        if extra > 0
+            && let Ok(permits) = self
+                .credit
+                .clone_inner()
+                .try_acquire_many_owned(extra as u32)
+        {
+            permits.forget();
+            self.held += extra;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ReliableWrite {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let want = buf.len().min(self.shared.config.max_frame_size);
+        ready!(self.poll_credit(cx, want))?;
+        ready!(self.tx.poll_reserve(cx)).map_err(|_| broken_pipe())?;
+
+        let len = self.held.min(want);
+        let id = self.id;
+        self.tx
+            .send_item(Frame::Data {
+                id,
+                data: Bytes::copy_from_slice(&buf[..len]),
+            })
+            .map_err(|_| broken_pipe())?;
+        self.held -= len;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.finished {
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(self.tx.poll_reserve(cx)).map_err(|_| broken_pipe())?;
+        let id = self.id;
+        self.tx
+            .send_item(Frame::Fin { id })
+            .map_err(|_| broken_pipe())?;
+        self.finished = true;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for ReliableWrite {
+    fn drop(&mut self) {
+        self.shared.remove_credit(self.id);
+
+        if self.finished {
+            return;
+        }
+
+        // dropping the write half finishes the stream, like quinn does
+        if let Some(tx) = self.tx.get_ref()
+            && let Err(TrySendError::Full(frame)) = tx.try_send(Frame::Fin { id: self.id })
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            let tx = tx.clone();
+            runtime.spawn(async move {
+                let _ = tx.send(frame).await;
+            });
+        }
+    }
+}
+
+impl AsyncRead for ReliableRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.pending.is_empty() {
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(Ok(data)) => self.pending = data,
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                // end of stream
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.pending.len().min(buf.remaining());
+        let data = self.pending.split_to(len);
+        buf.put_slice(&data);
+
+        self.consumed += len;
+        if self.consumed >= INITIAL_WINDOW as usize / 2 {
+            let consumed = std::mem::take(&mut self.consumed);
+            self.shared.grant(self.id, consumed);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for ReliableRead {
+    fn drop(&mut self) {
+        self.shared.remove_receiver(self.id);
+
+        // hand back the credit of everything that will never be read, so the
+        // peer's writer does not stall
+        let mut unread = self.consumed + self.pending.len();
+        while let Ok(Ok(data)) = self.rx.try_recv() {
+            unread += data.len();
+        }
+        self.shared.grant(self.id, unread);
+    }
+}
+
+impl DatagramWrite {
+    fn send(&self, data: Bytes) -> io::Result<()> {
+        if self.shared.is_closed() {
+            return Err(broken_pipe());
+        }
+
+        match self
+            .shared
+            .datagram_tx
+            .try_send(Frame::Datagram { id: self.id, data })
+        {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                tracing::trace!("send queue full, dropping datagram on stream {}", self.id);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(broken_pipe()),
+        }
+    }
+}
+
+impl AsyncWrite for DatagramWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let len = buf.len().min(self.shared.config.max_datagram_size);
+        self.send(Bytes::copy_from_slice(&buf[..len]))?;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl DatagramRead {
+    /// Receives the next whole message, or whatever is left of it after a
+    /// partial `AsyncRead`.
+    ///
+    /// Returns `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Bytes> {
+        if !self.pending.is_empty() {
+            return Some(std::mem::take(&mut self.pending));
+        }
+
+        self.rx.recv().await
+    }
+}
+
+impl AsyncRead for DatagramRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pending.is_empty() {
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(data) => self.pending = data,
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.pending.len().min(buf.remaining());
+        let data = self.pending.split_to(len);
+        buf.put_slice(&data);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DatagramRead {
+    fn drop(&mut self) {
+        self.shared.remove_datagrams(self.id);
+    }
+}
+
+impl AsyncWrite for MuxStreamWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MuxStreamWrite::Reliable(s) => Pin::new(s).poll_write(cx, buf),
+            MuxStreamWrite::Unreliable(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MuxStreamWrite::Reliable(s) => Pin::new(s).poll_flush(cx),
+            MuxStreamWrite::Unreliable(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MuxStreamWrite::Reliable(s) => Pin::new(s).poll_shutdown(cx),
+            MuxStreamWrite::Unreliable(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+impl AsyncRead for MuxStreamRead {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MuxStreamRead::Reliable(s) => Pin::new(s).poll_read(cx, buf),
+            MuxStreamRead::Unreliable(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+use bytes::Bytes;
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPEvent, UTPStream};
+use tcpfish::{MuxUTP, TcpConfig, TcpEndpoint};
+
+mod common;
+use common::create_test_certs;
+
+async fn connected_pair(server_config: TcpConfig, client_config: TcpConfig) -> (MuxUTP, MuxUTP) {
+    let server_endpoint = TcpEndpoint::server("127.0.0.1:0".parse().unwrap(), server_config)
+        .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move { server_endpoint.accept().await });
+
+    let client_endpoint =
+        TcpEndpoint::client(client_config).expect("Failed to create client endpoint");
+    let client = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .expect("Failed to connect");
+
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .expect("Server task failed")
+        .expect("Server should have accepted connection");
+
+    (server, client)
+}
+
+#[tokio::test]
+async fn test_reliable_stream() {
+    let (server, client) = connected_pair(TcpConfig::default(), TcpConfig::default()).await;
+
+    assert_eq!(client.peer_addr(), server.local_addr());
+
+    let stream = client.new_stream(IntegrityType::Reliable).await.unwrap();
+    let peer = server
+        .wait_stream(stream.id(), IntegrityType::Reliable)
+        .await
+        .unwrap();
+
+    let (mut write, mut read) = stream.split();
+    let (mut peer_write, mut peer_read) = peer.split();
+
+    write.write_all(b"muffin").await.unwrap();
+    let mut buf = [0; 6];
+    peer_read.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"muffin");
+
+    peer_write.write_all(b"cute").await.unwrap();
+    let mut buf = [0; 4];
+    read.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"cute");
+}
+
+#[tokio::test]
+async fn test_tls_protofish_connection() {
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let (server, client) = connected_pair(
+        TcpConfig::default().with_server_tls(server_crypto),
+        TcpConfig::default().with_client_tls(client_crypto),
+    )
+    .await;
+
+    let server = tokio::spawn(async move { protofish::accept(Arc::new(server)).await });
+    let client = protofish::connect(Arc::new(client)).await.unwrap();
+    let server = server.await.unwrap().unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    let data = vec![42u8; 1024 * 1024];
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+        stream.write_all(&data).await.unwrap();
+        stream
+    });
+
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, expected);
+
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_silent_client_does_not_block_accept() {
+    let (server_crypto, client_crypto) = create_test_certs();
+
+    let server_endpoint = TcpEndpoint::server(
+        "127.0.0.1:0".parse().unwrap(),
+        TcpConfig::default()
+            .with_server_tls(server_crypto)
+            .with_handshake_timeout(Duration::from_millis(200)),
+    )
+    .unwrap();
+    let server_addr = server_endpoint.local_addr().unwrap();
+    let server_handle = tokio::spawn(async move { server_endpoint.accept().await });
+
+    // connects and never starts the handshake
+    let _silent = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+
+    let client_endpoint =
+        TcpEndpoint::client(TcpConfig::default().with_client_tls(client_crypto)).unwrap();
+    let client = client_endpoint
+        .connect(server_addr, "localhost")
+        .await
+        .unwrap();
+
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("accept blocked behind the silent client")
+        .unwrap()
+        .unwrap();
+    assert_eq!(server.peer_addr(), client.local_addr());
+}
+
+#[tokio::test]
+async fn test_unreliable_stream() {
+    let (server, client) = connected_pair(TcpConfig::default(), TcpConfig::default()).await;
+
+    let stream = client.new_stream(IntegrityType::Unreliable).await.unwrap();
+    let peer = server
+        .wait_stream(stream.id(), IntegrityType::Unreliable)
+        .await
+        .unwrap();
+
+    let (mut write, _) = stream.split();
+    let (_, mut read) = peer.split();
+
+    for message in [&b"muffin"[..], b"is", b"cute"] {
+        <MuxUTP as UTP>::Stream::send_datagram(&mut write, Bytes::copy_from_slice(message))
+            .await
+            .unwrap();
+    }
+
+    for message in [&b"muffin"[..], b"is", b"cute"] {
+        let data = timeout(
+            Duration::from_secs(2),
+            <MuxUTP as UTP>::Stream::recv_datagram(&mut read),
+        )
+        .await
+        .expect("Receive timeout")
+        .unwrap();
+
+        assert_eq!(data, message);
+    }
+}
+
+#[tokio::test]
+async fn test_graceful_close_event() {
+    let (server, client) = connected_pair(TcpConfig::default(), TcpConfig::default()).await;
+
+    client.close(0, "bye").await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), server.next_event())
+        .await
+        .expect("Server timeout");
+
+    assert!(
+        matches!(&event, UTPEvent::Closed { reason } if reason == "bye"),
+        "unexpected event: {:?}",
+        event
+    );
+}
+
+#[tokio::test]
+async fn test_dropped_connection_is_unexpected_close() {
+    let (server, client) = connected_pair(TcpConfig::default(), TcpConfig::default()).await;
+
+    drop(client);
+
+    let event = timeout(Duration::from_secs(2), server.next_event())
+        .await
+        .expect("Server timeout");
+
+    assert!(matches!(event, UTPEvent::UnexpectedClose));
+}
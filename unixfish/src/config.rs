@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use tcpfish::MuxConfig;
+
+use crate::utp::PeerCred;
+
+/// Decides whether a server accepts a peer, given its credentials.
+pub type PeerFilter = Arc<dyn Fn(&PeerCred) -> bool + Send + Sync>;
+
+/// Settings of a `UnixEndpoint`.
+#[derive(Clone, Default)]
+pub struct UnixConfig {
+    pub mux: MuxConfig,
+    /// Checked by servers before a connection is handed out. Peers whose
+    /// credentials cannot be read are rejected when a filter is set.
+    pub peer_filter: Option<PeerFilter>,
+}
+
+impl UnixConfig {
+    pub fn with_mux(mut self, mux: MuxConfig) -> Self {
+        self.mux = mux;
+        self
+    }
+
+    pub fn with_peer_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerCred) -> bool + Send + Sync + 'static,
+    {
+        self.peer_filter = Some(Arc::new(filter));
+        self
+    }
+}
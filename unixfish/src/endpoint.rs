@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::config::UnixConfig;
+use crate::error::{Error, Result};
+use crate::utp::UnixUTP;
+
+/// Opens or accepts connections on a Unix domain socket.
+///
+/// A server endpoint removes its socket file when dropped.
+pub struct UnixEndpoint {
+    listener: Option<(UnixListener, PathBuf)>,
+    config: UnixConfig,
+}
+
+impl UnixEndpoint {
+    pub fn client(config: UnixConfig) -> Result<Self> {
+        if config.peer_filter.is_some() {
+            return Err(Error::Config("Peer filter provided for client".to_string()));
+        }
+
+        Ok(Self {
+            listener: None,
+            config,
+        })
+    }
+
+    /// Binds a listening socket at `path`. Must be called within a tokio
+    /// runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be bound, e.g. because `path`
+    /// already exists.
+    pub fn server(path: impl AsRef<Path>, config: UnixConfig) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let listener = UnixListener::bind(&path)?;
+
+        Ok(Self {
+            listener: Some((listener, path)),
+            config,
+        })
+    }
+
+    /// Connects to the server listening at `path`.
+    pub async fn connect(&self, path: impl AsRef<Path>) -> Result<UnixUTP> {
+        if self.listener.is_some() {
+            return Err(Error::Config(
+                "Cannot connect from server endpoint".to_string(),
+            ));
+        }
+
+        let stream = UnixStream::connect(path).await?;
+
+        Ok(UnixUTP::new(stream, false, self.config.mux.clone()))
+    }
+
+    /// Waits for the next client.
+    ///
+    /// Peers rejected by the configured filter are disconnected and skipped.
+    /// Returns `None` if this is a client endpoint or the listener fails.
+    pub async fn accept(&self) -> Option<UnixUTP> {
+        let (listener, _) = self.listener.as_ref()?;
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Unix socket accept failure: {}", e);
+                    return None;
+                }
+            };
+
+            let utp = UnixUTP::new(stream, true, self.config.mux.clone());
+
+            if let Some(filter) = &self.config.peer_filter {
+                match utp.peer_cred() {
+                    Some(cred) if filter(&cred) => {}
+                    cred => {
+                        tracing::warn!("rejected Unix socket peer {:?}", cred);
+                        continue;
+                    }
+                }
+            }
+
+            return Some(utp);
+        }
+    }
+
+    /// Returns the path the server endpoint is bound to.
+    pub fn local_path(&self) -> Result<&Path> {
+        match &self.listener {
+            Some((_, path)) => Ok(path),
+            None => Err(Error::Config("Client endpoint is not bound".to_string())),
+        }
+    }
+}
+
+impl Drop for UnixEndpoint {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.listener {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub struct UnixEndpointBuilder {
+    config: UnixConfig,
+    path: Option<PathBuf>,
+}
+
+impl UnixEndpointBuilder {
+    pub fn new_client() -> Self {
+        Self {
+            config: UnixConfig::default(),
+            path: None,
+        }
+    }
+
+    pub fn new_server(path: impl AsRef<Path>) -> Self {
+        Self {
+            config: UnixConfig::default(),
+            path: Some(path.as_ref().to_path_buf()),
+        }
+    }
+
+    pub fn with_config(mut self, config: UnixConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> Result<UnixEndpoint> {
+        match self.path {
+            Some(path) => UnixEndpoint::server(path, self.config),
+            None => UnixEndpoint::client(self.config),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+}
+
+impl From<Error> for protofish::utp::error::UTPError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => protofish::utp::error::UTPError::Io(e),
+            other => protofish::utp::error::UTPError::Fatal(other.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
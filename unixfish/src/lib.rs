@@ -0,0 +1,13 @@
+pub mod config;
+pub mod endpoint;
+pub mod error;
+pub mod utp;
+
+pub type Connection = protofish::Connection<UnixUTP>;
+pub type ArbContext = protofish::ArbContext<UnixUTP>;
+
+pub use config::{PeerFilter, UnixConfig};
+pub use endpoint::{UnixEndpoint, UnixEndpointBuilder};
+pub use error::{Error, Result};
+pub use tcpfish::MuxConfig;
+pub use utp::{PeerCred, UnixUTP};
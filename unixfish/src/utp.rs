@@ -0,0 +1,102 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use protofish::utp::error::UTPError;
+use protofish::utp::{UTP, UTPEvent, UTPStats};
+use protofish::{IntegrityType, StreamId};
+use tcpfish::{MuxConfig, MuxStream, MuxUTP};
+use tokio::net::UnixStream;
+
+/// Credentials of the process on the other end of a Unix socket, as
+/// reported by the kernel when the connection was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not reported on every platform.
+    pub pid: Option<i32>,
+}
+
+/// UTP over a Unix domain socket.
+///
+/// All streams share the socket through `MuxUTP`. Unix sockets have no IP
+/// addresses, so `peer_addr` and `local_addr` are always `None`; use
+/// `peer_cred` and `peer_path` instead.
+pub struct UnixUTP {
+    mux: MuxUTP,
+    peer_cred: Option<PeerCred>,
+    peer_path: Option<PathBuf>,
+}
+
+impl UnixUTP {
+    pub fn new(stream: UnixStream, is_server: bool, config: MuxConfig) -> Self {
+        let peer_cred = stream.peer_cred().ok().map(|cred| PeerCred {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        });
+        let peer_path = stream
+            .peer_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(Path::to_path_buf));
+
+        Self {
+            mux: MuxUTP::new(stream, is_server, config),
+            peer_cred,
+            peer_path,
+        }
+    }
+
+    /// Returns the credentials of the peer process, if they could be read.
+    pub fn peer_cred(&self) -> Option<PeerCred> {
+        self.peer_cred
+    }
+
+    /// Returns the path the peer is bound to. Clients are usually unnamed,
+    /// so this is mostly set on the client side.
+    pub fn peer_path(&self) -> Option<&Path> {
+        self.peer_path.as_deref()
+    }
+}
+
+#[async_trait]
+impl UTP for UnixUTP {
+    type Stream = MuxStream;
+
+    async fn connect(&self) -> Result<(), UTPError> {
+        self.mux.connect().await
+    }
+
+    async fn next_event(&self) -> UTPEvent {
+        self.mux.next_event().await
+    }
+
+    async fn new_stream(&self, integrity: IntegrityType) -> Result<Self::Stream, UTPError> {
+        self.mux.new_stream(integrity).await
+    }
+
+    async fn wait_stream(
+        &self,
+        id: StreamId,
+        integrity: IntegrityType,
+    ) -> Result<Self::Stream, UTPError> {
+        self.mux.wait_stream(id, integrity).await
+    }
+
+    async fn close(&self, code: u64, reason: &str) -> Result<(), UTPError> {
+        self.mux.close(code, reason).await
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn stats(&self) -> UTPStats {
+        self.mux.stats()
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPEvent};
+use unixfish::{UnixConfig, UnixEndpoint, UnixEndpointBuilder};
+
+fn socket_path() -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "unixfish-{}-{}.sock",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[tokio::test]
+async fn test_protofish_over_unix_socket() {
+    let path = socket_path();
+    let server_endpoint = UnixEndpointBuilder::new_server(&path)
+        .build()
+        .expect("Failed to create server endpoint");
+
+    let server_handle = tokio::spawn(async move {
+        let utp = server_endpoint.accept().await.expect("Failed to accept");
+        protofish::accept(Arc::new(utp)).await
+    });
+
+    let client_endpoint = UnixEndpoint::client(UnixConfig::default()).unwrap();
+    let utp = client_endpoint.connect(&path).await.unwrap();
+    assert_eq!(utp.peer_path(), Some(path.as_path()));
+
+    let client = protofish::connect(Arc::new(utp)).await.unwrap();
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    stream.write_all(b"muffin").await.unwrap();
+    let mut buf = [0; 6];
+    peer.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"muffin");
+}
+
+#[tokio::test]
+async fn test_peer_credentials() {
+    let path = socket_path();
+    let server_endpoint = UnixEndpoint::server(&path, UnixConfig::default()).unwrap();
+
+    let client_endpoint = UnixEndpoint::client(UnixConfig::default()).unwrap();
+    let (server, client) = tokio::join!(server_endpoint.accept(), client_endpoint.connect(&path));
+    let (server, client) = (server.unwrap(), client.unwrap());
+
+    let server_cred = server.peer_cred().expect("missing peer credentials");
+    let client_cred = client.peer_cred().expect("missing peer credentials");
+
+    assert_eq!(server_cred, client_cred);
+    if let Some(pid) = server_cred.pid {
+        assert_eq!(pid as u32, std::process::id());
+    }
+}
+
+#[tokio::test]
+async fn test_peer_filter_rejects() {
+    let path = socket_path();
+    let server_endpoint = UnixEndpoint::server(
+        &path,
+        UnixConfig::default().with_peer_filter(|cred| cred.uid == u32::MAX),
+    )
+    .unwrap();
+
+    tokio::spawn(async move { server_endpoint.accept().await });
+
+    let client_endpoint = UnixEndpoint::client(UnixConfig::default()).unwrap();
+    let client = client_endpoint.connect(&path).await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), client.next_event())
+        .await
+        .expect("Client timeout");
+
+    assert!(matches!(event, UTPEvent::UnexpectedClose));
+}
+
+#[tokio::test]
+async fn test_socket_removed_on_drop() {
+    let path = socket_path();
+    let server_endpoint = UnixEndpoint::server(&path, UnixConfig::default()).unwrap();
+    assert!(path.exists());
+
+    drop(server_endpoint);
+    assert!(!path.exists());
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tcpfish::MuxConfig;
+
+/// Settings of a `WsEndpoint`.
+pub struct WsConfig {
+    /// Request path served by a server endpoint. Upgrade requests for other
+    /// paths are answered with `404 Not Found`.
+    pub path: String,
+    pub nodelay: bool,
+    pub mux: MuxConfig,
+    /// TLS settings used by clients for `wss://` URLs
+    pub tls: Option<Arc<rustls::ClientConfig>>,
+    /// Time a server gives each client to complete the upgrade
+    pub handshake_timeout: Duration,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            path: "/".to_string(),
+            nodelay: true,
+            mux: MuxConfig::default(),
+            tls: None,
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WsConfig {
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn with_mux(mut self, mux: MuxConfig) -> Self {
+        self.mux = mux;
+        self
+    }
+
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    pub fn with_client_tls(mut self, crypto: rustls::ClientConfig) -> Self {
+        self.tls = Some(Arc::new(crypto));
+        self
+    }
+}
@@ -0,0 +1,221 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rustls::pki_types::ServerName;
+use tcpfish::{MuxConfig, MuxUTP, TlsConnector};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{StatusCode, Uri};
+
+use crate::config::WsConfig;
+use crate::error::{Error, Result};
+use crate::io::WsIo;
+
+/// Opens or accepts WebSocket connections and multiplexes protofish streams
+/// over their binary messages.
+pub struct WsEndpoint {
+    listener: Option<TcpListener>,
+    config: WsConfig,
+    /// Upgrades of accepted clients, run concurrently so that a slow client
+    /// does not hold up the others
+    upgrades: Mutex<JoinSet<Option<MuxUTP>>>,
+}
+
+/// Next event of the accept loop.
+enum Accepted {
+    Client(TcpStream, SocketAddr),
+    Upgrade(Option<MuxUTP>),
+    Failed(io::Error),
+}
+
+impl WsEndpoint {
+    pub fn client(config: WsConfig) -> Self {
+        Self {
+            listener: None,
+            config,
+            upgrades: Default::default(),
+        }
+    }
+
+    /// Binds a listening socket. Must be called within a tokio runtime.
+    pub fn server(bind_addr: SocketAddr, config: WsConfig) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(bind_addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self::from_listener(
+            TcpListener::from_std(listener)?,
+            config,
+        ))
+    }
+
+    /// Serves WebSocket connections on an already bound listener.
+    pub fn from_listener(listener: TcpListener, config: WsConfig) -> Self {
+        Self {
+            listener: Some(listener),
+            config,
+            upgrades: Default::default(),
+        }
+    }
+
+    /// Connects to a `ws://` or `wss://` URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid, `wss://` is used without TLS
+    /// settings, or the connection or upgrade fails.
+    pub async fn connect(&self, url: &str) -> Result<MuxUTP> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|e| Error::Config(format!("invalid URL {}: {}", url, e)))?;
+        let secure = match uri.scheme_str() {
+            Some("ws") => false,
+            Some("wss") => true,
+            _ => return Err(Error::Config(format!("not a WebSocket URL: {}", url))),
+        };
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::Config(format!("missing host in {}", url)))?;
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+        let stream = TcpStream::connect((host.trim_matches(['[', ']']), port)).await?;
+        stream.set_nodelay(self.config.nodelay)?;
+        let addrs = (stream.local_addr().ok(), stream.peer_addr().ok());
+
+        let utp = if secure {
+            let crypto =
+                self.config.tls.clone().ok_or_else(|| {
+                    Error::Config("wss:// requires client TLS settings".to_string())
+                })?;
+            let server_name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+                .map_err(|e| Error::Config(e.to_string()))?;
+            let stream = TlsConnector::from(crypto)
+                .connect(server_name, stream)
+                .await?;
+
+            self.connect_stream(url, stream).await?
+        } else {
+            self.connect_stream(url, stream).await?
+        };
+
+        Ok(utp.with_addrs(addrs.0, addrs.1))
+    }
+
+    /// Runs the client upgrade for `url` over an established stream, e.g.
+    /// one tunneled through a proxy.
+    pub async fn connect_stream<S>(&self, url: &str, io: S) -> Result<MuxUTP>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (ws, _) = tokio_tungstenite::client_async(url, io).await?;
+
+        Ok(MuxUTP::new(WsIo::new(ws), false, self.config.mux.clone()))
+    }
+
+    /// Waits for the next client on the endpoint's listener.
+    ///
+    /// Upgrades run concurrently, each within the configured
+    /// `handshake_timeout`, and keep running when this future is dropped.
+    /// Failed upgrades are logged and skipped. Returns `None` if this is a
+    /// client endpoint or the listener fails.
+    pub async fn accept(&self) -> Option<MuxUTP> {
+        let listener = self.listener.as_ref()?;
+        let mut upgrades = self.upgrades.lock().await;
+
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => Accepted::Client(stream, peer),
+                    Err(e) => Accepted::Failed(e),
+                },
+                Some(joined) = upgrades.join_next() => {
+                    Accepted::Upgrade(joined.unwrap_or_default())
+                }
+            };
+
+            let (stream, peer) = match accepted {
+                Accepted::Client(stream, peer) => (stream, peer),
+                Accepted::Upgrade(Some(utp)) => return Some(utp),
+                Accepted::Upgrade(None) => continue,
+                Accepted::Failed(e) => {
+                    tracing::warn!("TCP accept failure: {}", e);
+                    return None;
+                }
+            };
+
+            if let Err(e) = stream.set_nodelay(self.config.nodelay) {
+                tracing::warn!("failed to set TCP_NODELAY: {}", e);
+            }
+            let local = stream.local_addr().ok();
+
+            let path = self.config.path.clone();
+            let mux = self.config.mux.clone();
+            let timeout = self.config.handshake_timeout;
+
+            upgrades.spawn(async move {
+                match upgrade(stream, &path, mux, timeout).await {
+                    Ok(utp) => Some(utp.with_addrs(local, Some(peer))),
+                    Err(e) => {
+                        tracing::warn!("WebSocket upgrade from {} failed: {}", peer, e);
+                        None
+                    }
+                }
+            });
+        }
+    }
+
+    /// Runs the server upgrade over a connection accepted elsewhere, e.g. by
+    /// an existing listener or after TLS termination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is not a valid upgrade for the
+    /// configured path, or does not complete within the configured
+    /// `handshake_timeout`.
+    pub async fn accept_stream<S>(&self, io: S) -> Result<MuxUTP>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        upgrade(
+            io,
+            &self.config.path,
+            self.config.mux.clone(),
+            self.config.handshake_timeout,
+        )
+        .await
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        match &self.listener {
+            Some(listener) => Ok(listener.local_addr()?),
+            None => Err(Error::Config("Client endpoint is not bound".to_string())),
+        }
+    }
+}
+
+/// Runs the server upgrade for `path` over `io`, giving up after `timeout`.
+async fn upgrade<S>(io: S, path: &str, mux: MuxConfig, timeout: Duration) -> Result<MuxUTP>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // the callback signature, including its error type, is set by tungstenite
+    #[allow(clippy::result_large_err)]
+    let check_path = |request: &Request, response: Response| {
+        if request.uri().path() == path {
+            Ok(response)
+        } else {
+            let mut response = ErrorResponse::new(None);
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            Err(response)
+        }
+    };
+
+    let ws = tokio::time::timeout(timeout, tokio_tungstenite::accept_hdr_async(io, check_path))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "upgrade timed out"))??;
+
+    Ok(MuxUTP::new(WsIo::new(ws), true, mux))
+}
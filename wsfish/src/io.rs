@@ -0,0 +1,183 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use bytes::{Buf, Bytes};
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+/// Byte stream over the binary messages of a WebSocket.
+///
+/// Every write is sent as one binary message, and received messages are
+/// concatenated. Pings are answered by the socket itself, and a close
+/// message ends the stream.
+pub struct WsIo<S> {
+    ws: WebSocketStream<S>,
+    /// Unread part of the last received message.
+    pending: Bytes,
+    closed: bool,
+}
+
+impl<S> WsIo<S> {
+    pub fn new(ws: WebSocketStream<S>) -> Self {
+        Self {
+            ws,
+            pending: Bytes::new(),
+            closed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &WebSocketStream<S> {
+        &self.ws
+    }
+}
+
+impl<S> AsyncRead for WsIo<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.pending.is_empty() {
+            if this.closed {
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(Pin::new(&mut this.ws).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => this.pending = data,
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected text message",
+                    )));
+                }
+                Some(Ok(Message::Close(_))) | None => this.closed = true,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+
+        let len = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..len]);
+        this.pending.advance(len);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WsIo<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        ready!(Pin::new(&mut this.ws).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut this.ws)
+            .start_send(Message::Binary(Bytes::copy_from_slice(buf)))
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().ws)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match ready!(Pin::new(&mut self.get_mut().ws).poll_close(cx)) {
+            Ok(()) | Err(tungstenite::Error::ConnectionClosed) => Poll::Ready(Ok(())),
+            Err(e) => Poll::Ready(Err(into_io_error(e))),
+        }
+    }
+}
+
+fn into_io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::Error::new(io::ErrorKind::BrokenPipe, "WebSocket closed")
+        }
+        other => io::Error::other(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::SinkExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    async fn pair() -> (
+        WebSocketStream<tokio::io::DuplexStream>,
+        WebSocketStream<tokio::io::DuplexStream>,
+    ) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+
+        (
+            WebSocketStream::from_raw_socket(a, Role::Client, None).await,
+            WebSocketStream::from_raw_socket(b, Role::Server, None).await,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_messages_read_as_bytes() {
+        let (mut client, server) = pair().await;
+        let mut server = WsIo::new(server);
+
+        client
+            .send(Message::Binary(Bytes::from_static(b"muffin")))
+            .await
+            .unwrap();
+        client
+            .send(Message::Binary(Bytes::from_static(b"cute")))
+            .await
+            .unwrap();
+        client.close(None).await.unwrap();
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"muffincute");
+    }
+
+    #[tokio::test]
+    async fn test_text_message_rejected() {
+        let (mut client, server) = pair().await;
+        let mut server = WsIo::new(server);
+
+        client.send(Message::text("muffin")).await.unwrap();
+
+        let mut buf = [0; 6];
+        let err = server.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_binary_messages() {
+        let (client, mut server) = pair().await;
+        let mut client = WsIo::new(client);
+
+        client.write_all(b"muffin").await.unwrap();
+        client.flush().await.unwrap();
+
+        let message = futures::StreamExt::next(&mut server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message, Message::Binary(Bytes::from_static(b"muffin")));
+    }
+}
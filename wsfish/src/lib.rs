@@ -0,0 +1,17 @@
+pub mod config;
+pub mod endpoint;
+pub mod error;
+pub mod io;
+
+pub type Connection = protofish::Connection<WsUTP>;
+pub type ArbContext = protofish::ArbContext<WsUTP>;
+
+/// The UTP of a WebSocket connection, which is a `MuxUTP` over the binary
+/// messages of the socket.
+pub type WsUTP = tcpfish::MuxUTP;
+
+pub use config::WsConfig;
+pub use endpoint::WsEndpoint;
+pub use error::{Error, Result};
+pub use io::WsIo;
+pub use tcpfish::MuxConfig;
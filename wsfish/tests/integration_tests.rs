@@ -0,0 +1,198 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+use protofish::IntegrityType;
+use protofish::utp::{UTP, UTPEvent, UTPStream};
+use tcpfish::TlsAcceptor;
+use wsfish::{WsConfig, WsEndpoint};
+
+mod common;
+use common::create_test_certs;
+
+#[tokio::test]
+async fn test_protofish_over_websocket() {
+    let server_endpoint = WsEndpoint::server(
+        "127.0.0.1:0".parse().unwrap(),
+        WsConfig::default().with_path("/protofish"),
+    )
+    .expect("Failed to create server endpoint");
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move {
+        let utp = server_endpoint.accept().await.expect("Failed to accept");
+        protofish::accept(Arc::new(utp)).await
+    });
+
+    let client_endpoint = WsEndpoint::client(WsConfig::default());
+    let utp = client_endpoint
+        .connect(&format!("ws://{}/protofish", server_addr))
+        .await
+        .expect("Failed to connect");
+    assert_eq!(utp.peer_addr(), Some(server_addr));
+
+    let client = protofish::connect(Arc::new(utp)).await.unwrap();
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("Server timeout")
+        .unwrap()
+        .unwrap();
+
+    let arb = client.new_arb();
+    let mut stream = arb.new_stream(IntegrityType::Reliable).await.unwrap();
+
+    let peer_arb = server.next_arb().await.unwrap();
+    let mut peer = peer_arb.wait_stream().await.unwrap();
+
+    let data = vec![42u8; 1024 * 1024];
+    let expected = data.clone();
+    let writer = tokio::spawn(async move {
+        stream.write_all(&data).await.unwrap();
+        stream
+    });
+
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, expected);
+
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wrong_path_rejected() {
+    let server_endpoint = WsEndpoint::server(
+        "127.0.0.1:0".parse().unwrap(),
+        WsConfig::default().with_path("/protofish"),
+    )
+    .unwrap();
+    let server_addr = server_endpoint.local_addr().unwrap();
+
+    tokio::spawn(async move { server_endpoint.accept().await });
+
+    let client_endpoint = WsEndpoint::client(WsConfig::default());
+    let result = client_endpoint
+        .connect(&format!("ws://{}/elsewhere", server_addr))
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_silent_client_does_not_block_accept() {
+    let server_endpoint = WsEndpoint::server(
+        "127.0.0.1:0".parse().unwrap(),
+        WsConfig::default().with_handshake_timeout(Duration::from_millis(200)),
+    )
+    .unwrap();
+    let server_addr = server_endpoint.local_addr().unwrap();
+    let server_handle = tokio::spawn(async move { server_endpoint.accept().await });
+
+    // connects and never sends the upgrade request
+    let _silent = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+
+    let client_endpoint = WsEndpoint::client(WsConfig::default());
+    let client = client_endpoint
+        .connect(&format!("ws://{}/", server_addr))
+        .await
+        .unwrap();
+
+    let server = timeout(Duration::from_secs(2), server_handle)
+        .await
+        .expect("accept blocked behind the silent client")
+        .unwrap()
+        .unwrap();
+    assert_eq!(server.peer_addr(), client.local_addr());
+}
+
+#[tokio::test]
+async fn test_upgrade_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+
+    let _silent = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+
+    let endpoint =
+        WsEndpoint::client(WsConfig::default().with_handshake_timeout(Duration::from_millis(50)));
+    let result = timeout(Duration::from_secs(2), endpoint.accept_stream(stream))
+        .await
+        .expect("upgrade did not time out");
+
+    assert!(matches!(
+        result,
+        Err(wsfish::Error::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut
+    ));
+}
+
+#[tokio::test]
+async fn test_mounted_on_existing_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+
+    let server_handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let endpoint = WsEndpoint::client(WsConfig::default());
+        endpoint.accept_stream(stream).await
+    });
+
+    let client_endpoint = WsEndpoint::client(WsConfig::default());
+    let client = client_endpoint
+        .connect(&format!("ws://{}/", server_addr))
+        .await
+        .unwrap();
+    let server = server_handle.await.unwrap().unwrap();
+
+    client.close(0, "bye").await.unwrap();
+
+    let event = timeout(Duration::from_secs(2), server.next_event())
+        .await
+        .expect("Server timeout");
+
+    assert!(
+        matches!(&event, UTPEvent::Closed { reason } if reason == "bye"),
+        "unexpected event: {:?}",
+        event
+    );
+}
+
+#[tokio::test]
+async fn test_secure_websocket() {
+    let (server_crypto, client_crypto) = create_test_certs();
+    let server_crypto = Arc::new(server_crypto);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server_handle = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = TlsAcceptor::from(server_crypto)
+            .accept(stream)
+            .await
+            .unwrap();
+        let endpoint = WsEndpoint::client(WsConfig::default());
+        endpoint.accept_stream(stream).await
+    });
+
+    let client_endpoint = WsEndpoint::client(WsConfig::default().with_client_tls(client_crypto));
+    let client = client_endpoint
+        .connect(&format!("wss://localhost:{}/", port))
+        .await
+        .unwrap();
+    let server = server_handle.await.unwrap().unwrap();
+
+    let stream = client.new_stream(IntegrityType::Reliable).await.unwrap();
+    let peer = server
+        .wait_stream(stream.id(), IntegrityType::Reliable)
+        .await
+        .unwrap();
+
+    let (mut write, _) = stream.split();
+    let (_, mut read) = peer.split();
+
+    write.write_all(b"muffin").await.unwrap();
+    let mut buf = [0; 6];
+    read.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"muffin");
+}